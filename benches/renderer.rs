@@ -0,0 +1,126 @@
+//! Criterion benchmarks for the renderer: controller tessellation, sending
+//! batches to the renderer, recv_data() batching, texture uploads and whole
+//! frames. They draw to a hidden window, so need a display to run:
+//! `cargo bench --bench renderer`.
+
+#[macro_use]
+extern crate criterion;
+extern crate quick_gfx;
+
+use criterion::{BatchSize, Criterion};
+use quick_gfx::{QGFX, QGFXBuilder, TexHandle};
+
+const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+fn hidden_qgfx<'a>() -> QGFX<'a> {
+  QGFXBuilder::new().with_dimensions(640, 480).with_visibility(false).build().unwrap()
+}
+
+/// Caches two tiny textures, so draws can alternate between them.
+fn two_textures(qgfx: &mut QGFX) -> Vec<TexHandle> {
+  qgfx.cache_tex_from_rgba(&[(1, 1, &[0xff; 4][..]), (2, 1, &[0xff; 8][..])])
+    .into_iter()
+    .map(|t| t.unwrap())
+    .collect()
+}
+
+// Controllers are created outside the measurement, and dropped unflushed
+// afterwards, so what's drawn doesn't pile up between iterations.
+fn tessellation(c: &mut Criterion) {
+  let qgfx = hidden_qgfx();
+  c.bench_function("1000 rects", |b| {
+    b.iter_batched_ref(|| qgfx.get_renderer_controller(), |controller| {
+      for _ in 0..1000 {
+        controller.rect(&[0.0, 0.0, 8.0, 8.0], &WHITE);
+      }
+    }, BatchSize::SmallInput);
+  });
+  // Circles are tessellated once, then scaled and translated.
+  c.bench_function("1000 circles", |b| {
+    b.iter_batched_ref(|| qgfx.get_renderer_controller(), |controller| {
+      for _ in 0..1000 {
+        controller.circle(&[100.0, 100.0], 50.0, 0, &WHITE);
+      }
+    }, BatchSize::SmallInput);
+  });
+}
+
+fn channel_throughput(c: &mut Criterion) {
+  let mut qgfx = hidden_qgfx();
+  c.bench_function("flush 1000 rects", |b| {
+    b.iter(|| {
+      {
+        let mut controller = qgfx.get_renderer_controller();
+        for _ in 0..1000 {
+          controller.rect(&[0.0, 0.0, 1.0, 1.0], &WHITE);
+        }
+        controller.flush();
+      }
+      qgfx.recv_data();
+    });
+  });
+}
+
+fn recv_data_batching(c: &mut Criterion) {
+  let mut qgfx = hidden_qgfx();
+  let texs = two_textures(&mut qgfx);
+  // Interleave textures, so every quad starts a new run.
+  c.bench_function("recv_data 1000 interleaved quads", |b| {
+    b.iter(|| {
+      {
+        let mut controller = qgfx.get_renderer_controller();
+        for ii in 0..1000 {
+          controller.tex(texs[ii % 2], &[0.0, 0.0, 1.0, 1.0], &WHITE).unwrap();
+        }
+        controller.flush();
+      }
+      qgfx.recv_data();
+    });
+  });
+}
+
+fn upload(c: &mut Criterion) {
+  let mut qgfx = hidden_qgfx();
+  let pixels = vec![0x80; 64 * 64 * 4];
+  c.bench_function("cache and free a 64x64 texture", |b| {
+    b.iter(|| {
+      let tex = qgfx.cache_tex_from_rgba(&[(64, 64, &pixels[..])]).remove(0).unwrap();
+      qgfx.free_tex(&[tex]);
+    });
+  });
+}
+
+#[cfg(feature = "default-font")]
+fn text(c: &mut Criterion) {
+  let mut qgfx = hidden_qgfx();
+  let fh = qgfx.default_font().unwrap();
+  c.bench_function("text 55 glyphs", |b| {
+    b.iter_batched_ref(|| qgfx.get_renderer_controller(), |controller| {
+      controller.text("The quick brown fox jumps over the lazy dog! 0123456789", &[0.0, 12.0],
+                      fh, &WHITE).unwrap();
+    }, BatchSize::SmallInput);
+  });
+}
+
+#[cfg(not(feature = "default-font"))]
+fn text(_: &mut Criterion) {}
+
+fn frame(c: &mut Criterion) {
+  let mut qgfx = hidden_qgfx();
+  {
+    let mut controller = qgfx.get_renderer_controller();
+    for ii in 0..10000 {
+      controller.rect(&[(ii % 100) as f32 * 6.0, (ii / 100) as f32 * 4.0, 8.0, 8.0], &WHITE);
+    }
+    controller.flush();
+  }
+  qgfx.recv_data();
+  // Measures uploading the received batches to VBOs and drawing them.
+  c.bench_function("render 10000 rects", |b| {
+    b.iter(|| qgfx.render().unwrap());
+  });
+}
+
+criterion_group!(benches, tessellation, channel_throughput, recv_data_batching, upload, text,
+                 frame);
+criterion_main!(benches);
@@ -1,10 +1,15 @@
 extern crate quick_gfx;
 
+mod common;
+
 use std::cmp::min;
 
 fn main() {
+  // Pass --headless to render frames to PNGs instead, see common/mod.rs.
+  let mut runner = common::Runner::from_args();
+
   // Create the renderer and get a controller
-  let mut qgfx = quick_gfx::QGFX::new();
+  let mut qgfx = runner.build();
 
   // Get display size
   let (mut win_w, mut win_h) = qgfx.get_display_size();
@@ -23,19 +28,14 @@ fn main() {
     // changed
     qgfx.poll_events(|ev| {
       match ev {
-        quick_gfx::Event::WindowEvent{event: ev, ..} => {
-          match ev {
-            quick_gfx::WindowEvent::Closed => closed = true,
-            quick_gfx::WindowEvent::Resized(new_w, new_h) => {
-              // Window size has changed, reset the ball_radius and position of the ball
-              ball_pos = [new_w as f32 / 2.0, new_h as f32 / 2.0];
-              ball_rad = min(new_w / 10, new_h / 10) as f32;
-              // Update win size
-              win_w = new_w;
-              win_h = new_h;
-            }
-            _ => ()
-          }
+        quick_gfx::QgfxEvent::CloseRequested => closed = true,
+        quick_gfx::QgfxEvent::Resized{w: new_w, h: new_h} => {
+          // Window size has changed, reset the ball_radius and position of the ball
+          ball_pos = [new_w as f32 / 2.0, new_h as f32 / 2.0];
+          ball_rad = min(new_w / 10, new_h / 10) as f32;
+          // Update win size
+          win_w = new_w;
+          win_h = new_h;
         }
         _ => ()
       }
@@ -64,13 +64,13 @@ fn main() {
       ball_pos[1] = win_h as f32 - ball_rad;
     }
 
-    // Render the circle
-    {
-        let mut controller = qgfx.get_renderer_controller();
-        controller.circle(&ball_pos, ball_rad, 32, &[1.0, 0.0, 1.0, 1.0]);
-        controller.flush();
+    // Render the circle. Finishing the frame receives what was drawn, then
+    // renders it.
+    let mut frame = qgfx.begin_frame();
+    frame.controller().circle(&ball_pos, ball_rad, 32, &[1.0, 0.0, 1.0, 1.0]);
+    frame.finish().unwrap();
+    if runner.frame_done(&mut qgfx) {
+      break;
     }
-    qgfx.recv_data();
-    qgfx.render();
   }
 }
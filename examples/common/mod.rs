@@ -0,0 +1,83 @@
+//! Shared by the examples: lets each one run headless, rendering a fixed
+//! number of frames to PNGs instead of showing a window, so the examples can
+//! run on CI machines and their output can be compared between changes.
+//!
+//! ```text
+//! cargo run --example square -- --headless --frames 3 --out frames/
+//! ```
+//!
+//! writes frames/square_0.png, frames/square_1.png and frames/square_2.png,
+//! then exits. The hidden window still needs a display server, e.g. Xvfb.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use quick_gfx::{QGFX, QGFXBuilder, Deterministic};
+
+pub struct Runner {
+  /// The example's name, which prefixes the PNGs.
+  name: String,
+  headless: bool,
+  /// How many frames to render when headless.
+  frames: u32,
+  /// The directory the PNGs are written to.
+  out: PathBuf,
+  /// The number of frames rendered so far.
+  frame: u32,
+}
+
+impl Runner {
+  /// Parses `--headless`, `--frames N` (default 1) and `--out DIR` (default
+  /// the current directory) from the command line.
+  /// # Panics
+  /// Panics if the arguments are invalid.
+  pub fn from_args() -> Runner {
+    let mut args = env::args();
+    let name = args.next()
+      .and_then(|exe| Path::new(&exe).file_stem().map(|s| s.to_string_lossy().into_owned()))
+      .unwrap_or_else(|| "example".to_owned());
+    let mut runner = Runner {
+      name: name, headless: false, frames: 1, out: PathBuf::from("."), frame: 0,
+    };
+    while let Some(arg) = args.next() {
+      match &arg[..] {
+        "--headless" => runner.headless = true,
+        "--frames" => runner.frames = args.next().and_then(|n| n.parse().ok())
+          .expect("--frames needs a number"),
+        "--out" => runner.out = PathBuf::from(args.next().expect("--out needs a directory")),
+        _ => panic!("Unknown argument {}", arg),
+      }
+    }
+    runner
+  }
+
+  /// Creates the example's window. When headless, the window is hidden and
+  /// deterministic mode is enabled, so every run writes the same PNGs.
+  /// # Panics
+  /// Panics if the window couldn't be created.
+  pub fn build<'a>(&self) -> QGFX<'a> {
+    let mut qgfx = QGFXBuilder::new().with_visibility(!self.headless)
+      .build().expect("Failed to create QGFX");
+    if self.headless {
+      qgfx.set_deterministic(Some(Deterministic::default()));
+    }
+    qgfx
+  }
+
+  /// Call after rendering each frame. When headless, this writes the frame
+  /// to a PNG.
+  /// # Returns
+  /// True once the example should exit - when headless, after the last
+  /// frame.
+  pub fn frame_done(&mut self, qgfx: &mut QGFX) -> bool {
+    if !self.headless {
+      return false;
+    }
+    fs::create_dir_all(&self.out).expect("Failed to create the output directory");
+    let path = self.out.join(format!("{}_{}.png", self.name, self.frame));
+    qgfx.capture_frame().expect("Failed to capture the frame")
+      .save_png(&path).expect("Failed to write the frame");
+    self.frame += 1;
+    self.frame >= self.frames
+  }
+}
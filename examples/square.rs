@@ -1,8 +1,13 @@
 extern crate quick_gfx;
 
+mod common;
+
 fn main() {
+  // Pass --headless to render frames to PNGs instead, see common/mod.rs.
+  let mut runner = common::Runner::from_args();
+
   // Create the renderer, and get a controller
-  let mut g = quick_gfx::QGFX::new();
+  let mut g = runner.build();
 
 
   {
@@ -26,18 +31,15 @@ fn main() {
   while !closed {
     // Poll events to check if window has been closed
     g.poll_events(|ev| {
-      match ev {
-        quick_gfx::Event::WindowEvent{event: ev, window_id: _} => {
-          match ev {
-            quick_gfx::WindowEvent::Closed => closed = true,
-            _ => ()
-          }
-        },
-        _ => ()
+      if let quick_gfx::QgfxEvent::CloseRequested = ev {
+        closed = true;
       }
     });
 
     // Render everything
-    g.render();
+    g.render().unwrap();
+    if runner.frame_done(&mut g) {
+      break;
+    }
   }
 }
@@ -0,0 +1,73 @@
+//! Stress test: simulates and draws 20,000 particles every frame, as small
+//! circles and rects from several threads. Use the perf overlay to compare
+//! frame times between changes to the renderer.
+
+extern crate quick_gfx;
+
+use std::thread;
+
+const NUM_PARTICLES: usize = 20_000;
+const NUM_THREADS: usize = 4;
+const GRAVITY : f32 = 0.2;
+
+#[derive(Copy, Clone)]
+struct Particle {
+  pos: [f32; 2],
+  vel: [f32; 2],
+  col: [f32; 4],
+}
+
+fn main() {
+  let mut qgfx = quick_gfx::QGFX::new();
+  qgfx.show_perf_overlay(true);
+  let (w, h) = qgfx.get_display_size();
+  let (w, h) = (w as f32, h as f32);
+
+  let mut seed = 54321u32;
+  let mut rand = move || {
+    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+    (seed >> 8) as f32 / (1 << 24) as f32
+  };
+  let mut particles : Vec<Particle> = (0..NUM_PARTICLES).map(|_| Particle {
+    pos: [rand() * w, rand() * h],
+    vel: [rand() * 4.0 - 2.0, rand() * 4.0 - 2.0],
+    col: [rand(), rand(), rand(), 0.8],
+  }).collect();
+
+  let mut closed = false;
+  while !closed {
+    qgfx.poll_events(|ev| {
+      if let quick_gfx::QgfxEvent::CloseRequested = ev {
+        closed = true;
+      }
+    });
+
+    // Update and draw the particles in chunks, one thread per chunk, each
+    // with its own controller.
+    let chunk_size = NUM_PARTICLES / NUM_THREADS;
+    let handles : Vec<_> = particles.chunks(chunk_size).map(|chunk| {
+      let mut chunk = chunk.to_vec();
+      let mut controller = qgfx.get_renderer_controller();
+      thread::spawn(move || {
+        for p in &mut chunk {
+          p.vel[1] += GRAVITY;
+          p.pos[0] += p.vel[0];
+          p.pos[1] += p.vel[1];
+          if p.pos[0] < 0.0 || p.pos[0] > w { p.vel[0] = -p.vel[0]; }
+          if p.pos[1] > h { p.vel[1] = -p.vel[1].abs(); p.pos[1] = h; }
+          if p.vel[0].abs() > 1.0 {
+            controller.circle(&p.pos, 2.0, 8, &p.col);
+          } else {
+            controller.rect(&[p.pos[0] - 1.0, p.pos[1] - 1.0, 2.0, 2.0], &p.col);
+          }
+        }
+        controller.flush();
+        chunk
+      })
+    }).collect();
+    particles = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+
+    qgfx.recv_data();
+    qgfx.render().unwrap();
+  }
+}
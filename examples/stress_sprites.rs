@@ -0,0 +1,46 @@
+//! Stress test: draws 100,000 textured sprites every frame. Use the perf
+//! overlay to compare frame times between changes to the renderer.
+
+extern crate quick_gfx;
+
+const NUM_SPRITES: usize = 100_000;
+
+fn main() {
+  let mut qgfx = quick_gfx::QGFX::new();
+  qgfx.show_perf_overlay(true);
+
+  // A 2x2 checkerboard, so the example doesn't need any image files.
+  let pixels = [0xff, 0xff, 0xff, 0xff, 0x80, 0x80, 0x80, 0xff,
+                0x80, 0x80, 0x80, 0xff, 0xff, 0xff, 0xff, 0xff];
+  let tex = qgfx.cache_tex_from_rgba(&[(2, 2, &pixels[..])]).remove(0).unwrap();
+
+  // Scatter the sprites with a simple LCG, so the layout is the same every run.
+  let (w, h) = qgfx.get_display_size();
+  let mut seed = 12345u32;
+  let mut rand = move || {
+    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+    (seed >> 8) as f32 / (1 << 24) as f32
+  };
+  let sprites : Vec<[f32; 4]> = (0..NUM_SPRITES)
+    .map(|_| [rand() * w as f32, rand() * h as f32, 8.0, 8.0])
+    .collect();
+
+  let mut closed = false;
+  while !closed {
+    qgfx.poll_events(|ev| {
+      if let quick_gfx::QgfxEvent::CloseRequested = ev {
+        closed = true;
+      }
+    });
+
+    {
+      let mut controller = qgfx.get_renderer_controller();
+      for aabb in &sprites {
+        controller.tex(tex, aabb, &[1.0, 1.0, 1.0, 1.0]).unwrap();
+      }
+      controller.flush();
+    }
+    qgfx.recv_data();
+    qgfx.render().unwrap();
+  }
+}
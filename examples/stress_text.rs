@@ -0,0 +1,41 @@
+//! Stress test: draws 10,000 glyphs of text every frame, using the bundled
+//! default font. Use the perf overlay to compare frame times between changes
+//! to the renderer.
+
+extern crate quick_gfx;
+
+const LINE: &'static str = "The quick brown fox jumps over the lazy dog! 0123456789";
+const NUM_GLYPHS: usize = 10_000;
+
+fn main() {
+  let mut qgfx = quick_gfx::QGFX::new();
+  qgfx.show_perf_overlay(true);
+  let fh = qgfx.default_font().unwrap();
+
+  let num_lines = NUM_GLYPHS / LINE.chars().count();
+
+  let mut closed = false;
+  let mut frame = 0;
+  while !closed {
+    qgfx.poll_events(|ev| {
+      if let quick_gfx::QgfxEvent::CloseRequested = ev {
+        closed = true;
+      }
+    });
+
+    {
+      let mut controller = qgfx.get_renderer_controller();
+      // Scroll the text, so the layout can't be cached between frames.
+      let offset = (frame % 100) as f32;
+      for ii in 0..num_lines {
+        let y = (ii % 60) as f32 * 12.0 + 12.0;
+        let x = (ii / 60) as f32 * 4.0 + offset;
+        controller.text(LINE, &[x, y], fh, &[1.0, 1.0, 1.0, 1.0]).unwrap();
+      }
+      controller.flush();
+    }
+    qgfx.recv_data();
+    qgfx.render().unwrap();
+    frame += 1;
+  }
+}
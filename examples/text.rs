@@ -1,16 +1,15 @@
 extern crate quick_gfx;
 
-use std::collections::HashSet;
+mod common;
 
 fn main() {
-  // Create the renderer, then cache some glyphs.
-  let mut g = quick_gfx::QGFX::new();
-  let mut charsets = HashSet::new();
-  charsets.insert(quick_gfx::Charset::Lowercase);
-  charsets.insert(quick_gfx::Charset::Uppercase);
-  charsets.insert(quick_gfx::Charset::Numbers);
-  charsets.insert(quick_gfx::Charset::Punctuation);
-  let fh = g.cache_glyphs("Arial Unicode.ttf", 32.0, &quick_gfx::gen_charset(&charsets)[..]).unwrap();
+  // Pass --headless to render frames to PNGs instead, see common/mod.rs.
+  let mut runner = common::Runner::from_args();
+
+  // Create the renderer, then get the bundled default font. To use another
+  // font, cache its glyphs with cache_glyphs().
+  let mut g = runner.build();
+  let fh = g.default_font().unwrap();
   
   // Get a controller, and draw some text.
   {
@@ -28,19 +27,16 @@ fn main() {
   while !closed {
     // Poll events to check if window has been closed
     g.poll_events(|ev| {
-      match ev {
-        quick_gfx::Event::WindowEvent{event: ev, window_id: _} => {
-          match ev {
-            quick_gfx::WindowEvent::Closed => closed = true,
-            _ => ()
-          }
-        },
-        _ => ()
+      if let quick_gfx::QgfxEvent::CloseRequested = ev {
+        closed = true;
       }
     });
 
     // Render everything
-    g.render();
+    g.render().unwrap();
+    if runner.frame_done(&mut g) {
+      break;
+    }
   }
 }
 
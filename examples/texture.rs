@@ -1,8 +1,13 @@
 extern crate quick_gfx;
 
+mod common;
+
 fn main() {
+  // Pass --headless to render frames to PNGs instead, see common/mod.rs.
+  let mut runner = common::Runner::from_args();
+
   // Create the renderer.
-  let mut g = quick_gfx::QGFX::new();
+  let mut g = runner.build();
 
   // Cache a texture
   let tex_handle = &g.cache_tex(&["rust-logo.png"])[0];
@@ -28,19 +33,16 @@ fn main() {
   while !closed {
     // Poll events to check if window has been closed
     g.poll_events(|ev| {
-      match ev {
-        quick_gfx::Event::WindowEvent{event: ev, window_id: _} => {
-          match ev {
-            quick_gfx::WindowEvent::Closed => closed = true,
-            _ => ()
-          }
-        },
-        _ => ()
+      if let quick_gfx::QgfxEvent::CloseRequested = ev {
+        closed = true;
       }
     });
 
     // Render everything
-    g.render();
+    g.render().unwrap();
+    if runner.frame_done(&mut g) {
+      break;
+    }
   }
 }
 
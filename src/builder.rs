@@ -0,0 +1,400 @@
+//! QGFXBuilder, for configuring the window and renderer before creating a
+//! QGFX.
+
+use std::cell::Cell;
+use std::sync::Mutex;
+use glium;
+use glium::debug::{DebugCallbackBehavior, MessageType, Severity, Source};
+use glium::glutin::{GlProfile, GlRequest, Robustness};
+use error::QgfxError;
+use renderer::{Renderer, VirtualTarget, ColorSpace, HdrFormat, PostProcess, Tonemap};
+use context::QgfxContext;
+use QGFX;
+use frame::FrameClock;
+
+/// Configures and creates a QGFX. QGFX::new() is the same as
+/// `QGFXBuilder::new().build()`.
+///
+/// ```ignore
+/// let qgfx = QGFXBuilder::new()
+///   .with_title("My game")
+///   .with_virtual_resolution(320, 180)
+///   .pixel_art()
+///   .build()?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct QGFXBuilder {
+  title: String,
+  dimensions: (u32, u32),
+  multisampling: u16,
+  vsync: bool,
+  resizable: bool,
+  virtual_resolution: Option<(u32, u32)>,
+  integer_scaling: bool,
+  nearest_filtering: bool,
+  pixel_snap: bool,
+  color_space: ColorSpace,
+  hdr: Option<HdrFormat>,
+  tonemap: Tonemap,
+  transparent: bool,
+  decorations: bool,
+  visible: bool,
+  /// The index of the monitor to go fullscreen on. See with_fullscreen().
+  fullscreen: Option<usize>,
+  gl_request: GlRequest,
+  gl_profile: Option<GlProfile>,
+  depth_bits: Option<u8>,
+  stencil_bits: Option<u8>,
+  srgb_framebuffer: bool,
+  robustness: Robustness,
+  /// Whether to create a debug context and log its messages. See
+  /// with_gl_debug().
+  gl_debug: bool,
+  render_scale: f32,
+}
+
+impl Default for QGFXBuilder {
+  fn default() -> QGFXBuilder {
+    QGFXBuilder {
+      title: "Hello world".to_owned(),
+      dimensions: (1024, 768),
+      multisampling: 0,
+      vsync: false,
+      resizable: true,
+      virtual_resolution: None,
+      integer_scaling: false,
+      nearest_filtering: false,
+      pixel_snap: false,
+      color_space: ColorSpace::default(),
+      hdr: None,
+      tonemap: Tonemap::default(),
+      transparent: false,
+      decorations: true,
+      visible: true,
+      fullscreen: None,
+      gl_request: GlRequest::Latest,
+      gl_profile: None,
+      depth_bits: None,
+      stencil_bits: None,
+      srgb_framebuffer: false,
+      robustness: Robustness::NotRobust,
+      gl_debug: false,
+      render_scale: 1.0,
+    }
+  }
+}
+
+impl QGFXBuilder {
+  pub fn new() -> QGFXBuilder { QGFXBuilder::default() }
+
+  /// Sets the window title.
+  pub fn with_title<T: Into<String>>(mut self, title: T) -> QGFXBuilder {
+    self.title = title.into();
+    self
+  }
+
+  /// Sets the initial size of the window, in pixels. Defaults to 1024x768.
+  pub fn with_dimensions(mut self, w: u32, h: u32) -> QGFXBuilder {
+    self.dimensions = (w, h);
+    self
+  }
+
+  /// Sets the number of MSAA samples. 0 (the default) disables multisampling.
+  pub fn with_multisampling(mut self, samples: u16) -> QGFXBuilder {
+    self.multisampling = samples;
+    self
+  }
+
+  /// Sets whether buffer swaps wait for the monitor's vertical refresh, so
+  /// frames don't tear and the frame rate is capped at the refresh rate.
+  /// Defaults to false. Some drivers force vsync on or off regardless.
+  pub fn with_vsync(mut self, vsync: bool) -> QGFXBuilder {
+    self.vsync = vsync;
+    self
+  }
+
+  /// Sets whether the user can resize the window. Defaults to true. The
+  /// version of winit this crate uses has no option for this, so a window
+  /// which isn't resizable has its minimum and maximum size set to its
+  /// initial size.
+  pub fn with_resizable(mut self, resizable: bool) -> QGFXBuilder {
+    self.resizable = resizable;
+    self
+  }
+
+  /// Renders at a fixed virtual resolution, which is scaled up to fill the
+  /// window keeping its aspect ratio. Renderer controller coordinates are in
+  /// virtual pixels, and QGFX::get_display_size() returns the virtual
+  /// resolution. Use QGFX::window_to_virtual() to map mouse positions.
+  pub fn with_virtual_resolution(mut self, w: u32, h: u32) -> QGFXBuilder {
+    self.virtual_resolution = Some((w, h));
+    self
+  }
+
+  /// Sets whether the virtual resolution is only scaled up by whole numbers,
+  /// so every virtual pixel is the same size on screen. Has no effect without
+  /// a virtual resolution.
+  pub fn with_integer_scaling(mut self, integer_scaling: bool) -> QGFXBuilder {
+    self.integer_scaling = integer_scaling;
+    self
+  }
+
+  /// Sets whether textures, and the virtual resolution, are scaled with
+  /// nearest neighbour filtering rather than linear filtering.
+  pub fn with_nearest_filtering(mut self, nearest: bool) -> QGFXBuilder {
+    self.nearest_filtering = nearest;
+    self
+  }
+
+  /// Sets whether vertex positions are rounded to the nearest (virtual)
+  /// pixel, so shapes and textures line up with the pixel grid.
+  pub fn with_pixel_snap(mut self, snap: bool) -> QGFXBuilder {
+    self.pixel_snap = snap;
+    self
+  }
+
+  /// Sets the colour space colours are handled in. See ColorSpace. Defaults
+  /// to ColorSpace::Srgb. ColorSpace::Linear requires an sRGB framebuffer, so
+  /// build() fails if one isn't available.
+  pub fn with_color_space(mut self, color_space: ColorSpace) -> QGFXBuilder {
+    self.color_space = color_space;
+    self
+  }
+
+  /// Renders each frame into a floating point target, so colours brighter
+  /// than white (from HDR textures, see QGFX::cache_tex_hdr(), or additive
+  /// effects) aren't clipped. A final pass maps them to the window with the
+  /// tone mapping operator set with with_tonemap().
+  pub fn with_hdr(mut self, format: HdrFormat) -> QGFXBuilder {
+    self.hdr = Some(format);
+    self
+  }
+
+  /// Sets how HDR colours are mapped into the displayable range. Defaults to
+  /// Tonemap::Clamp. Has no effect without with_hdr().
+  pub fn with_tonemap(mut self, tonemap: Tonemap) -> QGFXBuilder {
+    self.tonemap = tonemap;
+    self
+  }
+
+  /// Sets the render scale, for supersampling (scales above 1) or drawing
+  /// fewer pixels (scales below 1). See QGFX::set_render_scale(), which can
+  /// change it later. Defaults to 1.0.
+  pub fn with_render_scale(mut self, scale: f32) -> QGFXBuilder {
+    self.render_scale = scale;
+    self
+  }
+
+  /// Sets whether the window is transparent, so the desktop shows through
+  /// wherever nothing opaque is drawn - for overlays and screen annotation
+  /// tools. The frame is cleared to transparent black every frame, and alpha
+  /// is blended so the compositor gets the right coverage. Whether the window
+  /// is actually transparent depends on the platform and compositor.
+  pub fn with_transparency(mut self, transparent: bool) -> QGFXBuilder {
+    self.transparent = transparent;
+    self
+  }
+
+  /// Makes the window fullscreen on a monitor, given its index in
+  /// available_monitors(). None (the default) creates a normal window. If
+  /// there's no monitor with the index, a normal window is created and a
+  /// warning logged.
+  pub fn with_fullscreen(mut self, monitor: Option<usize>) -> QGFXBuilder {
+    self.fullscreen = monitor;
+    self
+  }
+
+  /// Sets whether the window is shown. Defaults to true. A hidden window can
+  /// still render, with frames read back by QGFX::capture_frame() - e.g. to
+  /// run examples on CI machines. It still needs a display server (such as
+  /// Xvfb) to create its OpenGL context.
+  pub fn with_visibility(mut self, visible: bool) -> QGFXBuilder {
+    self.visible = visible;
+    self
+  }
+
+  /// Sets whether the window has a border and title bar. Defaults to true.
+  pub fn with_decorations(mut self, decorations: bool) -> QGFXBuilder {
+    self.decorations = decorations;
+    self
+  }
+
+  /// Sets which OpenGL (or OpenGL ES) version to request. Defaults to
+  /// GlRequest::Latest. The renderer needs at least OpenGL 3.0 or OpenGL ES
+  /// 2.0.
+  pub fn with_gl_request(mut self, request: GlRequest) -> QGFXBuilder {
+    self.gl_request = request;
+    self
+  }
+
+  /// Sets which OpenGL profile to request, or None (the default) to let the
+  /// platform choose. Only applies to OpenGL 3.2 and later.
+  pub fn with_gl_profile(mut self, profile: Option<GlProfile>) -> QGFXBuilder {
+    self.gl_profile = profile;
+    self
+  }
+
+  /// Sets the number of bits the default framebuffer's depth buffer needs,
+  /// for DepthTest overrides drawn straight to the window. None (the
+  /// default) leaves it to the platform, which may not provide one.
+  pub fn with_depth_buffer(mut self, bits: Option<u8>) -> QGFXBuilder {
+    self.depth_bits = bits;
+    self
+  }
+
+  /// Sets the number of bits the default framebuffer's stencil buffer needs.
+  /// None (the default) leaves it to the platform, which may not provide
+  /// one.
+  pub fn with_stencil_buffer(mut self, bits: Option<u8>) -> QGFXBuilder {
+    self.stencil_bits = bits;
+    self
+  }
+
+  /// Sets whether to request an sRGB capable framebuffer, which build() fails
+  /// without. ColorSpace::Linear always requests one, so this is only needed
+  /// to check for one with other colour spaces. Defaults to false.
+  pub fn with_srgb_framebuffer(mut self, srgb: bool) -> QGFXBuilder {
+    self.srgb_framebuffer = srgb;
+    self
+  }
+
+  /// Sets how robust the context must be against out of bounds accesses and
+  /// GPU resets. Defaults to Robustness::NotRobust.
+  pub fn with_gl_robustness(mut self, robustness: Robustness) -> QGFXBuilder {
+    self.robustness = robustness;
+    self
+  }
+
+  /// Sets whether to create a debug context, whose messages from the driver
+  /// (errors, performance warnings and so on) are logged: high severity
+  /// messages as errors, medium as warnings, low as info, and notifications
+  /// as debug. Messages are reported synchronously, so they're logged during
+  /// the call which caused them, at some cost to performance. Defaults to
+  /// false. Useful for diagnosing driver issues.
+  pub fn with_gl_debug(mut self, debug: bool) -> QGFXBuilder {
+    self.gl_debug = debug;
+    self
+  }
+
+  /// Configures everything needed for crisp pixel art: nearest filtering,
+  /// integer scaling of the virtual resolution, no multisampling, and pixel
+  /// snapping. Set a virtual resolution with with_virtual_resolution() to
+  /// draw at a low resolution.
+  pub fn pixel_art(self) -> QGFXBuilder {
+    self.with_nearest_filtering(true)
+      .with_integer_scaling(true)
+      .with_multisampling(0)
+      .with_pixel_snap(true)
+  }
+
+  /// Creates the window and renderer.
+  /// # Errors
+  /// Returns an error if the window, OpenGL context, or any of the renderer's
+  /// GPU resources couldn't be created.
+  pub fn build<'a>(self) -> Result<QGFX<'a>, QgfxError> {
+    let events_loop = glium::glutin::EventsLoop::new();
+    let mut window = glium::glutin::WindowBuilder::new()
+      .with_dimensions(self.dimensions.0, self.dimensions.1)
+      .with_title(self.title.clone())
+      .with_transparency(self.transparent)
+      .with_decorations(self.decorations)
+      .with_visibility(self.visible);
+    if !self.resizable {
+      let (w, h) = self.dimensions;
+      window = window.with_min_dimensions(w, h).with_max_dimensions(w, h);
+    }
+    if let Some(ix) = self.fullscreen {
+      match events_loop.get_available_monitors().nth(ix) {
+        Some(monitor) => window = window.with_fullscreen(monitor),
+        None => warn!("No monitor with index {}, creating a normal window", ix),
+      }
+    }
+    let mut context = glium::glutin::ContextBuilder::new()
+      .with_multisampling(self.multisampling)
+      .with_vsync(self.vsync)
+      .with_srgb(self.srgb_framebuffer || self.color_space == ColorSpace::Linear)
+      .with_gl(self.gl_request)
+      .with_gl_robustness(self.robustness)
+      .with_gl_debug_flag(self.gl_debug);
+    if self.transparent {
+      // A transparent window needs an alpha channel to be composited with.
+      context = context.with_pixel_format(24, 8);
+    }
+    if let Some(profile) = self.gl_profile {
+      context = context.with_gl_profile(profile);
+    }
+    if let Some(bits) = self.depth_bits {
+      context = context.with_depth_buffer(bits);
+    }
+    if let Some(bits) = self.stencil_bits {
+      context = context.with_stencil_buffer(bits);
+    }
+    let display = if self.gl_debug {
+      let debug = DebugCallbackBehavior::Custom {
+        callback: Box::new(log_gl_message),
+        synchronous: true,
+      };
+      try!(glium::Display::with_debug(window, context, &events_loop, debug))
+    } else {
+      try!(glium::Display::new(window, context, &events_loop))
+    };
+    info!("Created display with OpenGL version {}", display.get_opengl_version_string());
+
+    let mut renderer = try!(Renderer::with_color_space(&display, self.color_space));
+    renderer.set_nearest_filtering(self.nearest_filtering);
+    renderer.set_pixel_snap(self.pixel_snap);
+    renderer.set_transparent_blending(self.transparent);
+    let virtual_target = match self.virtual_resolution {
+      Some((w, h)) => {
+        renderer.set_projection_size(w, h);
+        Some(try!(VirtualTarget::new(&display, w, h, self.integer_scaling,
+                                     self.nearest_filtering, self.hdr)))
+      }
+      None => None,
+    };
+
+    // We need to buffer a small white rectangle, for when drawing coloured
+    // shapes. This is a single white RGBA pixel, so it doesn't need the image
+    // feature to decode.
+    let pixel = [0xff, 0xff, 0xff, 0xff];
+    let white_tex_handle = try!(renderer.cache_tex_from_rgba(&display, &[(1, 1, &pixel[..])])
+                                .remove(0));
+
+    let post = match self.hdr {
+      Some(format) => {
+        let mut post = try!(PostProcess::new(&display, Some(format), self.color_space));
+        post.set_tonemap(self.tonemap);
+        Some(post)
+      }
+      None => None,
+    };
+
+    let mut qgfx = QGFX {
+      context: QgfxContext::new(renderer, display, white_tex_handle),
+      events_loop: Mutex::new(events_loop),
+      virtual_target: virtual_target,
+      scaled_target: None,
+      post: post,
+      shake: None,
+      clock: FrameClock::new(),
+      deterministic: None,
+      frame_hooks: Vec::new(),
+      perf_overlay: None,
+      soft_cursor: None,
+      mouse_pos: Cell::new(None),
+    };
+    try!(qgfx.set_render_scale(self.render_scale));
+    Ok(qgfx)
+  }
+}
+
+/// Logs a message from a debug context. See QGFXBuilder::with_gl_debug().
+fn log_gl_message(source: Source, ty: MessageType, severity: Severity, id: u32,
+                  _report_debug_output_errors: bool, message: &str) {
+  match severity {
+    Severity::High => error!("OpenGL {:?} {:?} {}: {}", source, ty, id, message),
+    Severity::Medium => warn!("OpenGL {:?} {:?} {}: {}", source, ty, id, message),
+    Severity::Low => info!("OpenGL {:?} {:?} {}: {}", source, ty, id, message),
+    Severity::Notification => debug!("OpenGL {:?} {:?} {}: {}", source, ty, id, message),
+  }
+}
@@ -0,0 +1,133 @@
+//! A module containing Canvas, a CPU-side image which can be drawn to pixel by
+//! pixel and then drawn like any other texture.
+
+use res::tex::{TexHandle, CacheTexError};
+use res::tex::glium_cache::{RgbaBuffer, StreamingTexture};
+use QGFX;
+
+/// An RGBA image in CPU memory, backed by a streaming texture. Draw into it
+/// with set_pixel() and blit(), then call commit() to upload the changes.
+/// Only the region changed since the last commit is uploaded.
+///
+/// ```ignore
+/// let mut canvas = Canvas::new(&mut qgfx, 256, 256)?;
+/// canvas.set_pixel(10, 10, [255, 0, 0, 255]);
+/// canvas.commit(&qgfx)?;
+/// controller.tex(canvas.handle(), &[0.0, 0.0, 256.0, 256.0], &[1.0; 4]);
+/// ```
+pub struct Canvas {
+  image: RgbaBuffer,
+  tex: StreamingTexture,
+  /// The region changed since the last commit - X0, Y0, X1, Y1.
+  dirty: Option<[u32; 4]>,
+}
+
+impl Canvas {
+  /// Creates a transparent canvas of the given size, in pixels.
+  /// # Errors
+  /// Returns an error if its streaming texture couldn't be created.
+  pub fn new(qgfx: &mut QGFX, w: u32, h: u32) -> Result<Canvas, CacheTexError> {
+    Ok(Canvas {
+      image: RgbaBuffer { w: w, h: h, data: vec![0; w as usize * h as usize * 4] },
+      tex: try!(qgfx.create_streaming_texture(w, h)),
+      dirty: None,
+    })
+  }
+
+  /// The handle to draw this canvas with. Changes aren't visible until
+  /// they're committed.
+  pub fn handle(&self) -> TexHandle { self.tex.handle() }
+
+  /// The size of the canvas in pixels.
+  pub fn size(&self) -> (u32, u32) { (self.image.w, self.image.h) }
+
+  /// The canvas' contents, including changes which haven't been committed.
+  pub fn image(&self) -> &RgbaBuffer { &self.image }
+
+  /// Gets the colour of a pixel, or None if it's outside the canvas.
+  pub fn get_pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+    if x >= self.image.w || y >= self.image.h { return None; }
+    let ix = self.pixel_index(x, y);
+    let p = &self.image.data[ix..ix + 4];
+    Some([p[0], p[1], p[2], p[3]])
+  }
+
+  /// Sets the colour of a pixel. Pixels outside the canvas are ignored.
+  pub fn set_pixel(&mut self, x: u32, y: u32, col: [u8; 4]) {
+    if x >= self.image.w || y >= self.image.h { return; }
+    let ix = self.pixel_index(x, y);
+    self.image.data[ix..ix + 4].copy_from_slice(&col);
+    self.mark_dirty([x, y, x + 1, y + 1]);
+  }
+
+  /// Fills the whole canvas with a colour.
+  pub fn clear(&mut self, col: [u8; 4]) {
+    for p in self.image.data.chunks_mut(4) {
+      p.copy_from_slice(&col);
+    }
+    let (w, h) = self.size();
+    self.mark_dirty([0, 0, w, h]);
+  }
+
+  /// Copies an image onto the canvas with its top left at (x, y). The image
+  /// replaces the pixels under it rather than being blended. Parts of the
+  /// image outside the canvas are clipped.
+  pub fn blit(&mut self, x: i32, y: i32, src: &RgbaBuffer) {
+    let x0 = x.max(0);
+    let y0 = y.max(0);
+    let x1 = (x + src.w as i32).min(self.image.w as i32);
+    let y1 = (y + src.h as i32).min(self.image.h as i32);
+    if x0 >= x1 || y0 >= y1 { return; }
+    let row_len = (x1 - x0) as usize * 4;
+    for dst_y in y0..y1 {
+      let src_ix = ((dst_y - y) as usize * src.w as usize + (x0 - x) as usize) * 4;
+      let dst_ix = self.pixel_index(x0 as u32, dst_y as u32);
+      self.image.data[dst_ix..dst_ix + row_len]
+        .copy_from_slice(&src.data[src_ix..src_ix + row_len]);
+    }
+    self.mark_dirty([x0 as u32, y0 as u32, x1 as u32, y1 as u32]);
+  }
+
+  /// Uploads the region changed since the last commit to the texture.
+  /// # Errors
+  /// This shouldn't fail, as the region is always inside the canvas.
+  pub fn commit(&mut self, qgfx: &QGFX) -> Result<(), CacheTexError> {
+    let dirty = match self.dirty.take() {
+      Some(dirty) => dirty,
+      None => return Ok(()),
+    };
+    let (w, h) = (dirty[2] - dirty[0], dirty[3] - dirty[1]);
+    let mut data = Vec::with_capacity(w as usize * h as usize * 4);
+    for y in dirty[1]..dirty[3] {
+      let ix = self.pixel_index(dirty[0], y);
+      data.extend_from_slice(&self.image.data[ix..ix + w as usize * 4]);
+    }
+    qgfx.update_streaming_texture_region(&mut self.tex, [dirty[0], dirty[1], w, h], &data)
+  }
+
+  fn pixel_index(&self, x: u32, y: u32) -> usize {
+    (y as usize * self.image.w as usize + x as usize) * 4
+  }
+
+  fn mark_dirty(&mut self, rect: [u32; 4]) {
+    self.dirty = Some(match self.dirty {
+      Some(d) => union_rect(&d, &rect),
+      None => rect,
+    });
+  }
+}
+
+/// The smallest rect containing both rects. Rects are X0, Y0, X1, Y1.
+fn union_rect(a: &[u32; 4], b: &[u32; 4]) -> [u32; 4] {
+  [a[0].min(b[0]), a[1].min(b[1]), a[2].max(b[2]), a[3].max(b[3])]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn union_rect_covers_both() {
+    assert_eq!(union_rect(&[0, 5, 2, 6], &[3, 1, 4, 2]), [0, 1, 4, 6]);
+  }
+}
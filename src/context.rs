@@ -0,0 +1,495 @@
+//! QgfxContext, which owns the OpenGL context and the caches textures and
+//! glyphs are loaded into. QGFX owns a context alongside the window's
+//! events and frame state, and derefs to it, so every method here can be
+//! called on a QGFX too. Frame::context() gives access to it mid-frame.
+
+use std::sync::mpsc;
+#[cfg(feature = "default-font")]
+use std::collections::HashSet;
+#[cfg(any(feature = "font", feature = "image"))]
+use std::path::Path;
+use glium::Display;
+use renderer::{Renderer, HdrFormat};
+use loader::{AssetLoader, AssetRequest};
+use res::tex::{TexHandle, CacheTexError};
+use res::tex::glium_cache::{RgbaBuffer, StreamingTexture};
+#[cfg(feature = "font")]
+use res::font::{FontHandle, CacheGlyphError, CacheReport, PathCommand};
+#[cfg(feature = "default-font")]
+use res::font::{gen_charset, Charset};
+#[cfg(feature = "default-font")]
+use DEFAULT_FONT_SCALE;
+
+/// The resources side of QGFX: the OpenGL context, and the texture and
+/// glyph caches. Everything which loads, frees or configures cached
+/// resources lives here, while drawing frames lives on QGFX and Frame.
+pub struct QgfxContext<'a> {
+  pub(crate) renderer: Box<Renderer<'a>>,
+  pub(crate) display: Display,
+  /// A tex handle for a 1x1 white texture. Used when rendering colours.
+  pub(crate) white_tex_handle: TexHandle,
+  /// Requests queued by asset loaders, fulfilled in QGFX::recv_data().
+  asset_requests: (mpsc::Sender<AssetRequest>, mpsc::Receiver<AssetRequest>),
+  /// The default font, once it's been cached by default_font().
+  #[cfg(feature = "default-font")]
+  default_font: Option<FontHandle>,
+}
+
+impl<'a> QgfxContext<'a> {
+  pub(crate) fn new(renderer: Box<Renderer<'a>>, display: Display,
+                    white_tex_handle: TexHandle) -> QgfxContext<'a> {
+    QgfxContext {
+      renderer: renderer,
+      display: display,
+      white_tex_handle: white_tex_handle,
+      asset_requests: mpsc::channel(),
+      #[cfg(feature = "default-font")]
+      default_font: None,
+    }
+  }
+
+  /// Cache some glyphs from a font. Requires the `font` feature.
+  #[cfg(feature = "font")]
+  pub fn cache_glyphs<F: AsRef<Path>> (
+    &mut self, file: F, scale: f32, 
+    charset: &[char]) -> Result<FontHandle, CacheGlyphError> {
+    self.cache_glyphs_face(file, 0, scale, charset)
+  }
+
+  /// Cache some glyphs from a face in a font collection (.ttc file). Use
+  /// font_face_count() to find out how many faces a file contains. Face 0 is
+  /// the same face cache_glyphs() uses.
+  /// # Errors
+  /// As cache_glyphs(), and CacheGlyphError::NoSuchFace if the file doesn't
+  /// contain a face at `face_index`.
+  #[cfg(feature = "font")]
+  pub fn cache_glyphs_face<F: AsRef<Path>> (
+    &mut self, file: F, face_index: usize, scale: f32, 
+    charset: &[char]) -> Result<FontHandle, CacheGlyphError> {
+    self.cache_glyphs_face_xy(file, face_index, scale, scale, charset)
+  }
+
+  /// Cache some glyphs from a font, with separate horizontal and vertical
+  /// scales. Use this for condensed (x_scale < y_scale) or stretched
+  /// (x_scale > y_scale) text.
+  #[cfg(feature = "font")]
+  pub fn cache_glyphs_xy<F: AsRef<Path>> (
+    &mut self, file: F, x_scale: f32, y_scale: f32, 
+    charset: &[char]) -> Result<FontHandle, CacheGlyphError> {
+    self.cache_glyphs_face_xy(file, 0, x_scale, y_scale, charset)
+  }
+
+  /// Gets a handle for caching textures and glyphs from other threads. The
+  /// requests are fulfilled by QGFX::recv_data().
+  pub fn asset_loader(&self) -> AssetLoader {
+    AssetLoader::new(self.asset_requests.0.clone())
+  }
+
+  /// Fulfils the requests queued by asset loaders.
+  pub(crate) fn fulfil_asset_requests(&mut self) {
+    while let Ok(request) = self.asset_requests.1.try_recv() {
+      // If the requester dropped its Pending, nobody wants the result.
+      match request {
+        #[cfg(feature = "image")]
+        AssetRequest::Tex(path, sender) => {
+          let _ = sender.send(self.cache_tex(&[path]).remove(0));
+        }
+        AssetRequest::TexRgba(w, h, data, sender) => {
+          let _ = sender.send(self.cache_tex_from_rgba(&[(w, h, &data[..])]).remove(0));
+        }
+        #[cfg(feature = "font")]
+        AssetRequest::Glyphs(path, scale, charset, sender) => {
+          let _ = sender.send(self.cache_glyphs(path, scale, &charset));
+        }
+      }
+    }
+  }
+
+  /// Gets the bundled default font (DejaVu Sans Mono), caching its ASCII
+  /// glyphs at DEFAULT_FONT_SCALE the first time this is called. Use this to
+  /// draw text without having to ship a font file. Requires the
+  /// `default-font` feature.
+  #[cfg(feature = "default-font")]
+  pub fn default_font(&mut self) -> Result<FontHandle, CacheGlyphError> {
+    if let Some(fh) = self.default_font {
+      return Ok(fh);
+    }
+    let mut charsets = HashSet::new();
+    charsets.insert(Charset::Lowercase);
+    charsets.insert(Charset::Uppercase);
+    charsets.insert(Charset::Numbers);
+    charsets.insert(Charset::Punctuation);
+    let fh = try!(self.renderer.cache_default_font_glyphs(DEFAULT_FONT_SCALE,
+                                                          &gen_charset(&charsets)));
+    self.default_font = Some(fh);
+    Ok(fh)
+  }
+
+  /// A combination of cache_glyphs_face() and cache_glyphs_xy().
+  #[cfg(feature = "font")]
+  pub fn cache_glyphs_face_xy<F: AsRef<Path>> (
+    &mut self, file: F, face_index: usize, x_scale: f32, y_scale: f32, 
+    charset: &[char]) -> Result<FontHandle, CacheGlyphError> {
+    // Need to add the '?' char, which is out fallback for unknown glyphs. Pretty slow but who
+    // cares - we only call it once at the start.
+    let mut charset : Vec<char> = charset.to_owned();
+    if !charset.contains(&'?') {
+        charset.push('?');
+    }
+    self.renderer.cache_glyphs(file, face_index, x_scale, y_scale, &charset)
+  }
+
+  /// Like cache_glyphs(), but caches as many of the chars as it can instead
+  /// of failing if any can't be cached, so large or unusual charsets can
+  /// degrade gracefully. Chars the font doesn't support are skipped, and
+  /// chars at the end of `charset` which don't fit in the cache are left
+  /// out.
+  /// # Returns
+  /// A report of how many glyphs were cached, and which chars weren't.
+  /// # Errors
+  /// Returns an error if the font file couldn't be read.
+  #[cfg(feature = "font")]
+  pub fn cache_available_glyphs<F: AsRef<Path>> (
+    &mut self, file: F, scale: f32,
+    charset: &[char]) -> Result<CacheReport, CacheGlyphError> {
+    self.cache_available_glyphs_face_xy(file, 0, scale, scale, charset)
+  }
+
+  /// A combination of cache_available_glyphs(), cache_glyphs_face() and
+  /// cache_glyphs_xy().
+  #[cfg(feature = "font")]
+  pub fn cache_available_glyphs_face_xy<F: AsRef<Path>> (
+    &mut self, file: F, face_index: usize, x_scale: f32, y_scale: f32,
+    charset: &[char]) -> Result<CacheReport, CacheGlyphError> {
+    // '?' goes first, so it isn't the glyph left out if the cache is full.
+    let mut with_fallback = vec!['?'];
+    with_fallback.extend(charset.iter().filter(|c| **c != '?'));
+    self.renderer.cache_available_glyphs(file, face_index, x_scale, y_scale, &with_fallback)
+  }
+
+  /// Gets the vector outline of a glyph in a font loaded with cache_glyphs(),
+  /// for rendering text as geometry rather than from the glyph cache (e.g.
+  /// tessellating huge text, or animating outlines). The glyph itself
+  /// doesn't need to be cached.
+  /// # Returns
+  /// The outline as a list of path commands, in pixels relative to the
+  /// glyph's origin on the baseline, with y pointing down. None if the font
+  /// doesn't contain the char.
+  #[cfg(feature = "font")]
+  pub fn glyph_outline(&self, fh: FontHandle, c: char) -> Option<Vec<PathCommand>> {
+    self.renderer.glyph_outline(fh, c)
+  }
+
+  /// Sets whether glyphs are cached on demand, as they're rendered. This is
+  /// for large charsets like Chinese, Japanese or Korean, which don't fit in
+  /// the font cache all at once. Fonts still need to be loaded with
+  /// cache_glyphs() (an empty charset is fine), but any of their glyphs can
+  /// then be rendered: glyphs which aren't cached are drawn as '?' for one
+  /// frame, then cached by the next call to QGFX::recv_data(). The least
+  /// recently cached glyphs are evicted to make room, and are cached again if
+  /// they're rendered again. Defaults to false.
+  /// # Panics
+  /// Panics if there are any renderer controllers alive, as with
+  /// cache_glyphs().
+  #[cfg(feature = "font")]
+  pub fn set_cache_glyphs_on_demand(&mut self, on_demand: bool) {
+    self.renderer.set_cache_glyphs_on_demand(on_demand)
+  }
+
+  /// Sets how large the font cache texture may grow, in bytes, or None to
+  /// never grow it. The cache starts at 4096x4096 (64MB). When glyphs don't
+  /// fit, the cache doubles in width and height (quadrupling its memory) if
+  /// that stays within the budget, and every glyph cached so far is cached
+  /// again in the larger texture, instead of cache_glyphs() failing with
+  /// CacheTooSmall. Don't set a budget larger than the GPU can hold in one
+  /// texture - if creating the larger texture fails, every cached glyph is
+  /// lost. Defaults to None.
+  #[cfg(feature = "font")]
+  pub fn set_font_cache_growth_budget(&mut self, max_bytes: Option<usize>) {
+    self.renderer.set_font_cache_growth_budget(max_bytes)
+  }
+
+  /// Sets whether glyphs are rasterised on a worker thread, so caching a
+  /// large charset doesn't freeze the window for seconds. When enabled,
+  /// cache_glyphs() returns as soon as the glyphs are queued, and the
+  /// rasterised glyphs are written to the font cache by a later call to
+  /// QGFX::recv_data(). Until then no glyphs can be drawn - text is drawn
+  /// according to its MissingGlyphPolicy - so check is_rasterising_glyphs()
+  /// to show a loading screen. Caching more glyphs waits for the previous glyphs to
+  /// finish. Errors like the font cache being too small are logged rather
+  /// than returned. Defaults to false.
+  #[cfg(feature = "font")]
+  pub fn set_background_glyph_rasterisation(&mut self, background: bool) {
+    self.renderer.set_background_glyph_rasterisation(background)
+  }
+
+  /// True whilst glyphs are being rasterised on a worker thread. See
+  /// set_background_glyph_rasterisation().
+  #[cfg(feature = "font")]
+  pub fn is_rasterising_glyphs(&self) -> bool {
+    self.renderer.is_rasterising_glyphs()
+  }
+
+  /// Draw a cached texture in place of a char whenever it appears in text,
+  /// in any font. This is how to render emoji and other colour glyphs, which
+  /// can't be rendered from font files: cache an image of each glyph (e.g.
+  /// from an emoji atlas) with cache_tex(), then set it as the char's sprite.
+  /// Sprites are drawn as a square the height of the line, and aren't tinted
+  /// by the text colour. Chars with sprites don't need to be passed to
+  /// cache_glyphs().
+  ///
+  /// Pass None as `tex` to remove a char's sprite.
+  /// # Panics
+  /// Panics if there are any renderer controllers alive, as with
+  /// cache_glyphs().
+  #[cfg(feature = "font")]
+  pub fn set_glyph_sprite(&mut self, c: char, tex: Option<TexHandle>) {
+    self.renderer.set_glyph_sprite(c, tex)
+  }
+
+  /// A function to cache some textures and return texture handles.
+  /// 
+  ///
+  /// # Params
+  /// * `filepaths` - The list of textures as filepaths.
+  ///
+  /// # Returns
+  /// A list of texture handles, corresponding to textures on the GPU. Texture
+  /// handles are returned in a slice with the indices corresponding to the
+  /// indices in the slice of texture files given.
+  ///
+  /// # Errors
+  /// Each texture may cause an error separately. Errors may occur if a texture
+  /// is too big for the texture cache, or if there was an error loading the
+  /// image etc.
+  ///
+  /// Requires the `image` feature. Without it, use cache_tex_from_rgba().
+  #[cfg(feature = "image")]
+  pub fn cache_tex<F: AsRef<Path>>(&mut self, filepaths: &[F]) -> Vec<Result<TexHandle, CacheTexError>> {
+    self.renderer.cache_tex(&self.display, filepaths)
+  }
+
+  /// Like cache_tex, but makes the colour `key` transparent in these
+  /// textures, for sprites without an alpha channel. Textures cached
+  /// afterwards aren't affected.
+  ///
+  /// ```ignore
+  /// let sprites = qgfx.cache_tex_color_keyed(&["old_sprites.bmp"], [255, 0, 255]);
+  /// ```
+  #[cfg(feature = "image")]
+  pub fn cache_tex_color_keyed<F: AsRef<Path>>(&mut self, filepaths: &[F], key: [u8; 3])
+    -> Vec<Result<TexHandle, CacheTexError>> {
+    self.renderer.cache_tex_color_keyed(&self.display, filepaths, key)
+  }
+
+  /// Like cache_tex, but gives each texture a human readable name (see
+  /// set_tex_name()). Failures are logged with the texture's name.
+  ///
+  /// # Params
+  /// * `textures` - A list of (name, filepath) pairs.
+  #[cfg(feature = "image")]
+  pub fn cache_tex_named<F: AsRef<Path>>(&mut self, textures: &[(&str, F)])
+    -> Vec<Result<TexHandle, CacheTexError>> {
+    let paths: Vec<&Path> = textures.iter().map(|&(_, ref path)| path.as_ref()).collect();
+    let result = self.renderer.cache_tex(&self.display, &paths);
+    for (&(name, _), res) in textures.iter().zip(result.iter()) {
+      match *res {
+        Ok(tex) => self.renderer.set_tex_name(tex, name),
+        Err(ref e) => warn!("Failed to cache texture '{}': {}", name, e),
+      }
+    }
+    result
+  }
+
+  /// Gives a texture a human readable name. The name is included in log
+  /// messages about the texture, to make them easier to diagnose.
+  pub fn set_tex_name(&mut self, tex: TexHandle, name: &str) {
+    self.renderer.set_tex_name(tex, name)
+  }
+
+  /// The name given to a texture with cache_tex_named() or set_tex_name(),
+  /// if any.
+  pub fn tex_name(&self, tex: TexHandle) -> Option<&str> {
+    self.renderer.tex_name(tex)
+  }
+
+  /// Reads a cached texture back from the GPU, e.g. to check it was uploaded
+  /// correctly, or to export it. This waits for the GPU, so avoid calling it
+  /// every frame. Returns None if the texture isn't cached.
+  pub fn read_tex(&self, tex: TexHandle) -> Option<RgbaBuffer> {
+    self.renderer.read_tex(tex)
+  }
+
+  /// Like cache_tex, but reads and decodes the texture files in parallel on
+  /// worker threads, which is much faster for large sets of textures. Only
+  /// the upload to the GPU happens on this thread.
+  ///
+  /// # Params
+  /// * `filepaths` - The list of textures as filepaths.
+  /// * `progress` - A callback, called every time a texture has been decoded
+  ///                with the number of textures decoded so far and the total
+  ///                number of textures. Use this to draw a progress bar.
+  ///
+  /// # Returns
+  /// See cache_tex.
+  #[cfg(feature = "image")]
+  pub fn cache_tex_with_progress<F, P>(&mut self, filepaths: &[F], progress: P) 
+    -> Vec<Result<TexHandle, CacheTexError>> 
+    where F: AsRef<Path>, P: FnMut(usize, usize) {
+    self.renderer.cache_tex_parallel(&self.display, filepaths, progress)
+  }
+
+  #[cfg(feature = "image")]
+  pub fn cache_tex_from_bytes(&mut self, bytes: &[&[u8]]) -> Vec<Result<TexHandle, CacheTexError>> {
+    self.renderer.cache_tex_from_bytes(&self.display, bytes)
+  }
+
+  /// Like cache_tex_from_bytes, but makes the colour `key` transparent in
+  /// these textures. See cache_tex_color_keyed().
+  #[cfg(feature = "image")]
+  pub fn cache_tex_from_bytes_color_keyed(&mut self, bytes: &[&[u8]], key: [u8; 3])
+    -> Vec<Result<TexHandle, CacheTexError>> {
+    self.renderer.cache_tex_from_bytes_color_keyed(&self.display, bytes, key)
+  }
+
+  /// Cache textures from raw, already decoded pixel data. This is always
+  /// available, even without the `image` feature.
+  ///
+  /// # Params
+  /// * `images` - A list of (width, height, data) tuples. The data is 8-bit
+  ///              RGBA, row by row from the top-left, so must be exactly
+  ///              width * height * 4 bytes long.
+  ///
+  /// # Returns
+  /// See cache_tex.
+  ///
+  /// # Errors
+  /// Returns CacheTexError::InvalidRgbaData for an image whose data is the
+  /// wrong length.
+  pub fn cache_tex_from_rgba(&mut self, images: &[(u32, u32, &[u8])])
+    -> Vec<Result<TexHandle, CacheTexError>> {
+    self.renderer.cache_tex_from_rgba(&self.display, images)
+  }
+
+  /// Frees cached textures, so the space they took up in the cache can be
+  /// used by textures cached afterwards. Textures which aren't cached are
+  /// ignored, and drawing a freed texture fails as it isn't cached.
+  ///
+  /// Panics if there are any renderer controllers alive, as with
+  /// cache_glyphs().
+  pub fn free_tex(&mut self, tex: &[TexHandle]) {
+    self.renderer.free_tex(tex)
+  }
+
+  /// Creates a streaming texture of the given size, in pixels. Draw it with
+  /// its handle() like any other texture, and replace its contents with
+  /// update_streaming_texture(), e.g. every frame of a video.
+  /// # Errors
+  /// Returns an error if there's no space for another cache texture, or it
+  /// couldn't be created.
+  pub fn create_streaming_texture(&mut self, w: u32, h: u32)
+    -> Result<StreamingTexture, CacheTexError> {
+    self.renderer.create_streaming_tex(&self.display, w, h)
+  }
+
+  /// Replaces the contents of a streaming texture with a frame of RGBA data,
+  /// with rows from top to bottom and 4 bytes per pixel.
+  /// # Errors
+  /// Returns CacheTexError::InvalidRgbaData if the frame is the wrong size.
+  pub fn update_streaming_texture(&self, tex: &mut StreamingTexture, frame: &[u8])
+    -> Result<(), CacheTexError> {
+    let (w, h) = tex.size();
+    self.renderer.update_streaming_tex(tex, [0, 0, w, h], frame)
+  }
+
+  /// Like update_streaming_texture(), but only replaces part of the texture.
+  /// # Params
+  /// * `region` - The region to replace, in pixels - X, Y, W, H, with 0, 0 at
+  ///              the top left. This must be inside the texture.
+  /// * `data` - The region's new RGBA data.
+  pub fn update_streaming_texture_region(&self, tex: &mut StreamingTexture, region: [u32; 4],
+                                         data: &[u8]) -> Result<(), CacheTexError> {
+    self.renderer.update_streaming_tex(tex, region, data)
+  }
+
+  /// Sets the size above which a texture is given its own dedicated cache
+  /// texture of exactly its size, rather than being packed into a shared
+  /// 2048x2048 atlas page. A texture is dedicated if its width is greater than
+  /// `w` or its height is greater than `h`. Dedicated textures waste less
+  /// memory than a mostly empty page, may be bigger than an atlas page, and
+  /// are sampled with REPEAT wrapping (so tex_tiled() only needs a single quad
+  /// for them). Defaults to 1024x1024. 0, 0 disables dedicated textures.
+  ///
+  /// This only affects textures cached after this is called.
+  pub fn set_dedicated_tex_threshold(&mut self, w: u32, h: u32) {
+    self.renderer.set_dedicated_tex_threshold(w, h)
+  }
+
+  /// Sets whether textures may be rotated 90 degrees to fit into the cache
+  /// when they don't fit in their normal orientation. This improves how
+  /// tightly long, thin textures are packed. Rotation is compensated for when
+  /// drawing, so it's invisible to users of the RendererController. Defaults
+  /// to false.
+  ///
+  /// This only affects textures cached after this is called.
+  pub fn set_allow_tex_rotation(&mut self, allow: bool) {
+    self.renderer.set_allow_tex_rotation(allow)
+  }
+
+  /// Caches a texture from floating point RGBA data, with its own dedicated
+  /// cache texture. Its colours are linear (not sRGB encoded) and can be
+  /// brighter than white - render in HDR (see QGFXBuilder::with_hdr()) so
+  /// they aren't clipped.
+  /// # Params
+  /// * `data` - Rows of pixels from top to bottom, 4 floats per pixel.
+  pub fn cache_tex_hdr(&mut self, w: u32, h: u32, data: &[f32], 
+                       format: HdrFormat) -> Result<TexHandle, CacheTexError> {
+    self.renderer.cache_tex_hdr(&self.display, w, h, data, format)
+  }
+
+  /// Sets whether grayscale textures are stored in their own cache pages with
+  /// one channel (or two, with alpha), rather than being expanded to RGBA.
+  /// This saves VRAM for mask-style textures, but grayscale and colour
+  /// textures can't be drawn in the same batch. Defaults to false.
+  ///
+  /// This only affects textures cached after this is called.
+  pub fn set_grayscale_pages(&mut self, gray_pages: bool) {
+    self.renderer.set_grayscale_pages(gray_pages)
+  }
+
+  /// Sets a colour which is made transparent in every texture cached after
+  /// this is called, for when all of an application's sprites lack an alpha
+  /// channel. None turns colour keying off. Defaults to None. To key
+  /// particular textures, use cache_tex_color_keyed() instead.
+  pub fn set_color_key(&mut self, key: Option<[u8; 3]>) {
+    self.renderer.set_color_key(key)
+  }
+
+  /// Sets whether fully transparent borders are trimmed from textures as
+  /// they're cached, so sprites drawn on large canvases take up less of the
+  /// cache. Trimmed textures are still drawn in the same place and at the
+  /// same size by tex(), tex_rotated(), tex_tiled() and push_quad(). Defaults
+  /// to false.
+  ///
+  /// This only affects textures cached after this is called.
+  pub fn set_trim_transparent(&mut self, trim: bool) {
+    self.renderer.set_trim_transparent(trim)
+  }
+
+  /// Sets the packing group textures cached afterwards belong to, or None
+  /// for no group. Textures in a group are packed onto the group's own cache
+  /// pages where possible, so related textures which are drawn together
+  /// (like all of a UI's icons) share pages and can be drawn in fewer draw
+  /// calls. Textures are only packed onto other groups' pages once the
+  /// texture cache's maximum number of pages has been reached. Defaults to
+  /// None.
+  ///
+  /// ```ignore
+  /// qgfx.set_packing_group(Some("ui"));
+  /// let icons = qgfx.cache_tex(&icon_paths);
+  /// qgfx.set_packing_group(None);
+  /// ```
+  pub fn set_packing_group(&mut self, group: Option<&str>) {
+    self.renderer.set_packing_group(group)
+  }
+}
@@ -0,0 +1,69 @@
+//! Soft cursors, textures drawn at the mouse position in place of the OS
+//! cursor. See QGFX::set_soft_cursor().
+
+use renderer::{RendererController, RenderTextureError};
+use res::tex::TexHandle;
+
+/// A cached texture drawn at the mouse position every frame, on top of
+/// everything else, in place of the OS cursor.
+///
+/// ```ignore
+/// let tex = qgfx.cache_tex(&["cursor.png"]).remove(0)?;
+/// qgfx.set_soft_cursor(Some(SoftCursor::new(tex, [32.0, 32.0]).hotspot([4.0, 2.0])));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SoftCursor {
+  /// The texture to draw.
+  pub tex: TexHandle,
+  /// The size to draw the texture at, in pixels.
+  pub size: [f32; 2],
+  /// The point in the texture which is placed at the mouse position, in
+  /// pixels from the top left of the texture as drawn.
+  pub hotspot: [f32; 2],
+  /// The colour to tint the texture.
+  pub tint: [f32; 4],
+}
+
+impl SoftCursor {
+  /// A cursor drawn at `size` pixels, with its hotspot at the top left and
+  /// no tint.
+  pub fn new(tex: TexHandle, size: [f32; 2]) -> SoftCursor {
+    SoftCursor { tex: tex, size: size, hotspot: [0.0, 0.0], tint: [1.0; 4] }
+  }
+
+  /// Sets the hotspot, in pixels from the top left of the texture as drawn.
+  pub fn hotspot(mut self, hotspot: [f32; 2]) -> SoftCursor {
+    self.hotspot = hotspot;
+    self
+  }
+
+  /// Sets the colour to tint the texture.
+  pub fn tint(mut self, tint: [f32; 4]) -> SoftCursor {
+    self.tint = tint;
+    self
+  }
+
+  /// Where the cursor is drawn for a mouse position - X, Y, W, H.
+  fn aabb(&self, pos: [f32; 2]) -> [f32; 4] {
+    [pos[0] - self.hotspot[0], pos[1] - self.hotspot[1], self.size[0], self.size[1]]
+  }
+
+  /// Draws the cursor with its hotspot at `pos`.
+  /// # Errors
+  /// Returns an error if the cursor's texture isn't in the cache.
+  pub fn draw(&self, controller: &mut RendererController, pos: [f32; 2])
+    -> Result<(), RenderTextureError> {
+    controller.tex(self.tex, &self.aabb(pos), &self.tint)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn hotspot_is_placed_at_the_mouse() {
+    let cursor = SoftCursor::new(TexHandle(0), [32.0, 16.0]).hotspot([4.0, 2.0]);
+    assert_eq!(cursor.aabb([100.0, 50.0]), [96.0, 48.0, 32.0, 16.0]);
+  }
+}
@@ -0,0 +1,108 @@
+//! Debug drawing. Shapes can be drawn from anywhere in an application without
+//! access to a RendererController, which is useful for visualising physics or
+//! AI state:
+//!
+//! ```ignore
+//! qgfx::debug::line([0.0, 0.0], [100.0, 100.0], [1.0, 0.0, 0.0, 1.0], 0.0);
+//! qgfx::debug::rect(&[10.0, 10.0, 20.0, 20.0], [0.0, 1.0, 0.0, 1.0], 2.0);
+//! ```
+//!
+//! Shapes are collected globally, and drawn on top of everything else by
+//! QGFX::recv_data(). A shape drawn with a duration of 0 seconds is drawn for
+//! a single frame, otherwise it's drawn every frame until the duration has
+//! passed.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use renderer::RendererController;
+#[cfg(feature = "font")]
+use res::font::{FontHandle, TextAnchor};
+
+/// The width of debug lines, in pixels.
+const LINE_WIDTH: f32 = 1.0;
+
+enum Shape {
+  Line([f32; 2], [f32; 2]),
+  Rect([f32; 4]),
+  #[cfg(feature = "font")]
+  Text([f32; 2], String),
+}
+
+struct DebugDraw {
+  /// The shapes to draw, with their colours and the time they expire at. A
+  /// shape with no expiry time is drawn for one frame.
+  shapes: Vec<(Shape, [f32; 4], Option<Instant>)>,
+  /// The font to draw debug text with.
+  #[cfg(feature = "font")]
+  font: Option<FontHandle>,
+}
+
+lazy_static! {
+  static ref DEBUG_DRAW: Mutex<DebugDraw> = Mutex::new(DebugDraw {
+    shapes: Vec::new(),
+    #[cfg(feature = "font")]
+    font: None,
+  });
+}
+
+fn push_shape(shape: Shape, col: [f32; 4], secs: f32) {
+  let expiry = if secs > 0.0 {
+    let nanos = (secs.fract() * 1_000_000_000.0) as u32;
+    Some(Instant::now() + Duration::new(secs as u64, nanos))
+  } else {
+    None
+  };
+  DEBUG_DRAW.lock().unwrap().shapes.push((shape, col, expiry));
+}
+
+/// Draws a line from `p1` to `p2` for `secs` seconds.
+pub fn line(p1: [f32; 2], p2: [f32; 2], col: [f32; 4], secs: f32) {
+  push_shape(Shape::Line(p1, p2), col, secs);
+}
+
+/// Draws a filled rectangle (X, Y, W, H) for `secs` seconds.
+pub fn rect(aabb: &[f32; 4], col: [f32; 4], secs: f32) {
+  push_shape(Shape::Rect(*aabb), col, secs);
+}
+
+/// Draws some text with its top left at `pos` for `secs` seconds. Text is only
+/// drawn once a font has been set with set_font().
+#[cfg(feature = "font")]
+pub fn text(pos: [f32; 2], text: &str, col: [f32; 4], secs: f32) {
+  push_shape(Shape::Text(pos, text.to_owned()), col, secs);
+}
+
+/// Sets the font used to draw debug text. The font's glyphs must be cached.
+#[cfg(feature = "font")]
+pub fn set_font(font: Option<FontHandle>) {
+  DEBUG_DRAW.lock().unwrap().font = font;
+}
+
+/// Removes all debug shapes, including timed shapes which haven't expired.
+pub fn clear() {
+  DEBUG_DRAW.lock().unwrap().shapes.clear();
+}
+
+/// Draws all the debug shapes with the given controller, then removes
+/// one-frame and expired shapes.
+pub(crate) fn draw(controller: &mut RendererController) {
+  let mut debug_draw = DEBUG_DRAW.lock().unwrap();
+  #[cfg(feature = "font")]
+  let font = debug_draw.font;
+  for &(ref shape, col, _) in &debug_draw.shapes {
+    match *shape {
+      Shape::Line(p1, p2) => controller.line(p1, p2, LINE_WIDTH, col),
+      Shape::Rect(ref aabb) => controller.rect(aabb, &col),
+      #[cfg(feature = "font")]
+      Shape::Text(ref pos, ref text) => {
+        if let Some(font) = font {
+          if controller.text_anchored(text, pos, font, TextAnchor::TopLeft, &col).is_err() {
+            warn!("Debug text {:?} has glyphs missing from the cache", text);
+          }
+        }
+      }
+    }
+  }
+  let now = Instant::now();
+  debug_draw.shapes.retain(|&(_, _, expiry)| expiry.map_or(false, |expiry| expiry > now));
+}
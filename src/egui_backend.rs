@@ -0,0 +1,201 @@
+//! An integration for the egui immediate-mode UI library. egui's output is
+//! rendered through the RendererController like any other geometry, so no
+//! second GL context is needed:
+//!
+//! ```ignore
+//! let mut egui_ctx = egui::CtxRef::default();
+//! let mut input = EguiInput::new();
+//! let mut painter = EguiPainter::new();
+//! loop {
+//!   qgfx.poll_raw_events(|ev| input.handle_event(&ev));
+//!   let (w, h) = qgfx.get_display_size();
+//!   egui_ctx.begin_frame(input.take(w, h));
+//!   egui::Window::new("Hello").show(&egui_ctx, |ui| { ui.label("Hello, world!"); });
+//!   let (_, shapes) = egui_ctx.end_frame();
+//!   let meshes = egui_ctx.tessellate(shapes);
+//!   painter.upload_font_texture(&mut qgfx, &egui_ctx.texture()).unwrap();
+//!   {
+//!     let mut controller = qgfx.get_renderer_controller();
+//!     painter.paint(&mut controller, &meshes);
+//!     controller.flush();
+//!   }
+//!   ...
+//! }
+//! ```
+//!
+//! egui's clip rects aren't applied, as the renderer doesn't support
+//! scissoring. Only egui's own font texture is supported - meshes using user
+//! textures are skipped.
+
+use egui;
+use glium::glutin::{Event, WindowEvent, ElementState, MouseButton, MouseScrollDelta,
+                    VirtualKeyCode};
+use renderer::{RendererController, MeshVertex};
+use res::tex::{TexHandle, CacheTexError};
+use {QGFX, SCROLL_LINE_PIXELS};
+
+/// Collects window events into egui's RawInput.
+#[derive(Default)]
+pub struct EguiInput {
+  raw: egui::RawInput,
+  pointer_pos: egui::Pos2,
+}
+
+impl EguiInput {
+  pub fn new() -> EguiInput { EguiInput::default() }
+
+  /// Translates a window event into egui input. Events egui doesn't use are
+  /// ignored.
+  pub fn handle_event(&mut self, ev: &Event) {
+    let ev = match *ev {
+      Event::WindowEvent { ref event, .. } => event,
+      _ => return,
+    };
+    match *ev {
+      WindowEvent::MouseMoved { position: (x, y), .. } => {
+        self.pointer_pos = egui::pos2(x as f32, y as f32);
+        self.raw.events.push(egui::Event::PointerMoved(self.pointer_pos));
+      }
+      WindowEvent::MouseInput { state, button, .. } => {
+        let button = match button {
+          MouseButton::Left => egui::PointerButton::Primary,
+          MouseButton::Right => egui::PointerButton::Secondary,
+          MouseButton::Middle => egui::PointerButton::Middle,
+          _ => return,
+        };
+        self.raw.events.push(egui::Event::PointerButton {
+          pos: self.pointer_pos,
+          button: button,
+          pressed: state == ElementState::Pressed,
+          modifiers: self.raw.modifiers,
+        });
+      }
+      WindowEvent::MouseWheel { delta, .. } => {
+        let delta = match delta {
+          MouseScrollDelta::LineDelta(x, y) =>
+            egui::vec2(x * SCROLL_LINE_PIXELS, y * SCROLL_LINE_PIXELS),
+          MouseScrollDelta::PixelDelta(x, y) => egui::vec2(x, y),
+        };
+        self.raw.scroll_delta += delta;
+      }
+      WindowEvent::ReceivedCharacter(c) => {
+        if !c.is_control() {
+          self.raw.events.push(egui::Event::Text(c.to_string()));
+        }
+      }
+      WindowEvent::KeyboardInput { input, .. } => {
+        if let Some(key) = input.virtual_keycode.and_then(translate_key) {
+          self.raw.events.push(egui::Event::Key {
+            key: key,
+            pressed: input.state == ElementState::Pressed,
+            modifiers: self.raw.modifiers,
+          });
+        }
+      }
+      WindowEvent::Focused(false) => {
+        self.raw.events.push(egui::Event::PointerGone);
+      }
+      _ => (),
+    }
+  }
+
+  /// Takes the input collected since the last call, for passing to
+  /// egui::CtxRef::begin_frame().
+  /// # Params
+  /// * `w`, `h` - The size of the display, in pixels.
+  pub fn take(&mut self, w: u32, h: u32) -> egui::RawInput {
+    self.raw.screen_rect = Some(egui::Rect::from_min_size(
+      egui::Pos2::new(0.0, 0.0), egui::vec2(w as f32, h as f32)));
+    self.raw.take()
+  }
+}
+
+/// Maps the keys egui cares about.
+fn translate_key(key: VirtualKeyCode) -> Option<egui::Key> {
+  Some(match key {
+    VirtualKeyCode::Back => egui::Key::Backspace,
+    VirtualKeyCode::Delete => egui::Key::Delete,
+    VirtualKeyCode::Return => egui::Key::Enter,
+    VirtualKeyCode::Tab => egui::Key::Tab,
+    VirtualKeyCode::Escape => egui::Key::Escape,
+    VirtualKeyCode::Left => egui::Key::ArrowLeft,
+    VirtualKeyCode::Right => egui::Key::ArrowRight,
+    VirtualKeyCode::Up => egui::Key::ArrowUp,
+    VirtualKeyCode::Down => egui::Key::ArrowDown,
+    VirtualKeyCode::Home => egui::Key::Home,
+    VirtualKeyCode::End => egui::Key::End,
+    _ => return None,
+  })
+}
+
+/// Renders egui's tessellated output through a RendererController.
+#[derive(Default)]
+pub struct EguiPainter {
+  /// egui's font atlas in the texture cache, and the version of it uploaded.
+  font_tex: Option<(TexHandle, u64)>,
+}
+
+impl EguiPainter {
+  pub fn new() -> EguiPainter { EguiPainter::default() }
+
+  /// Uploads egui's font texture into the texture cache, if it's changed
+  /// since it was last uploaded. Call this every frame before paint(). The
+  /// previous version is freed once the new one is cached.
+  pub fn upload_font_texture(&mut self, qgfx: &mut QGFX, tex: &egui::Texture)
+    -> Result<(), CacheTexError> {
+    if let Some((_, version)) = self.font_tex {
+      if version == tex.version { return Ok(()); }
+    }
+    // egui's font texture is coverage only - draw it as white with alpha.
+    let mut data = Vec::with_capacity(tex.pixels.len() * 4);
+    for &a in &tex.pixels {
+      data.extend_from_slice(&[255, 255, 255, a]);
+    }
+    let handle = try!(qgfx.cache_tex_from_rgba(&[(tex.width as u32, tex.height as u32, &data)])
+                      .remove(0));
+    debug!("Uploaded egui font texture version {} as {:?}", tex.version, handle);
+    if let Some((old, _)) = self.font_tex {
+      qgfx.free_tex(&[old]);
+    }
+    self.font_tex = Some((handle, tex.version));
+    Ok(())
+  }
+
+  /// Draws egui's tessellated meshes.
+  pub fn paint(&self, controller: &mut RendererController, meshes: &[egui::ClippedMesh]) {
+    let font_tex = match self.font_tex {
+      Some((handle, _)) => handle,
+      None => {
+        warn!("egui meshes painted before the font texture was uploaded");
+        return;
+      }
+    };
+    let mut vertices = Vec::new();
+    for &egui::ClippedMesh(_, ref mesh) in meshes {
+      if mesh.texture_id != egui::TextureId::Egui {
+        debug!("Skipping egui mesh with unsupported texture {:?}", mesh.texture_id);
+        continue;
+      }
+      vertices.clear();
+      vertices.extend(mesh.vertices.iter().map(|v| {
+        MeshVertex {
+          pos: [v.pos.x, v.pos.y],
+          uv: [v.uv.x, v.uv.y],
+          col: unpremultiply(v.color),
+        }
+      }));
+      if controller.tex_mesh(font_tex, &vertices, &mesh.indices).is_err() {
+        error!("egui font texture {:?} is not in the texture cache", font_tex);
+        return;
+      }
+    }
+  }
+}
+
+/// Converts egui's premultiplied colours to the straight alpha colours the
+/// renderer blends with.
+fn unpremultiply(c: egui::Color32) -> [f32; 4] {
+  let a = c.a() as f32 / 255.0;
+  if a == 0.0 { return [0.0, 0.0, 0.0, 0.0]; }
+  [c.r() as f32 / 255.0 / a, c.g() as f32 / 255.0 / a, c.b() as f32 / 255.0 / a, a]
+}
@@ -0,0 +1,220 @@
+//! Module containing the crate-level error type.
+
+use glium;
+use std;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use renderer::RenderTextureError;
+#[cfg(feature = "font")]
+use res::font::{CacheGlyphError, MissingGlyphsError};
+use res::tex::CacheTexError;
+
+/// An error returned by the QGFX API. This wraps the more specific errors
+/// returned by different parts of the library, so that they can all be
+/// propagated with try!() / the ? operator. The wrapped error is available
+/// with Error::source().
+#[derive(Debug)]
+pub enum QgfxError {
+  /// An error occurred when caching a texture.
+  CacheTex(CacheTexError),
+
+  /// An error occurred when caching glyphs.
+  #[cfg(feature = "font")]
+  CacheGlyph(CacheGlyphError),
+
+  /// Text was rendered with glyphs which weren't cached, with
+  /// MissingGlyphPolicy::Error.
+  #[cfg(feature = "font")]
+  MissingGlyphs(MissingGlyphsError),
+
+  /// An error occurred when drawing a texture with a RendererController.
+  RenderTexture(RenderTextureError),
+
+  /// Vertex data was sent to the renderer referencing a cache texture which
+  /// doesn't exist. Contains the index of the missing cache texture.
+  MissingTexture(usize),
+
+  /// A draw call failed.
+  Draw(glium::DrawError),
+
+  /// Swapping the buffers at the end of a frame failed. If the OpenGL
+  /// context was lost, QGFX has to be created again - see is_recoverable().
+  SwapBuffers(glium::SwapBuffersError),
+
+  /// Creating the window or its OpenGL context failed.
+  DisplayCreation(glium::backend::glutin::DisplayCreationError),
+
+  /// Compiling the shader program failed.
+  ProgramCreation(glium::ProgramCreationError),
+
+  /// Creating a texture on the GPU failed.
+  TextureCreation(glium::texture::TextureCreationError),
+
+  /// Creating a vertex buffer on the GPU failed.
+  BufferCreation(glium::vertex::BufferCreationError),
+
+  /// Creating an offscreen framebuffer failed.
+  FramebufferCreation(glium::framebuffer::ValidationError),
+
+  /// A frame graph couldn't be scheduled. Contains a description of the
+  /// problem.
+  InvalidFrameGraph(String),
+}
+
+impl QgfxError {
+  /// True if the error only spoils the frame being rendered, so rendering
+  /// can carry on with the next frame. Offscreen targets can fail to be
+  /// created whilst the window is being resized or minimised, for example.
+  /// False for errors like a lost OpenGL context, which the renderer can't
+  /// recover from - GPU resources like cached textures are gone, so QGFX has
+  /// to be created again.
+  ///
+  /// Only texture failures caused by the target's size are recoverable, as
+  /// offscreen targets follow the window's size, which will change. A
+  /// format or texture type the driver doesn't support fails every frame.
+  pub fn is_recoverable(&self) -> bool {
+    use glium::texture::TextureCreationError;
+    match *self {
+      QgfxError::FramebufferCreation(_) => true,
+      QgfxError::TextureCreation(TextureCreationError::DimensionsNotSupported) => true,
+      QgfxError::SwapBuffers(glium::SwapBuffersError::AlreadySwapped) => true,
+      _ => false,
+    }
+  }
+}
+
+/// Passes on the result of rendering a frame, unless it failed with a
+/// recoverable error, which is logged and dropped. See
+/// QgfxError::is_recoverable().
+pub(crate) fn skip_recoverable(res: Result<(), QgfxError>) -> Result<(), QgfxError> {
+  match res {
+    Err(ref e) if e.is_recoverable() => {
+      warn!("Skipped rendering a frame: {}", e);
+      Ok(())
+    }
+    res => res,
+  }
+}
+
+impl Display for QgfxError {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      QgfxError::MissingTexture(ix) => 
+        write!(f, "Vertex data with tex ID {} buffered, but texture with this ix does not exist.", ix),
+      QgfxError::InvalidFrameGraph(ref problem) => write!(f, "Invalid frame graph: {}", problem),
+      _ => write!(f, "{}: {}", self.description(), self.source().unwrap()),
+    }
+  }
+}
+
+impl Error for QgfxError {
+  fn description(&self) -> &str {
+    match *self {
+      QgfxError::CacheTex(_) => "Failed to cache a texture",
+      #[cfg(feature = "font")]
+      QgfxError::CacheGlyph(_) => "Failed to cache glyphs",
+      #[cfg(feature = "font")]
+      QgfxError::MissingGlyphs(_) => "Text contained glyphs which were not cached",
+      QgfxError::RenderTexture(_) => "Failed to render a texture",
+      QgfxError::MissingTexture(_) => "Vertex data referenced a cache texture which does not exist",
+      QgfxError::Draw(_) => "A draw call failed",
+      QgfxError::SwapBuffers(_) => "Failed to swap buffers",
+      QgfxError::DisplayCreation(_) => "Failed to create the display",
+      QgfxError::ProgramCreation(_) => "Failed to compile the shader program",
+      QgfxError::TextureCreation(_) => "Failed to create a texture",
+      QgfxError::BufferCreation(_) => "Failed to create a vertex buffer",
+      QgfxError::FramebufferCreation(_) => "Failed to create a framebuffer",
+      QgfxError::InvalidFrameGraph(_) => "A frame graph couldn't be scheduled",
+    }
+  }
+
+  fn source(&self) -> Option<&(Error + 'static)> {
+    match *self {
+      QgfxError::CacheTex(ref e) => Some(e),
+      #[cfg(feature = "font")]
+      QgfxError::CacheGlyph(ref e) => Some(e),
+      #[cfg(feature = "font")]
+      QgfxError::MissingGlyphs(ref e) => Some(e),
+      QgfxError::RenderTexture(ref e) => Some(e),
+      QgfxError::MissingTexture(_) => None,
+      QgfxError::Draw(ref e) => Some(e),
+      QgfxError::SwapBuffers(ref e) => Some(e),
+      QgfxError::DisplayCreation(ref e) => Some(e),
+      QgfxError::ProgramCreation(ref e) => Some(e),
+      QgfxError::TextureCreation(ref e) => Some(e),
+      QgfxError::BufferCreation(ref e) => Some(e),
+      QgfxError::FramebufferCreation(ref e) => Some(e),
+      QgfxError::InvalidFrameGraph(_) => None,
+    }
+  }
+}
+
+impl std::convert::From<CacheTexError> for QgfxError {
+  fn from(e: CacheTexError) -> Self { QgfxError::CacheTex(e) }
+}
+
+#[cfg(feature = "font")]
+impl std::convert::From<CacheGlyphError> for QgfxError {
+  fn from(e: CacheGlyphError) -> Self { QgfxError::CacheGlyph(e) }
+}
+
+#[cfg(feature = "font")]
+impl std::convert::From<MissingGlyphsError> for QgfxError {
+  fn from(e: MissingGlyphsError) -> Self { QgfxError::MissingGlyphs(e) }
+}
+
+impl std::convert::From<RenderTextureError> for QgfxError {
+  fn from(e: RenderTextureError) -> Self { QgfxError::RenderTexture(e) }
+}
+
+impl std::convert::From<glium::DrawError> for QgfxError {
+  fn from(e: glium::DrawError) -> Self { QgfxError::Draw(e) }
+}
+
+impl std::convert::From<glium::SwapBuffersError> for QgfxError {
+  fn from(e: glium::SwapBuffersError) -> Self { QgfxError::SwapBuffers(e) }
+}
+
+impl std::convert::From<glium::backend::glutin::DisplayCreationError> for QgfxError {
+  fn from(e: glium::backend::glutin::DisplayCreationError) -> Self { 
+    QgfxError::DisplayCreation(e) 
+  }
+}
+
+impl std::convert::From<glium::ProgramCreationError> for QgfxError {
+  fn from(e: glium::ProgramCreationError) -> Self { QgfxError::ProgramCreation(e) }
+}
+
+impl std::convert::From<glium::texture::TextureCreationError> for QgfxError {
+  fn from(e: glium::texture::TextureCreationError) -> Self { QgfxError::TextureCreation(e) }
+}
+
+impl std::convert::From<glium::vertex::BufferCreationError> for QgfxError {
+  fn from(e: glium::vertex::BufferCreationError) -> Self { QgfxError::BufferCreation(e) }
+}
+
+impl std::convert::From<glium::framebuffer::ValidationError> for QgfxError {
+  fn from(e: glium::framebuffer::ValidationError) -> Self { QgfxError::FramebufferCreation(e) }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use glium::texture::TextureCreationError;
+
+  #[test]
+  fn only_transient_texture_failures_are_skipped() {
+    let resized = QgfxError::TextureCreation(TextureCreationError::DimensionsNotSupported);
+    assert!(resized.is_recoverable());
+    assert!(skip_recoverable(Err(resized)).is_ok());
+
+    // An unsupported format will fail again next frame, so has to reach the
+    // caller.
+    let unsupported = QgfxError::TextureCreation(TextureCreationError::FormatNotSupported);
+    assert!(!unsupported.is_recoverable());
+    match skip_recoverable(Err(unsupported)) {
+      Err(QgfxError::TextureCreation(TextureCreationError::FormatNotSupported)) => (),
+      res => panic!("Expected the texture error to propagate, got {:?}", res),
+    }
+  }
+}
@@ -0,0 +1,102 @@
+//! A simplified window event type. QGFX::poll_events() produces these rather
+//! than the windowing library's own events, so code handling them isn't
+//! broken by windowing library upgrades. The raw events are still available
+//! with QGFX::poll_raw_events().
+
+use glium::glutin::{self, Event, WindowEvent, ElementState, MouseScrollDelta};
+
+/// A key's code. This is the windowing library's own key code type, as
+/// there are too many keys to usefully copy.
+pub use glium::glutin::VirtualKeyCode as KeyCode;
+
+/// How many pixels one line of mouse wheel scrolling is. Touchpads scroll in
+/// pixels, and are converted to lines with this.
+pub const SCROLL_LINE_PIXELS: f32 = 24.0;
+
+/// Whether a key or button was pressed or released.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ButtonState {
+  Pressed,
+  Released,
+}
+
+/// A mouse button.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum MouseButton {
+  Left,
+  Right,
+  Middle,
+  Other(u8),
+}
+
+/// A window event.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum QgfxEvent {
+  /// The window's close button was pressed.
+  CloseRequested,
+  /// The window was resized to `w` x `h` pixels.
+  Resized { w: u32, h: u32 },
+  /// The window gained (true) or lost (false) focus.
+  Focused(bool),
+  /// A key was pressed or released. `code` is None for keys without a key
+  /// code, which can still be told apart by their `scancode`.
+  Key { code: Option<KeyCode>, scancode: u32, state: ButtonState },
+  /// The mouse moved to (`x`, `y`) in the window, in pixels.
+  MouseMove { x: f32, y: f32 },
+  /// A mouse button was pressed or released.
+  MouseButton { button: MouseButton, state: ButtonState },
+  /// The mouse wheel was scrolled, in lines. Positive `y` is up.
+  Scroll { x: f32, y: f32 },
+  /// A character was typed. Control characters, like backspace, are
+  /// included.
+  Text(char),
+}
+
+impl QgfxEvent {
+  /// Converts a raw window event.
+  /// # Returns
+  /// None for events with no QgfxEvent equivalent.
+  pub fn from_raw(ev: &Event) -> Option<QgfxEvent> {
+    let ev = match *ev {
+      Event::WindowEvent { ref event, .. } => event,
+      _ => return None,
+    };
+    Some(match *ev {
+      WindowEvent::Closed => QgfxEvent::CloseRequested,
+      WindowEvent::Resized(w, h) => QgfxEvent::Resized { w: w, h: h },
+      WindowEvent::Focused(focused) => QgfxEvent::Focused(focused),
+      WindowEvent::KeyboardInput { input, .. } => QgfxEvent::Key {
+        code: input.virtual_keycode,
+        scancode: input.scancode,
+        state: button_state(input.state),
+      },
+      WindowEvent::MouseMoved { position: (x, y), .. } => {
+        QgfxEvent::MouseMove { x: x as f32, y: y as f32 }
+      }
+      WindowEvent::MouseInput { state, button, .. } => QgfxEvent::MouseButton {
+        button: match button {
+          glutin::MouseButton::Left => MouseButton::Left,
+          glutin::MouseButton::Right => MouseButton::Right,
+          glutin::MouseButton::Middle => MouseButton::Middle,
+          glutin::MouseButton::Other(b) => MouseButton::Other(b),
+        },
+        state: button_state(state),
+      },
+      WindowEvent::MouseWheel { delta, .. } => match delta {
+        MouseScrollDelta::LineDelta(x, y) => QgfxEvent::Scroll { x: x, y: y },
+        MouseScrollDelta::PixelDelta(x, y) => {
+          QgfxEvent::Scroll { x: x / SCROLL_LINE_PIXELS, y: y / SCROLL_LINE_PIXELS }
+        }
+      },
+      WindowEvent::ReceivedCharacter(c) => QgfxEvent::Text(c),
+      _ => return None,
+    })
+  }
+}
+
+fn button_state(state: ElementState) -> ButtonState {
+  match state {
+    ElementState::Pressed => ButtonState::Pressed,
+    ElementState::Released => ButtonState::Released,
+  }
+}
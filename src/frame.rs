@@ -0,0 +1,199 @@
+//! Frame timing and hooks for the main loop run by QGFX::run(). Integrations
+//! like audio or physics can implement FrameHook to run at fixed points in
+//! every frame, without owning the loop themselves.
+
+use std::time::{Duration, Instant};
+
+use {QGFX, QgfxContext, QgfxError, RendererController};
+
+/// Timing information for a frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameInfo {
+  /// The number of frames before this one.
+  pub frame: u64,
+  /// The time since the start of the previous frame, in seconds. 0 for the
+  /// first frame.
+  pub dt: f32,
+  /// The time since the first frame started, in seconds.
+  pub elapsed: f32,
+}
+
+/// Settings for deterministic mode, where time-based effects advance by a
+/// fixed step every frame instead of by real time, and random effects are
+/// seeded. Rendering the same frames then gives the same images on every
+/// run, for comparing against golden images. See QGFX::set_deterministic().
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Deterministic {
+  /// The length of every frame, in seconds.
+  pub dt: f32,
+  /// The seed for random effects, like screen shake.
+  pub seed: u32,
+}
+
+impl Default for Deterministic {
+  fn default() -> Deterministic {
+    Deterministic { dt: 1.0 / 60.0, seed: 0 }
+  }
+}
+
+/// How long each stage of QGFX::warm_up() took, in seconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WarmUpTimings {
+  /// Drawing shapes and textures offscreen, which has the driver finish
+  /// compiling the main shader program.
+  pub draw: f32,
+  /// Rendering a whole frame offscreen, through the virtual resolution,
+  /// post-processing and picking passes that are enabled.
+  pub frame: f32,
+  /// The whole warm up.
+  pub total: f32,
+}
+
+/// A frame being drawn, from QGFX::begin_frame(). Draw with the frame's
+/// controller, then finish() the frame to render it. The frame receives
+/// everything drawn before rendering, so there's no need to call recv_data()
+/// or flush the controller.
+///
+/// ```ignore
+/// let mut frame = qgfx.begin_frame();
+/// frame.set_camera(player_pos);
+/// frame.controller().rect(&[0.0, 0.0, 64.0, 64.0], &[1.0, 0.0, 0.0, 1.0]);
+/// frame.finish()?;
+/// ```
+///
+/// Dropping a frame without finishing it discards what was drawn with its
+/// controller.
+pub struct Frame<'q, 'a: 'q> {
+  qgfx: &'q mut QGFX<'a>,
+  /// The frame's controller, created the first time it's used.
+  controller: Option<Box<RendererController<'a>>>,
+}
+
+impl<'q, 'a: 'q> Frame<'q, 'a> {
+  pub(crate) fn new(qgfx: &'q mut QGFX<'a>) -> Frame<'q, 'a> {
+    Frame { qgfx: qgfx, controller: None }
+  }
+
+  /// The controller to draw the frame with. It's flushed when the frame is
+  /// finished. Other controllers can still be used, e.g. from other threads,
+  /// but have to be flushed before the frame is finished.
+  pub fn controller(&mut self) -> &mut RendererController<'a> {
+    if self.controller.is_none() {
+      self.controller = Some(self.qgfx.get_renderer_controller());
+    }
+    self.controller.as_mut().unwrap()
+  }
+
+  /// Moves the camera for this frame and the frames after it. See
+  /// QGFX::set_camera().
+  pub fn set_camera(&mut self, pos: [f32; 2]) {
+    self.qgfx.set_camera(pos)
+  }
+
+  /// The context the frame is drawn with, for caching textures and glyphs
+  /// mid-frame. Caching needs the frame's controller to be flushed first,
+  /// so this flushes it.
+  pub fn context(&mut self) -> &mut QgfxContext<'a> {
+    if let Some(mut controller) = self.controller.take() {
+      controller.flush();
+    }
+    &mut self.qgfx.context
+  }
+
+  /// Receives everything drawn for the frame, then renders and displays it.
+  /// # Errors
+  /// As QGFX::render().
+  pub fn finish(mut self) -> Result<(), QgfxError> {
+    if let Some(mut controller) = self.controller.take() {
+      controller.flush();
+    }
+    self.qgfx.recv_data();
+    self.qgfx.render()
+  }
+}
+
+/// Code run at fixed points in every frame of QGFX::run(). Register hooks
+/// with QGFX::add_frame_hook(). Both methods do nothing by default, so only
+/// the stages a hook needs have to be implemented.
+pub trait FrameHook {
+  /// Called at the start of every frame, after events are polled and before
+  /// the frame callback draws the frame.
+  fn pre_update(&mut self, _info: &FrameInfo) {}
+
+  /// Called at the end of every frame, after it's been rendered and
+  /// displayed.
+  fn post_render(&mut self, _info: &FrameInfo) {}
+}
+
+/// Measures the time between frames.
+pub struct FrameClock {
+  start: Option<Instant>,
+  last: Option<Instant>,
+  frame: u64,
+  /// If set, every frame is this long instead of being timed. See
+  /// Deterministic.
+  fixed_dt: Option<f32>,
+  /// The elapsed time of the last frame with a fixed step.
+  fixed_elapsed: f32,
+}
+
+impl FrameClock {
+  pub fn new() -> FrameClock {
+    FrameClock { start: None, last: None, frame: 0, fixed_dt: None, fixed_elapsed: 0.0 }
+  }
+
+  /// Sets a fixed length for every frame after the first, or None to time
+  /// frames. The elapsed time restarts from 0.
+  pub fn set_fixed_dt(&mut self, dt: Option<f32>) {
+    *self = FrameClock { fixed_dt: dt, ..FrameClock::new() };
+  }
+
+  /// Starts a new frame.
+  pub fn tick(&mut self) -> FrameInfo {
+    let info = match self.fixed_dt {
+      Some(fixed_dt) => {
+        let dt = if self.frame == 0 { 0.0 } else { fixed_dt };
+        self.fixed_elapsed += dt;
+        FrameInfo { frame: self.frame, dt: dt, elapsed: self.fixed_elapsed }
+      }
+      None => {
+        let now = Instant::now();
+        let start = *self.start.get_or_insert(now);
+        let dt = self.last.map_or(0.0, |last| secs(now - last));
+        self.last = Some(now);
+        FrameInfo { frame: self.frame, dt: dt, elapsed: secs(now - start) }
+      }
+    };
+    self.frame += 1;
+    info
+  }
+}
+
+/// Converts a duration to seconds.
+pub fn secs(d: Duration) -> f32 {
+  d.as_secs() as f32 + d.subsec_nanos() as f32 / 1_000_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn clock_counts_frames() {
+    let mut clock = FrameClock::new();
+    let first = clock.tick();
+    assert_eq!((first.frame, first.dt, first.elapsed), (0, 0.0, 0.0));
+    let second = clock.tick();
+    assert_eq!(second.frame, 1);
+    assert!(second.dt >= 0.0 && second.elapsed >= second.dt);
+  }
+
+  #[test]
+  fn fixed_clock_steps_evenly() {
+    let mut clock = FrameClock::new();
+    clock.set_fixed_dt(Some(0.5));
+    assert_eq!(clock.tick().elapsed, 0.0);
+    assert_eq!(clock.tick(), FrameInfo { frame: 1, dt: 0.5, elapsed: 0.5 });
+    assert_eq!(clock.tick().elapsed, 1.0);
+  }
+}
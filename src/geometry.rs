@@ -0,0 +1,192 @@
+//! Hit-testing for the shapes the RendererController draws. The parameters
+//! match the drawing functions', so a shape can be tested with the same
+//! values it was drawn with:
+//!
+//! ```ignore
+//! controller.circle(&pos, 20.0, 32, &col);
+//! if geometry::point_in_circle(&mouse, &pos, 20.0) { ... }
+//! ```
+
+use vec::Vec2;
+
+/// True if `p` is inside the rect `aabb` (X, Y, W, H), as drawn by
+/// RendererController::rect(). Points on the left and top edges are inside,
+/// points on the right and bottom edges aren't.
+pub fn point_in_rect(p: &[f32; 2], aabb: &[f32; 4]) -> bool {
+  p[0] >= aabb[0] && p[0] < aabb[0] + aabb[2] && p[1] >= aabb[1] && p[1] < aabb[1] + aabb[3]
+}
+
+/// True if `p` is inside the circle at `pos` with radius `rad`, as drawn by
+/// RendererController::circle(). The drawn circle is a polygon, so points
+/// very near the edge may be inside this circle but outside the drawn one.
+pub fn point_in_circle(p: &[f32; 2], pos: &[f32; 2], rad: f32) -> bool {
+  Vec2(*p).sub(Vec2(*pos)).len() <= rad
+}
+
+/// True if `p` is inside the polygon with the given vertices, using the
+/// even-odd rule. The polygon doesn't have to be convex, and is closed
+/// between its last and first vertices.
+pub fn point_in_polygon(p: &[f32; 2], vertices: &[[f32; 2]]) -> bool {
+  let mut inside = false;
+  let mut prev = match vertices.last() {
+    Some(v) => v,
+    None => return false,
+  };
+  for v in vertices {
+    // Count the edges crossing a ray from p in the +x direction.
+    if (v[1] > p[1]) != (prev[1] > p[1]) {
+      let x = v[0] + (p[1] - v[1]) * (prev[0] - v[0]) / (prev[1] - v[1]);
+      if p[0] < x {
+        inside = !inside;
+      }
+    }
+    prev = v;
+  }
+  inside
+}
+
+/// True if `p` is within `dist` of the line segment from `p1` to `p2`. To
+/// test a line drawn by RendererController::line(), use half the line's
+/// width as `dist`. Unlike the drawn line, this has rounded ends.
+pub fn point_near_segment(p: &[f32; 2], p1: &[f32; 2], p2: &[f32; 2], dist: f32) -> bool {
+  let seg = Vec2(*p2).sub(Vec2(*p1));
+  let to_p = Vec2(*p).sub(Vec2(*p1));
+  let len_sq = seg[0] * seg[0] + seg[1] * seg[1];
+  // How far along the segment the closest point is, from 0 to 1.
+  let t = if len_sq == 0.0 {
+    0.0
+  } else {
+    ((to_p[0] * seg[0] + to_p[1] * seg[1]) / len_sq).max(0.0).min(1.0)
+  };
+  to_p.sub(seg.mul(t)).len() <= dist
+}
+
+/// The outline of a rect (X, Y, W, H) with rounded corners, as drawn by
+/// RendererController::gradient_rounded_rect(), clockwise from the top left
+/// corner. Test points against it with point_in_polygon(). The radius is
+/// limited to half the rect's width and height.
+/// # Params
+/// * `segments` - The number of straight segments each corner is made of.
+pub fn rounded_rect(aabb: &[f32; 4], radius: f32, segments: usize) -> Vec<[f32; 2]> {
+  use std::f32::consts::PI;
+  let radius = radius.min(aabb[2] / 2.0).min(aabb[3] / 2.0).max(0.0);
+  let segments = if radius > 0.0 { segments.max(1) } else { 0 };
+  let (x0, y0) = (aabb[0] + radius, aabb[1] + radius);
+  let (x1, y1) = (aabb[0] + aabb[2] - radius, aabb[1] + aabb[3] - radius);
+  // Corner centres, with the angle each corner's arc starts at (y is down)
+  let corners = [(x0, y0, PI), (x1, y0, 1.5 * PI), (x1, y1, 0.0), (x0, y1, 0.5 * PI)];
+  let mut outline = Vec::with_capacity(4 * (segments + 1));
+  for &(cx, cy, start) in &corners {
+    for ii in 0..segments + 1 {
+      let a = start + 0.5 * PI * ii as f32 / segments.max(1) as f32;
+      outline.push([cx + radius * a.cos(), cy + radius * a.sin()]);
+    }
+  }
+  outline
+}
+
+/// Points along a cubic bezier curve, as stroked by
+/// RendererController::bezier().
+/// # Params
+/// * `p0`, `p3` - The ends of the curve.
+/// * `p1`, `p2` - The control points.
+/// * `segments` - The number of straight segments to split the curve into.
+///                There's one more point than segments.
+pub fn cubic_bezier(p0: &[f32; 2], p1: &[f32; 2], p2: &[f32; 2], p3: &[f32; 2],
+                    segments: usize) -> Vec<[f32; 2]> {
+  let segments = segments.max(1);
+  (0..segments + 1).map(|ii| {
+    let t = ii as f32 / segments as f32;
+    let u = 1.0 - t;
+    let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+    [a * p0[0] + b * p1[0] + c * p2[0] + d * p3[0], a * p0[1] + b * p1[1] + c * p2[1] + d * p3[1]]
+  }).collect()
+}
+
+/// The shadow an occluder casts from a point light, as drawn by
+/// RendererController::shadow(). Every edge of the occluder which faces away
+/// from the light is extruded away from it by `length`, giving one quad per
+/// edge, with corners in order around the quad. Together with the occluder
+/// itself, the quads cover everything the occluder hides from the light, up
+/// to `length` away. Concave occluders can give overlapping quads.
+/// # Params
+/// * `light` - The position of the light.
+/// * `occluder` - The occluder polygon, in either winding order.
+/// * `length` - How far to extrude each edge. This should be at least the
+///              light's radius.
+pub fn shadow_quads(light: &[f32; 2], occluder: &[[f32; 2]], length: f32) -> Vec<[[f32; 2]; 4]> {
+  let n = occluder.len();
+  if n < 3 {
+    return Vec::new();
+  }
+  // Twice the signed area, to tell which side of each edge is outside
+  let mut area = 0.0;
+  for ii in 0..n {
+    let (a, b) = (occluder[ii], occluder[(ii + 1) % n]);
+    area += a[0] * b[1] - b[0] * a[1];
+  }
+  let winding = if area < 0.0 { -1.0 } else { 1.0 };
+  let light = Vec2(*light);
+  let extrude = |p: [f32; 2]| Vec2(p).add(Vec2(p).sub(light).nor().mul(length)).0;
+  let mut quads = Vec::new();
+  for ii in 0..n {
+    let (a, b) = (occluder[ii], occluder[(ii + 1) % n]);
+    let edge = Vec2(b).sub(Vec2(a));
+    let outward = [edge[1] * winding, -edge[0] * winding];
+    let to_light = light.sub(Vec2(a));
+    if outward[0] * to_light[0] + outward[1] * to_light[1] < 0.0 {
+      quads.push([a, b, extrude(b), extrude(a)]);
+    }
+  }
+  quads
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rounded_rect_outline() {
+    let outline = rounded_rect(&[0.0, 0.0, 100.0, 50.0], 10.0, 4);
+    assert_eq!(outline.len(), 20);
+    assert!(point_in_polygon(&[50.0, 25.0], &outline));
+    assert!(!point_in_polygon(&[1.0, 1.0], &outline));
+    assert_eq!(rounded_rect(&[0.0, 0.0, 10.0, 10.0], 0.0, 4).len(), 4);
+  }
+
+  #[test]
+  fn point_in_polygon_concave() {
+    // An L shape.
+    let l = [[0.0, 0.0], [2.0, 0.0], [2.0, 1.0], [1.0, 1.0], [1.0, 2.0], [0.0, 2.0]];
+    assert!(point_in_polygon(&[0.5, 1.5], &l));
+    assert!(point_in_polygon(&[1.5, 0.5], &l));
+    assert!(!point_in_polygon(&[1.5, 1.5], &l));
+    assert!(!point_in_polygon(&[0.5, 0.5], &[]));
+  }
+
+  #[test]
+  fn point_near_segment_ends() {
+    assert!(point_near_segment(&[5.0, 1.0], &[0.0, 0.0], &[10.0, 0.0], 1.0));
+    assert!(!point_near_segment(&[5.0, 1.5], &[0.0, 0.0], &[10.0, 0.0], 1.0));
+    assert!(!point_near_segment(&[11.5, 0.0], &[0.0, 0.0], &[10.0, 0.0], 1.0));
+    assert!(point_near_segment(&[0.5, 0.5], &[0.0, 0.0], &[0.0, 0.0], 1.0));
+  }
+
+  #[test]
+  fn shadow_quads_extrude_back_edges() {
+    // A unit square, lit from the left.
+    let square = [[1.0, 0.0], [2.0, 0.0], [2.0, 1.0], [1.0, 1.0]];
+    let quads = shadow_quads(&[0.0, 0.5], &square, 10.0);
+    // Top, right and bottom face away from the light; the left edge doesn't.
+    assert_eq!(quads.len(), 3);
+    assert!(quads.iter().any(|q| q[0] == [2.0, 0.0] && q[1] == [2.0, 1.0]));
+    for q in &quads {
+      assert!(q[2][0] > 5.0 && q[3][0] > 5.0);
+    }
+    // The same square wound the other way casts the same shadow.
+    let mut reversed = square;
+    reversed.reverse();
+    assert_eq!(shadow_quads(&[0.0, 0.5], &reversed, 10.0).len(), 3);
+    assert!(shadow_quads(&[0.0, 0.5], &square[..2], 10.0).is_empty());
+  }
+}
@@ -0,0 +1,370 @@
+//! Action mapping, so game code asks whether "jump" is pressed rather than
+//! which key is down, and players can rebind controls:
+//!
+//! ```ignore
+//! let mut input = InputMap::new();
+//! input.bind("jump", Binding::key(KeyCode::Space));
+//! input.bind("save", "Ctrl+S".parse()?);
+//! qgfx.run(|qgfx, info, events| {
+//!   input.update(events);
+//!   if input.is_action_just_pressed("jump") { ... }
+//!   true
+//! })
+//! ```
+//!
+//! Bindings are written as text like "Ctrl+Shift+S", "MouseLeft" or
+//! "Scancode57", so they can be saved to a config file. With the serialize
+//! feature, they're serialized as that text too. Gamepads aren't supported,
+//! as the windowing library doesn't report them.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use event::{QgfxEvent, KeyCode, ButtonState, MouseButton};
+
+/// The modifier keys held with a binding's key or button.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Modifiers {
+  pub ctrl: bool,
+  pub shift: bool,
+  pub alt: bool,
+  /// The Windows, Command or Super key.
+  pub logo: bool,
+}
+
+impl Modifiers {
+  fn any(&self) -> bool {
+    self.ctrl || self.shift || self.alt || self.logo
+  }
+}
+
+/// A key or button which can be bound to an action.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Input {
+  Key(KeyCode),
+  /// A key by scancode, for keys without a key code, or to bind keys by
+  /// their position on the keyboard rather than their label.
+  Scancode(u32),
+  Mouse(MouseButton),
+}
+
+/// A key or mouse button, with the modifiers which must be held with it.
+///
+/// A binding with no modifiers is pressed whenever its key or button is,
+/// whatever modifiers are held, so holding shift to run doesn't stop the
+/// jump key working. A binding with modifiers is only pressed when exactly
+/// those modifiers are held, so "S" and "Ctrl+S" can be bound to different
+/// actions.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Binding {
+  pub input: Input,
+  pub modifiers: Modifiers,
+}
+
+impl Binding {
+  /// A key, with no modifiers.
+  pub fn key(code: KeyCode) -> Binding {
+    Binding { input: Input::Key(code), modifiers: Modifiers::default() }
+  }
+
+  /// A mouse button, with no modifiers.
+  pub fn mouse(button: MouseButton) -> Binding {
+    Binding { input: Input::Mouse(button), modifiers: Modifiers::default() }
+  }
+
+  /// Requires ctrl to be held.
+  pub fn ctrl(mut self) -> Binding {
+    self.modifiers.ctrl = true;
+    self
+  }
+
+  /// Requires shift to be held.
+  pub fn shift(mut self) -> Binding {
+    self.modifiers.shift = true;
+    self
+  }
+
+  /// Requires alt to be held.
+  pub fn alt(mut self) -> Binding {
+    self.modifiers.alt = true;
+    self
+  }
+
+  /// Requires the Windows, Command or Super key to be held.
+  pub fn logo(mut self) -> Binding {
+    self.modifiers.logo = true;
+    self
+  }
+}
+
+impl Display for Binding {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    let m = self.modifiers;
+    for &(held, name) in &[(m.ctrl, "Ctrl"), (m.shift, "Shift"), (m.alt, "Alt"), (m.logo, "Logo")] {
+      if held {
+        try!(write!(f, "{}+", name));
+      }
+    }
+    match self.input {
+      Input::Key(code) => write!(f, "{:?}", code),
+      Input::Scancode(scancode) => write!(f, "Scancode{}", scancode),
+      Input::Mouse(MouseButton::Left) => write!(f, "MouseLeft"),
+      Input::Mouse(MouseButton::Right) => write!(f, "MouseRight"),
+      Input::Mouse(MouseButton::Middle) => write!(f, "MouseMiddle"),
+      Input::Mouse(MouseButton::Other(b)) => write!(f, "Mouse{}", b),
+    }
+  }
+}
+
+impl FromStr for Binding {
+  type Err = ParseBindingError;
+
+  /// Parses a binding written like Display writes it: any of "Ctrl+",
+  /// "Shift+", "Alt+" and "Logo+", then a key code's name (e.g. "S", "Key1",
+  /// "Space", "F5"), "Scancode" and a number, or "MouseLeft", "MouseRight",
+  /// "MouseMiddle" or "Mouse" and a number. Only common key codes can be
+  /// parsed - bind other keys by scancode.
+  fn from_str(s: &str) -> Result<Binding, ParseBindingError> {
+    let err = || ParseBindingError(s.to_owned());
+    let mut parts: Vec<&str> = s.split('+').map(|part| part.trim()).collect();
+    let input = try!(parts.pop().ok_or_else(&err));
+    let mut modifiers = Modifiers::default();
+    for part in parts {
+      match part {
+        "Ctrl" => modifiers.ctrl = true,
+        "Shift" => modifiers.shift = true,
+        "Alt" => modifiers.alt = true,
+        "Logo" => modifiers.logo = true,
+        _ => return Err(err()),
+      }
+    }
+    let input = match input {
+      "MouseLeft" => Input::Mouse(MouseButton::Left),
+      "MouseRight" => Input::Mouse(MouseButton::Right),
+      "MouseMiddle" => Input::Mouse(MouseButton::Middle),
+      _ if input.starts_with("Scancode") => {
+        Input::Scancode(try!(input["Scancode".len()..].parse().map_err(|_| err())))
+      }
+      _ if input.starts_with("Mouse") => {
+        Input::Mouse(MouseButton::Other(try!(input["Mouse".len()..].parse().map_err(|_| err()))))
+      }
+      _ => Input::Key(try!(key_from_name(input).ok_or_else(&err))),
+    };
+    Ok(Binding { input: input, modifiers: modifiers })
+  }
+}
+
+#[cfg(feature = "serialize")]
+impl ::serde::Serialize for Binding {
+  fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+#[cfg(feature = "serialize")]
+impl<'de> ::serde::Deserialize<'de> for Binding {
+  fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Binding, D::Error> {
+    let text: String = try!(::serde::Deserialize::deserialize(deserializer));
+    text.parse().map_err(::serde::de::Error::custom)
+  }
+}
+
+/// An error returned when parsing a binding fails. Contains the text which
+/// couldn't be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseBindingError(pub String);
+
+impl Display for ParseBindingError {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    write!(f, "Invalid key binding: {:?}", self.0)
+  }
+}
+
+impl Error for ParseBindingError {
+  fn description(&self) -> &str { "Invalid key binding" }
+}
+
+/// Maps names to key code variants for parsing, as key codes can be written
+/// but not parsed.
+macro_rules! key_names {
+  ($($key:ident),*) => {
+    fn key_from_name(name: &str) -> Option<KeyCode> {
+      match name {
+        $(stringify!($key) => Some(KeyCode::$key),)*
+        _ => None,
+      }
+    }
+  }
+}
+
+key_names!(
+  Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+  A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+  Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+  Snapshot, Scroll, Pause, Insert, Home, Delete, End, PageDown, PageUp,
+  Left, Up, Right, Down, Back, Return, Space, Tab,
+  Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+  Add, Subtract, Multiply, Divide, Decimal, NumpadEnter,
+  Apostrophe, Backslash, Comma, Equals, Grave, LBracket, RBracket, Minus, Period, Semicolon,
+  Slash, LAlt, LControl, LShift, LWin, RAlt, RControl, RShift, RWin
+);
+
+/// Tracks which keys and mouse buttons are held from window events, and maps
+/// them to named actions. Each action can have any number of bindings, and
+/// is pressed when any of them are.
+#[derive(Clone, Debug, Default)]
+pub struct InputMap {
+  bindings: HashMap<String, Vec<Binding>>,
+  held: HashSet<Input>,
+  /// The inputs pressed in the events passed to the last update().
+  just_pressed: HashSet<Input>,
+}
+
+impl InputMap {
+  pub fn new() -> InputMap {
+    InputMap::default()
+  }
+
+  /// Adds a binding to an action, keeping its existing bindings.
+  pub fn bind(&mut self, action: &str, binding: Binding) {
+    let bindings = self.bindings.entry(action.to_owned()).or_insert_with(Vec::new);
+    if !bindings.contains(&binding) {
+      bindings.push(binding);
+    }
+  }
+
+  /// Replaces all of an action's bindings with one binding, for rebinding
+  /// controls at runtime.
+  pub fn rebind(&mut self, action: &str, binding: Binding) {
+    self.bindings.insert(action.to_owned(), vec![binding]);
+  }
+
+  /// Removes all of an action's bindings.
+  pub fn unbind(&mut self, action: &str) {
+    self.bindings.remove(action);
+  }
+
+  /// An action's bindings. Empty if it has none.
+  pub fn action_bindings(&self, action: &str) -> &[Binding] {
+    self.bindings.get(action).map_or(&[][..], |bindings| &bindings[..])
+  }
+
+  /// Every action's bindings, for saving them.
+  pub fn bindings(&self) -> &HashMap<String, Vec<Binding>> {
+    &self.bindings
+  }
+
+  /// Replaces every action's bindings, for loading saved bindings.
+  pub fn set_bindings(&mut self, bindings: HashMap<String, Vec<Binding>>) {
+    self.bindings = bindings;
+  }
+
+  /// Updates which keys and buttons are held from a frame's events. Call
+  /// this once per frame, with all of the frame's events (even if there are
+  /// none), so is_action_just_pressed() only reports presses from this
+  /// frame. Everything is released when the window loses focus, as releases
+  /// aren't reported whilst it's unfocused.
+  pub fn update(&mut self, events: &[QgfxEvent]) {
+    self.just_pressed.clear();
+    for ev in events {
+      let (input, state) = match *ev {
+        QgfxEvent::Key { code: Some(code), scancode, state } => {
+          self.set_held(Input::Scancode(scancode), state);
+          (Input::Key(code), state)
+        }
+        QgfxEvent::Key { code: None, scancode, state } => (Input::Scancode(scancode), state),
+        QgfxEvent::MouseButton { button, state } => (Input::Mouse(button), state),
+        QgfxEvent::Focused(false) => {
+          self.held.clear();
+          continue;
+        }
+        _ => continue,
+      };
+      self.set_held(input, state);
+    }
+  }
+
+  fn set_held(&mut self, input: Input, state: ButtonState) {
+    match state {
+      // Key repeats aren't new presses.
+      ButtonState::Pressed => if self.held.insert(input) {
+        self.just_pressed.insert(input);
+      },
+      ButtonState::Released => {
+        self.held.remove(&input);
+      }
+    }
+  }
+
+  /// The modifier keys currently held.
+  pub fn modifiers(&self) -> Modifiers {
+    let held = |l, r| self.held.contains(&Input::Key(l)) || self.held.contains(&Input::Key(r));
+    Modifiers {
+      ctrl: held(KeyCode::LControl, KeyCode::RControl),
+      shift: held(KeyCode::LShift, KeyCode::RShift),
+      alt: held(KeyCode::LAlt, KeyCode::RAlt),
+      logo: held(KeyCode::LWin, KeyCode::RWin),
+    }
+  }
+
+  /// True if a key or mouse button is held.
+  pub fn is_held(&self, input: Input) -> bool {
+    self.held.contains(&input)
+  }
+
+  fn modifiers_match(&self, binding: &Binding) -> bool {
+    !binding.modifiers.any() || binding.modifiers == self.modifiers()
+  }
+
+  /// True if any of an action's bindings are held. False for actions with
+  /// no bindings.
+  pub fn is_action_pressed(&self, action: &str) -> bool {
+    self.action_bindings(action).iter().any(|b| {
+      self.held.contains(&b.input) && self.modifiers_match(b)
+    })
+  }
+
+  /// True if any of an action's bindings were pressed in the events passed
+  /// to the last update().
+  pub fn is_action_just_pressed(&self, action: &str) -> bool {
+    self.action_bindings(action).iter().any(|b| {
+      self.just_pressed.contains(&b.input) && self.modifiers_match(b)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn key(code: KeyCode, state: ButtonState) -> QgfxEvent {
+    QgfxEvent::Key { code: Some(code), scancode: 0, state: state }
+  }
+
+  #[test]
+  fn bindings_round_trip_through_text() {
+    for text in &["Ctrl+Shift+S", "Space", "MouseLeft", "Alt+Mouse4", "Scancode57", "Key1"] {
+      let binding: Binding = text.parse().unwrap();
+      assert_eq!(binding.to_string(), *text);
+    }
+    assert_eq!("Ctrl+S".parse(), Ok(Binding::key(KeyCode::S).ctrl()));
+    assert!("Hyper+S".parse::<Binding>().is_err());
+    assert!("Ctrl+".parse::<Binding>().is_err());
+  }
+
+  #[test]
+  fn modifiers_must_match_exactly() {
+    let mut input = InputMap::new();
+    input.bind("jump", Binding::key(KeyCode::S));
+    input.bind("save", Binding::key(KeyCode::S).ctrl());
+    input.update(&[key(KeyCode::LControl, ButtonState::Pressed),
+                   key(KeyCode::S, ButtonState::Pressed)]);
+    assert!(input.is_action_pressed("save") && input.is_action_just_pressed("save"));
+    assert!(input.is_action_pressed("jump"));
+    input.update(&[key(KeyCode::LShift, ButtonState::Pressed)]);
+    assert!(!input.is_action_pressed("save") && !input.is_action_just_pressed("jump"));
+    input.update(&[QgfxEvent::Focused(false)]);
+    assert!(!input.is_action_pressed("jump"));
+  }
+}
@@ -7,154 +7,990 @@ extern crate test;
 #[macro_use]
 extern crate glium;
 extern crate winit;
+#[cfg(feature = "font")]
 extern crate rusttype;
+#[cfg(feature = "font")]
+extern crate unicode_bidi;
+#[cfg(feature = "image")]
 extern crate image;
+#[cfg(feature = "egui")]
+extern crate egui;
+#[cfg(feature = "lyon")]
+extern crate lyon;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate lazy_static;
+#[cfg(feature = "serialize")]
+extern crate serde;
+#[cfg(feature = "serialize")]
+#[macro_use]
+extern crate serde_derive;
 
+mod error;
 mod renderer;
 mod vec;
 mod res;
 mod test_helper;
+mod perf;
+mod builder;
+pub mod debug;
+pub mod geometry;
+pub mod tween;
+pub mod palette;
+mod canvas;
+mod loader;
+mod shake;
+mod cursor;
+mod frame;
+mod context;
+mod monitor;
+mod event;
+mod input;
+#[cfg(feature = "ui")]
+pub mod ui;
+#[cfg(feature = "egui")]
+pub mod egui_backend;
+#[cfg(feature = "lyon")]
+pub mod lyon_interop;
 
-pub use renderer::RendererController;
+pub use renderer::{RendererController, MeshVertex, ColorSpace, SamplerSettings, Layer,
+                   DrawOverrides, DrawOverridesHandle, TessellationCache, BatchRecord,
+                   BatchTexture, HdrFormat, Tonemap, Bloom, Crt, FrameGraph, PassTarget,
+                   PassId, DrawPass, Clear, Gradient, StrokeStyle, LineCap, LineJoin, Path,
+                   FillRule, FillStyle, CommandList, Affine};
+pub use glium::draw_parameters::{DepthTest, PolygonMode};
+pub use event::{QgfxEvent, KeyCode, ButtonState, MouseButton, SCROLL_LINE_PIXELS};
+pub use input::{InputMap, Binding, Input, Modifiers, ParseBindingError};
+// The raw window events, for poll_raw_events().
 pub use glium::glutin::Event;
 pub use glium::glutin::WindowEvent;
 pub use glium::glutin::DeviceEvent;
 pub use winit::{VirtualKeyCode, ElementState};
-pub use res::font::{gen_charset, Charset};
+// OpenGL context options, for QGFXBuilder.
+pub use glium::glutin::{Api, GlProfile, GlRequest, Robustness};
+#[cfg(feature = "font")]
+pub use res::font::{gen_charset, font_face_count, Charset};
+pub use error::QgfxError;
+pub use builder::QGFXBuilder;
+pub use canvas::Canvas;
+pub use cursor::SoftCursor;
+pub use loader::{AssetLoader, Pending};
+pub use renderer::RenderTextureError;
 
 use glium::Display;
 use glium::glutin::EventsLoop;
-use renderer::Renderer;
+use renderer::{Renderer, VirtualTarget, ScaledTarget, PostProcess};
+use frame::FrameClock;
+use std::cell::Cell;
+use std::ops::{Deref, DerefMut};
 use std::sync::Mutex;
-use std::path::Path;
-pub use res::font::{FontHandle, CacheGlyphError};
+use std::time::Instant;
+#[cfg(feature = "font")]
+pub use res::font::{FontHandle, CacheGlyphError, CacheReport, TextAnchor, TextLayout, TextStyle, TextRun,
+                     GlyphPlacement, MissingGlyphPolicy, MissingGlyphsError, PathCommand};
 pub use res::tex::{TexHandle, CacheTexError};
+pub use res::tex::glium_cache::{RgbaBuffer, StreamingTexture};
+pub use frame::{Frame, FrameInfo, FrameHook, Deterministic, WarmUpTimings};
+pub use context::QgfxContext;
+pub use monitor::{MonitorInfo, available_monitors};
 
 
 /// The API of the library.
 pub struct QGFX<'a> {
-  renderer: Box<Renderer<'a>>,
-  display: Display,
+  /// The OpenGL context and resource caches. QGFX derefs to this.
+  context: QgfxContext<'a>,
   events_loop: Mutex<EventsLoop>,
-  /// A tex handle for a 1x1 white texture. Used when rendering colours.
-  white_tex_handle: TexHandle,
+  /// The offscreen target rendered to when using a virtual resolution. See
+  /// QGFXBuilder::with_virtual_resolution().
+  virtual_target: Option<VirtualTarget>,
+  /// The offscreen target frames are rendered into at the render scale,
+  /// without a virtual resolution. See set_render_scale().
+  scaled_target: Option<ScaledTarget>,
+  /// The post-processing pass, if frames are rendered offscreen first. See
+  /// QGFXBuilder::with_hdr().
+  post: Option<PostProcess>,
+  /// The screen shake in progress, if any. See shake().
+  shake: Option<shake::Shake>,
+  /// Times the frames render() draws, for time-based effects like screen
+  /// shake.
+  clock: FrameClock,
+  /// The deterministic mode settings, if it's enabled. See
+  /// set_deterministic().
+  deterministic: Option<Deterministic>,
+  /// Hooks run every frame by run(). See add_frame_hook().
+  frame_hooks: Vec<Box<FrameHook>>,
+  /// The performance overlay, if it's shown.
+  perf_overlay: Option<perf::PerfOverlay>,
+  /// The texture drawn in place of the OS cursor, if any. See
+  /// set_soft_cursor().
+  soft_cursor: Option<SoftCursor>,
+  /// The mouse position in the window, from the last event polled, or None
+  /// if it's outside the window.
+  mouse_pos: Cell<Option<[f32; 2]>>,
+}
+
+/// QGFX used to hold the resource caching methods itself. They now live on
+/// QgfxContext, and are reached through this for compatibility.
+impl<'a> Deref for QGFX<'a> {
+  type Target = QgfxContext<'a>;
+
+  fn deref(&self) -> &QgfxContext<'a> {
+    &self.context
+  }
+}
+
+impl<'a> DerefMut for QGFX<'a> {
+  fn deref_mut(&mut self) -> &mut QgfxContext<'a> {
+    &mut self.context
+  }
 }
 
+/// The size default_font() caches the default font at, in pixels.
+#[cfg(feature = "default-font")]
+pub const DEFAULT_FONT_SCALE: f32 = 16.0;
+
 impl<'a> QGFX<'a> {
   /// Create a display with a renderer and return it. This function will open a window.
+  /// # Panics
+  /// Panics if the display or renderer couldn't be created. Use try_new() to
+  /// handle this error instead.
   pub fn new() -> QGFX<'a> {
-    let (display, events_loop) = init_display();
-    let mut renderer = Renderer::new(&display);
-
-    // We need to buffer a small white rectangle, for when drawing coloured
-    // shapes. The following is an array for a bitmap with a 1x1 white pixel.
-    let bytes = [0x42, 0x4d, 0x42, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                 0x3e, 0x00, 0x00, 0x00, 0x28, 0x00, 0x00, 0x00, 0x01, 0x00,
-                 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00,
-                 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
-                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff,
-                 0xff, 0x00, 0x80, 0x00, 0x00, 0x00];
-    let t_vec_ref = &renderer.cache_tex_from_bytes(&display, &[&bytes[..]])[0];
-    if t_vec_ref.is_err() {
-      println!("{:?}", t_vec_ref.as_ref().err().unwrap());
-    }
-    let white_tex_handle = t_vec_ref.as_ref().unwrap();
+    QGFX::try_new().expect("Failed to create QGFX")
+  }
 
-    QGFX { 
-      renderer: renderer,
-      display: display,
-      events_loop: Mutex::new(events_loop),
-      white_tex_handle: white_tex_handle.clone(),
-    }
+  /// Create a display with a renderer and return it. This function will open a window.
+  /// # Errors
+  /// Returns an error if the window, OpenGL context, or any of the renderer's
+  /// GPU resources couldn't be created.
+  pub fn try_new() -> Result<QGFX<'a>, QgfxError> {
+    QGFXBuilder::new().build()
   }
 
   /// Get a renderer controller to send VBO data to this renderer. These can be
   /// cloned.
-  pub fn get_renderer_controller(&'a self) -> Box<RendererController<'a>> {
-    return self.renderer.get_renderer_controller(self.white_tex_handle);
+  pub fn get_renderer_controller(&self) -> Box<RendererController<'a>> {
+    return self.context.renderer.get_renderer_controller(self.context.white_tex_handle);
   }
 
-  /// Cache some glyphs from a font.
-  pub fn cache_glyphs<F: AsRef<Path>> (
-    &mut self, file: F, scale: f32, 
-    charset: &[char]) -> Result<FontHandle, CacheGlyphError> {
-    // Need to add the '?' char, which is out fallback for unknown glyphs. Pretty slow but who
-    // cares - we only call it once at the start.
-    let mut charset : Vec<char> = charset.to_owned();
-    if !charset.contains(&'?') {
-        charset.push('?');
-    }
-    self.renderer.cache_glyphs(file, scale, &charset)
+  /// Gets a renderer controller which submits what it draws on a stream, for
+  /// recording a frame on several threads at once. Give each thread its own
+  /// stream, and what they draw is always drawn in order of stream, whatever
+  /// order the threads finish in. See RendererController::set_stream().
+  pub fn get_stream_controller(&self, stream: u32) -> Box<RendererController<'a>> {
+    let mut controller = self.get_renderer_controller();
+    controller.set_stream(Some(stream));
+    controller
   }
 
-  /// A function to cache some textures and return texture handles.
-  /// 
+  /// Sets how textures and glyphs are sampled when they're drawn scaled, for
+  /// every texture page without its own settings. See SamplerSettings.
+  /// Defaults to linear filtering without anisotropy, or nearest filtering if
+  /// the QGFX was built with nearest filtering.
+  pub fn set_sampler(&mut self, settings: SamplerSettings) {
+    self.context.renderer.set_sampler(settings)
+  }
+
+  /// Sets how the texture cache page containing `tex` is sampled, overriding
+  /// set_sampler(). None returns the page to the global settings. As this
+  /// affects every texture on the page, it's best used with textures big
+  /// enough to get a dedicated page (see set_dedicated_tex_threshold()).
+  /// Returns false if `tex` isn't cached.
+  pub fn set_page_sampler(&mut self, tex: TexHandle, settings: Option<SamplerSettings>) -> bool {
+    self.context.renderer.set_page_sampler(tex, settings)
+  }
+
+  /// Sets whether everything is drawn as a wireframe, showing the triangles
+  /// that circles, text quads and meshes are made of. This is for debugging,
+  /// and isn't supported by OpenGL ES. Debug drawing and the performance
+  /// overlay are drawn as wireframes too.
+  pub fn set_wireframe(&mut self, wireframe: bool) {
+    self.context.renderer.set_wireframe(wireframe)
+  }
+
+  /// Sets whether an overdraw heatmap is shown instead of the normal output,
+  /// to find layers which waste time drawing over the same pixels - like
+  /// full screen backgrounds hidden behind other full screen backgrounds.
+  /// Every triangle drawn adds to the pixels it covers, so pixels go from
+  /// black (not drawn) through red (drawn 4 times), yellow (10 times) to
+  /// white (25 or more times). Transparent parts of textures count, as they
+  /// cost as much to draw as opaque ones. Debug drawing and the performance
+  /// overlay are counted too. This is for debugging.
+  /// # Errors
+  /// Returns an error if the heatmap's shader program couldn't be compiled.
+  pub fn set_overdraw_heatmap(&mut self, overdraw: bool) -> Result<(), QgfxError> {
+    self.context.renderer.set_overdraw_heatmap(&self.context.display, overdraw)
+  }
+
+  /// Sets whether everything is tinted with a colour identifying the texture
+  /// page it was drawn from - a different hue for each texture cache page,
+  /// and grey for text. Every change of colour across the screen is a
+  /// change of texture, and so a separate draw call, so this shows why
+  /// batching is splitting into many draw calls, and which textures should
+  /// be cached together. This is for debugging.
+  pub fn set_page_tint(&mut self, page_tint: bool) {
+    self.context.renderer.set_page_tint(page_tint)
+  }
+
+  /// Sets whether the batches received by recv_data() are recorded, for
+  /// testing batching behaviour. See batch_records().
+  pub fn set_batch_recording(&mut self, record: bool) {
+    self.context.renderer.set_batch_recording(record)
+  }
+
+  /// The batches received by the last call to recv_data(), in the order
+  /// they're drawn - one per draw call. Empty unless batch recording is
+  /// enabled with set_batch_recording().
+  pub fn batch_records(&self) -> &[BatchRecord] {
+    self.context.renderer.batch_records()
+  }
+
+  /// Overrides the draw parameters used for every batch in a layer, e.g. to
+  /// mask out the alpha channel or rasterise everything as lines. Use
+  /// DrawOverrides::new() to remove the overrides.
+  pub fn set_layer_draw_overrides(&mut self, layer: Layer, overrides: DrawOverrides) {
+    self.context.renderer.set_layer_overrides(layer, overrides)
+  }
+
+  /// The colour space the renderer handles colours in. See ColorSpace.
+  pub fn color_space(&self) -> ColorSpace {
+    self.context.renderer.color_space()
+  }
+
+  /// Converts an sRGB colour, like the colours in the palette module, to the
+  /// form vertex colours take in the renderer's colour space, so it looks
+  /// the same whether or not the framebuffer is sRGB. See
+  /// palette::in_space().
+  pub fn color(&self, srgb: [f32; 4]) -> [f32; 4] {
+    palette::in_space(srgb, self.context.renderer.color_space())
+  }
+
+  /// Sets what each frame is cleared to before it's drawn: the window, and
+  /// the virtual resolution and post-processing targets if they're used.
+  /// By default the colour is cleared to transparent black. Not clearing the
+  /// colour keeps the previous frame's, for trails and accumulation effects.
+  /// Only write some colour channels with the layers' draw overrides (see
+  /// set_layer_draw_overrides()).
+  pub fn set_clear(&mut self, clear: Clear) {
+    self.context.renderer.set_clear(clear)
+  }
+
+  /// Sets the colour each frame is cleared to, keeping whether depth and
+  /// stencil are cleared as set with set_clear(). This saves drawing a
+  /// fullscreen rect every frame for a background colour. Like vertex
+  /// colours, the colour is in the renderer's colour space - see color().
   ///
-  /// # Params
-  /// * `filepaths` - The list of textures as filepaths.
+  /// ```ignore
+  /// let sky = qgfx.color([0.6, 0.8, 1.0, 1.0]);
+  /// qgfx.set_clear_color(sky[0], sky[1], sky[2], sky[3]);
+  /// ```
+  pub fn set_clear_color(&mut self, r: f32, g: f32, b: f32, a: f32) {
+    let clear = self.context.renderer.get_clear().color(r, g, b, a);
+    self.context.renderer.set_clear(clear)
+  }
+
+  /// Clears the next frame drawn with render() or render_graph() to the
+  /// given colour, rather than the colour set with set_clear_color(). This
+  /// only lasts one frame, e.g. for flashing the background when the player
+  /// is hit.
+  pub fn set_frame_clear_color(&mut self, r: f32, g: f32, b: f32, a: f32) {
+    let clear = self.context.renderer.get_clear().color(r, g, b, a);
+    self.context.renderer.set_frame_clear(Some(clear))
+  }
+
+  /// Registers draw parameter overrides for individual batches. Pass the
+  /// returned handle to RendererController::set_draw_overrides() to draw
+  /// shapes with them. They're applied on top of the layer's overrides.
+  /// Registered overrides live as long as the QGFX, so register them once
+  /// rather than every frame.
+  pub fn register_draw_overrides(&mut self, overrides: DrawOverrides) -> DrawOverridesHandle {
+    self.context.renderer.register_draw_overrides(overrides)
+  }
+
+  /// Sets the gamma correction applied to text when blending it with the
+  /// background. Text is blended using its glyph coverage, which is linear,
+  /// so on an sRGB display light text on a dark background looks too thin and
+  /// dark text on a light background looks too heavy. Coverage is raised to
+  /// the power of 1 / `gamma`: values above 1.0 make text heavier, values
+  /// below 1.0 make it thinner. 1.0 (the default) leaves coverage as is.
+  /// Values around 1.4 - 2.2 usually suit light text on dark backgrounds.
+  #[cfg(feature = "font")]
+  pub fn set_text_gamma(&mut self, gamma: f32) {
+    self.context.renderer.set_text_gamma(gamma)
+  }
+
+  /// Shows or hides the performance overlay in the top left of the display.
+  /// This shows the FPS, a graph of recent frame times (one pixel per
+  /// millisecond, with a line at 60 FPS), the number of draw calls in the
+  /// last frame, and the number of texture cache pages with their average
+  /// occupancy. It's drawn on top of everything else by recv_data().
+  pub fn show_perf_overlay(&mut self, show: bool) {
+    if show && self.perf_overlay.is_none() {
+      self.perf_overlay = Some(perf::PerfOverlay::new());
+    } else if !show {
+      self.perf_overlay = None;
+    }
+  }
+
+  /// Sets a texture to draw at the mouse position in place of the OS cursor,
+  /// or None to show the OS cursor again. The cursor is drawn on top of
+  /// everything else, including the performance overlay, by recv_data(), at
+  /// the mouse position from the last events polled. It's hidden whilst the
+  /// mouse is outside the window. The windowing library can't set the OS
+  /// cursor to an image, so this is how to have custom cursors.
+  pub fn set_soft_cursor(&mut self, cursor: Option<SoftCursor>) {
+    use glium::glutin::CursorState;
+    let state = if cursor.is_some() { CursorState::Hide } else { CursorState::Normal };
+    if let Err(e) = self.context.display.gl_window().set_cursor_state(state) {
+      warn!("Failed to set the OS cursor's state: {}", e);
+    }
+    self.soft_cursor = cursor;
+  }
+
+  /// The mouse position in the window, as the windowing library reports it,
+  /// from the last events polled. None if the mouse is outside the window,
+  /// or hasn't moved since the window opened. Use window_to_virtual() to
+  /// convert it to renderer controller coordinates, or see
+  /// mouse_world_position().
+  pub fn mouse_position(&self) -> Option<[f32; 2]> {
+    self.mouse_pos.get()
+  }
+
+  /// Renders what a closure draws into an offscreen image of the given size
+  /// (in pixels), and reads it back. The window isn't affected, so this can
+  /// be used for thumbnails, baking minimaps, or exporting images whilst the
+  /// app is running.
   ///
+  /// ```ignore
+  /// let thumb = qgfx.render_to_image((64, 64), |controller| {
+  ///   controller.rect(&[0.0, 0.0, 32.0, 32.0], &[1.0, 0.0, 0.0, 1.0]);
+  /// })?;
+  /// ```
+  /// # Errors
+  /// Returns an error if the offscreen texture couldn't be created, or if
+  /// drawing failed.
+  pub fn render_to_image<C: FnOnce(&mut RendererController<'a>)>(
+    &mut self, size: (u32, u32), draw: C) -> Result<RgbaBuffer, QgfxError> {
+    let context = &mut self.context;
+    context.renderer.render_to_image(&context.display, context.white_tex_handle, size, draw)
+  }
+
+  /// Renders a string once into a new cached texture, so static labels can
+  /// be drawn as a single quad every frame rather than a quad per glyph. The
+  /// text is white, with its top left at the top left of the texture - tint
+  /// it when drawing it. The texture is the size of the text's glyph cells,
+  /// so draw it at that size to match text drawn with a controller.
   /// # Returns
-  /// A list of texture handles, corresponding to textures on the GPU. Texture
-  /// handles are returned in a slice with the indices corresponding to the
-  /// indices in the slice of texture files given.
-  ///
+  /// The handle of the texture, and its size in pixels.
   /// # Errors
-  /// Each texture may cause an error separately. Errors may occur if a texture
-  /// is too big for the texture cache, or if there was an error loading the
-  /// image etc.
-  pub fn cache_tex<F: AsRef<Path>>(&mut self, filepaths: &[F]) -> Vec<Result<TexHandle, CacheTexError>> {
-    self.renderer.cache_tex(&self.display, filepaths)
+  /// Returns an error if the text has glyphs which aren't cached (with
+  /// MissingGlyphPolicy::Error), or if rendering or caching the texture
+  /// failed.
+  #[cfg(feature = "font")]
+  pub fn bake_text(&mut self, text: &str, font: FontHandle, style: TextStyle)
+    -> Result<(TexHandle, (u32, u32)), QgfxError> {
+    const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+    // Lay the text out to find its size. The controller is never flushed, so
+    // nothing is drawn.
+    let (w, h) = {
+      let mut controller = self.get_renderer_controller();
+      let layout = try!(controller.text_styled(text, &[0.0, 0.0], font, TextAnchor::TopLeft,
+                                               style, &WHITE));
+      layout.glyphs.iter().fold(layout.size, |(w, h), g| {
+        (w.max(g.rect[0] + g.rect[2]), h.max(g.rect[1] + g.rect[3]))
+      })
+    };
+    let size = (w.ceil().max(1.0) as u32, h.ceil().max(1.0) as u32);
+    let mut res = Ok(());
+    let image = try!(self.render_to_image(size, |controller| {
+      res = controller.text_styled(text, &[0.0, 0.0], font, TextAnchor::TopLeft, style, &WHITE)
+        .map(|_| ());
+    }));
+    try!(res);
+    let handle = try!(self.cache_tex_from_rgba(&[(image.w, image.h, &image.data[..])]).remove(0));
+    Ok((handle, size))
   }
 
-  pub fn cache_tex_from_bytes(&mut self, bytes: &[&[u8]]) -> Vec<Result<TexHandle, CacheTexError>> {
-    self.renderer.cache_tex_from_bytes(&self.display, bytes)
+  /// Enables or disables picking. When enabled, render() also renders what
+  /// was drawn into an offscreen ID buffer, so pick() can find what's under
+  /// the mouse. Tag what's drawn with RendererController::set_pick_id().
+  pub fn set_picking(&mut self, picking: bool) {
+    self.context.renderer.set_picking(picking);
   }
 
-  /// Get the size of the display in pixels.
+  /// Finds the topmost tagged shape at a point, as of the last call to
+  /// render(). The point is in renderer controller coordinates - use
+  /// window_to_virtual() to convert mouse positions.
+  /// # Returns
+  /// The pick ID of the shape, or None if picking is disabled or there's
+  /// nothing tagged at the point. Untagged shapes hide tagged shapes under
+  /// them, but overlay shapes (debug drawing and the performance overlay)
+  /// don't.
+  pub fn pick(&self, x: f32, y: f32) -> Option<u32> {
+    if x < 0.0 || y < 0.0 {
+      return None;
+    }
+    self.context.renderer.pick(x as u32, y as u32)
+  }
+
+  /// Get the size of the display in pixels. When using a virtual resolution,
+  /// this is the virtual resolution.
   pub fn get_display_size(&self) -> (u32, u32) {
-    self.display.get_framebuffer_dimensions()
+    match self.virtual_target {
+      Some(ref vt) => vt.size(),
+      None => self.context.display.get_framebuffer_dimensions(),
+    }
+  }
+
+  /// Lists the monitors connected to the system. See available_monitors(),
+  /// which can be called before the window is created.
+  pub fn monitors(&self) -> Vec<MonitorInfo> {
+    monitor::list_monitors(&self.events_loop.lock().unwrap())
+  }
+
+  /// Maps a position in the window (e.g. the mouse position) to framebuffer
+  /// pixels. On high DPI displays, some platforms report window positions in
+  /// points rather than pixels, so these differ by the DPI scale factor.
+  pub fn window_to_framebuffer(&self, pos: [f32; 2]) -> [f32; 2] {
+    let (fb_w, fb_h) = self.context.display.get_framebuffer_dimensions();
+    match self.context.display.gl_window().get_inner_size() {
+      Some((w, h)) if w > 0 && h > 0 => {
+        [pos[0] * fb_w as f32 / w as f32, pos[1] * fb_h as f32 / h as f32]
+      }
+      _ => pos,
+    }
+  }
+
+  /// Maps a position in the window (e.g. the mouse position) to renderer
+  /// controller coordinates, for drawing in the overlay layer or with the
+  /// camera at the origin. These are framebuffer pixels (see
+  /// window_to_framebuffer()) unless a virtual resolution is used.
+  pub fn window_to_virtual(&self, pos: [f32; 2]) -> [f32; 2] {
+    let pos = self.window_to_framebuffer(pos);
+    match self.virtual_target {
+      Some(ref vt) => vt.window_to_virtual(self.context.display.get_framebuffer_dimensions(), pos),
+      None => pos,
+    }
+  }
+
+  /// Maps a position in the window (e.g. the mouse position) to world
+  /// coordinates - where something drawn in the main layer would have to be
+  /// drawn to appear at that position, given the camera (see set_camera())
+  /// and any screen shake. This is for layers without parallax - for a
+  /// layer with a parallax factor, scale the camera's position by it and
+  /// add it to window_to_virtual() instead.
+  pub fn window_to_world(&self, pos: [f32; 2]) -> [f32; 2] {
+    let pos = self.window_to_virtual(pos);
+    let (camera, offset) = (self.context.renderer.camera(), self.context.renderer.offset());
+    [pos[0] + camera[0] - offset[0], pos[1] + camera[1] - offset[1]]
+  }
+
+  /// The mouse position in world coordinates (see window_to_world()), from
+  /// the last events polled. None if the mouse is outside the window.
+  pub fn mouse_world_position(&self) -> Option<[f32; 2]> {
+    self.mouse_position().map(|pos| self.window_to_world(pos))
+  }
+
+  /// Starts drawing a frame. Draw with the frame's controller, then call its
+  /// finish() to receive and render everything drawn. This replaces calling
+  /// recv_data() then render() by hand, which are kept for compatibility.
+  pub fn begin_frame<'q>(&'q mut self) -> Frame<'q, 'a> {
+    Frame::new(self)
   }
 
   /// Receive all the data sent by renderer controllers. This should be called
-  /// before rendering to make sure the data is up to date.
+  /// before rendering to make sure the data is up to date. Shapes drawn with
+  /// the debug module are drawn here too, on top of everything else. Requests
+  /// made with asset loaders are fulfilled first.
   pub fn recv_data(&mut self) {
-    self.renderer.recv_data();
+    self.context.fulfil_asset_requests();
+    {
+      let mut overlay = self.context.renderer.get_overlay_controller(self.context.white_tex_handle);
+      debug::draw(&mut overlay);
+      if let Some(ref perf_overlay) = self.perf_overlay {
+        let stats = perf::PerfStats {
+          draw_calls: self.context.renderer.draw_call_count(),
+          skipped_batches: self.context.renderer.skipped_batch_count(),
+          batch_allocs: self.context.renderer.arena_stats().allocs,
+          batch_reuses: self.context.renderer.arena_stats().reuses,
+          packet_allocs: self.context.renderer.packet_stats().allocs,
+          packet_reuses: self.context.renderer.packet_stats().reuses,
+          tex_page_occupancy: self.context.renderer.tex_page_occupancy(),
+        };
+        perf_overlay.draw(&mut overlay, &stats);
+      }
+      if let (Some(cursor), Some(pos)) = (self.soft_cursor, self.mouse_pos.get()) {
+        if cursor.draw(&mut overlay, self.window_to_virtual(pos)).is_err() {
+          warn!("Soft cursor texture {:?} isn't in the cache", cursor.tex);
+        }
+      }
+      overlay.flush();
+    }
+    self.context.renderer.recv_data();
+  }
+
+  /// Render the data received in the last call to recv_data(). Anything
+  /// drawn with a texture which isn't in the cache is skipped, logged, and
+  /// counted in the performance overlay.
+  /// # Errors
+  /// Returns an error if a draw call fails, or if swapping the buffers fails.
+  /// Check QgfxError::is_recoverable() to tell errors which only spoil this
+  /// frame (e.g. whilst the window is being resized) from fatal ones.
+  pub fn render(&mut self) -> Result<(), QgfxError> {
+    let info = self.clock.tick();
+    self.render_frame(&info)
+  }
+
+  /// Renders a frame, as render(), with timing from a tick of self.clock.
+  fn render_frame(&mut self, info: &FrameInfo) -> Result<(), QgfxError> {
+    let offset = self.shake.as_mut().and_then(|shake| shake.advance(info.dt));
+    if offset.is_none() {
+      self.shake = None;
+    }
+    self.context.renderer.set_offset(offset.unwrap_or([0.0, 0.0]));
+    let mut target = self.context.display.draw();
+    self.context.renderer.get_clear().apply(&mut target);
+    let res = {
+      let (display, renderer, virtual_target, scaled_target) = 
+        (&self.context.display, &mut self.context.renderer, &self.virtual_target,
+         &mut self.scaled_target);
+      match self.post {
+        Some(ref mut post) => post.render(display, &mut target, renderer, |fb, renderer| {
+          draw_frame(display, renderer, virtual_target, scaled_target, fb)
+        }),
+        None => draw_frame(display, renderer, virtual_target, scaled_target, &mut target),
+      }
+    };
+    let res = res.and_then(|_| self.context.renderer.render_pick_buffer(&self.context.display));
+    if let Some(ref mut perf_overlay) = self.perf_overlay {
+      if info.frame > 0 {
+        perf_overlay.frame(info.dt);
+      }
+    }
+    // The frame must always be finished, even if rendering failed.
+    let finish_res = finish_frame(target);
+    self.context.renderer.set_frame_clear(None);
+    try!(res);
+    finish_res
   }
 
-  pub fn render(&mut self) {
+  /// Draws and displays a frame with a frame graph, instead of the data
+  /// received by recv_data(). Passes drawing to the screen are drawn
+  /// straight to the window, without the virtual resolution or
+  /// post-processing.
+  /// # Errors
+  /// Returns QgfxError::InvalidFrameGraph if the graph couldn't be
+  /// scheduled, or an error if a target couldn't be created, drawing failed
+  /// or swapping the buffers failed.
+  pub fn render_graph<'g>(&mut self, graph: &mut FrameGraph<'g, 'a>) -> Result<(), QgfxError> {
+    let mut target = self.context.display.draw();
+    let res = {
+      let context = &mut self.context;
+      context.renderer.render_graph(&context.display, context.white_tex_handle, graph, &mut target)
+    };
+    // The frame must always be finished, even if rendering failed.
+    let finish_res = finish_frame(target);
+    self.context.renderer.set_frame_clear(None);
+    try!(res);
+    finish_res
+  }
+
+  /// Does the work that would otherwise make the first frames hitch, so it
+  /// can be done behind a splash screen: draws shapes and textures offscreen,
+  /// which has the driver finish compiling shaders and uploading the first
+  /// cache pages, then renders a whole frame offscreen through all of the
+  /// enabled passes (virtual resolution, post-processing and picking), which
+  /// creates their textures and programs. Call this after setting up those
+  /// passes, and before the first call to recv_data(), as the frame it
+  /// renders is the data last received. Nothing is shown.
+  /// # Returns
+  /// How long each stage took.
+  /// # Errors
+  /// Returns an error if any of the offscreen drawing failed.
+  pub fn warm_up(&mut self) -> Result<WarmUpTimings, QgfxError> {
+    let start = Instant::now();
+    try!(self.render_to_image((16, 16), |controller| {
+      controller.rect(&[0.0, 0.0, 16.0, 16.0], &[1.0, 1.0, 1.0, 1.0]);
+    }));
+    let drawn = Instant::now();
+    try!(self.capture_frame());
+    try!(self.context.renderer.render_pick_buffer(&self.context.display));
+    let end = Instant::now();
+    let timings = WarmUpTimings {
+      draw: frame::secs(drawn - start),
+      frame: frame::secs(end - drawn),
+      total: frame::secs(end - start),
+    };
+    debug!("Warmed up in {:.3}s", timings.total);
+    Ok(timings)
+  }
+
+  /// Renders the data received in the last call to recv_data() into an
+  /// offscreen texture the size of the window, and reads it back, e.g. for
+  /// screenshots or comparing frames in tests. The frame is rendered exactly
+  /// as render() would, including post-processing, but isn't shown.
+  /// # Errors
+  /// Returns an error if the offscreen texture couldn't be created, or if
+  /// drawing failed.
+  pub fn capture_frame(&mut self) -> Result<RgbaBuffer, QgfxError> {
     use glium::Surface;
-    let mut target = self.display.draw();
-    target.clear_color(0.0, 0.0, 0.0, 0.0);
-    self.renderer.render(&mut target);
-    target.finish().unwrap();
+    let (w, h) = self.context.display.get_framebuffer_dimensions();
+    let tex = try!(glium::texture::Texture2d::empty_with_format(
+      &self.context.display, glium::texture::UncompressedFloatFormat::U8U8U8U8,
+      glium::texture::MipmapsOption::NoMipmap, w, h));
+    {
+      let mut fb = try!(glium::framebuffer::SimpleFrameBuffer::new(&self.context.display, &tex));
+      fb.clear_color(0.0, 0.0, 0.0, 0.0);
+      let (display, renderer, virtual_target, scaled_target) =
+        (&self.context.display, &mut self.context.renderer, &self.virtual_target,
+         &mut self.scaled_target);
+      try!(match self.post {
+        Some(ref mut post) => post.render(display, &mut fb, renderer, |target, renderer| {
+          draw_frame(display, renderer, virtual_target, scaled_target, target)
+        }),
+        None => draw_frame(display, renderer, virtual_target, scaled_target, &mut fb),
+      });
+    }
+    // Rows are read bottom first, but RgbaBuffer is top first.
+    let pixels: Vec<Vec<(u8, u8, u8, u8)>> = tex.read();
+    let mut data = Vec::with_capacity(w as usize * h as usize * 4);
+    for row in pixels.iter().rev() {
+      for &(r, g, b, a) in row {
+        data.extend_from_slice(&[r, g, b, a]);
+      }
+    }
+    Ok(RgbaBuffer::new(w, h, data).expect("Frame read back at the wrong size"))
+  }
+
+  /// Sets the render scale: frames are rendered offscreen at this multiple
+  /// of the window's resolution (or the virtual resolution, if there is one)
+  /// then scaled to the window. Scales above 1 supersample, smoothing edges
+  /// at the cost of drawing more pixels - 2.0 draws four times as many.
+  /// Scales below 1 draw fewer pixels, trading sharpness for speed. Renderer
+  /// controller coordinates are unchanged. Can be changed at any time.
+  /// Defaults to 1.0, which renders straight to the window.
+  ///
+  /// Supersampling a virtual resolution with nearest filtering (as with
+  /// QGFXBuilder::pixel_art()) doesn't smooth anything, as the target is
+  /// scaled to the window without blending pixels.
+  /// # Errors
+  /// Returns an error if the offscreen target for the virtual resolution
+  /// couldn't be created at the new size. Without a virtual resolution, the
+  /// target is created by render(), which returns any error instead.
+  /// # Panics
+  /// Panics if `scale` isn't positive.
+  pub fn set_render_scale(&mut self, scale: f32) -> Result<(), QgfxError> {
+    assert!(scale > 0.0, "Render scale must be positive, not {}", scale);
+    if let Some(ref mut vt) = self.virtual_target {
+      return vt.set_scale(&self.context.display, scale);
+    }
+    if scale == 1.0 {
+      self.scaled_target = None;
+    } else if self.render_scale() != scale {
+      let hdr = self.post.as_ref().and_then(|post| post.format());
+      self.scaled_target = Some(ScaledTarget::new(scale, hdr));
+    }
+    Ok(())
+  }
+
+  /// The render scale, as set by set_render_scale().
+  pub fn render_scale(&self) -> f32 {
+    match (&self.virtual_target, &self.scaled_target) {
+      (&Some(ref vt), _) => vt.scale(),
+      (&None, &Some(ref st)) => st.scale(),
+      (&None, &None) => 1.0,
+    }
+  }
+
+  /// Shakes the screen, offsetting everything drawn with renderer controllers
+  /// by a random amount each frame. The offset is up to `amplitude` pixels at
+  /// first, and decays to nothing over `secs` seconds. Debug drawing and the
+  /// performance overlay don't shake. A new shake replaces the current one.
+  pub fn shake(&mut self, amplitude: f32, secs: f32) {
+    let seed = self.deterministic.map_or(0, |d| d.seed);
+    self.shake = Some(shake::Shake::new(amplitude, secs, seed));
+  }
+
+  /// Enables or disables deterministic mode. In deterministic mode, every
+  /// frame is treated as `dt` seconds long - by time-based effects like
+  /// screen shake, the performance overlay, and the FrameInfo passed to
+  /// run()'s callback - and random effects use the seed. With the same input,
+  /// every run then renders the same frames, so headless runs can be compared
+  /// against golden images. Time restarts from 0 when this is called.
+  ///
+  /// Debug shapes drawn with a duration still expire in real time, so
+  /// avoid them in golden image tests.
+  pub fn set_deterministic(&mut self, deterministic: Option<Deterministic>) {
+    self.deterministic = deterministic;
+    self.clock.set_fixed_dt(deterministic.map(|d| d.dt));
+  }
+
+  /// Sets the camera's position, in pixels, scrolling everything drawn with
+  /// renderer controllers. (0, 0) shows the top left of the world at the top
+  /// left of the screen. To scroll background layers slower than the camera,
+  /// draw them with DrawOverrides::parallax() - e.g. a factor of 0.5 scrolls
+  /// half as far. Debug drawing and the performance overlay don't scroll.
+  pub fn set_camera(&mut self, pos: [f32; 2]) {
+    self.context.renderer.set_camera(pos)
+  }
+
+  /// The camera's position, as set by set_camera().
+  pub fn camera(&self) -> [f32; 2] {
+    self.context.renderer.camera()
+  }
+
+  /// Sets how HDR colours are mapped into the displayable range. Has no
+  /// effect unless HDR was enabled with QGFXBuilder::with_hdr().
+  pub fn set_tonemap(&mut self, tonemap: Tonemap) {
+    if let Some(ref mut post) = self.post {
+      post.set_tonemap(tonemap);
+    }
+  }
+
+  /// Sets the exposure HDR colours are multiplied by before tone mapping.
+  /// Defaults to 1.0. Has no effect unless HDR was enabled with
+  /// QGFXBuilder::with_hdr().
+  pub fn set_exposure(&mut self, exposure: f32) {
+    if let Some(ref mut post) = self.post {
+      post.set_exposure(exposure);
+    }
+  }
+
+  /// Sets a colour grading LUT, applied to the whole frame, for effects like
+  /// day/night tints and filmic looks. Pass None to remove it.
+  ///
+  /// The LUT is a cached texture in the standard strip layout: N tiles of N x
+  /// N pixels side by side (e.g. 256x16 for N = 16). Red increases from left
+  /// to right within each tile, green from top to bottom, and blue from tile
+  /// to tile. An unmodified strip leaves colours unchanged, so grade a
+  /// screenshot with one in an image editor to make a LUT. Don't allow
+  /// texture rotation when caching it.
+  /// # Returns
+  /// False (and removes the LUT) if the texture isn't cached, or isn't a
+  /// valid LUT.
+  /// # Errors
+  /// Returns an error if the offscreen target for post-processing couldn't
+  /// be created.
+  pub fn set_color_grading(&mut self, lut: Option<TexHandle>) -> Result<bool, QgfxError> {
+    if let Some(tex) = lut {
+      if self.context.renderer.color_grading_lut(tex).is_none() {
+        if let Some(ref mut post) = self.post {
+          post.set_color_grading(None);
+        }
+        return Ok(false);
+      }
+    }
+    if self.post.is_none() && lut.is_some() {
+      self.post = Some(try!(PostProcess::new(&self.context.display, None,
+                                             self.context.renderer.color_space())));
+    }
+    if let Some(ref mut post) = self.post {
+      post.set_color_grading(lut);
+    }
+    Ok(true)
+  }
+
+  /// Blurs the whole frame, e.g. behind a pause menu. Pass None to stop
+  /// blurring.
+  /// # Params
+  /// * `radius` - The blur radius in pixels, up to 32.
+  /// # Errors
+  /// Returns an error if the offscreen target for post-processing couldn't
+  /// be created.
+  pub fn set_blur(&mut self, radius: Option<f32>) -> Result<(), QgfxError> {
+    if self.post.is_none() && radius.is_some() {
+      self.post = Some(try!(PostProcess::new(&self.context.display, None,
+                                             self.context.renderer.color_space())));
+    }
+    if let Some(ref mut post) = self.post {
+      post.set_blur(radius);
+    }
+    Ok(())
+  }
+
+  /// Makes bright colours glow, by blurring the parts of the frame brighter
+  /// than a threshold and adding them back. Pass None to remove the bloom.
+  /// Bloom works best with HDR (see QGFXBuilder::with_hdr()), where colours
+  /// can be brighter than white.
+  /// # Errors
+  /// Returns an error if the offscreen target for post-processing couldn't
+  /// be created.
+  pub fn set_bloom(&mut self, bloom: Option<Bloom>) -> Result<(), QgfxError> {
+    if self.post.is_none() && bloom.is_some() {
+      self.post = Some(try!(PostProcess::new(&self.context.display, None,
+                                             self.context.renderer.color_space())));
+    }
+    if let Some(ref mut post) = self.post {
+      post.set_bloom(bloom);
+    }
+    Ok(())
+  }
+
+  /// Applies a CRT effect to the frame - curvature, scanlines, chromatic
+  /// aberration and a vignette - for a retro look. Pass None to remove it.
+  /// # Errors
+  /// Returns an error if the offscreen target for post-processing couldn't
+  /// be created.
+  pub fn set_crt(&mut self, crt: Option<Crt>) -> Result<(), QgfxError> {
+    if self.post.is_none() && crt.is_some() {
+      self.post = Some(try!(PostProcess::new(&self.context.display, None,
+                                             self.context.renderer.color_space())));
+    }
+    if let Some(ref mut post) = self.post {
+      post.set_crt(crt);
+    }
+    Ok(())
+  }
+
+  /// Pixelates the frame into blocks `block_size` pixels across, e.g. for
+  /// scene transitions or censoring. The block size can be animated by
+  /// calling this every frame. 1 or less removes the pixelation.
+  /// # Errors
+  /// Returns an error if the offscreen target for post-processing couldn't
+  /// be created.
+  pub fn set_pixelate(&mut self, block_size: f32) -> Result<(), QgfxError> {
+    if self.post.is_none() && block_size > 1.0 {
+      self.post = Some(try!(PostProcess::new(&self.context.display, None,
+                                             self.context.renderer.color_space())));
+    }
+    if let Some(ref mut post) = self.post {
+      post.set_pixelate(block_size);
+    }
+    Ok(())
+  }
+
+  /// Adds a hook, run at the start and end of every frame of run(). Hooks
+  /// run in the order they were added.
+  pub fn add_frame_hook(&mut self, hook: Box<FrameHook>) {
+    self.frame_hooks.push(hook);
+  }
+
+  /// Runs the main loop until `frame` returns false or the window is closed.
+  /// Each frame:
+  ///
+  /// 1. Events are polled.
+  /// 2. Every frame hook's pre_update() is called.
+  /// 3. `frame` is called with the frame's timing and events, to update and
+  ///    draw the frame. Flush any renderer controllers before it returns.
+  /// 4. The frame is received and rendered, as with recv_data() and render().
+  /// 5. Every frame hook's post_render() is called.
+  ///
+  /// Writing the loop by hand works just as well - this is for when the
+  /// crate should own it.
+  /// # Errors
+  /// Returns an error if rendering a frame fails, unless the error is
+  /// recoverable (see QgfxError::is_recoverable()), in which case it's
+  /// logged and the loop carries on with the next frame. Otherwise the loop
+  /// stops.
+  pub fn run<F>(&mut self, mut frame: F) -> Result<(), QgfxError>
+    where F: FnMut(&mut QGFX<'a>, &FrameInfo, &[QgfxEvent]) -> bool {
+    let mut events = Vec::new();
+    loop {
+      // The same clock as render(), so hooks see the frame the shake and
+      // performance overlay are timed by.
+      let info = self.clock.tick();
+      events.clear();
+      self.poll_events(|ev| events.push(ev));
+      let closed = events.iter().any(|ev| *ev == QgfxEvent::CloseRequested);
+      for hook in &mut self.frame_hooks {
+        hook.pre_update(&info);
+      }
+      let keep_running = frame(self, &info, &events);
+      self.recv_data();
+      try!(error::skip_recoverable(self.render_frame(&info)));
+      for hook in &mut self.frame_hooks {
+        hook.post_render(&info);
+      }
+      if closed || !keep_running {
+        return Ok(());
+      }
+    }
+  }
+
+  /// Shuts down the graphics layer: discards anything renderer controllers
+  /// have sent, hides the window, waits for the GPU to finish, then frees
+  /// every GPU resource (cached textures and glyphs, offscreen targets and
+  /// so on) and closes the window. Dropping QGFX does the same - this makes
+  /// the point it happens explicit. A new QGFX can be created afterwards.
+  ///
+  /// Renderer controllers don't hold any GPU resources, so they can safely
+  /// outlive QGFX. They can still draw and flush, but what they draw is
+  /// discarded - drop them too, as they keep their share of the texture and
+  /// glyph lookups alive.
+  pub fn shutdown(self) {
+    drop(self)
   }
 
   /// Poll events on this window. If there are any events available, call the
-  /// provided callback F with the given event as an argument.
+  /// provided callback F with the given event as an argument. Events with no
+  /// QgfxEvent equivalent are skipped - use poll_raw_events() to get every
+  /// event.
   /// This will lock the events loop inside this structure. It will panic if
   /// the mutex lock is poisoned. This is intentional (Rather a panic than
   /// something as crucial as an event loop erroring silently).
-  pub fn poll_events<F: FnMut(Event) -> ()>(&self, callback: F) {
-    self.events_loop.lock().unwrap().poll_events(callback)
+  pub fn poll_events<F: FnMut(QgfxEvent) -> ()>(&self, mut callback: F) {
+    self.events_loop.lock().unwrap().poll_events(|ev| if let Some(ev) = self.track_mouse(&ev) {
+      callback(ev)
+    })
   }
-}
 
-fn init_display() -> (Display, EventsLoop) {
-  // 1. The **winit::EventsLoop** for handling events.
-  let events_loop = glium::glutin::EventsLoop::new();
-
-  // 2. Parameters for building the Window.
-  let window = glium::glutin::WindowBuilder::new()
-    .with_dimensions(1024, 768)
-    .with_title("Hello world");
+  /// Poll events on this window, as the windowing library's own event type.
+  /// Raw events carry everything the windowing library reports, but change
+  /// with its version. Panics like poll_events().
+  pub fn poll_raw_events<F: FnMut(Event) -> ()>(&self, mut callback: F) {
+    self.events_loop.lock().unwrap().poll_events(|ev| {
+      self.track_mouse(&ev);
+      callback(ev)
+    })
+  }
 
-  // 3. Parameters for building the OpenGL context.
-  let context = glium::glutin::ContextBuilder::new();
+  /// Records the mouse position from an event, for mouse_position() and the
+  /// soft cursor.
+  /// # Returns
+  /// The event's QgfxEvent equivalent, if it has one.
+  fn track_mouse(&self, ev: &Event) -> Option<QgfxEvent> {
+    if let Event::WindowEvent { event: WindowEvent::CursorLeft { .. }, .. } = *ev {
+      self.mouse_pos.set(None);
+    }
+    let qgfx_ev = QgfxEvent::from_raw(ev);
+    if let Some(QgfxEvent::MouseMove { x, y }) = qgfx_ev {
+      self.mouse_pos.set(Some([x, y]));
+    }
+    qgfx_ev
+  }
+}
 
-  // 4. Build the Display with the given window and OpenGL context parameters and register the
-  //    window with the events_loop.
-  (glium::Display::new(window, context, &events_loop).unwrap(), events_loop)
+impl<'a> Drop for QGFX<'a> {
+  fn drop(&mut self) {
+    self.context.renderer.discard_data();
+    self.context.display.gl_window().hide();
+    // Make sure the GPU has finished with everything before it's freed.
+    self.context.display.finish();
+    debug!("Shut down QGFX");
+  }
 }
 
+/// Swaps the buffers to display a frame. A frame whose buffers were already
+/// swapped has been displayed, so that isn't an error.
+fn finish_frame(target: glium::Frame) -> Result<(), QgfxError> {
+  match target.finish() {
+    Err(glium::SwapBuffersError::AlreadySwapped) => {
+      debug!("The frame's buffers were already swapped");
+      Ok(())
+    }
+    res => Ok(try!(res)),
+  }
+}
 
+/// Renders a frame to `target`, through the virtual target if there is one,
+/// or the scaled target if there's a render scale.
+fn draw_frame<S: glium::Surface>(display: &Display, renderer: &mut Renderer, 
+                                 virtual_target: &Option<VirtualTarget>, 
+                                 scaled_target: &mut Option<ScaledTarget>,
+                                 target: &mut S) -> Result<(), QgfxError> {
+  match (virtual_target, scaled_target) {
+    (&Some(ref vt), _) => vt.render(display, renderer, target),
+    (&None, &mut Some(ref mut st)) => st.render(display, renderer, target),
+    (&None, &mut None) => renderer.render(target),
+  }
+}
@@ -0,0 +1,104 @@
+//! A module containing AssetLoader, for requesting textures and glyphs to be
+//! cached from any thread.
+
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
+use res::tex::{TexHandle, CacheTexError};
+#[cfg(feature = "font")]
+use res::font::{FontHandle, CacheGlyphError};
+
+/// A request queued by an AssetLoader, fulfilled by QGFX::recv_data().
+pub(crate) enum AssetRequest {
+  #[cfg(feature = "image")]
+  Tex(PathBuf, mpsc::Sender<Result<TexHandle, CacheTexError>>),
+  TexRgba(u32, u32, Vec<u8>, mpsc::Sender<Result<TexHandle, CacheTexError>>),
+  #[cfg(feature = "font")]
+  Glyphs(PathBuf, f32, Vec<char>, mpsc::Sender<Result<FontHandle, CacheGlyphError>>),
+}
+
+/// A handle for caching textures and glyphs from any thread. Caching needs
+/// the OpenGL context, so requests are queued and fulfilled on the main
+/// thread the next time QGFX::recv_data() is called. Get one with
+/// QGFX::asset_loader(). Loaders can be cloned and sent between threads.
+///
+/// ```ignore
+/// let loader = qgfx.asset_loader();
+/// thread::spawn(move || {
+///   let tex = loader.cache_tex("res/player.png").wait();
+/// });
+/// ```
+#[derive(Clone)]
+pub struct AssetLoader {
+  sender: Arc<Mutex<mpsc::Sender<AssetRequest>>>,
+}
+
+impl AssetLoader {
+  pub(crate) fn new(sender: mpsc::Sender<AssetRequest>) -> AssetLoader {
+    AssetLoader { sender: Arc::new(Mutex::new(sender)) }
+  }
+
+  /// Requests a texture file to be cached. See QGFX::cache_tex().
+  #[cfg(feature = "image")]
+  pub fn cache_tex<F: Into<PathBuf>>(&self, file: F) -> Pending<TexHandle, CacheTexError> {
+    let (sender, pending) = Pending::new();
+    self.send(AssetRequest::Tex(file.into(), sender));
+    pending
+  }
+
+  /// Requests raw RGBA data to be cached as a texture. See
+  /// QGFX::cache_tex_from_rgba().
+  pub fn cache_tex_from_rgba(&self, w: u32, h: u32, data: Vec<u8>)
+    -> Pending<TexHandle, CacheTexError> {
+    let (sender, pending) = Pending::new();
+    self.send(AssetRequest::TexRgba(w, h, data, sender));
+    pending
+  }
+
+  /// Requests glyphs from a font to be cached. See QGFX::cache_glyphs().
+  #[cfg(feature = "font")]
+  pub fn cache_glyphs<F: Into<PathBuf>>(&self, file: F, scale: f32, charset: &[char])
+    -> Pending<FontHandle, CacheGlyphError> {
+    let (sender, pending) = Pending::new();
+    self.send(AssetRequest::Glyphs(file.into(), scale, charset.to_vec(), sender));
+    pending
+  }
+
+  fn send(&self, request: AssetRequest) {
+    // If the QGFX has been dropped, the request's Pending never completes.
+    let _ = self.sender.lock().unwrap().send(request);
+  }
+}
+
+/// The result of a request made with an AssetLoader, which arrives once the
+/// main thread has fulfilled the request.
+pub struct Pending<T, E> {
+  receiver: mpsc::Receiver<Result<T, E>>,
+  result: Option<Result<T, E>>,
+}
+
+impl<T, E> Pending<T, E> {
+  fn new() -> (mpsc::Sender<Result<T, E>>, Pending<T, E>) {
+    let (sender, receiver) = mpsc::channel();
+    (sender, Pending { receiver: receiver, result: None })
+  }
+
+  /// Checks whether the request has been fulfilled, without blocking.
+  /// Returns the result once it has.
+  pub fn poll(&mut self) -> Option<&Result<T, E>> {
+    if self.result.is_none() {
+      self.result = self.receiver.try_recv().ok();
+    }
+    self.result.as_ref()
+  }
+
+  /// Blocks until the request has been fulfilled, and returns the result.
+  /// Don't call this on the main thread, as requests are fulfilled there.
+  /// # Returns
+  /// None if the QGFX was dropped before fulfilling the request.
+  pub fn wait(self) -> Option<Result<T, E>> {
+    match self.result {
+      Some(result) => Some(result),
+      None => self.receiver.recv().ok(),
+    }
+  }
+}
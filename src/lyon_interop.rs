@@ -0,0 +1,73 @@
+//! Drawing geometry tessellated by the lyon vector graphics library, for
+//! shapes beyond what Path handles - SVG-style arcs, anti-aliasing friendly
+//! tolerances, and lyon's more robust fill tessellator. Tessellate into
+//! VertexBuffers, then draw them with a RendererController:
+//!
+//! ```ignore
+//! let mut buffers: VertexBuffers<FillVertex, u16> = VertexBuffers::new();
+//! FillTessellator::new()
+//!   .tessellate_path(path.iter(), &FillOptions::default(), &mut simple_builder(&mut buffers))
+//!   .unwrap();
+//! lyon_interop::draw(&mut controller, &buffers, &[1.0, 0.5, 0.0, 1.0]);
+//! ```
+//!
+//! Buffers can hold FillVertex, StrokeVertex or plain points, with u16 or
+//! u32 indices. Only vertex positions are used.
+
+use lyon::math::Point;
+use lyon::tessellation::{FillVertex, StrokeVertex, VertexBuffers};
+use renderer::{RendererController, MeshVertex, RenderTextureError};
+use res::tex::TexHandle;
+
+/// A vertex produced by lyon's tessellators.
+pub trait LyonVertex {
+  /// The position of the vertex, in pixels.
+  fn position(&self) -> [f32; 2];
+}
+
+impl LyonVertex for Point {
+  fn position(&self) -> [f32; 2] { [self.x, self.y] }
+}
+
+impl LyonVertex for FillVertex {
+  fn position(&self) -> [f32; 2] { [self.position.x, self.position.y] }
+}
+
+impl LyonVertex for StrokeVertex {
+  fn position(&self) -> [f32; 2] { [self.position.x, self.position.y] }
+}
+
+/// Draws tessellated geometry in a single colour.
+pub fn draw<V, I>(controller: &mut RendererController, buffers: &VertexBuffers<V, I>,
+                  col: &[f32; 4])
+  where V: LyonVertex, I: Copy + Into<u32> {
+  let positions: Vec<[f32; 2]> = buffers.vertices.iter().map(|v| v.position()).collect();
+  let indices: Vec<u32> = buffers.indices.iter().map(|&ix| ix.into()).collect();
+  controller.solid_mesh(&positions, &indices, col);
+}
+
+/// Draws tessellated geometry filled with a texture, which is stretched over
+/// a rect, so the geometry shows the part of the texture under it. The
+/// texture's position in the cache is handled here, like
+/// RendererController::tex_mesh().
+/// # Params
+/// * `tex` - The texture to fill with.
+/// * `aabb` - Where the texture is stretched over - X, Y, W, H.
+/// * `tint` - The colour to tint the texture.
+/// # Errors
+/// Returns an error if the texture isn't in the cache.
+pub fn draw_textured<V, I>(controller: &mut RendererController, buffers: &VertexBuffers<V, I>,
+                           tex: TexHandle, aabb: &[f32; 4], tint: &[f32; 4])
+                           -> Result<(), RenderTextureError>
+  where V: LyonVertex, I: Copy + Into<u32> {
+  let vertices: Vec<MeshVertex> = buffers.vertices.iter().map(|v| {
+    let pos = v.position();
+    MeshVertex {
+      pos: pos,
+      uv: [(pos[0] - aabb[0]) / aabb[2], (pos[1] - aabb[1]) / aabb[3]],
+      col: *tint,
+    }
+  }).collect();
+  let indices: Vec<u32> = buffers.indices.iter().map(|&ix| ix.into()).collect();
+  controller.tex_mesh(tex, &vertices, &indices)
+}
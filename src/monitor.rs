@@ -0,0 +1,33 @@
+//! Monitor enumeration, for choosing which monitor to go fullscreen on. See
+//! QGFXBuilder::with_fullscreen().
+
+use glium::glutin::EventsLoop;
+
+/// A monitor connected to the system.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MonitorInfo {
+  /// The monitor's index, for QGFXBuilder::with_fullscreen().
+  pub index: usize,
+  /// The monitor's name, if the platform provides one.
+  pub name: Option<String>,
+  /// The monitor's resolution, in pixels.
+  pub dimensions: (u32, u32),
+}
+
+/// Lists the monitors connected to the system. The first is usually the
+/// primary monitor, but this depends on the platform.
+///
+/// The version of winit this crate uses doesn't report DPI, refresh rates or
+/// video modes, so fullscreen windows always use the monitor's current mode.
+pub fn available_monitors() -> Vec<MonitorInfo> {
+  list_monitors(&EventsLoop::new())
+}
+
+/// Lists the monitors known to an events loop.
+pub fn list_monitors(events_loop: &EventsLoop) -> Vec<MonitorInfo> {
+  events_loop.get_available_monitors().enumerate().map(|(ix, monitor)| MonitorInfo {
+    index: ix,
+    name: monitor.get_name(),
+    dimensions: monitor.get_dimensions(),
+  }).collect()
+}
@@ -0,0 +1,131 @@
+//! Named colours, and utilities for converting and deriving colours. Colours
+//! are `[f32; 4]` RGBA, matching the drawing functions.
+//!
+//! The named colours are sRGB, as colours usually are when they're picked in
+//! an image editor or written as hex. With ColorSpace::Srgb, the renderer
+//! uses vertex colours as they are. With ColorSpace::Linear it treats them as
+//! linear, so sRGB colours come out too bright unless they're converted with
+//! in_space() (or QGFX::color()) first:
+//!
+//! ```ignore
+//! let sky = palette::in_space(palette::CORNFLOWER_BLUE, qgfx.color_space());
+//! controller.rect(&[0.0, 0.0, 640.0, 480.0], &sky);
+//! ```
+//!
+//! tint(), shade() and mix() blend in linear space, so the results look
+//! evenly spaced, but take and return sRGB colours.
+
+use renderer::ColorSpace;
+
+pub const TRANSPARENT: [f32; 4] = [0.0, 0.0, 0.0, 0.0];
+pub const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+pub const GREY: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+pub const DARK_GREY: [f32; 4] = [0.25, 0.25, 0.25, 1.0];
+pub const LIGHT_GREY: [f32; 4] = [0.75, 0.75, 0.75, 1.0];
+pub const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+pub const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+pub const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+pub const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+pub const CYAN: [f32; 4] = [0.0, 1.0, 1.0, 1.0];
+pub const MAGENTA: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
+pub const ORANGE: [f32; 4] = [1.0, 0.647, 0.0, 1.0];
+pub const PURPLE: [f32; 4] = [0.502, 0.0, 0.502, 1.0];
+pub const PINK: [f32; 4] = [1.0, 0.753, 0.796, 1.0];
+pub const BROWN: [f32; 4] = [0.647, 0.165, 0.165, 1.0];
+pub const CORNFLOWER_BLUE: [f32; 4] = [0.392, 0.584, 0.929, 1.0];
+
+/// Converts an sRGB channel value (0 to 1) to linear.
+pub fn srgb_to_linear(c: f32) -> f32 {
+  if c <= 0.04045 {
+    c / 12.92
+  } else {
+    ((c + 0.055) / 1.055).powf(2.4)
+  }
+}
+
+/// Converts a linear channel value (0 to 1) to sRGB.
+pub fn linear_to_srgb(c: f32) -> f32 {
+  if c <= 0.0031308 {
+    c * 12.92
+  } else {
+    1.055 * c.powf(1.0 / 2.4) - 0.055
+  }
+}
+
+/// Converts an sRGB colour to linear. Alpha is unchanged.
+pub fn to_linear(color: [f32; 4]) -> [f32; 4] {
+  [srgb_to_linear(color[0]), srgb_to_linear(color[1]), srgb_to_linear(color[2]), color[3]]
+}
+
+/// Converts a linear colour to sRGB. Alpha is unchanged.
+pub fn to_srgb(color: [f32; 4]) -> [f32; 4] {
+  [linear_to_srgb(color[0]), linear_to_srgb(color[1]), linear_to_srgb(color[2]), color[3]]
+}
+
+/// Converts an sRGB colour to the form the renderer expects vertex colours
+/// in for the given colour space, so it looks the same in either.
+pub fn in_space(color: [f32; 4], space: ColorSpace) -> [f32; 4] {
+  match space {
+    ColorSpace::Srgb => color,
+    ColorSpace::Linear => to_linear(color),
+  }
+}
+
+/// An sRGB colour from a hex value, like 0x6495ED. Alpha is 1.
+pub fn from_hex(hex: u32) -> [f32; 4] {
+  let channel = |shift: u32| ((hex >> shift) & 0xff) as f32 / 255.0;
+  [channel(16), channel(8), channel(0), 1.0]
+}
+
+/// Mixes two sRGB colours, from `a` at t = 0 to `b` at t = 1. Colours are
+/// mixed in linear space, and alpha is mixed linearly.
+pub fn mix(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+  let (a, b) = (to_linear(a), to_linear(b));
+  let channel = |i: usize| a[i] + (b[i] - a[i]) * t;
+  to_srgb([channel(0), channel(1), channel(2), channel(3)])
+}
+
+/// Lightens an sRGB colour towards white, by `amount` from 0 (unchanged) to
+/// 1 (white). Alpha is unchanged.
+pub fn tint(color: [f32; 4], amount: f32) -> [f32; 4] {
+  with_alpha(mix(color, WHITE, amount), color[3])
+}
+
+/// Darkens an sRGB colour towards black, by `amount` from 0 (unchanged) to
+/// 1 (black). Alpha is unchanged.
+pub fn shade(color: [f32; 4], amount: f32) -> [f32; 4] {
+  with_alpha(mix(color, BLACK, amount), color[3])
+}
+
+/// A colour with its alpha replaced.
+pub fn with_alpha(color: [f32; 4], alpha: f32) -> [f32; 4] {
+  [color[0], color[1], color[2], alpha]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn close(a: [f32; 4], b: [f32; 4]) -> bool {
+    a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-4)
+  }
+
+  #[test]
+  fn conversions_round_trip() {
+    for &color in &[CORNFLOWER_BLUE, ORANGE, GREY, BLACK, WHITE] {
+      assert!(close(to_srgb(to_linear(color)), color), "{:?}", color);
+    }
+    assert!((srgb_to_linear(0.5) - 0.2140).abs() < 1e-3);
+    assert_eq!(in_space(GREY, ColorSpace::Srgb), GREY);
+  }
+
+  #[test]
+  fn tints_and_shades_reach_white_and_black() {
+    let half_red = with_alpha(RED, 0.5);
+    assert!(close(tint(half_red, 1.0), with_alpha(WHITE, 0.5)));
+    assert!(close(shade(half_red, 1.0), with_alpha(BLACK, 0.5)));
+    assert!(close(tint(half_red, 0.0), half_red));
+    assert!(close(from_hex(0x6495ED), [100.0 / 255.0, 149.0 / 255.0, 237.0 / 255.0, 1.0]));
+  }
+}
@@ -0,0 +1,184 @@
+//! The performance overlay, shown with QGFX::show_perf_overlay(). This draws
+//! the FPS, a graph of recent frame times, the number of draw calls and the
+//! texture cache occupancy in the top left of the display.
+//!
+//! The overlay's text is drawn with a tiny built-in bitmap font made of rects,
+//! so it works without any fonts being cached (or without the font feature).
+
+use std::collections::VecDeque;
+use renderer::RendererController;
+
+/// The number of frames shown in the frame time graph.
+const GRAPH_FRAMES: usize = 120;
+/// The size of a bitmap font pixel, in display pixels.
+const PIXEL_SIZE: f32 = 2.0;
+/// The height of the frame time graph in pixels. One pixel is one millisecond.
+const GRAPH_HEIGHT: f32 = 50.0;
+/// The frame time marked on the graph, in milliseconds.
+const TARGET_FRAME_MS: f32 = 1000.0 / 60.0;
+const PADDING: f32 = 4.0;
+
+const BG_COL: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const TEXT_COL: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const BAR_COL: [f32; 4] = [0.3, 0.8, 0.3, 1.0];
+const SLOW_BAR_COL: [f32; 4] = [0.9, 0.3, 0.2, 1.0];
+const TARGET_COL: [f32; 4] = [1.0, 1.0, 1.0, 0.4];
+
+/// The stats shown in the overlay, other than frame times.
+pub struct PerfStats {
+  pub draw_calls: usize,
+  /// The number of batches skipped because their texture wasn't cached.
+  pub skipped_batches: usize,
+  /// The number of vertex batch buffers allocated, and reused, this frame.
+  pub batch_allocs: usize,
+  pub batch_reuses: usize,
+  /// The number of vertex packets controllers allocated, and reused, this
+  /// frame.
+  pub packet_allocs: usize,
+  pub packet_reuses: usize,
+  /// The occupancy of each texture cache page, from 0.0 to 1.0.
+  pub tex_page_occupancy: Vec<f32>,
+}
+
+/// Records frame times, and draws the overlay.
+pub struct PerfOverlay {
+  /// The most recent frame times, in milliseconds, oldest first.
+  frame_times: VecDeque<f32>,
+}
+
+impl PerfOverlay {
+  pub fn new() -> PerfOverlay {
+    PerfOverlay {
+      frame_times: VecDeque::with_capacity(GRAPH_FRAMES),
+    }
+  }
+
+  /// Records the time since the last frame, in seconds. Call this once per
+  /// frame, after the first.
+  pub fn frame(&mut self, dt: f32) {
+    if self.frame_times.len() == GRAPH_FRAMES {
+      self.frame_times.pop_front();
+    }
+    self.frame_times.push_back(dt * 1000.0);
+  }
+
+  /// The mean frame time over the graph, in milliseconds.
+  fn mean_frame_time(&self) -> f32 {
+    if self.frame_times.is_empty() { return 0.0; }
+    self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+  }
+
+  pub fn draw(&self, controller: &mut RendererController, stats: &PerfStats) {
+    let mean = self.mean_frame_time();
+    let fps = if mean > 0.0 { 1000.0 / mean } else { 0.0 };
+    let occupancy = if stats.tex_page_occupancy.is_empty() {
+      0.0
+    } else {
+      stats.tex_page_occupancy.iter().sum::<f32>() / stats.tex_page_occupancy.len() as f32
+    };
+    let mut lines = vec![
+      format!("FPS {:.1}", fps),
+      format!("FRAME {:.2}MS", mean),
+      format!("DRAWS {}", stats.draw_calls),
+      format!("TEX PAGES {} {:.0}%", stats.tex_page_occupancy.len(), occupancy * 100.0),
+      format!("ALLOCS {} REUSES {}", stats.batch_allocs, stats.batch_reuses),
+      format!("PACKETS {} REUSES {}", stats.packet_allocs, stats.packet_reuses),
+    ];
+    if stats.skipped_batches > 0 {
+      lines.push(format!("SKIPPED {}", stats.skipped_batches));
+    }
+
+    let line_h = (GLYPH_H as f32 + 2.0) * PIXEL_SIZE;
+    let graph_w = GRAPH_FRAMES as f32;
+    let text_h = line_h * lines.len() as f32;
+    controller.rect(&[0.0, 0.0, graph_w + PADDING * 2.0, text_h + GRAPH_HEIGHT + PADDING * 3.0],
+                    &BG_COL);
+    for (ii, line) in lines.iter().enumerate() {
+      draw_bitmap_text(controller, line, [PADDING, PADDING + line_h * ii as f32]);
+    }
+
+    // Frame time graph, newest frame on the right.
+    let graph_bottom = text_h + GRAPH_HEIGHT + PADDING * 2.0;
+    let x0 = PADDING + graph_w - self.frame_times.len() as f32;
+    for (ii, &ms) in self.frame_times.iter().enumerate() {
+      let h = ms.min(GRAPH_HEIGHT);
+      let col = if ms > TARGET_FRAME_MS * 1.5 { SLOW_BAR_COL } else { BAR_COL };
+      controller.rect(&[x0 + ii as f32, graph_bottom - h, 1.0, h], &col);
+    }
+    controller.rect(&[PADDING, graph_bottom - TARGET_FRAME_MS, graph_w, 1.0], &TARGET_COL);
+  }
+}
+
+const GLYPH_W: usize = 3;
+const GLYPH_H: usize = 5;
+
+/// Looks up a char in the bitmap font. Each row is 3 bits, most significant
+/// bit on the left. Only the chars the overlay needs are included.
+fn bitmap_glyph(c: char) -> Option<[u8; GLYPH_H]> {
+  Some(match c {
+    '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+    '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+    '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+    '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+    '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+    '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+    '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+    '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+    '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+    '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+    '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+    '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+    'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+    'C' => [0b111, 0b100, 0b100, 0b100, 0b111],
+    'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+    'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+    'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+    'G' => [0b111, 0b100, 0b101, 0b101, 0b111],
+    'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+    'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+    'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+    'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+    'O' => [0b111, 0b101, 0b101, 0b101, 0b111],
+    'P' => [0b111, 0b101, 0b111, 0b100, 0b100],
+    'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+    'S' => [0b111, 0b100, 0b111, 0b001, 0b111],
+    'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+    'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+    'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+    'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+    _ => return None,
+  })
+}
+
+/// Draws text with the bitmap font, with its top left at `pos`. Chars not in
+/// the font are drawn as spaces.
+fn draw_bitmap_text(controller: &mut RendererController, text: &str, pos: [f32; 2]) {
+  let advance = (GLYPH_W + 1) as f32 * PIXEL_SIZE;
+  for (ii, c) in text.chars().enumerate() {
+    let rows = match bitmap_glyph(c) {
+      Some(rows) => rows,
+      None => continue,
+    };
+    let x = pos[0] + advance * ii as f32;
+    for (row, bits) in rows.iter().enumerate() {
+      for col in 0..GLYPH_W {
+        if bits & (1 << (GLYPH_W - 1 - col)) != 0 {
+          controller.rect(&[x + col as f32 * PIXEL_SIZE, pos[1] + row as f32 * PIXEL_SIZE,
+                            PIXEL_SIZE, PIXEL_SIZE], &TEXT_COL);
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn bitmap_font_covers_overlay_text() {
+    for c in "FPS FRAME MS DRAWS TEX PAGES ALLOCS REUSES PACKETS 0123456789.%".chars() {
+      assert!(c == ' ' || bitmap_glyph(c).is_some(), "{:?} missing from bitmap font", c);
+    }
+  }
+}
@@ -0,0 +1,172 @@
+use renderer::{Vertex, VertexPacket};
+
+/// The maximum number of empty vertex packets kept in a PacketArena.
+const MAX_FREE_PACKETS: usize = 16;
+
+/// A pool of vertex batch buffers, reused from frame to frame. Batches are
+/// padded to VBO_SIZE, so allocating them fresh every frame means several
+/// large allocations per frame. Instead, last frame's batches are recycled
+/// into the arena when new data is received, and new batches are taken from
+/// it.
+///
+/// Only as many buffers as were handed out since the last reset_stats() are
+/// kept, so a spike in batches isn't held on to forever.
+pub struct VertexArena {
+    /// Empty buffers, ready to be reused.
+    free: Vec<Vec<Vertex>>,
+    /// Allocation stats since the last call to reset_stats().
+    stats: ArenaStats,
+}
+
+/// The vertex packets controllers draw into. A controller takes a packet
+/// when it's created and every time it flushes, and recv_data() returns
+/// packets here once it's copied their vertices into batches, so a frame's
+/// packets reuse the memory of the last frame's.
+pub struct PacketArena {
+    /// Empty packets, ready to be reused.
+    free: Vec<VertexPacket>,
+    /// Allocation stats since the last call to take_stats().
+    stats: ArenaStats,
+}
+
+/// How many buffers an arena allocated, and how many it reused, in a frame.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ArenaStats {
+    /// Buffers which had to be allocated, as the arena was empty.
+    pub allocs: usize,
+    /// Buffers which were reused from an earlier frame.
+    pub reuses: usize,
+}
+
+impl VertexArena {
+    pub fn new() -> VertexArena {
+        VertexArena {
+            free: Vec::new(),
+            stats: ArenaStats::default(),
+        }
+    }
+
+    /// Takes an empty buffer from the arena, allocating one if there are
+    /// none free.
+    pub fn alloc(&mut self) -> Vec<Vertex> {
+        match self.free.pop() {
+            Some(buf) => {
+                self.stats.reuses += 1;
+                buf
+            }
+            None => {
+                self.stats.allocs += 1;
+                Vec::new()
+            }
+        }
+    }
+
+    /// Returns buffers to the arena, to be reused. Their memory is kept, up
+    /// to the number of buffers handed out since the last reset_stats() -
+    /// any more than that are freed.
+    pub fn recycle<I: IntoIterator<Item = Vec<Vertex>>>(&mut self, bufs: I) {
+        for mut buf in bufs {
+            buf.clear();
+            self.free.push(buf);
+        }
+        let keep = self.stats.allocs + self.stats.reuses;
+        self.free.truncate(keep);
+    }
+
+    /// The allocation stats since the last call to reset_stats().
+    pub fn stats(&self) -> ArenaStats {
+        self.stats
+    }
+
+    /// Resets the allocation stats. Call this at the start of each frame.
+    pub fn reset_stats(&mut self) {
+        self.stats = ArenaStats::default();
+    }
+}
+
+impl PacketArena {
+    pub fn new() -> PacketArena {
+        PacketArena {
+            free: Vec::new(),
+            stats: ArenaStats::default(),
+        }
+    }
+
+    /// Takes an empty packet from the arena, allocating one if there are
+    /// none free.
+    pub fn alloc(&mut self) -> VertexPacket {
+        match self.free.pop() {
+            Some(packet) => {
+                self.stats.reuses += 1;
+                packet
+            }
+            None => {
+                self.stats.allocs += 1;
+                VertexPacket::new()
+            }
+        }
+    }
+
+    /// Returns a packet to the arena, to be reused. Its memory is kept,
+    /// unless the arena already has MAX_FREE_PACKETS free.
+    pub fn recycle(&mut self, mut packet: VertexPacket) {
+        if self.free.len() < MAX_FREE_PACKETS {
+            packet.clear();
+            self.free.push(packet);
+        }
+    }
+
+    /// Frees every packet waiting to be reused.
+    pub fn clear(&mut self) {
+        self.free.clear();
+    }
+
+    /// Returns the allocation stats since this was last called, and resets
+    /// them.
+    pub fn take_stats(&mut self) -> ArenaStats {
+        use std::mem::replace;
+        replace(&mut self.stats, ArenaStats::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recycled_buffers_are_reused() {
+        let mut arena = VertexArena::new();
+        let mut buf = arena.alloc();
+        buf.reserve(16);
+        arena.recycle(vec![buf]);
+        let buf = arena.alloc();
+        assert!(buf.is_empty() && buf.capacity() >= 16);
+        assert_eq!(arena.stats(), ArenaStats { allocs: 1, reuses: 1 });
+    }
+
+    #[test]
+    fn packets_are_reused_and_counted() {
+        let mut arena = PacketArena::new();
+        let mut packet = arena.alloc();
+        packet.vertices.reserve(16);
+        arena.recycle(packet);
+        let packet = arena.alloc();
+        assert!(packet.vertices.is_empty() && packet.vertices.capacity() >= 16);
+        assert_eq!(arena.take_stats(), ArenaStats { allocs: 1, reuses: 1 });
+        assert_eq!(arena.take_stats(), ArenaStats::default());
+    }
+
+    #[test]
+    fn retained_buffers_are_capped_to_last_frame() {
+        let mut arena = VertexArena::new();
+        let bufs: Vec<_> = (0..8).map(|_| arena.alloc()).collect();
+        arena.recycle(bufs);
+        arena.reset_stats();
+        assert_eq!(arena.free.len(), 8);
+
+        // A quieter frame only keeps as many buffers as it used.
+        let bufs: Vec<_> = (0..2).map(|_| arena.alloc()).collect();
+        arena.recycle(bufs);
+        assert_eq!(arena.free.len(), 2);
+    }
+}
@@ -0,0 +1,210 @@
+use renderer::{Vertex, VertexPacket, GlyphInstance, BatchKey};
+#[cfg(feature = "font")]
+use renderer::TexType;
+
+/// An affine transform, as the top 2 rows of a 3x3 matrix. A point [x, y]
+/// is transformed to [m[0][0] * x + m[0][1] * y + m[0][2], m[1][0] * x +
+/// m[1][1] * y + m[1][2]].
+pub type Affine = [[f32; 3]; 2];
+
+/// The transform which leaves points where they are.
+pub const IDENTITY: Affine = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+/// A sequence of controller calls, recorded with RendererController::record()
+/// and replayed with RendererController::replay(). Replaying is a copy of the
+/// recorded geometry, so semi-static things like UI panels can be laid out
+/// and tessellated once, then drawn every frame for little more than the cost
+/// of the copy.
+///
+/// The list refers to textures and glyphs by where they were in the cache
+/// when it was recorded. Record it again after uncaching textures it draws,
+/// or after the font cache is rebuilt.
+///
+/// ```ignore
+/// let panel = controller.record(|c| {
+///   c.rect(&[0.0, 0.0, 200.0, 100.0], &[0.1, 0.1, 0.1, 1.0]);
+///   c.text("Inventory", &[8.0, 20.0], font, &[1.0; 4]).unwrap();
+/// });
+/// // Each frame:
+/// controller.replay_with(&panel, &translation(x, y), &[1.0, 1.0, 1.0, 0.5]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CommandList {
+    packet: VertexPacket,
+}
+
+impl CommandList {
+    pub fn new(packet: VertexPacket) -> CommandList {
+        CommandList { packet: packet }
+    }
+
+    /// True if nothing was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.packet.vertices.is_empty() && self.packet.glyphs.is_empty()
+    }
+
+    /// The number of vertices recorded, counting each instanced glyph as 6.
+    pub fn vertex_count(&self) -> usize {
+        self.packet.vertices.len() + self.packet.glyphs.len() * 6
+    }
+
+    /// Appends the recorded geometry to `packet`, transformed and with its
+    /// colour multiplied by `tint`.
+    pub fn append_to(&self, packet: &mut VertexPacket, transform: &Affine, tint: &[f32; 4]) {
+        let src = &self.packet;
+        packet.vertices.reserve(src.vertices.len());
+        for (key, run) in runs(&src.runs, &src.vertices) {
+            packet.set_key(key);
+            packet.vertices.extend(run.iter().map(|v| {
+                Vertex {
+                    pos: transform_point(transform, &v.pos),
+                    col: tint_color(&v.col, tint),
+                    ..*v
+                }
+            }));
+        }
+        // Glyph quads stay axis aligned under a scale and translation, so
+        // they can stay instances. Anything else is drawn as vertices.
+        let axis_aligned = transform[0][1] == 0.0 && transform[1][0] == 0.0;
+        for (draw_overrides, run) in runs(&src.glyph_runs, &src.glyphs) {
+            if axis_aligned {
+                packet.set_glyph_overrides(draw_overrides);
+                packet.glyphs.extend(run.iter().map(|g| {
+                    scale_glyph(g, transform, tint)
+                }));
+            } else {
+                packet.set_key(glyph_key(draw_overrides));
+                for g in run {
+                    packet.vertices.extend(g.vertices().iter().map(|v| {
+                        Vertex {
+                            pos: transform_point(transform, &v.pos),
+                            col: tint_color(&v.col, tint),
+                            ..*v
+                        }
+                    }));
+                }
+            }
+        }
+    }
+}
+
+/// A transform which moves points by x, y.
+pub fn translation(x: f32, y: f32) -> Affine {
+    [[1.0, 0.0, x], [0.0, 1.0, y]]
+}
+
+/// A transform which scales points about the origin by sx, sy, then moves
+/// them by x, y.
+pub fn scale_translation(sx: f32, sy: f32, x: f32, y: f32) -> Affine {
+    [[sx, 0.0, x], [0.0, sy, y]]
+}
+
+/// Splits `items` into its runs, each with the key given in `runs`. See
+/// VertexPacket::runs.
+fn runs<'a, K: Copy, T>(runs: &'a [(K, usize)], items: &'a [T]) -> Vec<(K, &'a [T])> {
+    runs.iter()
+        .enumerate()
+        .map(|(ii, &(key, start))| {
+            let end = runs.get(ii + 1).map_or(items.len(), |&(_, next)| next);
+            (key, &items[start..end])
+        })
+        .filter(|&(_, run)| !run.is_empty())
+        .collect()
+}
+
+fn transform_point(m: &Affine, p: &[f32; 2]) -> [f32; 2] {
+    [
+        m[0][0] * p[0] + m[0][1] * p[1] + m[0][2],
+        m[1][0] * p[0] + m[1][1] * p[1] + m[1][2],
+    ]
+}
+
+fn tint_color(col: &[u8; 4], tint: &[f32; 4]) -> [u8; 4] {
+    let mul = |c: u8, t: f32| (c as f32 * t.max(0.0).min(1.0) + 0.5) as u8;
+    [mul(col[0], tint[0]), mul(col[1], tint[1]), mul(col[2], tint[2]), mul(col[3], tint[3])]
+}
+
+/// Transforms an instanced glyph by a transform with no rotation or skew.
+/// The shear is scaled too, so faux italics keep their slant on screen.
+fn scale_glyph(g: &GlyphInstance, m: &Affine, tint: &[f32; 4]) -> GlyphInstance {
+    let (sx, sy) = (m[0][0], m[1][1]);
+    let tl = transform_point(m, &[g.rect[0], g.rect[1]]);
+    let br = transform_point(m, &[g.rect[2], g.rect[3]]);
+    GlyphInstance {
+        rect: [tl[0], tl[1], br[0], br[1]],
+        uv: g.uv,
+        col: tint_color(&g.col, tint),
+        shear: [g.shear[0] * sy + m[1][2], g.shear[1] * sx / sy],
+    }
+}
+
+/// The key glyphs are batched with when they're drawn as vertices.
+#[cfg(feature = "font")]
+fn glyph_key(draw_overrides: usize) -> BatchKey {
+    BatchKey {
+        tex_type: TexType::Font,
+        tex_ix: 0,
+        draw_overrides: draw_overrides,
+    }
+}
+
+/// Without the font subsystem, nothing is recorded as instanced glyphs.
+#[cfg(not(feature = "font"))]
+fn glyph_key(_: usize) -> BatchKey {
+    unreachable!("Instanced glyphs recorded without the font subsystem")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use renderer::TexType;
+
+    fn vertex(pos: [f32; 2]) -> Vertex {
+        Vertex {
+            pos: pos,
+            col: [255, 255, 255, 255],
+            tex_coords: [0.0, 0.0],
+            pick_id: 0.0,
+        }
+    }
+
+    #[test]
+    fn replay_transforms_and_tints_runs() {
+        let key = |tex_ix| BatchKey {
+            tex_type: TexType::Texture,
+            tex_ix: tex_ix,
+            draw_overrides: 0,
+        };
+        let mut recorded = VertexPacket::new();
+        recorded.set_key(key(0));
+        recorded.vertices.push(vertex([1.0, 2.0]));
+        recorded.set_key(key(1));
+        recorded.vertices.push(vertex([3.0, 4.0]));
+        let list = CommandList::new(recorded);
+
+        let mut packet = VertexPacket::new();
+        packet.set_key(key(1));
+        packet.vertices.push(vertex([0.0, 0.0]));
+        let m = scale_translation(2.0, 2.0, 10.0, 0.0);
+        list.append_to(&mut packet, &m, &[1.0, 0.5, 0.0, 1.0]);
+        let pos: Vec<[f32; 2]> = packet.vertices.iter().map(|v| v.pos).collect();
+        assert_eq!(pos, vec![[0.0, 0.0], [12.0, 4.0], [16.0, 8.0]]);
+        assert_eq!(packet.vertices[1].col, [255, 128, 0, 255]);
+        assert_eq!(packet.runs, vec![(key(1), 0), (key(0), 1), (key(1), 2)]);
+    }
+
+    #[test]
+    fn scaled_glyphs_keep_their_slant() {
+        let glyph = GlyphInstance {
+            rect: [10.0, 0.0, 20.0, 10.0],
+            uv: [0.0, 0.0, 1.0, 1.0],
+            col: [255; 4],
+            shear: [10.0, 0.5],
+        };
+        let m = scale_translation(2.0, 4.0, 5.0, 7.0);
+        let scaled = scale_glyph(&glyph, &m, &[1.0; 4]);
+        for (v, s) in glyph.vertices().iter().zip(scaled.vertices().iter()) {
+            assert_eq!(transform_point(&m, &v.pos), s.pos);
+        }
+    }
+}
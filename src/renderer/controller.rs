@@ -1,12 +1,18 @@
-use renderer::{Vertex, TexType};
+use renderer::{Vertex, VertexPacket, GlyphInstance, BatchKey, TexType, DrawOverridesHandle,
+               TessellationCache, BufferPool, Gradient, StrokeStyle, Path, FillStyle, CommandList,
+               Affine, IDENTITY, pack_color};
+use renderer::path::curve_segments;
 use std;
-use std::sync::{mpsc, Arc};
+use std::sync::mpsc;
+use std::sync::Arc;
+#[cfg(feature = "font")]
 use res::font::glium_cache::GliumGlyphLookup;
-use res::font::{self, FontHandle, CacheReadError};
-use res::tex::{TexHandle, TexHandleLookup};
+#[cfg(feature = "font")]
+use res::font::{FontHandle, CacheReadError, TextAnchor, TextLayout, TextStyle, TextRun,
+                GlyphPlacement, MissingGlyphPolicy, MissingGlyphsError};
+use res::tex::{TexHandle, TexHandleLookup, UNTRIMMED};
 use res::tex::glium_cache::GliumTexHandleLookup;
-use vec::Vec2;
-use rusttype::Scale;
+use geometry;
 use std::marker::PhantomData;
 
 #[derive(Copy, Clone, Hash, Debug)]
@@ -22,6 +28,7 @@ impl std::error::Error for RenderTextureError {
         "Texture rendering failed - texture wasn't cached."
     }
 }
+#[cfg(feature = "font")]
 impl std::convert::From<CacheReadError> for RenderTextureError {
     fn from(_: CacheReadError) -> Self {
         RenderTextureError
@@ -29,55 +36,227 @@ impl std::convert::From<CacheReadError> for RenderTextureError {
 }
 
 
-/// This struct wraps a Sender<Vec<Vertex>>, and has convenience methods to
+/// A vertex of a mesh drawn with RendererController::tex_mesh().
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MeshVertex {
+    /// The position of the vertex, in pixels.
+    pub pos: [f32; 2],
+    /// The texture coordinate, from 0,0 at the top left of the texture to 1,1
+    /// at the bottom right.
+    pub uv: [f32; 2],
+    /// The colour to tint the texture at this vertex.
+    pub col: [f32; 4],
+}
+
+/// This struct wraps a Sender<VertexPacket>, and has convenience methods to
 /// draw certain geometry.
 #[derive(Clone)]
-pub struct RendererController<
-    'a,
-    GlyphLookup: 'a + font::GlyphLookup + Send + Sync = Arc<GliumGlyphLookup<'a>>,
-    TexLookup: TexHandleLookup + Send + Sync = GliumTexHandleLookup,
-> {
-    font_cache: GlyphLookup,
+pub struct RendererController<'a, TexLookup: TexHandleLookup + Send + Sync = GliumTexHandleLookup> {
+    #[cfg(feature = "font")]
+    font_cache: Arc<GliumGlyphLookup<'a>>,
+    #[cfg(feature = "font")]
+    missing_glyph_policy: MissingGlyphPolicy,
+    /// If true, glyphs are drawn as GlyphInstances. See set_instanced_text().
+    #[cfg(feature = "font")]
+    instanced_text: bool,
     tex_cache: TexLookup,
     white: TexHandle,
-    sender: mpsc::Sender<Vec<Vertex>>,
+    sender: mpsc::Sender<VertexPacket>,
     /// A buffer for vertices. When flush() is called, these will be sent with sender.
-    buffer: Vec<Vertex>,
-    phantom: PhantomData<&'a GlyphLookup>,
+    buffer: VertexPacket,
+    /// The pick ID given to vertices drawn from now on. See set_pick_id().
+    pick_id: u32,
+    /// The draw overrides everything drawn from now on is drawn with, as
+    /// stored in BatchKey::draw_overrides. See set_draw_overrides().
+    draw_overrides: usize,
+    /// The number of vertices at the start of `buffer` which have been given
+    /// their pick ID.
+    tagged_len: usize,
+    /// The number of screen pixels per unit, used to choose the number of
+    /// segments for circles. See set_tessellation_scale().
+    tessellation_scale: f32,
+    /// Unit geometry shared with the renderer's other controllers.
+    tessellation: Arc<TessellationCache>,
+    /// Empty buffers the renderer has finished with, to replace `buffer` with
+    /// when flushing.
+    buffer_pool: BufferPool,
+    /// The stream flushed buffers are submitted on. See set_stream().
+    stream: Option<u32>,
+    /// The sequence number of the next buffer flushed on the stream.
+    sequence: u64,
+    phantom: PhantomData<&'a ()>,
 }
 
-impl<'a, GlyphLookup: font::GlyphLookup + Send + Sync, TexLookup: TexHandleLookup + Send + Sync>
-    RendererController<'a, GlyphLookup, TexLookup> {
+impl<'a, TexLookup: TexHandleLookup + Send + Sync> RendererController<'a, TexLookup> {
     /// Creates a new renderer controller with a given mpsc sender. If you want
     /// to get a renderer controller, look at the
     /// renderer::Renderer::get_renderer_controller() function.
     pub fn new(
-        sender: mpsc::Sender<Vec<Vertex>>,
-        font_cache: GlyphLookup,
+        sender: mpsc::Sender<VertexPacket>,
+        #[cfg(feature = "font")] font_cache: Arc<GliumGlyphLookup<'a>>,
         tex_cache: TexLookup,
+        tessellation: Arc<TessellationCache>,
+        buffer_pool: BufferPool,
         white: TexHandle,
-    ) -> Box<RendererController<'a, GlyphLookup, TexLookup>> {
+    ) -> Box<RendererController<'a, TexLookup>> {
+        let buffer = buffer_pool.lock().unwrap().alloc();
         Box::new(RendererController {
             sender: sender,
-            buffer: Vec::new(),
+            buffer: buffer,
+            pick_id: 0,
+            draw_overrides: 0,
+            tagged_len: 0,
+            tessellation_scale: 1.0,
+            tessellation: tessellation,
+            buffer_pool: buffer_pool,
+            #[cfg(feature = "font")]
             font_cache: font_cache,
+            #[cfg(feature = "font")]
+            missing_glyph_policy: MissingGlyphPolicy::default(),
+            #[cfg(feature = "font")]
+            instanced_text: false,
             tex_cache: tex_cache,
             white: white,
+            stream: None,
+            sequence: 0,
             phantom: PhantomData,
         })
     }
 
-    /// Flush this controller & send to renderer
+    /// Flush this controller & send to renderer. The buffer is replaced with
+    /// one the renderer has finished with, if there is one, so its memory is
+    /// reused. If the renderer has been shut down (see QGFX::shutdown()),
+    /// what was drawn is discarded.
     pub fn flush(&mut self) {
         use std::mem::replace;
-        let empty = Vec::new();
-        let v_data = replace(&mut self.buffer, empty);
-        self.sender.send(v_data).unwrap();
+        self.tag_vertices();
+        self.tagged_len = 0;
+        let recycled = self.buffer_pool.lock().unwrap().alloc();
+        let mut v_data = replace(&mut self.buffer, recycled);
+        if let Some(stream) = self.stream {
+            v_data.submission = Some((stream, self.sequence));
+            self.sequence += 1;
+        }
+        // Sending only fails once the renderer's gone, so nothing will draw
+        // the data anyway.
+        let _ = self.sender.send(v_data);
+    }
+
+    /// Sets the stream this controller submits what it draws on, or None
+    /// for no stream. When several threads record a frame at once, the
+    /// renderer receives their flushes in whichever order they happen to
+    /// arrive. Give each thread's controller its own stream, and flushes are
+    /// instead drawn in order of stream, then of when they were flushed on
+    /// the stream, so parallel recording gives the same output every frame.
+    /// Streamed flushes are drawn after flushes with no stream. Controllers
+    /// shouldn't share a stream, or their flushes are interleaved.
+    pub fn set_stream(&mut self, stream: Option<u32>) {
+        self.stream = stream;
+        self.sequence = 0;
+    }
+
+    /// Reserves space for at least `additional` more vertices before the next
+    /// flush. Each rect or textured quad is 6 vertices, and a circle is 3 per
+    /// segment. Reserving up front avoids reallocating the buffer when a lot
+    /// is drawn.
+    pub fn reserve(&mut self, additional: usize) {
+        self.buffer.vertices.reserve(additional);
+    }
+
+    /// Sets the ID everything drawn from now on is tagged with, for picking
+    /// (see QGFX::pick()). None stops tagging what's drawn. IDs must be from 1
+    /// to 2^24 - 1.
+    pub fn set_pick_id(&mut self, id: Option<u32>) {
+        self.tag_vertices();
+        self.pick_id = match id {
+            Some(id) => {
+                debug_assert!(id > 0 && id < 1 << 24, "Pick ID {} out of range", id);
+                id
+            }
+            None => 0,
+        };
+    }
+
+    /// Sets the draw parameter overrides everything drawn from now on is
+    /// drawn with (see QGFX::register_draw_overrides()), or None to draw with
+    /// the layer's draw parameters. Shapes with different overrides are drawn
+    /// in separate batches.
+    pub fn set_draw_overrides(&mut self, overrides: Option<DrawOverridesHandle>) {
+        self.draw_overrides = match overrides {
+            Some(DrawOverridesHandle(ix)) => ix,
+            None => 0,
+        };
+    }
+
+    /// Sets the number of screen pixels per unit, e.g. the zoom of the camera
+    /// if the coordinates given to this controller are scaled before drawing.
+    /// This is used to pick how many segments circles drawn with 0 segments
+    /// have. Defaults to 1.0.
+    pub fn set_tessellation_scale(&mut self, scale: f32) {
+        self.tessellation_scale = scale;
+    }
+
+    /// Records what `draw` draws with this controller into a CommandList,
+    /// rather than drawing it. Replay the list with replay() to draw it, as
+    /// many times as needed. The controller's settings, such as the pick ID
+    /// and draw overrides, apply while recording as they do when drawing.
+    /// `draw` mustn't call flush().
+    pub fn record<F: FnOnce(&mut Self)>(&mut self, draw: F) -> CommandList {
+        use std::mem::replace;
+        self.tag_vertices();
+        let outer = replace(&mut self.buffer, VertexPacket::new());
+        self.tagged_len = 0;
+        draw(self);
+        self.tag_vertices();
+        let recorded = replace(&mut self.buffer, outer);
+        self.tagged_len = self.buffer.vertices.len();
+        CommandList::new(recorded)
+    }
+
+    /// Draws a recorded CommandList as it was recorded.
+    pub fn replay(&mut self, list: &CommandList) {
+        self.replay_with(list, &IDENTITY, &[1.0; 4]);
+    }
+
+    /// Draws a recorded CommandList, transformed by `transform` and with its
+    /// colours multiplied by `tint` - e.g. to move or fade a panel without
+    /// recording it again. Recorded draw overrides are kept, and the current
+    /// pick ID is applied if one is set.
+    pub fn replay_with(&mut self, list: &CommandList, transform: &Affine, tint: &[f32; 4]) {
+        list.append_to(&mut self.buffer, transform, tint);
+    }
+
+    /// Gives the vertices drawn since the pick ID was last changed the
+    /// current pick ID.
+    fn tag_vertices(&mut self) {
+        if self.pick_id != 0 {
+            for v in &mut self.buffer.vertices[self.tagged_len..] {
+                v.pick_id = self.pick_id as f32;
+            }
+        }
+        self.tagged_len = self.buffer.vertices.len();
+    }
+
+    /// The key for vertices drawn from now on with the given texture.
+    fn batch_key(&self, tex_ix: usize, tex_type: TexType) -> BatchKey {
+        BatchKey {
+            tex_type: tex_type,
+            tex_ix: tex_ix,
+            draw_overrides: self.draw_overrides,
+        }
+    }
+
+    /// Makes the vertices pushed from now on part of a batch drawn with the
+    /// given texture.
+    fn start_batch(&mut self, tex_ix: usize, tex_type: TexType) {
+        let key = self.batch_key(tex_ix, tex_type);
+        self.buffer.set_key(key);
     }
 
     /// Lookup a texture handle, and transform the rectangle coordinates into x0,
-    /// y0, x1, y1 (as opposed to x,y,w,h).
-    fn lookup_tex(&self, tex: TexHandle) -> Option<(usize, [f32; 4])> {
+    /// y0, x1, y1 (as opposed to x,y,w,h). Also returns whether the texture
+    /// was rotated when packed into the cache (see sub_tex_uvs).
+    fn lookup_tex(&self, tex: TexHandle) -> Option<(usize, [f32; 4], bool)> {
         // Get the index of this texture.
         let ix_rect_opt = {
             self.tex_cache.rect_for(tex)
@@ -89,77 +268,157 @@ impl<'a, GlyphLookup: font::GlyphLookup + Send + Sync, TexLookup: TexHandleLooku
         // Transform from x,y,w,h to x0,y0,x1,y1
         rect[2] = rect[0] + rect[2];
         rect[3] = rect[1] + rect[3];
-        Some((tex_ix, rect))
+        Some((tex_ix, rect, self.tex_cache.is_tex_rotated(tex)))
     }
 
-    /// Draws a line given a start and an endpoint.
+    /// Draws triangles of a single colour.
+    fn push_solid(&mut self, tris: &[[f32; 2]], col: &[f32; 4]) {
+        if tris.is_empty() {
+            return;
+        }
+        let col = pack_color(col);
+        let (tex_ix, rect, _) = self.lookup_tex(self.white).unwrap();
+        let t = [(rect[0] + rect[2]) / 2.0, (rect[1] + rect[3]) / 2.0];
+        self.start_batch(tex_ix, TexType::Texture);
+        self.buffer.vertices.reserve(tris.len());
+        for &pos in tris {
+            self.buffer.vertices.push(Vertex {
+                pos: pos,
+                col: col,
+                pick_id: 0.0,
+                tex_coords: t,
+            });
+        }
+    }
+
+    /// Draws a line with a stroke style. See StrokeStyle.
+    /// # Params
+    /// * `p1` - The starting point
+    /// * `p2` - The ending point
+    /// * `style` - How the line is stroked.
+    /// * `col` - The colour of the line
+    pub fn line_styled(
+        &mut self,
+        p1: [f32; 2],
+        p2: [f32; 2],
+        style: &StrokeStyle,
+        col: [f32; 4],
+    ) {
+        self.polyline(&[p1, p2], false, style, &col);
+    }
+
+    /// Strokes a path through a list of points. See StrokeStyle.
+    /// # Params
+    /// * `points` - The points to stroke through.
+    /// * `closed` - If true, the path is joined from the last point back to
+    ///              the first.
+    /// * `style` - How the path is stroked.
+    /// * `col` - The colour of the stroke.
+    pub fn polyline(&mut self, points: &[[f32; 2]], closed: bool, style: &StrokeStyle,
+                    col: &[f32; 4]) {
+        let tris = style.stroke(points, closed);
+        self.push_solid(&tris, col);
+    }
+
+    /// Strokes a cubic bezier curve. See geometry::cubic_bezier().
+    /// # Params
+    /// * `p0`, `p3` - The ends of the curve.
+    /// * `p1`, `p2` - The control points.
+    /// * `segments` - The number of straight segments to draw the curve with.
+    ///                0 picks a number based on the curve's size on screen
+    ///                (see set_tessellation_scale()).
+    /// * `style` - How the curve is stroked.
+    /// * `col` - The colour of the curve.
+    pub fn bezier(
+        &mut self,
+        p0: &[f32; 2],
+        p1: &[f32; 2],
+        p2: &[f32; 2],
+        p3: &[f32; 2],
+        segments: usize,
+        style: &StrokeStyle,
+        col: &[f32; 4],
+    ) {
+        let segments = if segments == 0 {
+            curve_segments(&[*p0, *p1, *p2, *p3], self.tessellation_scale)
+        } else {
+            segments
+        };
+        let points = geometry::cubic_bezier(p0, p1, p2, p3, segments);
+        self.polyline(&points, false, style, col);
+    }
+
+    /// Fills a path. See Path.
+    pub fn fill_path(&mut self, path: &Path, style: &FillStyle) {
+        let traps = path.fill_trapezoids(self.tessellation_scale);
+        match *style {
+            FillStyle::Solid(col) => {
+                let tris: Vec<[f32; 2]> = traps
+                    .iter()
+                    .flat_map(|t| vec![t[0], t[1], t[2], t[0], t[2], t[3]])
+                    .collect();
+                self.push_solid(&tris, &col);
+            }
+            FillStyle::Gradient(ref gradient) => for trap in &traps {
+                self.gradient_polygon(trap, gradient);
+            },
+        }
+    }
+
+    /// Strokes a path. Only closed contours are joined back to their start.
+    /// See Path and StrokeStyle.
+    pub fn stroke_path(&mut self, path: &Path, style: &StrokeStyle, col: &[f32; 4]) {
+        for (points, closed) in path.flatten(self.tessellation_scale) {
+            self.polyline(&points, closed, style, col);
+        }
+    }
+
+    /// Strokes the outline of a circle. See StrokeStyle.
+    /// # Params
+    /// * `pos` - The centre of the circle.
+    /// * `rad` - The radius of the circle, to the middle of the stroke.
+    /// * `segments` - As circle().
+    /// * `style` - How the outline is stroked.
+    /// * `col` - The colour of the outline.
+    pub fn circle_outline(
+        &mut self,
+        pos: &[f32; 2],
+        rad: f32,
+        segments: usize,
+        style: &StrokeStyle,
+        col: &[f32; 4],
+    ) {
+        let segments = if segments == 0 {
+            auto_segments(rad * self.tessellation_scale)
+        } else {
+            segments
+        };
+        let points: Vec<[f32; 2]> = self.tessellation.unit_circle(segments)[..segments]
+            .iter()
+            .map(|p| [pos[0] + rad * p[0], pos[1] + rad * p[1]])
+            .collect();
+        self.polyline(&points, true, style, col);
+    }
+
+    /// Strokes the outline of a rect. See StrokeStyle.
+    /// # Params
+    /// * `aabb` - The rect, to the middle of the stroke - X, Y, W, H
+    /// * `style` - How the outline is stroked.
+    /// * `col` - The colour of the outline.
+    pub fn rect_outline(&mut self, aabb: &[f32; 4], style: &StrokeStyle, col: &[f32; 4]) {
+        let (x0, y0, x1, y1) = (aabb[0], aabb[1], aabb[0] + aabb[2], aabb[1] + aabb[3]);
+        self.polyline(&[[x0, y0], [x1, y0], [x1, y1], [x0, y1]], true, style, col);
+    }
+
+    /// Draws a line given a start and an endpoint. The same as line_styled()
+    /// with StrokeStyle::new(w).
     /// #Params
     /// * `p1` - The starting point
     /// * `p2` - The ending point
     /// * `w` - The line width
     /// * `col` - The colour of the line
     pub fn line(&mut self, p1: [f32; 2], p2: [f32; 2], w: f32, col: [f32; 4]) {
-        let mut data = Vec::with_capacity(6);
-        let p1 = Vec2(p1);
-        let p2 = Vec2(p2);
-        let half_w = w / 2.0;
-        let p1p2 = p2.sub(p1);
-
-        // Get the 4 corners of the 'rectangle' (the line is just a rectangle)
-        let perp_l_1 = Vec2([-p1p2[1], p1p2[0]]).nor().mul(half_w).add(p1);
-        let perp_r_1 = Vec2([p1p2[1], -p1p2[0]]).nor().mul(half_w).add(p1);
-        let perp_l_2 = Vec2([-p1p2[1], p1p2[0]]).nor().mul(half_w).add(p2);
-        let perp_r_2 = Vec2([p1p2[1], -p1p2[0]]).nor().mul(half_w).add(p2);
-
-        // Generate the vertex data
-        // tri 1
-        data.push(Vertex {
-            pos: [perp_l_1[0], perp_l_1[1]],
-            col: col.clone(),
-            tex_coords: [0.0, 0.0],
-            tex_type: TexType::Texture,
-            tex_ix: 0,
-        });
-        data.push(Vertex {
-            pos: [perp_r_1[0], perp_r_1[1]],
-            col: col.clone(),
-            tex_coords: [0.0, 0.0],
-            tex_type: TexType::Texture,
-            tex_ix: 0,
-        });
-        data.push(Vertex {
-            pos: [perp_l_2[0], perp_l_2[1]],
-            col: col.clone(),
-            tex_coords: [0.0, 0.0],
-            tex_type: TexType::Texture,
-            tex_ix: 0,
-        });
-
-        // tri 2
-        data.push(Vertex {
-            pos: [perp_l_2[0], perp_l_2[1]],
-            col: col.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: 0,
-            tex_coords: [0.0, 0.0],
-        });
-        data.push(Vertex {
-            pos: [perp_r_2[0], perp_r_2[1]],
-            col: col.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: 0,
-            tex_coords: [0.0, 0.0],
-        });
-        data.push(Vertex {
-            pos: [perp_r_1[0], perp_r_1[1]],
-            col: col.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: 0,
-            tex_coords: [0.0, 0.0],
-        });
-
-        // Send the vertex data through the sender
-        self.buffer.append(&mut data);
+        self.line_styled(p1, p2, &StrokeStyle::new(w), col);
     }
 
     /// Draws a line given a start and an endpoint.
@@ -167,62 +426,95 @@ impl<'a, GlyphLookup: font::GlyphLookup + Send + Sync, TexLookup: TexHandleLooku
     /// * `aabb` - The AABB box for the rectangle - X, Y, W, H
     /// * `col` - The colour of the rectangle
     pub fn rect(&mut self, aabb: &[f32; 4], col: &[f32; 4]) {
-        let mut data = Vec::with_capacity(6);
-
+        let col = pack_color(col);
         // Lookup white texture
-        let (tex_ix, rect) = self.lookup_tex(self.white).unwrap();
+        let (tex_ix, rect, _) = self.lookup_tex(self.white).unwrap();
         let t_x = (rect[0] + rect[2]) / 2.0;
         let t_y = (rect[1] + rect[3]) / 2.0;
+        self.start_batch(tex_ix, TexType::Texture);
+        self.buffer.vertices.reserve(6);
 
         // Generate vertex data
         // Tri 1
-        data.push(Vertex {
+        self.buffer.vertices.push(Vertex {
             pos: [aabb[0], aabb[1]],
             col: col.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: tex_ix,
+            pick_id: 0.0,
             tex_coords: [t_x, t_y],
         });
-        data.push(Vertex {
+        self.buffer.vertices.push(Vertex {
             pos: [aabb[0] + aabb[2], aabb[1]],
             col: col.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: tex_ix,
+            pick_id: 0.0,
             tex_coords: [t_x, t_y],
         });
-        data.push(Vertex {
+        self.buffer.vertices.push(Vertex {
             pos: [aabb[0] + aabb[2], aabb[1] + aabb[3]],
             col: col.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: tex_ix,
+            pick_id: 0.0,
             tex_coords: [t_x, t_y],
         });
 
         // Tri 2
-        data.push(Vertex {
+        self.buffer.vertices.push(Vertex {
             pos: [aabb[0], aabb[1]],
             col: col.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: tex_ix,
+            pick_id: 0.0,
             tex_coords: [t_x, t_y],
         });
-        data.push(Vertex {
+        self.buffer.vertices.push(Vertex {
             pos: [aabb[0], aabb[1] + aabb[3]],
             col: col.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: tex_ix,
+            pick_id: 0.0,
             tex_coords: [t_x, t_y],
         });
-        data.push(Vertex {
+        self.buffer.vertices.push(Vertex {
             pos: [aabb[0] + aabb[2], aabb[1] + aabb[3]],
             col: col.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: tex_ix,
+            pick_id: 0.0,
             tex_coords: [t_x, t_y],
         });
+    }
 
-        // Send the data
-        self.buffer.append(&mut data);
+    /// Draws the lines of a grid covering a rectangle, including its border.
+    /// Lines are centred on the cell edges. If the rectangle isn't a whole
+    /// number of cells, the last row and column are partial.
+    /// # Params
+    /// * `aabb` - The rectangle to cover - X, Y, W, H
+    /// * `cell_w`, `cell_h` - The size of a grid cell.
+    /// * `line_width` - The width of the grid lines.
+    /// * `col` - The colour of the grid lines.
+    pub fn grid(
+        &mut self,
+        aabb: &[f32; 4],
+        cell_w: f32,
+        cell_h: f32,
+        line_width: f32,
+        col: &[f32; 4],
+    ) {
+        if cell_w <= 0.0 || cell_h <= 0.0 {
+            return;
+        }
+        let (tex_ix, rect, _) = self.lookup_tex(self.white).unwrap();
+        let t = [(rect[0] + rect[2]) / 2.0, (rect[1] + rect[3]) / 2.0];
+        let uvs = [t; 4];
+        let half_w = line_width / 2.0;
+        let cols = (aabb[2] / cell_w).ceil() as usize;
+        let rows = (aabb[3] / cell_h).ceil() as usize;
+        self.start_batch(tex_ix, TexType::Texture);
+        self.buffer.vertices.reserve((cols + rows + 2) * 6);
+
+        // Vertical lines, then horizontal lines
+        for ii in 0..cols + 1 {
+            let x = (aabb[0] + cell_w * ii as f32).min(aabb[0] + aabb[2]);
+            let line = [x - half_w, aabb[1] - half_w, line_width, aabb[3] + line_width];
+            push_tex_quad(&mut self.buffer.vertices, &line, &uvs, col);
+        }
+        for ii in 0..rows + 1 {
+            let y = (aabb[1] + cell_h * ii as f32).min(aabb[1] + aabb[3]);
+            let line = [aabb[0] - half_w, y - half_w, aabb[2] + line_width, line_width];
+            push_tex_quad(&mut self.buffer.vertices, &line, &uvs, col);
+        }
     }
 
     /// Draws a circle.
@@ -230,59 +522,129 @@ impl<'a, GlyphLookup: font::GlyphLookup + Send + Sync, TexLookup: TexHandleLooku
     /// * `pos` The position on screen of the circle
     /// * `rad` The radius of the circle
     /// * `segments` The number of triangle segments to use when drawing. More = smoother circle.
+    ///              0 picks a number based on the radius on screen (see
+    ///              set_tessellation_scale()), so the circle looks smooth
+    ///              without wasting triangles.
     /// * `col` - The colour of the circle.
     pub fn circle(&mut self, pos: &[f32; 2], rad: f32, segments: usize, col: &[f32; 4]) {
-        use std::f64::consts::PI;
+        let col = pack_color(col);
+        let segments = if segments == 0 {
+            auto_segments(rad * self.tessellation_scale)
+        } else {
+            segments
+        };
 
         // Lookup white texture
-        let (tex_ix, rect) = self.lookup_tex(self.white).unwrap();
+        let (tex_ix, rect, _) = self.lookup_tex(self.white).unwrap();
         let t_x = (rect[0] + rect[2]) / 2.0;
         let t_y = (rect[1] + rect[3]) / 2.0;
 
-        let mut data = Vec::with_capacity(segments * 3);
-        let mut curr_angle = 0.0f32;
-        let angle_increment = 2.0 * (PI as f32) * (1.0 / segments as f32);
-        for _ in 0..segments {
+        // Scale the cached unit circle to the circle's size
+        let points = self.tessellation.unit_circle(segments);
+        self.start_batch(tex_ix, TexType::Texture);
+        self.buffer.vertices.reserve(segments * 3);
+        for ii in 0..segments {
             // Vertex at the centre of the circle
-            data.push(Vertex {
+            self.buffer.vertices.push(Vertex {
                 pos: pos.clone(),
                 col: col.clone(),
-                tex_type: TexType::Texture,
-                tex_ix: tex_ix,
+                pick_id: 0.0,
                 tex_coords: [t_x, t_y],
             });
 
             // Other two vertices of the triangle
-            data.push(Vertex {
-                pos: [
-                    pos[0] + rad * (curr_angle.cos()),
-                    pos[1] + rad * (curr_angle.sin()),
-                ],
-                tex_type: TexType::Texture,
-                tex_ix: tex_ix,
-                col: col.clone(),
-                tex_coords: [t_x, t_y],
-            });
-            data.push(Vertex {
-                pos: [
-                    pos[0] + rad * ((curr_angle + angle_increment).cos()),
-                    pos[1] + rad * ((curr_angle + angle_increment).sin()),
-                ],
-                tex_type: TexType::Texture,
-                tex_ix: tex_ix,
-                col: col.clone(),
-                tex_coords: [t_x, t_y],
-            });
+            for p in &points[ii..ii + 2] {
+                self.buffer.vertices.push(Vertex {
+                    pos: [pos[0] + rad * p[0], pos[1] + rad * p[1]],
+                    pick_id: 0.0,
+                    col: col.clone(),
+                    tex_coords: [t_x, t_y],
+                });
+            }
+        }
+    }
 
-            // Increment the angle for the next loop
-            curr_angle += angle_increment;
+    /// Fills a convex polygon with a gradient. See Gradient.
+    /// # Params
+    /// * `vertices` - The polygon's vertices, in either winding order.
+    /// * `gradient` - The gradient to fill the polygon with.
+    pub fn gradient_polygon(&mut self, vertices: &[[f32; 2]], gradient: &Gradient) {
+        let tris = gradient.fill(vertices);
+        if tris.is_empty() {
+            return;
+        }
+        let (tex_ix, rect, _) = self.lookup_tex(self.white).unwrap();
+        let t = [(rect[0] + rect[2]) / 2.0, (rect[1] + rect[3]) / 2.0];
+        self.start_batch(tex_ix, TexType::Texture);
+        self.buffer.vertices.reserve(tris.len());
+        for &(pos, col) in &tris {
+            self.buffer.vertices.push(Vertex {
+                pos: pos,
+                col: pack_color(&col),
+                pick_id: 0.0,
+                tex_coords: t,
+            });
         }
+    }
+
+    /// Fills a rect with a gradient. See Gradient.
+    /// # Params
+    /// * `aabb` - The AABB box for the rectangle - X, Y, W, H
+    /// * `gradient` - The gradient to fill the rectangle with.
+    pub fn gradient_rect(&mut self, aabb: &[f32; 4], gradient: &Gradient) {
+        let (x0, y0, x1, y1) = (aabb[0], aabb[1], aabb[0] + aabb[2], aabb[1] + aabb[3]);
+        self.gradient_polygon(&[[x0, y0], [x1, y0], [x1, y1], [x0, y1]], gradient);
+    }
+
+    /// Fills a rect with rounded corners with a gradient. See Gradient, and
+    /// geometry::rounded_rect() for the outline.
+    /// # Params
+    /// * `aabb` - The AABB box for the rectangle - X, Y, W, H
+    /// * `radius` - The radius of the corners.
+    /// * `gradient` - The gradient to fill the rectangle with.
+    pub fn gradient_rounded_rect(&mut self, aabb: &[f32; 4], radius: f32, gradient: &Gradient) {
+        // A quarter of what a circle with the same radius would use
+        let segments = (auto_segments(radius * self.tessellation_scale) + 3) / 4;
+        let outline = geometry::rounded_rect(aabb, radius, segments);
+        self.gradient_polygon(&outline, gradient);
+    }
 
-        // Send the data
-        self.buffer.append(&mut data);
+    /// Draws the shadow an occluder casts from a point light - see
+    /// geometry::shadow_quads(). There's no lighting pass, so the shadow is
+    /// drawn like any other shape: draw it over the lit scene with a
+    /// translucent dark colour, or draw the occluders' shadows for a light
+    /// before drawing the light itself. Shadows which overlap are darker.
+    /// # Params
+    /// * `light` - The position of the light.
+    /// * `occluder` - The occluder polygon, in either winding order.
+    /// * `length` - How far the shadow extends from the occluder. This should
+    ///              be at least the light's radius.
+    /// * `col` - The colour of the shadow.
+    pub fn shadow(&mut self, light: &[f32; 2], occluder: &[[f32; 2]], length: f32, col: &[f32; 4]) {
+        let quads = geometry::shadow_quads(light, occluder, length);
+        if quads.is_empty() {
+            return;
+        }
+        let col = pack_color(col);
+        let (tex_ix, rect, _) = self.lookup_tex(self.white).unwrap();
+        let t = [(rect[0] + rect[2]) / 2.0, (rect[1] + rect[3]) / 2.0];
+        self.start_batch(tex_ix, TexType::Texture);
+        self.buffer.vertices.reserve(quads.len() * 6);
+        for quad in &quads {
+            for &ix in &[0, 1, 2, 0, 2, 3] {
+                self.buffer.vertices.push(Vertex {
+                    pos: quad[ix],
+                    col: col,
+                    pick_id: 0.0,
+                    tex_coords: t,
+                });
+            }
+        }
     }
 
-    /// Render a texture.
+    /// Render a texture. If the texture's transparent border was trimmed
+    /// when it was cached, the quad is cropped to match, so the texture is
+    /// drawn in the same place either way.
     /// # Params
     /// * `tex` - The handle of the texture to render.
     /// * `aabb` - The AABB bounding box of the final texture - X, Y, W, H.
@@ -293,88 +655,367 @@ impl<'a, GlyphLookup: font::GlyphLookup + Send + Sync, TexLookup: TexHandleLooku
         aabb: &[f32; 4],
         tint: &[f32; 4],
     ) -> Result<(), RenderTextureError> {
-        let (x, y, w, h) = (aabb[0], aabb[1], aabb[2], aabb[3]);
-        let (tex_ix, rect) = try!(self.lookup_tex(tex).ok_or(RenderTextureError));
+        let (tex_ix, rect, rotated) = try!(self.lookup_tex(tex).ok_or(RenderTextureError));
+        if self.tex_cache.trim_for(tex) != UNTRIMMED {
+            return self.push_quad(tex, &aabb_corners(aabb), &TEX_UVS, tint);
+        }
+        let uvs = sub_tex_uvs(&rect, rotated, &[0.0, 0.0, 1.0, 1.0]);
+        self.tex_quad(tex_ix, aabb, &uvs, tint);
+        return Ok(());
+    }
 
-        let mut vertices = Vec::with_capacity(6);
-        // Generate vertex data.
-        vertices.push(Vertex {
-            pos: [x, y],
-            col: tint.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: tex_ix,
-            tex_coords: [rect[0], rect[3]],
-        });
-        vertices.push(Vertex {
-            pos: [x + w, y],
-            col: tint.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: tex_ix,
-            tex_coords: [rect[2], rect[3]],
-        });
-        vertices.push(Vertex {
-            pos: [x + w, y + h],
-            col: tint.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: tex_ix,
-            tex_coords: [rect[2], rect[1]],
-        });
-        vertices.push(Vertex {
-            pos: [x, y],
-            col: tint.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: tex_ix,
-            tex_coords: [rect[0], rect[3]],
-        });
-        vertices.push(Vertex {
-            pos: [x, y + h],
-            col: tint.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: tex_ix,
-            tex_coords: [rect[0], rect[1]],
-        });
-        vertices.push(Vertex {
-            pos: [x + w, y + h],
-            col: tint.clone(),
-            tex_type: TexType::Texture,
-            tex_ix: tex_ix,
-            tex_coords: [rect[2], rect[1]],
-        });
+    /// Draws a rectangle rotated about a pivot point.
+    /// # Params
+    /// * `aabb` - The rectangle before it's rotated - X, Y, W, H.
+    /// * `angle` - The angle to rotate by, in radians. Positive angles rotate
+    ///             clockwise, as y points down.
+    /// * `pivot` - The point to rotate about, e.g. the centre of the
+    ///             rectangle.
+    /// * `col` - The colour of the rectangle.
+    pub fn rect_rotated(&mut self, aabb: &[f32; 4], angle: f32, pivot: &[f32; 2], col: &[f32; 4]) {
+        let c = rotated_corners(aabb, angle, pivot);
+        self.push_solid(&[c[0], c[1], c[2], c[0], c[2], c[3]], col);
+    }
 
-        self.buffer.append(&mut vertices);
+    /// Render a texture rotated about a pivot point. See rect_rotated() for
+    /// `angle` and `pivot`, and tex() for the other params.
+    /// # Errors
+    /// Returns an error if the texture isn't cached.
+    pub fn tex_rotated(
+        &mut self,
+        tex: TexHandle,
+        aabb: &[f32; 4],
+        angle: f32,
+        pivot: &[f32; 2],
+        tint: &[f32; 4],
+    ) -> Result<(), RenderTextureError> {
+        let corners = rotated_corners(aabb, angle, pivot);
+        self.push_quad(tex, &corners, &TEX_UVS, tint)
+    }
+
+    /// Render an arbitrary indexed triangle mesh with a texture. This is for
+    /// integrating geometry generated elsewhere (e.g. by a UI library).
+    /// # Params
+    /// * `tex` - The handle of the texture to render with. UVs are remapped
+    ///           into the texture's rect in the cache.
+    /// * `vertices` - The mesh's vertices. UVs are relative to the texture,
+    ///                from 0,0 at the top left to 1,1 at the bottom right. If
+    ///                the texture's transparent border was trimmed, UVs in
+    ///                the border are clamped to its edge - the mesh itself
+    ///                can't be cropped, so should avoid sampling the border.
+    /// * `indices` - Indices into `vertices`, 3 per triangle.
+    pub fn tex_mesh(
+        &mut self,
+        tex: TexHandle,
+        vertices: &[MeshVertex],
+        indices: &[u32],
+    ) -> Result<(), RenderTextureError> {
+        let (tex_ix, rect, rotated) = try!(self.lookup_tex(tex).ok_or(RenderTextureError));
+        let trim = self.tex_cache.trim_for(tex);
+        self.start_batch(tex_ix, TexType::Texture);
+        self.buffer.vertices.reserve(indices.len());
+        for &ix in indices {
+            let v = &vertices[ix as usize];
+            let uv = if trim != UNTRIMMED { trimmed_uv(&clamp_uv(&v.uv, &trim), &trim) } else { v.uv };
+            self.buffer.vertices.push(Vertex {
+                pos: v.pos,
+                col: pack_color(&v.col),
+                pick_id: 0.0,
+                tex_coords: tex_uv(&rect, rotated, uv[0], uv[1]),
+            });
+        }
+        Ok(())
+    }
+
+    /// Render an arbitrary indexed triangle mesh in a single colour, like
+    /// tex_mesh() without a texture.
+    /// # Params
+    /// * `positions` - The positions of the mesh's vertices.
+    /// * `indices` - Indices into `positions`, 3 per triangle.
+    /// * `col` - The colour of the mesh.
+    pub fn solid_mesh(&mut self, positions: &[[f32; 2]], indices: &[u32], col: &[f32; 4]) {
+        let tris: Vec<[f32; 2]> = indices.iter().map(|&ix| positions[ix as usize]).collect();
+        self.push_solid(&tris, col);
+    }
+
+    /// Draws a quad with arbitrary corners, e.g. one which has already been
+    /// transformed by a UI library or tilemap. The texture's position in the
+    /// cache is handled here, so `uvs` are relative to the texture itself.
+    /// # Params
+    /// * `tex` - The handle of the texture to draw with.
+    /// * `corners` - The positions of the quad's corners, in order around the
+    ///               quad (e.g. top left, top right, bottom right, bottom
+    ///               left).
+    /// * `uvs` - The UV of each corner, from 0,0 at the top left of the
+    ///           texture to 1,1 at the bottom right.
+    /// * `col` - The colour to tint the texture.
+    ///
+    /// If the texture's transparent border was trimmed when it was cached,
+    /// the quad is cropped to the part of the texture which was kept. This is
+    /// exact for quads whose UVs form an axis aligned rectangle - otherwise
+    /// UVs in the border are clamped to its edge.
+    /// # Errors
+    /// Returns an error if the texture isn't cached.
+    pub fn push_quad(
+        &mut self,
+        tex: TexHandle,
+        corners: &[[f32; 2]; 4],
+        uvs: &[[f32; 2]; 4],
+        col: &[f32; 4],
+    ) -> Result<(), RenderTextureError> {
+        let (tex_ix, rect, rotated) = try!(self.lookup_tex(tex).ok_or(RenderTextureError));
+        let trim = self.tex_cache.trim_for(tex);
+        let (corners, uvs) = if trim == UNTRIMMED {
+            (*corners, *uvs)
+        } else {
+            match trim_quad(corners, uvs, &trim) {
+                Some(quad) => quad,
+                // The quad only shows the trimmed, transparent, border.
+                None => return Ok(()),
+            }
+        };
+        let col = pack_color(col);
+        self.start_batch(tex_ix, TexType::Texture);
+        self.buffer.vertices.reserve(6);
+        for &ix in &[0, 1, 2, 0, 2, 3] {
+            self.buffer.vertices.push(Vertex {
+                pos: corners[ix],
+                col: col,
+                pick_id: 0.0,
+                tex_coords: tex_uv(&rect, rotated, uvs[ix][0], uvs[ix][1]),
+            });
+        }
+        Ok(())
+    }
+
+    /// Render a texture tiled across a rectangle. Textures packed into an
+    /// atlas can't use GPU repeat wrapping - instead, a quad is emitted for
+    /// every tile. Tiles on the right and bottom edges are cropped (both in
+    /// size and UVs) so the tiling ends exactly at the edge of the AABB.
+    ///
+    /// Textures with their own dedicated cache texture (see
+    /// QGFX::set_dedicated_tex_threshold) are sampled with REPEAT wrapping,
+    /// so are drawn with a single quad, unless their transparent border was
+    /// trimmed.
+    /// # Params
+    /// * `tex` - The handle of the texture to tile.
+    /// * `aabb` - The AABB bounding box to fill with tiles - X, Y, W, H.
+    /// * `tile_size` - The size of a single tile - W, H.
+    /// * `tint` - The colour to tint the texture.
+    pub fn tex_tiled(
+        &mut self,
+        tex: TexHandle,
+        aabb: &[f32; 4],
+        tile_size: &[f32; 2],
+        tint: &[f32; 4],
+    ) -> Result<(), RenderTextureError> {
+        let (tex_ix, rect, rotated) = try!(self.lookup_tex(tex).ok_or(RenderTextureError));
+        if tile_size[0] <= 0.0 || tile_size[1] <= 0.0 {
+            return Ok(());
+        }
+
+        let trimmed = self.tex_cache.trim_for(tex) != UNTRIMMED;
+        if self.tex_cache.is_page_dedicated(tex_ix) && !trimmed {
+            let sub = [0.0, 0.0, aabb[2] / tile_size[0], aabb[3] / tile_size[1]];
+            let uvs = sub_tex_uvs(&rect, rotated, &sub);
+            self.tex_quad(tex_ix, aabb, &uvs, tint);
+            return Ok(());
+        }
+
+        let mut y = 0.0;
+        while y < aabb[3] {
+            let h = tile_size[1].min(aabb[3] - y);
+            let mut x = 0.0;
+            while x < aabb[2] {
+                let w = tile_size[0].min(aabb[2] - x);
+                let tile = [aabb[0] + x, aabb[1] + y, w, h];
+                // Crop the UVs by the same fraction the tile was cropped by.
+                let (sx, sy) = (w / tile_size[0], h / tile_size[1]);
+                if trimmed {
+                    let uvs = [[0.0, 0.0], [sx, 0.0], [sx, sy], [0.0, sy]];
+                    try!(self.push_quad(tex, &aabb_corners(&tile), &uvs, tint));
+                } else {
+                    let uvs = sub_tex_uvs(&rect, rotated, &[0.0, 0.0, sx, sy]);
+                    self.tex_quad(tex_ix, &tile, &uvs, tint);
+                }
+                x += tile_size[0];
+            }
+            y += tile_size[1];
+        }
         return Ok(());
     }
 
+    /// Push a textured quad into the buffer.
+    /// # Params
+    /// * `tex_ix` - The index of the cache texture to draw with.
+    /// * `aabb` - The AABB of the quad - X, Y, W, H.
+    /// * `uvs` - The UVs of the top left, top right, bottom right and bottom
+    ///           left corners of the AABB. See sub_tex_uvs.
+    /// * `tint` - The colour to tint the texture.
+    fn tex_quad(&mut self, tex_ix: usize, aabb: &[f32; 4], uvs: &[[f32; 2]; 4], tint: &[f32; 4]) {
+        self.start_batch(tex_ix, TexType::Texture);
+        push_tex_quad(&mut self.buffer.vertices, aabb, uvs, tint);
+    }
+
     /// Render some text.
     /// # Params
-    /// * `text` - The text to render
+    /// * `text` - The text to render. '\n' starts a new line.
     /// * `pos` - The position to render the text at - this is the bottom left of the first character.
     /// * `font_handle` - This is the font to render the text with.
     /// * `tint` - The tint to apply to the font.
     /// # Returns
     /// The size of the bounding box of the rendered text.
-    /// Error if not all the glyphs for this font were cached. To cache glyphs,
-    /// use the cache_glyphs method on your QGFX instance.
+    /// # Errors
+    /// Glyphs which aren't cached are handled according to the controller's
+    /// missing glyph policy (see set_missing_glyph_policy). With
+    /// MissingGlyphPolicy::Error, nothing is rendered and the missing glyphs
+    /// are returned. To cache glyphs, use the cache_glyphs method on your
+    /// QGFX instance.
+    #[cfg(feature = "font")]
     pub fn text(
         &mut self,
         text: &str,
         pos: &[f32; 2],
         font_handle: FontHandle,
         tint: &[f32; 4],
-    ) -> (f32, f32) {
+    ) -> Result<(f32, f32), MissingGlyphsError> {
+        self.text_anchored(text, pos, font_handle, TextAnchor::Baseline, tint)
+    }
+
+    /// Render some text, positioned relative to `pos` according to `anchor`.
+    /// The anchor is computed from the font's vertical metrics rather than the
+    /// glyphs in the text, so a line of text lands in the same place
+    /// regardless of which characters it contains. See TextAnchor.
+    /// # Params
+    /// * `anchor` - Which point of the text `pos` refers to.
+    ///
+    /// See text() for the other params and the return value.
+    #[cfg(feature = "font")]
+    pub fn text_anchored(
+        &mut self,
+        text: &str,
+        pos: &[f32; 2],
+        font_handle: FontHandle,
+        anchor: TextAnchor,
+        tint: &[f32; 4],
+    ) -> Result<(f32, f32), MissingGlyphsError> {
+        self.text_with_layout(text, pos, font_handle, anchor, tint).map(|layout| layout.size)
+    }
+
+    /// Render some text like text_anchored(), but return where every glyph
+    /// and line was placed. Use this to draw selections or underlines, or to
+    /// position things next to specific words.
+    /// # Returns
+    /// A TextLayout. See its documentation for details.
+    #[cfg(feature = "font")]
+    pub fn text_with_layout(
+        &mut self,
+        text: &str,
+        pos: &[f32; 2],
+        font_handle: FontHandle,
+        anchor: TextAnchor,
+        tint: &[f32; 4],
+    ) -> Result<TextLayout, MissingGlyphsError> {
+        self.text_internal(text, pos, font_handle, anchor, TextStyle::default(), tint)
+    }
+
+    /// Lays out and renders text. Decorations in `style` are ignored here, as
+    /// they're drawn on top of the layout by text_styled().
+    #[cfg(feature = "font")]
+    fn text_internal(
+        &mut self,
+        text: &str,
+        pos: &[f32; 2],
+        font_handle: FontHandle,
+        anchor: TextAnchor,
+        style: TextStyle,
+        tint: &[f32; 4],
+    ) -> Result<TextLayout, MissingGlyphsError> {
+        use res::font::GlyphLookup;
+        if self.missing_glyph_policy == MissingGlyphPolicy::Error {
+            try!(self.check_glyphs(text, font_handle));
+        }
+
         let font_cache = &self.font_cache;
-        let &(ref font, (scale, _)) = font_cache.get_font_ref(font_handle).unwrap();
-        let mut vertices = Vec::with_capacity(text.len() * 6);
-        let mut cursor = pos.clone();
+        let &(ref font, scale) = font_cache.get_font_ref(font_handle).unwrap();
+        // Glyphs are pushed straight onto the buffer, which is taken out of
+        // self so it can be pushed to while the font cache is borrowed.
+        let mut packet = std::mem::replace(&mut self.buffer, VertexPacket::new());
+        if self.instanced_text {
+            packet.glyphs.reserve(text.len());
+        } else {
+            packet.vertices.reserve(text.len() * 6);
+        }
+
+        // Work out where the baseline of the first character should go. y
+        // points down, and descent is negative.
+        let v_metrics = font.v_metrics(scale);
+        let line_h = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+        let bold_offset = if style.bold { bold_offset(scale.x) } else { 0.0 };
+        let shear = if style.italic { ITALIC_SHEAR } else { 0.0 };
+        let origin = match anchor {
+            TextAnchor::Baseline => pos.clone(),
+            TextAnchor::TopLeft => [pos[0], pos[1] + v_metrics.ascent],
+            TextAnchor::Centre => {
+                let w = self.text_width(text, font_handle, style);
+                let n_lines = text.split('\n').count();
+                [
+                    pos[0] - w / 2.0,
+                    pos[1] + (v_metrics.ascent + v_metrics.descent) / 2.0 -
+                        (n_lines - 1) as f32 * line_h / 2.0,
+                ]
+            }
+        };
+
+        let (chars, runs) = visual_order(text);
+        let mut layout = TextLayout {
+            glyphs: Vec::with_capacity(text.len()),
+            line_breaks: Vec::new(),
+            runs: runs,
+            size: (0.0, 0.0),
+        };
+        let mut cursor = origin;
         let mut last_glyph_id = None; // For kerning.
         let (mut bb_x, mut bb_y) = (0.0f32, 0.0f32);
-        for c in text.chars() {
+        let mut line_w = 0.0f32;
+        for (ix, c) in chars {
+            if c == '\n' {
+                bb_x = bb_x.max(line_w);
+                line_w = 0.0;
+                cursor = [origin[0], cursor[1] + line_h];
+                last_glyph_id = None;
+                layout.line_breaks.push(ix + 1);
+                continue;
+            }
+            let glyph_x = cursor[0];
+            let line_y = cursor[1] - origin[1];
+
+            // Chars with a sprite (e.g. emoji) are drawn as a square texture
+            // filling the line, keeping the text's alpha but not its colour.
+            let sprite = font_cache.sprite_for(c).and_then(|t| self.lookup_tex(t));
+            if let Some((tex_ix, rect, rotated)) = sprite {
+                let size = v_metrics.ascent - v_metrics.descent;
+                let aabb = [cursor[0], cursor[1] - v_metrics.ascent, size, size];
+                let uvs = sub_tex_uvs(&rect, rotated, &[0.0, 0.0, 1.0, 1.0]);
+                packet.set_key(self.batch_key(tex_ix, TexType::Texture));
+                push_tex_quad(&mut packet.vertices, &aabb, &uvs, &[1.0, 1.0, 1.0, tint[3]]);
+                cursor[0] += size;
+                line_w += size;
+                bb_y = bb_y.max(line_y - v_metrics.descent);
+                last_glyph_id = None;
+                layout.glyphs.push(GlyphPlacement { ix: ix, c: c, rect: aabb });
+                continue;
+            }
+
+            // Pick the glyph to draw, applying the missing glyph policy if
+            // this one isn't cached.
+            let draw_c = match self.substitute_glyph(font_handle, c) {
+                Some(draw_c) => draw_c,
+                None => continue,
+            };
+
             // Get the glyph metrics
-            let glyph = font_cache.get_glyph(font_handle, c).unwrap_or(
-                font_cache
-                    .get_glyph(font_handle, '?')
-                    .unwrap(),
-            );
+            let glyph = font_cache.get_glyph(font_handle, draw_c).unwrap();
             let h_metrics = glyph.unpositioned().h_metrics();
             let (x, y, w, h) = {
                 let rect = glyph.pixel_bounding_box();
@@ -390,80 +1031,536 @@ impl<'a, GlyphLookup: font::GlyphLookup + Send + Sync, TexLookup: TexHandleLooku
                     (0.0, 0.0, 0.0, 0.0)
                 }
             };
-            bb_y = bb_y.max(y + h);
+            bb_y = bb_y.max(line_y + y + h);
 
-            let rect = font_cache.rect_for(font_handle, c).unwrap_or(
-                font_cache
-                    .rect_for(font_handle, '?')
-                    .unwrap(),
-            );
+            let rect = font_cache.rect_for(font_handle, draw_c).unwrap();
             // If none, just advance cursor and continue. Nothing to draw, but glyph
             // has dimensions
             if rect.is_none() {
                 cursor[0] += h_metrics.left_side_bearing;
                 cursor[0] += h_metrics.advance_width;
-                bb_x += h_metrics.left_side_bearing + h_metrics.advance_width;
+                line_w += h_metrics.left_side_bearing + h_metrics.advance_width;
+                layout.glyphs.push(GlyphPlacement {
+                    ix: ix,
+                    c: c,
+                    rect: [glyph_x, cursor[1] - v_metrics.ascent,
+                           cursor[0] - glyph_x, v_metrics.ascent - v_metrics.descent],
+                });
                 continue;
             }
             let rect = rect.unwrap();
 
             if last_glyph_id.is_some() {
                 cursor[0] +=
-                    font.pair_kerning(Scale::uniform(scale), last_glyph_id.unwrap(), glyph.id());
+                    font.pair_kerning(scale, last_glyph_id.unwrap(), glyph.id());
             }
             last_glyph_id = Some(glyph.id());
 
             cursor[0] += h_metrics.left_side_bearing;
 
-            // Generate vertices
-            vertices.push(Vertex {
-                pos: [x + cursor[0], y + cursor[1]],
-                col: tint.clone(),
-                tex_type: TexType::Font,
-                tex_ix: 0,
-                tex_coords: [rect[0], rect[1]],
-            });
-            vertices.push(Vertex {
-                pos: [x + cursor[0] + w, y + cursor[1]],
-                col: tint.clone(),
-                tex_type: TexType::Font,
-                tex_ix: 0,
-                tex_coords: [rect[2], rect[1]],
-            });
-            vertices.push(Vertex {
-                pos: [x + cursor[0] + w, y + cursor[1] + h],
-                col: tint.clone(),
-                tex_type: TexType::Font,
-                tex_ix: 0,
-                tex_coords: [rect[2], rect[3]],
-            });
-            vertices.push(Vertex {
-                pos: [x + cursor[0], y + cursor[1]],
-                col: tint.clone(),
-                tex_type: TexType::Font,
-                tex_ix: 0,
-                tex_coords: [rect[0], rect[1]],
-            });
-            vertices.push(Vertex {
-                pos: [x + cursor[0], y + cursor[1] + h],
-                col: tint.clone(),
-                tex_type: TexType::Font,
-                tex_ix: 0,
-                tex_coords: [rect[0], rect[3]],
-            });
-            vertices.push(Vertex {
-                pos: [x + cursor[0] + w, y + cursor[1] + h],
-                col: tint.clone(),
-                tex_type: TexType::Font,
-                tex_ix: 0,
-                tex_coords: [rect[2], rect[3]],
+            // Generate the glyph. Faux bold draws it a second time, offset to
+            // the right.
+            let aabb = [x + cursor[0], y + cursor[1], x + cursor[0] + w, y + cursor[1] + h];
+            let glyph = glyph_instance(&aabb, cursor[1], shear, &rect, tint);
+            let bold = GlyphInstance {
+                rect: [aabb[0] + bold_offset, aabb[1], aabb[2] + bold_offset, aabb[3]],
+                ..glyph
+            };
+            let glyphs = [glyph, bold];
+            let glyphs = if style.bold { &glyphs[..] } else { &glyphs[..1] };
+            if self.instanced_text {
+                packet.set_glyph_overrides(self.draw_overrides);
+                packet.glyphs.extend_from_slice(glyphs);
+            } else {
+                packet.set_key(self.batch_key(0, TexType::Font));
+                for glyph in glyphs {
+                    packet.vertices.extend_from_slice(&glyph.vertices());
+                }
+            }
+
+            cursor[0] += h_metrics.advance_width + bold_offset;
+            line_w += h_metrics.advance_width + bold_offset;
+            layout.glyphs.push(GlyphPlacement {
+                ix: ix,
+                c: c,
+                rect: [glyph_x, cursor[1] - v_metrics.ascent,
+                       cursor[0] - glyph_x, v_metrics.ascent - v_metrics.descent],
             });
+        }
 
-            cursor[0] += h_metrics.advance_width;
-            bb_x += h_metrics.advance_width;
+        self.buffer = packet;
+        layout.size = (bb_x.max(line_w), bb_y);
+        return Ok(layout);
+    }
+
+    /// Returns the char to draw in place of `c`, according to the missing
+    /// glyph policy. Returns `c` itself if its glyph is cached, or None if
+    /// nothing should be drawn. Missing glyphs are requested for on-demand
+    /// caching.
+    #[cfg(feature = "font")]
+    fn substitute_glyph(&self, font_handle: FontHandle, c: char) -> Option<char> {
+        use res::font::GlyphLookup;
+        if self.font_cache.rect_for(font_handle, c).is_ok() {
+            return Some(c);
+        }
+        self.font_cache.request_glyph(font_handle, c);
+        match self.missing_glyph_policy {
+            MissingGlyphPolicy::ReplaceWith(r) if self.font_cache.rect_for(font_handle, r).is_ok() => {
+                Some(r)
+            }
+            _ => None,
         }
+    }
+
+    /// Checks that all the glyphs needed to render `text` are cached.
+    /// # Errors
+    /// Returns the missing glyphs, if any.
+    #[cfg(feature = "font")]
+    fn check_glyphs(&self, text: &str, font_handle: FontHandle) -> Result<(), MissingGlyphsError> {
+        use res::font::GlyphLookup;
+        let mut missing = Vec::new();
+        for c in text.chars() {
+            if c == '\n' || missing.contains(&c) ||
+                self.font_cache.sprite_for(c).and_then(|t| self.lookup_tex(t)).is_some() {
+                continue;
+            }
+            if self.font_cache.rect_for(font_handle, c).is_err() {
+                self.font_cache.request_glyph(font_handle, c);
+                missing.push(c);
+            }
+        }
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(MissingGlyphsError(missing))
+        }
+    }
+
+    /// Sets what text rendering does when it comes across a glyph which isn't
+    /// cached. Defaults to MissingGlyphPolicy::ReplaceWith('?').
+    #[cfg(feature = "font")]
+    pub fn set_missing_glyph_policy(&mut self, policy: MissingGlyphPolicy) {
+        self.missing_glyph_policy = policy;
+    }
+
+    /// Sets whether glyphs are drawn with instancing, as one quad instance
+    /// each rather than six vertices. This is much cheaper for thousands of
+    /// glyphs, e.g. logs or code, as less is generated and uploaded each
+    /// frame. Instanced glyphs are drawn after everything else in their
+    /// layer, and aren't pickable. Sprites for chars (see
+    /// QGFX::set_glyph_sprite()) are still drawn as vertices. Defaults to
+    /// false.
+    #[cfg(feature = "font")]
+    pub fn set_instanced_text(&mut self, instanced: bool) {
+        self.instanced_text = instanced;
+    }
+
+    /// Render some text like text_with_layout(), styled according to `style`.
+    /// Decorations are drawn as rectangles in the same colour as the text,
+    /// spanning each line of text. Bold and italic are synthesised from the
+    /// regular glyphs (see TextStyle), so only one face of a font needs to be
+    /// cached.
+    ///
+    /// rusttype doesn't expose the font's own underline and strikethrough
+    /// metrics, so these are approximated from the font scale: lines are
+    /// 1/16th of the scale thick, underlines sit a tenth of the scale below the
+    /// baseline, and strikethroughs sit roughly halfway up lowercase letters.
+    #[cfg(feature = "font")]
+    pub fn text_styled(
+        &mut self,
+        text: &str,
+        pos: &[f32; 2],
+        font_handle: FontHandle,
+        anchor: TextAnchor,
+        style: TextStyle,
+        tint: &[f32; 4],
+    ) -> Result<TextLayout, MissingGlyphsError> {
+        let layout = try!(self.text_internal(text, pos, font_handle, anchor, style, tint));
+        if !style.underline && !style.strikethrough {
+            return Ok(layout);
+        }
+
+        let (ascent, scale) = {
+            use res::font::GlyphLookup;
+            let &(ref font, scale) = self.font_cache.get_font_ref(font_handle).unwrap();
+            (font.v_metrics(scale).ascent, scale.y)
+        };
+        let thickness = (scale / 16.0).max(1.0);
+
+        // Glyph cells on the same line share the same y, so find the extents
+        // of each line and decorate it.
+        let mut lines: Vec<(f32, f32, f32)> = Vec::new(); // (cell y, x0, x1)
+        for g in &layout.glyphs {
+            let extends_line = match lines.last() {
+                Some(&(y, _, _)) => y == g.rect[1],
+                None => false,
+            };
+            if extends_line {
+                lines.last_mut().unwrap().2 = g.rect[0] + g.rect[2];
+            } else {
+                lines.push((g.rect[1], g.rect[0], g.rect[0] + g.rect[2]));
+            }
+        }
+        for &(y, x0, x1) in &lines {
+            let baseline = y + ascent;
+            if style.underline {
+                let y = baseline + scale * 0.1 - thickness / 2.0;
+                self.rect(&[x0, y, x1 - x0, thickness], tint);
+            }
+            if style.strikethrough {
+                let y = baseline - scale * 0.25 - thickness / 2.0;
+                self.rect(&[x0, y, x1 - x0, thickness], tint);
+            }
+        }
+        Ok(layout)
+    }
+
+    /// Measures how far the cursor advances when rendering the given text,
+    /// i.e. the width of the widest line of the text as laid out by
+    /// text_with_layout().
+    #[cfg(feature = "font")]
+    fn text_width(&self, text: &str, font_handle: FontHandle, style: TextStyle) -> f32 {
+        use res::font::GlyphLookup;
+        let font_cache = &self.font_cache;
+        let &(ref font, scale) = font_cache.get_font_ref(font_handle).unwrap();
+        let bold_offset = if style.bold { bold_offset(scale.x) } else { 0.0 };
+        let mut max_width = 0.0f32;
+        let mut width = 0.0;
+        let mut last_glyph_id = None;
+        for c in text.chars() {
+            if c == '\n' {
+                max_width = max_width.max(width);
+                width = 0.0;
+                last_glyph_id = None;
+                continue;
+            }
+            if font_cache.sprite_for(c).and_then(|t| self.lookup_tex(t)).is_some() {
+                let v_metrics = font.v_metrics(scale);
+                width += v_metrics.ascent - v_metrics.descent;
+                last_glyph_id = None;
+                continue;
+            }
+            let draw_c = match self.substitute_glyph(font_handle, c) {
+                Some(draw_c) => draw_c,
+                None => continue,
+            };
+            let glyph = font_cache.get_glyph(font_handle, draw_c).unwrap();
+            let h_metrics = glyph.unpositioned().h_metrics();
+            if glyph.pixel_bounding_box().is_some() {
+                if last_glyph_id.is_some() {
+                    width +=
+                        font.pair_kerning(scale, last_glyph_id.unwrap(), glyph.id());
+                }
+                last_glyph_id = Some(glyph.id());
+            }
+            width += h_metrics.left_side_bearing + h_metrics.advance_width;
+            if glyph.pixel_bounding_box().is_some() {
+                width += bold_offset;
+            }
+        }
+        max_width.max(width)
+    }
+}
+
+/// Reorders text into the order it's displayed, left to right, using the
+/// Unicode bidirectional algorithm.
+/// # Returns
+/// The chars of the text with their byte indices, in display order. Each line
+/// is followed by its '\n' (if any). Also returns the direction runs of each
+/// line, in display order.
+#[cfg(feature = "font")]
+fn visual_order(text: &str) -> (Vec<(usize, char)>, Vec<TextRun>) {
+    use unicode_bidi::BidiInfo;
+    let bidi_info = BidiInfo::new(text, None);
+    let mut chars = Vec::with_capacity(text.len());
+    let mut runs = Vec::new();
+    // Paragraphs are split by (and include) newlines.
+    for para in &bidi_info.paragraphs {
+        let mut line = para.range.clone();
+        let newline = text[line.clone()].ends_with('\n');
+        if newline {
+            line.end -= 1;
+        }
+        let (levels, level_runs) = bidi_info.visual_runs(para, line.clone());
+        for run in level_runs {
+            let rtl = levels[run.start].is_rtl();
+            let run_chars = text[run.clone()].char_indices().map(|(ix, c)| (ix + run.start, c));
+            if rtl {
+                chars.extend(run_chars.rev());
+            } else {
+                chars.extend(run_chars);
+            }
+            runs.push(TextRun { range: run, rtl: rtl });
+        }
+        if newline {
+            chars.push((line.end, '\n'));
+        }
+    }
+    (chars, runs)
+}
+
+/// Pushes a textured quad onto `vertices`. See RendererController::tex_quad.
+fn push_tex_quad(
+    vertices: &mut Vec<Vertex>,
+    aabb: &[f32; 4],
+    uvs: &[[f32; 2]; 4],
+    tint: &[f32; 4],
+) {
+    let (x, y, w, h) = (aabb[0], aabb[1], aabb[2], aabb[3]);
+    let corners = [
+        ([x, y], uvs[0]),
+        ([x + w, y], uvs[1]),
+        ([x + w, y + h], uvs[2]),
+        ([x, y], uvs[0]),
+        ([x, y + h], uvs[3]),
+        ([x + w, y + h], uvs[2]),
+    ];
+    let col = pack_color(tint);
+    vertices.reserve(6);
+    for &(pos, tex_coords) in &corners {
+        vertices.push(Vertex {
+            pos: pos,
+            col: col,
+            pick_id: 0.0,
+            tex_coords: tex_coords,
+        });
+    }
+}
+
+/// How far faux italic glyphs lean - the x offset per pixel above the
+/// baseline. Roughly a 12 degree slant.
+#[cfg(feature = "font")]
+const ITALIC_SHEAR: f32 = 0.2;
+
+/// How far right faux bold glyphs are drawn a second time, for a font of the
+/// given scale.
+#[cfg(feature = "font")]
+fn bold_offset(scale: f32) -> f32 {
+    (scale / 24.0).max(1.0)
+}
+
+/// Creates a glyph quad, to push as an instance or as vertices.
+/// # Params
+/// * `aabb` - The quad's corners - X0, Y0, X1, Y1.
+/// * `baseline` - The y coordinate of the baseline the glyph sits on.
+/// * `shear` - The x offset per pixel above the baseline, for faux italics.
+/// * `uv` - The glyph's rect in the font cache - X0, Y0, X1, Y1.
+#[cfg(feature = "font")]
+fn glyph_instance(
+    aabb: &[f32; 4],
+    baseline: f32,
+    shear: f32,
+    uv: &[f32; 4],
+    tint: &[f32; 4],
+) -> GlyphInstance {
+    GlyphInstance {
+        rect: *aabb,
+        uv: *uv,
+        col: pack_color(tint),
+        shear: [baseline, shear],
+    }
+}
+
+/// Get the UVs in the cache for a sub-rectangle of a texture.
+/// # Params
+/// * `rect` - The rect of the texture in the cache - X0, Y0, X1, Y1.
+/// * `rotated` - Whether the texture was rotated 90 degrees clockwise when it
+///               was packed into the cache.
+/// * `sub` - The sub-rectangle of the texture, as fractions of the texture's
+///           size with the origin in the top left - X0, Y0, X1, Y1. Values
+///           outside of 0 - 1 are valid, for repeated textures.
+/// # Returns
+/// The UVs of the top left, top right, bottom right and bottom left corners
+/// of the sub-rectangle.
+fn sub_tex_uvs(rect: &[f32; 4], rotated: bool, sub: &[f32; 4]) -> [[f32; 2]; 4] {
+    [
+        tex_uv(rect, rotated, sub[0], sub[1]),
+        tex_uv(rect, rotated, sub[2], sub[1]),
+        tex_uv(rect, rotated, sub[2], sub[3]),
+        tex_uv(rect, rotated, sub[0], sub[3]),
+    ]
+}
+
+/// Get the UV in the cache for a point on a texture. See sub_tex_uvs.
+/// # Params
+/// * `x`, `y` - The point on the texture, from 0,0 at the top left to 1,1 at
+///              the bottom right.
+fn tex_uv(rect: &[f32; 4], rotated: bool, x: f32, y: f32) -> [f32; 2] {
+    let (uv_w, uv_h) = (rect[2] - rect[0], rect[3] - rect[1]);
+    // Textures are uploaded upside down, so the top of the texture is the
+    // bottom of the UV rect. Rotated textures are stored rotated clockwise, so
+    // the texture's x axis runs down the UV rect, and its y axis runs right to
+    // left.
+    if rotated {
+        [rect[2] - y * uv_w, rect[3] - x * uv_h]
+    } else {
+        [rect[0] + x * uv_w, rect[3] - y * uv_h]
+    }
+}
+
+/// The UVs of a whole texture - top left, top right, bottom right, bottom
+/// left.
+const TEX_UVS: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+
+/// The corners of a rectangle - X, Y, W, H - in the same order as TEX_UVS.
+fn aabb_corners(aabb: &[f32; 4]) -> [[f32; 2]; 4] {
+    let (x0, y0, x1, y1) = (aabb[0], aabb[1], aabb[0] + aabb[2], aabb[1] + aabb[3]);
+    [[x0, y0], [x1, y0], [x1, y1], [x0, y1]]
+}
+
+/// Crops a quad to the part of its texture which was kept when the
+/// texture's transparent border was trimmed (see TexHandleLookup::trim_for),
+/// and maps its UVs onto the trimmed texture.
+///
+/// If the UVs form an axis aligned rectangle (in either orientation), the
+/// corners are moved in to where the kept part starts and ends. Otherwise
+/// the corners are left alone, and UVs in the border are clamped to its edge.
+/// # Returns
+/// The new corners and UVs, or None if the quad only shows the border.
+fn trim_quad(
+    corners: &[[f32; 2]; 4],
+    uvs: &[[f32; 2]; 4],
+    trim: &[f32; 4],
+) -> Option<([[f32; 2]; 4], [[f32; 2]; 4])> {
+    // The UVs across (from corner 0 to 1) and down (from corner 0 to 3).
+    let across = [uvs[1][0] - uvs[0][0], uvs[1][1] - uvs[0][1]];
+    let down = [uvs[3][0] - uvs[0][0], uvs[3][1] - uvs[0][1]];
+    let rectangular = (across[0] == 0.0 || down[0] == 0.0) && (across[1] == 0.0 || down[1] == 0.0) &&
+        uvs[2][0] == uvs[1][0] + down[0] && uvs[2][1] == uvs[1][1] + down[1];
+    if !rectangular {
+        let mut trimmed = *uvs;
+        for uv in &mut trimmed {
+            *uv = trimmed_uv(&clamp_uv(uv, trim), trim);
+        }
+        return Some((*corners, trimmed));
+    }
+
+    // Find the fractions of the way across and down the quad the kept part
+    // of the texture covers. Each UV component changes in at most one of
+    // the two directions.
+    let mut s = (0.0, 1.0);
+    let mut t = (0.0, 1.0);
+    for k in 0..2 {
+        let (lo, hi) = (trim[k], trim[k + 2]);
+        let res = if across[k] != 0.0 {
+            intersect_span(s, uvs[0][k], uvs[1][k], lo, hi).map(|span| s = span)
+        } else if down[k] != 0.0 {
+            intersect_span(t, uvs[0][k], uvs[3][k], lo, hi).map(|span| t = span)
+        } else if uvs[0][k] >= lo && uvs[0][k] <= hi {
+            Some(())
+        } else {
+            None
+        };
+        if res.is_none() {
+            return None;
+        }
+    }
+    let lerp = |a: &[f32; 2], b: &[f32; 2], f: f32| [a[0] + (b[0] - a[0]) * f, a[1] + (b[1] - a[1]) * f];
+    let point = |q: &[[f32; 2]; 4], s: f32, t: f32| {
+        lerp(&lerp(&q[0], &q[1], s), &lerp(&q[3], &q[2], s), t)
+    };
+    let params = [(s.0, t.0), (s.1, t.0), (s.1, t.1), (s.0, t.1)];
+    let mut new_corners = [[0.0; 2]; 4];
+    let mut new_uvs = [[0.0; 2]; 4];
+    for (ii, &(s, t)) in params.iter().enumerate() {
+        new_corners[ii] = point(corners, s, t);
+        new_uvs[ii] = trimmed_uv(&point(uvs, s, t), trim);
+    }
+    Some((new_corners, new_uvs))
+}
+
+/// Narrows `span`, a range of fractions along an edge whose UV component
+/// goes from `a` to `b` (which must differ), to where the component is
+/// between `lo` and `hi`. Returns None if the span would be empty.
+fn intersect_span(span: (f32, f32), a: f32, b: f32, lo: f32, hi: f32) -> Option<(f32, f32)> {
+    let (pa, pb) = ((lo - a) / (b - a), (hi - a) / (b - a));
+    let (p0, p1) = (pa.min(pb), pa.max(pb));
+    let span = (span.0.max(p0), span.1.min(p1));
+    if span.0 < span.1 { Some(span) } else { None }
+}
+
+fn clamp_uv(uv: &[f32; 2], trim: &[f32; 4]) -> [f32; 2] {
+    [uv[0].max(trim[0]).min(trim[2]), uv[1].max(trim[1]).min(trim[3])]
+}
+
+/// Maps a UV on the original texture onto the trimmed texture.
+fn trimmed_uv(uv: &[f32; 2], trim: &[f32; 4]) -> [f32; 2] {
+    [(uv[0] - trim[0]) / (trim[2] - trim[0]), (uv[1] - trim[1]) / (trim[3] - trim[1])]
+}
+
+/// The corners of a rectangle rotated by `angle` radians about `pivot` -
+/// top left, top right, bottom right, bottom left, before rotating.
+fn rotated_corners(aabb: &[f32; 4], angle: f32, pivot: &[f32; 2]) -> [[f32; 2]; 4] {
+    let (sin, cos) = angle.sin_cos();
+    let rotate = |x: f32, y: f32| {
+        let (dx, dy) = (x - pivot[0], y - pivot[1]);
+        [pivot[0] + dx * cos - dy * sin, pivot[1] + dx * sin + dy * cos]
+    };
+    let (x0, y0, x1, y1) = (aabb[0], aabb[1], aabb[0] + aabb[2], aabb[1] + aabb[3]);
+    [rotate(x0, y0), rotate(x1, y0), rotate(x1, y1), rotate(x0, y1)]
+}
+
+/// The number of segments needed for a circle of the given radius in pixels
+/// to look smooth, i.e. for no point on its edge to be more than a quarter of
+/// a pixel from the true circle.
+fn auto_segments(rad: f32) -> usize {
+    use std::f32::consts::PI;
+    const MAX_ERROR: f32 = 0.25;
+    const MIN_SEGMENTS: usize = 8;
+    const MAX_SEGMENTS: usize = 512;
+    let rad = rad.abs();
+    if rad <= MAX_ERROR {
+        return MIN_SEGMENTS;
+    }
+    // Each segment is a chord subtending an angle of 2 * acos(1 - e / r).
+    let angle = 2.0 * (1.0 - MAX_ERROR / rad).acos();
+    let segments = (2.0 * PI / angle).ceil() as usize;
+    segments.max(MIN_SEGMENTS).min(MAX_SEGMENTS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_segments_grows_with_radius() {
+        assert_eq!(auto_segments(0.0), 8);
+        assert_eq!(auto_segments(2.0), 8);
+        let small = auto_segments(10.0);
+        let big = auto_segments(500.0);
+        assert!(small > 8 && big > small, "{} {}", small, big);
+        assert_eq!(auto_segments(1e9), 512);
+    }
+
+    #[test]
+    fn rotated_corners_turn_clockwise_about_the_pivot() {
+        use std::f32::consts::FRAC_PI_2;
+        let corners = rotated_corners(&[0.0, 0.0, 4.0, 2.0], FRAC_PI_2, &[0.0, 0.0]);
+        let expected = [[0.0, 0.0], [0.0, 4.0], [-2.0, 4.0], [-2.0, 0.0]];
+        for (c, e) in corners.iter().zip(expected.iter()) {
+            assert!((c[0] - e[0]).abs() < 1e-5 && (c[1] - e[1]).abs() < 1e-5, "{:?}", corners);
+        }
+        // Rotating about the centre leaves the centre where it is.
+        let c = rotated_corners(&[10.0, 10.0, 4.0, 2.0], 1.0, &[12.0, 11.0]);
+        assert!(((c[0][0] + c[2][0]) / 2.0 - 12.0).abs() < 1e-5);
+        assert!(((c[0][1] + c[2][1]) / 2.0 - 11.0).abs() < 1e-5);
+    }
 
-        self.buffer.append(&mut vertices);
-        return (bb_x, bb_y);
+    #[test]
+    fn trimmed_quads_are_cropped_to_the_kept_part() {
+        // The right half and bottom quarter of the texture were trimmed.
+        let trim = [0.0, 0.0, 0.5, 0.75];
+        let corners = aabb_corners(&[10.0, 10.0, 8.0, 8.0]);
+        let (c, uvs) = trim_quad(&corners, &TEX_UVS, &trim).unwrap();
+        assert_eq!(c, aabb_corners(&[10.0, 10.0, 4.0, 6.0]));
+        assert_eq!(uvs, TEX_UVS);
+        // Flipped horizontally, the kept part is on the right.
+        let flipped = [[1.0, 0.0], [0.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+        let (c, uvs) = trim_quad(&corners, &flipped, &trim).unwrap();
+        assert_eq!(c, aabb_corners(&[14.0, 10.0, 4.0, 6.0]));
+        assert_eq!(uvs, [[1.0, 0.0], [0.0, 0.0], [0.0, 1.0], [1.0, 1.0]]);
+        // A quad showing only the trimmed part draws nothing.
+        let border = [[0.75, 0.0], [1.0, 0.0], [1.0, 1.0], [0.75, 1.0]];
+        assert!(trim_quad(&corners, &border, &trim).is_none());
     }
 }
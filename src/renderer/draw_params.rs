@@ -0,0 +1,191 @@
+use glium::{self, Surface};
+use glium::draw_parameters::{Depth, DepthTest, PolygonMode};
+
+/// A set of overrides for the glium DrawParameters used to draw a layer or a
+/// batch. Only settings which can't break the renderer are exposed - blending,
+/// viewport and scissoring are always controlled by the renderer. Settings
+/// which aren't overridden are left as the renderer sets them.
+///
+/// Overrides can also set a parallax factor, which scales how far the camera
+/// scrolls what they're drawn with. See QGFX::set_camera().
+///
+/// ```ignore
+/// let overrides = DrawOverrides::new()
+///     .color_mask(true, true, true, false)
+///     .dithering(false);
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct DrawOverrides {
+    depth: Option<(DepthTest, bool)>,
+    color_mask: Option<(bool, bool, bool, bool)>,
+    dithering: Option<bool>,
+    polygon_mode: Option<PolygonMode>,
+    parallax: Option<[f32; 2]>,
+}
+
+/// A handle to DrawOverrides registered with
+/// QGFX::register_draw_overrides(), for use with
+/// RendererController::set_draw_overrides().
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct DrawOverridesHandle(pub(crate) usize);
+
+impl DrawOverrides {
+    /// Creates a set of overrides which doesn't override anything.
+    pub fn new() -> DrawOverrides {
+        DrawOverrides::default()
+    }
+
+    /// Sets the depth test, and whether depth values are written. Everything
+    /// the renderer draws is at depth 0, and the default framebuffer may not
+    /// have a depth buffer, so this is only useful for custom render targets.
+    pub fn depth(mut self, test: DepthTest, write: bool) -> DrawOverrides {
+        self.depth = Some((test, write));
+        self
+    }
+
+    /// Sets which colour channels (red, green, blue, alpha) are written.
+    pub fn color_mask(mut self, r: bool, g: bool, b: bool, a: bool) -> DrawOverrides {
+        self.color_mask = Some((r, g, b, a));
+        self
+    }
+
+    /// Sets whether the output is dithered.
+    pub fn dithering(mut self, dithering: bool) -> DrawOverrides {
+        self.dithering = Some(dithering);
+        self
+    }
+
+    /// Sets how triangles are rasterised - filled, as lines or as points.
+    pub fn polygon_mode(mut self, mode: PolygonMode) -> DrawOverrides {
+        self.polygon_mode = Some(mode);
+        self
+    }
+
+    /// Sets how far the camera scrolls what's drawn with these overrides,
+    /// relative to the camera's movement. 1 (the default) moves with the
+    /// camera, less than 1 moves slower, as if further away, and 0 doesn't
+    /// move at all, for fixed backgrounds.
+    pub fn parallax(mut self, x: f32, y: f32) -> DrawOverrides {
+        self.parallax = Some([x, y]);
+        self
+    }
+
+    /// The parallax factor set with parallax(), or 1, 1.
+    pub fn parallax_factor(&self) -> [f32; 2] {
+        self.parallax.unwrap_or([1.0, 1.0])
+    }
+
+    /// Combines two sets of overrides, with the settings in `other` taking
+    /// priority.
+    pub fn merge(&self, other: &DrawOverrides) -> DrawOverrides {
+        DrawOverrides {
+            depth: other.depth.or(self.depth),
+            color_mask: other.color_mask.or(self.color_mask),
+            dithering: other.dithering.or(self.dithering),
+            polygon_mode: other.polygon_mode.or(self.polygon_mode),
+            parallax: other.parallax.or(self.parallax),
+        }
+    }
+
+    /// Applies these overrides to a set of draw parameters.
+    pub fn apply(&self, params: &mut glium::DrawParameters) {
+        if let Some((test, write)) = self.depth {
+            params.depth = Depth {
+                test: test,
+                write: write,
+                ..Default::default()
+            };
+        }
+        if let Some(mask) = self.color_mask {
+            params.color_mask = mask;
+        }
+        if let Some(dithering) = self.dithering {
+            params.dithering = dithering;
+        }
+        if let Some(mode) = self.polygon_mode {
+            params.polygon_mode = mode;
+        }
+    }
+}
+
+/// What a render target is cleared to before anything is drawn to it.
+/// Parts which aren't cleared keep the target's previous contents, so
+/// clearing nothing accumulates what's drawn over several frames. Depth and
+/// stencil are only cleared for targets which have those buffers.
+///
+/// ```ignore
+/// // Keep the last frame's colours, for motion trails.
+/// qgfx.set_clear(Clear::none().depth(1.0));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Clear {
+    color: Option<[f32; 4]>,
+    depth: Option<f32>,
+    stencil: Option<i32>,
+}
+
+/// Clears the colour to transparent black, which is what the renderer did
+/// before clearing was configurable.
+impl Default for Clear {
+    fn default() -> Clear {
+        Clear::none().color(0.0, 0.0, 0.0, 0.0)
+    }
+}
+
+impl Clear {
+    /// Clears nothing.
+    pub fn none() -> Clear {
+        Clear {
+            color: None,
+            depth: None,
+            stencil: None,
+        }
+    }
+
+    /// Sets the colour to clear to.
+    pub fn color(mut self, r: f32, g: f32, b: f32, a: f32) -> Clear {
+        self.color = Some([r, g, b, a]);
+        self
+    }
+
+    /// Sets the depth to clear to.
+    pub fn depth(mut self, depth: f32) -> Clear {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Sets the stencil value to clear to.
+    pub fn stencil(mut self, stencil: i32) -> Clear {
+        self.stencil = Some(stencil);
+        self
+    }
+
+    /// Clears a surface. Does nothing if nothing is cleared.
+    pub fn apply<S: Surface>(&self, target: &mut S) {
+        if *self == Clear::none() {
+            return;
+        }
+        let color = self.color.map(|c| (c[0], c[1], c[2], c[3]));
+        target.clear(None, color, false, self.depth, self.stencil);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_prefers_other_and_keeps_unset() {
+        let a = DrawOverrides::new().dithering(false).color_mask(true, true, true, false);
+        let b = DrawOverrides::new().dithering(true).polygon_mode(PolygonMode::Line);
+        let merged = a.merge(&b);
+        let mut params = glium::DrawParameters::default();
+        merged.apply(&mut params);
+        assert_eq!(params.dithering, true);
+        assert_eq!(params.color_mask, (true, true, true, false));
+        assert_eq!(params.polygon_mode, PolygonMode::Line);
+        assert_eq!(params.depth.write, false);
+        assert_eq!(merged.parallax_factor(), [1.0, 1.0]);
+        assert_eq!(a.merge(&b.parallax(0.5, 0.0)).parallax_factor(), [0.5, 0.0]);
+    }
+}
@@ -0,0 +1,227 @@
+use std::f32::consts::PI;
+
+/// The number of wedges a radial gradient is split into. Within a wedge, the
+/// distance from the centre is approximated by the distance along the
+/// wedge's bisector, which is at most 0.12% short with this many wedges.
+const RADIAL_WEDGES: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Shape {
+    Linear { start: [f32; 2], end: [f32; 2] },
+    Radial { centre: [f32; 2], radius: f32 },
+}
+
+/// A fill whose colour varies across a shape, with any number of colour
+/// stops. Colours are interpolated between stops, and the nearest stop's
+/// colour is used before the first stop and after the last. Draw with
+/// RendererController::gradient_rect(), gradient_rounded_rect() or
+/// gradient_polygon().
+///
+/// ```ignore
+/// let sky = Gradient::linear([0.0, 0.0], [0.0, 480.0])
+///     .stop(0.0, [0.1, 0.2, 0.6, 1.0])
+///     .stop(0.7, [0.9, 0.6, 0.4, 1.0])
+///     .stop(1.0, [1.0, 0.9, 0.7, 1.0]);
+/// controller.gradient_rect(&[0.0, 0.0, 640.0, 480.0], &sky);
+/// ```
+///
+/// Gradients are drawn by splitting shapes into bands between the stops, so
+/// the colour changes linearly within each band. Linear gradients are exact.
+/// Radial gradients are also split into wedges around the centre, so their
+/// bands are very slightly polygonal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    shape: Shape,
+    /// The stops' positions and colours, sorted by position.
+    stops: Vec<(f32, [f32; 4])>,
+}
+
+impl Gradient {
+    /// A gradient along the line from `start` (position 0) to `end`
+    /// (position 1). The colour is constant across the line.
+    pub fn linear(start: [f32; 2], end: [f32; 2]) -> Gradient {
+        Gradient {
+            shape: Shape::Linear {
+                start: start,
+                end: end,
+            },
+            stops: Vec::new(),
+        }
+    }
+
+    /// A gradient out from `centre` (position 0) to `radius` (position 1).
+    pub fn radial(centre: [f32; 2], radius: f32) -> Gradient {
+        Gradient {
+            shape: Shape::Radial {
+                centre: centre,
+                radius: radius,
+            },
+            stops: Vec::new(),
+        }
+    }
+
+    /// Adds a colour stop at a position along the gradient. Stops can be
+    /// added in any order. A gradient with no stops draws nothing.
+    pub fn stop(mut self, pos: f32, col: [f32; 4]) -> Gradient {
+        let ix = self.stops
+            .iter()
+            .position(|&(p, _)| p > pos)
+            .unwrap_or(self.stops.len());
+        self.stops.insert(ix, (pos, col));
+        self
+    }
+
+    /// The colour at a position along the gradient.
+    pub fn color_at(&self, pos: f32) -> [f32; 4] {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return [0.0; 4],
+        };
+        if pos <= first.0 {
+            return first.1;
+        }
+        for pair in self.stops.windows(2) {
+            let ((p0, c0), (p1, c1)) = (pair[0], pair[1]);
+            if pos < p1 {
+                let t = (pos - p0) / (p1 - p0);
+                return [
+                    c0[0] + (c1[0] - c0[0]) * t,
+                    c0[1] + (c1[1] - c0[1]) * t,
+                    c0[2] + (c1[2] - c0[2]) * t,
+                    c0[3] + (c1[3] - c0[3]) * t,
+                ];
+            }
+        }
+        last.1
+    }
+
+    /// Triangulates a convex polygon filled with the gradient.
+    /// # Returns
+    /// The triangles' vertices and their colours, three per triangle.
+    pub(crate) fn fill(&self, polygon: &[[f32; 2]]) -> Vec<([f32; 2], [f32; 4])> {
+        let mut tris = Vec::new();
+        if polygon.len() < 3 || self.stops.is_empty() {
+            return tris;
+        }
+        match self.shape {
+            Shape::Linear { start, end } => {
+                let dir = [end[0] - start[0], end[1] - start[1]];
+                let len2 = dir[0] * dir[0] + dir[1] * dir[1];
+                let axis = if len2 > 0.0 {
+                    [dir[0] / len2, dir[1] / len2]
+                } else {
+                    [0.0, 0.0]
+                };
+                self.fill_bands(polygon, start, axis, &mut tris);
+            }
+            Shape::Radial { centre, radius } => {
+                let step = 2.0 * PI / RADIAL_WEDGES as f32;
+                let scale = if radius > 0.0 { 1.0 / radius } else { 0.0 };
+                for ii in 0..RADIAL_WEDGES {
+                    let (a0, a1) = (step * ii as f32, step * (ii + 1) as f32);
+                    let mid = (a0 + a1) / 2.0;
+                    let side = |a: f32, p: [f32; 2]| {
+                        (p[0] - centre[0]) * -a.sin() + (p[1] - centre[1]) * a.cos()
+                    };
+                    let wedge = clip(&clip(polygon, |p| side(a0, p)), |p| -side(a1, p));
+                    let axis = [mid.cos() * scale, mid.sin() * scale];
+                    self.fill_bands(&wedge, centre, axis, &mut tris);
+                }
+            }
+        }
+        tris
+    }
+
+    /// Fills a convex polygon, where the position along the gradient is
+    /// dot(p - origin, axis). The polygon is split into bands between the
+    /// stops, so the colours can be interpolated between the vertices.
+    fn fill_bands(
+        &self,
+        polygon: &[[f32; 2]],
+        origin: [f32; 2],
+        axis: [f32; 2],
+        tris: &mut Vec<([f32; 2], [f32; 4])>,
+    ) {
+        let pos = |p: [f32; 2]| (p[0] - origin[0]) * axis[0] + (p[1] - origin[1]) * axis[1];
+        let mut bounds: Vec<f32> = self.stops.iter().map(|&(p, _)| p).collect();
+        bounds.dedup();
+        for ii in 0..bounds.len() + 1 {
+            let mut band = polygon.to_vec();
+            if ii > 0 {
+                band = clip(&band, |p| pos(p) - bounds[ii - 1]);
+            }
+            if ii < bounds.len() {
+                band = clip(&band, |p| bounds[ii] - pos(p));
+            }
+            for jj in 1..band.len().saturating_sub(1) {
+                for &p in &[band[0], band[jj], band[jj + 1]] {
+                    tris.push((p, self.color_at(pos(p))));
+                }
+            }
+        }
+    }
+}
+
+/// Clips a convex polygon to the half-plane where `dist` is at least 0.
+/// `dist` must be linear.
+fn clip<F: Fn([f32; 2]) -> f32>(polygon: &[[f32; 2]], dist: F) -> Vec<[f32; 2]> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for ii in 0..polygon.len() {
+        let (a, b) = (polygon[ii], polygon[(ii + 1) % polygon.len()]);
+        let (da, db) = (dist(a), dist(b));
+        if da >= 0.0 {
+            clipped.push(a);
+        }
+        // Vertices on the edge are kept, rather than adding an intersection
+        // too, so bands touching the polygon at a point or edge are empty.
+        if (da > 0.0 && db < 0.0) || (da < 0.0 && db > 0.0) {
+            let t = da / (da - db);
+            clipped.push([a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]);
+        }
+    }
+    clipped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(tris: &[([f32; 2], [f32; 4])]) -> f32 {
+        tris.chunks(3)
+            .map(|t| {
+                let (a, b, c) = (t[0].0, t[1].0, t[2].0);
+                ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])).abs() / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn stops_interpolate_and_clamp() {
+        let g = Gradient::linear([0.0, 0.0], [10.0, 0.0])
+            .stop(1.0, [0.0, 0.0, 1.0, 1.0])
+            .stop(0.0, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(g.color_at(-1.0), [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(g.color_at(0.5), [0.5, 0.0, 0.5, 1.0]);
+        assert_eq!(g.color_at(2.0), [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn fills_cover_the_polygon() {
+        let square = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]];
+        let linear = Gradient::linear([0.0, 0.0], [10.0, 0.0])
+            .stop(0.0, [1.0; 4])
+            .stop(0.5, [0.5; 4])
+            .stop(1.0, [0.0; 4]);
+        let tris = linear.fill(&square);
+        assert!((area(&tris) - 100.0).abs() < 1e-3);
+        // Two bands inside the square, and the vertices match the stops.
+        assert_eq!(tris.len(), 12);
+        for &(p, col) in &tris {
+            assert!((col[0] - (1.0 - p[0] / 10.0)).abs() < 1e-4);
+        }
+
+        let radial = Gradient::radial([5.0, 5.0], 5.0).stop(0.0, [1.0; 4]).stop(1.0, [0.0; 4]);
+        assert!((area(&radial.fill(&square)) - 100.0).abs() < 1e-2);
+        assert!(Gradient::radial([5.0, 5.0], 5.0).fill(&square).is_empty());
+    }
+}
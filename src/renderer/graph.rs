@@ -0,0 +1,432 @@
+//! Frame graphs, for drawing a frame in several passes. Each pass declares
+//! the targets it reads and the target it draws into, and the renderer works
+//! out the order to run them in. Intermediate targets are transient: they
+//! only exist while a pass needs them, and their textures are pooled, so
+//! targets of the same size share textures between passes and frames.
+//!
+//! ```ignore
+//! let mut graph = FrameGraph::new();
+//! let scene = graph.transient(320, 180);
+//! graph.pass("scene", &[], scene, |controller, _| {
+//!     controller.rect(&[0.0, 0.0, 32.0, 32.0], &[1.0, 0.0, 0.0, 1.0]);
+//! });
+//! graph.pass("upscale", &[scene], PassTarget::Screen, |controller, inputs| {
+//!     controller.sprite(&[0.0, 0.0, 1280.0, 720.0], inputs[0]);
+//! });
+//! qgfx.render_graph(&mut graph)?;
+//! ```
+//!
+//! Targets can be cleared differently, or not at all, with
+//! FrameGraph::set_clear(), and passes can set their own draw parameter
+//! overrides, e.g. to only write some colour channels.
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+use glium::{self, Surface};
+use glium::framebuffer::SimpleFrameBuffer;
+use error::QgfxError;
+use res::tex::TexHandle;
+use res::tex::glium_cache::CacheTexture;
+use renderer::{recv_batches, pad_batches, Clear, DrawOverrides, Renderer, RendererController};
+
+/// A target which a frame graph pass draws into.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PassTarget {
+    /// The window. Passes drawing to the screen are always run.
+    Screen,
+    /// A transient target, created with FrameGraph::transient().
+    Transient(usize),
+}
+
+/// A pass declared with FrameGraph::pass().
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PassId(usize);
+
+/// The closure drawing a pass. It's given a controller drawing into the
+/// pass's target, and the textures of the pass's inputs, in the order they
+/// were declared.
+pub type DrawPass<'g, 'a> = Box<FnMut(&mut RendererController<'a>, &[TexHandle]) + 'g>;
+
+struct Pass<'g, 'a: 'g> {
+    name: String,
+    inputs: Vec<PassTarget>,
+    output: PassTarget,
+    overrides: DrawOverrides,
+    draw: DrawPass<'g, 'a>,
+}
+
+/// A set of passes to draw a frame with. See the module documentation.
+pub struct FrameGraph<'g, 'a: 'g> {
+    /// The size of each transient target, in pixels.
+    transients: Vec<(u32, u32)>,
+    passes: Vec<Pass<'g, 'a>>,
+    /// How targets are cleared, if not the default. See set_clear().
+    clears: HashMap<PassTarget, Clear>,
+}
+
+impl<'g, 'a: 'g> FrameGraph<'g, 'a> {
+    pub fn new() -> FrameGraph<'g, 'a> {
+        FrameGraph {
+            transients: Vec::new(),
+            passes: Vec::new(),
+            clears: HashMap::new(),
+        }
+    }
+
+    /// Declares a transient target of the given size, in pixels. Its
+    /// contents start out transparent every frame.
+    pub fn transient(&mut self, w: u32, h: u32) -> PassTarget {
+        self.transients.push((w, h));
+        PassTarget::Transient(self.transients.len() - 1)
+    }
+
+    /// Declares a pass. Passes run after every pass drawing into one of their
+    /// inputs, and passes drawing into the same target run in the order they
+    /// were declared. Passes drawing into transient targets which nothing
+    /// reads are skipped.
+    /// # Params
+    /// * `name` - The pass's name, for error messages.
+    /// * `inputs` - The targets the pass reads.
+    /// * `output` - The target the pass draws into. Transient targets are
+    ///              drawn with (0, 0) at their top left and (w, h) at their
+    ///              bottom right, and the screen with the projection size.
+    /// * `draw` - Draws the pass.
+    pub fn pass<F>(
+        &mut self,
+        name: &str,
+        inputs: &[PassTarget],
+        output: PassTarget,
+        draw: F,
+    ) -> PassId
+    where
+        F: FnMut(&mut RendererController<'a>, &[TexHandle]) + 'g,
+    {
+        self.passes.push(Pass {
+            name: name.to_owned(),
+            inputs: inputs.to_vec(),
+            output: output,
+            overrides: DrawOverrides::new(),
+            draw: Box::new(draw),
+        });
+        PassId(self.passes.len() - 1)
+    }
+
+    /// Sets what a target is cleared to before its first pass. Transient
+    /// targets are cleared to transparent black by default, and the screen
+    /// as set with QGFX::set_clear().
+    ///
+    /// A transient target which isn't cleared starts out with whatever its
+    /// pooled texture last held. Pooled textures are handed out in the same
+    /// order every frame, so in a graph which doesn't change, that's the
+    /// target's contents from the previous frame, for accumulating.
+    pub fn set_clear(&mut self, target: PassTarget, clear: Clear) {
+        self.clears.insert(target, clear);
+    }
+
+    /// Sets draw parameter overrides for everything a pass draws, applied on
+    /// top of the main layer's overrides.
+    pub fn set_pass_overrides(&mut self, pass: PassId, overrides: DrawOverrides) {
+        self.passes[pass.0].overrides = overrides;
+    }
+
+    /// Orders the passes so that every pass runs after the passes drawing
+    /// into its inputs, leaving out passes whose output is never read.
+    /// # Returns
+    /// The indices of the passes to run, in order.
+    /// # Errors
+    /// Returns QgfxError::InvalidFrameGraph if a pass reads the screen, a
+    /// target nothing draws into or its own output, or if passes depend on
+    /// each other in a cycle.
+    fn schedule(&self) -> Result<Vec<usize>, QgfxError> {
+        let writers = |target: PassTarget| {
+            self.passes
+                .iter()
+                .enumerate()
+                .filter(move |&(_, pass)| pass.output == target)
+                .map(|(ix, _)| ix)
+        };
+        for pass in &self.passes {
+            for &target in pass.inputs.iter().chain(Some(&pass.output)) {
+                if let PassTarget::Transient(t) = target {
+                    if t >= self.transients.len() {
+                        return Err(invalid(pass, "uses a target from another frame graph"));
+                    }
+                }
+            }
+            for &input in &pass.inputs {
+                if input == PassTarget::Screen {
+                    return Err(invalid(pass, "reads the screen"));
+                } else if input == pass.output {
+                    return Err(invalid(pass, "reads its own output"));
+                } else if writers(input).next().is_none() {
+                    return Err(invalid(pass, "reads a target no pass draws into"));
+                }
+            }
+        }
+
+        // Passes drawing to the screen are needed, and so are the passes
+        // drawing into the inputs of needed passes.
+        let mut needed: Vec<bool> = self.passes
+            .iter()
+            .map(|pass| pass.output == PassTarget::Screen)
+            .collect();
+        let mut stack: Vec<usize> = (0..self.passes.len()).filter(|&ix| needed[ix]).collect();
+        while let Some(ix) = stack.pop() {
+            for &input in &self.passes[ix].inputs {
+                for writer in writers(input) {
+                    if !needed[writer] {
+                        needed[writer] = true;
+                        stack.push(writer);
+                    }
+                }
+            }
+        }
+
+        // The passes each pass has to run after.
+        let deps: Vec<Vec<usize>> = self.passes
+            .iter()
+            .enumerate()
+            .map(|(ix, pass)| {
+                let mut deps: Vec<usize> = pass.inputs
+                    .iter()
+                    .flat_map(|&input| writers(input))
+                    .chain(writers(pass.output).take_while(|&writer| writer < ix))
+                    .collect();
+                deps.sort();
+                deps.dedup();
+                deps
+            })
+            .collect();
+
+        // Run the first pass that's ready each time, so independent passes
+        // keep the order they were declared in.
+        let mut order = Vec::new();
+        let mut done = vec![false; self.passes.len()];
+        let count = needed.iter().filter(|&&n| n).count();
+        while order.len() < count {
+            let next = (0..self.passes.len()).find(|&ix| {
+                needed[ix] && !done[ix] && deps[ix].iter().all(|&dep| done[dep])
+            });
+            match next {
+                Some(ix) => {
+                    done[ix] = true;
+                    order.push(ix);
+                }
+                None => {
+                    let stuck = (0..self.passes.len())
+                        .find(|&ix| needed[ix] && !done[ix])
+                        .unwrap();
+                    let problem = "is in, or depends on, a cycle of passes";
+                    return Err(invalid(&self.passes[stuck], problem));
+                }
+            }
+        }
+        Ok(order)
+    }
+}
+
+fn invalid(pass: &Pass, problem: &str) -> QgfxError {
+    QgfxError::InvalidFrameGraph(format!("Pass '{}' {}", pass.name, problem))
+}
+
+/// A projection for drawing into a cache texture, with (0, 0) at the top
+/// left and (w, h) at the bottom right. Cache textures store their top row
+/// first, so this is flipped compared to ortho_proj().
+fn target_proj(w: u32, h: u32) -> [[f32; 4]; 4] {
+    [
+        [2.0 / w as f32, 0.0, 0.0, 0.0],
+        [0.0, 2.0 / h as f32, 0.0, 0.0],
+        [0.0, 0.0, -1.0, 0.0],
+        [-1.0, -1.0, 0.0, 1.0],
+    ]
+}
+
+impl<'a> Renderer<'a> {
+    /// Runs the passes of a frame graph, drawing passes which target the
+    /// screen into `screen`, which is cleared first. Transient targets are taken from the target
+    /// pool, creating a dedicated cache texture for each size that isn't
+    /// free, and go back to the pool after the last pass using them.
+    /// # Errors
+    /// Returns an error if the graph is invalid (see FrameGraph::pass()), a
+    /// target texture couldn't be created, or drawing failed.
+    pub fn render_graph<'g, F, S>(
+        &mut self,
+        display: &F,
+        white: TexHandle,
+        graph: &mut FrameGraph<'g, 'a>,
+        screen: &mut S,
+    ) -> Result<(), QgfxError>
+    where
+        F: glium::backend::Facade,
+        S: Surface,
+    {
+        let order = try!(graph.schedule());
+        graph.clears.get(&PassTarget::Screen).cloned().unwrap_or(self.get_clear()).apply(screen);
+
+        // The position in the order after which each transient is unused.
+        let mut last_use = HashMap::new();
+        for (pos, &ix) in order.iter().enumerate() {
+            let pass = &graph.passes[ix];
+            for &target in pass.inputs.iter().chain(Some(&pass.output)) {
+                if let PassTarget::Transient(t) = target {
+                    last_use.insert(t, pos);
+                }
+            }
+        }
+
+        let mut free = self.target_pool.clone();
+        let mut assigned: HashMap<usize, TexHandle> = HashMap::new();
+        for (pos, &ix) in order.iter().enumerate() {
+            // Drawing into a transient for the first time takes a texture
+            // from the pool.
+            let mut clear = None;
+            if let PassTarget::Transient(t) = graph.passes[ix].output {
+                if !assigned.contains_key(&t) {
+                    let size = graph.transients[t];
+                    let tex = match free.iter().position(|&(s, _)| s == size) {
+                        Some(free_ix) => free.remove(free_ix).1,
+                        None => {
+                            let tex = try!(self.create_streaming_tex(display, size.0, size.1));
+                            debug!("Frame graph target pool grew to {} textures",
+                                   self.target_pool.len() + 1);
+                            self.target_pool.push((size, tex.handle()));
+                            tex.handle()
+                        }
+                    };
+                    assigned.insert(t, tex);
+                    clear = Some(graph.clears
+                        .get(&PassTarget::Transient(t))
+                        .cloned()
+                        .unwrap_or_default());
+                }
+            }
+
+            let pass = &mut graph.passes[ix];
+            let inputs: Vec<TexHandle> = pass.inputs
+                .iter()
+                .map(|input| match *input {
+                    PassTarget::Transient(t) => assigned[&t],
+                    PassTarget::Screen => unreachable!(),
+                })
+                .collect();
+            let (sender, receiver) = mpsc::channel();
+            {
+                let pool = self.buffer_pool.clone();
+                let mut controller = self.controller_with_sender(sender, pool, white);
+                (pass.draw)(&mut controller, &inputs);
+                controller.flush();
+            }
+            let (_, mut batches, glyphs) =
+                recv_batches(&receiver, &self.buffer_pool, &mut self.arena);
+            pad_batches(&mut batches);
+
+            let overrides = self.main_overrides.merge(&pass.overrides);
+            let res = match pass.output {
+                PassTarget::Screen => {
+                    self.draw_batches(screen, &batches, self.proj_mat, [0.0, 0.0], &overrides)
+                        .and_then(|_| {
+                            self.draw_glyph_batches(screen, &glyphs, self.proj_mat, [0.0, 0.0],
+                                                    &overrides)
+                        })
+                }
+                PassTarget::Transient(t) => {
+                    self.draw_to_target(display, assigned[&t], clear, &batches, &glyphs,
+                                        &overrides)
+                }
+            };
+            self.arena.recycle(batches.into_iter().map(|(_, list)| list));
+            try!(res);
+
+            for &target in pass.inputs.iter().chain(Some(&pass.output)) {
+                if let PassTarget::Transient(t) = target {
+                    if last_use[&t] == pos {
+                        if let Some(tex) = assigned.remove(&t) {
+                            free.push((graph.transients[t], tex));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws batches into a pooled target texture, clearing it first if
+    /// `clear` is set. Alpha is blended as coverage, as targets start out
+    /// transparent, so partly transparent pixels hold premultiplied colours.
+    fn draw_to_target<F: glium::backend::Facade>(
+        &mut self,
+        display: &F,
+        tex: TexHandle,
+        clear: Option<Clear>,
+        batches: &[(super::BatchKey, Vec<super::Vertex>)],
+        glyphs: &[(usize, Vec<super::GlyphInstance>)],
+        overrides: &DrawOverrides,
+    ) -> Result<usize, QgfxError> {
+        use res::tex::{TexCache, TexHandleLookup};
+        let page = self.tex_cache
+            .rect_for(tex)
+            .expect("Frame graph target missing from the texture cache")
+            .0;
+        let transparent = self.transparent;
+        self.transparent = true;
+        let res = match self.tex_cache.get_tex_with_ix(page) {
+            Some(&CacheTexture::Rgba(ref page)) => SimpleFrameBuffer::new(display, page)
+                .map_err(QgfxError::from)
+                .and_then(|mut fb| {
+                    if let Some(clear) = clear {
+                        clear.apply(&mut fb);
+                    }
+                    let (w, h) = page.dimensions();
+                    try!(self.draw_batches(&mut fb, batches, target_proj(w, h), [0.0, 0.0],
+                                           overrides));
+                    self.draw_glyph_batches(&mut fb, glyphs, target_proj(w, h), [0.0, 0.0],
+                                            overrides)
+                }),
+            _ => panic!("Frame graph target isn't an RGBA cache texture"),
+        };
+        self.transparent = transparent;
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph_order(graph: &FrameGraph) -> Vec<String> {
+        graph.schedule()
+            .unwrap()
+            .into_iter()
+            .map(|ix| graph.passes[ix].name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn passes_run_after_their_inputs() {
+        let mut graph = FrameGraph::new();
+        let scene = graph.transient(64, 64);
+        let blur = graph.transient(32, 32);
+        let unused = graph.transient(8, 8);
+        graph.pass("composite", &[scene, blur], PassTarget::Screen, |_, _| {});
+        graph.pass("blur", &[scene], blur, |_, _| {});
+        graph.pass("unused", &[], unused, |_, _| {});
+        graph.pass("scene", &[], scene, |_, _| {});
+        graph.pass("scene overlay", &[], scene, |_, _| {});
+        assert_eq!(graph_order(&graph), vec!["scene", "scene overlay", "blur", "composite"]);
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let mut graph = FrameGraph::new();
+        let a = graph.transient(8, 8);
+        let b = graph.transient(8, 8);
+        graph.pass("a", &[b], a, |_, _| {});
+        graph.pass("b", &[a], b, |_, _| {});
+        graph.pass("screen", &[a], PassTarget::Screen, |_, _| {});
+        assert!(graph.schedule().is_err());
+
+        let mut graph = FrameGraph::new();
+        graph.pass("screen", &[PassTarget::Screen], PassTarget::Screen, |_, _| {});
+        assert!(graph.schedule().is_err());
+    }
+}
@@ -4,44 +4,424 @@ mod shader;
 /// send data to the renderer.
 mod controller;
 
-pub use self::controller::RendererController;
+/// Rendering at a fixed virtual resolution, scaled up to the window.
+mod virtual_res;
 
+/// Safe overrides for the draw parameters of layers and batches.
+mod draw_params;
+
+/// Caching of tessellated shape geometry.
+mod tessellation;
+
+/// Reuse of vertex batch buffers between frames.
+mod arena;
+
+/// The offscreen target and final pass for post-processing.
+mod post;
+
+/// Frame graphs, for scheduling render passes.
+mod graph;
+
+/// Gradient fills with multiple colour stops.
+mod gradient;
+
+/// Stroke styles, and triangulating strokes.
+mod stroke;
+
+/// Vector paths made of lines and curves.
+mod path;
+
+/// Recording controller calls to replay later.
+mod command_list;
+
+pub use self::controller::{RendererController, RenderTextureError, MeshVertex};
+pub use self::virtual_res::{VirtualTarget, ScaledTarget};
+pub use self::draw_params::{DrawOverrides, DrawOverridesHandle, Clear};
+pub use self::tessellation::TessellationCache;
+pub use self::arena::ArenaStats;
+use self::arena::{PacketArena, VertexArena};
+pub use self::post::{PostProcess, Tonemap, Bloom, Crt};
+pub use self::graph::{FrameGraph, PassTarget, PassId, DrawPass};
+pub use self::gradient::Gradient;
+pub use self::stroke::{StrokeStyle, LineCap, LineJoin};
+pub use self::path::{Path, FillRule, FillStyle};
+pub use self::command_list::{CommandList, Affine, IDENTITY, translation, scale_translation};
+
+#[cfg(any(feature = "font", feature = "image"))]
 use std::path::Path;
-use std::sync::mpsc;
+#[cfg(not(feature = "font"))]
+use std::marker::PhantomData;
+
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Instant;
 use glium::{self, VertexBuffer};
+use error::QgfxError;
+#[cfg(feature = "font")]
 use res::font::glium_cache::GliumFontCache;
-use res::font::{CacheGlyphError, FontHandle};
+#[cfg(feature = "font")]
+use res::font::{CacheGlyphError, CacheReport, FontHandle, PathCommand};
 use res::tex::{CacheTexError, TexHandle};
-use res::tex::glium_cache::GliumTexCache;
+use res::tex::glium_cache::{GliumTexCache, RgbaBuffer, StreamingTexture, CacheTexture,
+                            PixelFormat};
+pub use res::tex::glium_cache::HdrFormat;
+use glium::uniforms::{AsUniformValue, SamplerBehavior, UniformValue};
 
 /// The constant size of the renderer's VBO in vertices (i.e. can contain 1024 vertices)
 pub const VBO_SIZE: usize = 65563;
 
+/// The number of glyphs the renderer's instance buffer holds. Larger batches
+/// of instanced glyphs are drawn in several draw calls.
+pub const GLYPH_VBO_SIZE: usize = 16384;
+
+/// The colour each fragment adds to its pixel in the overdraw heatmap. See
+/// Renderer::set_overdraw_heatmap().
+pub const OVERDRAW_STEP: [f32; 4] = [0.25, 0.1, 0.04, 1.0];
+
+/// Empty vertex packets which the renderer has finished with, kept so
+/// controllers can reuse their memory rather than allocating new buffers.
+pub type BufferPool = Arc<Mutex<PacketArena>>;
+
 /// An enum for texture types. For example, when rendering a font, vertices
 /// should be send with a 'Font' texture type, to indicate they will be drawn
 /// with the font texture as the loaded uniform.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum TexType {
     Texture,
+    #[cfg(feature = "font")]
     Font,
 }
 
+/// How colours are handled between the cache textures, the shader and the
+/// framebuffer. Cache textures are always stored as sRGB, so sampling them
+/// decodes their colours to linear space.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ColorSpace {
+    /// Colours are handled in sRGB space, like most 2D software. Sampled
+    /// texels are encoded back to sRGB in the shader, the shader's output is
+    /// written to the framebuffer unconverted, and blending happens in sRGB
+    /// space. Texture colours come out exactly as they were loaded. This is
+    /// the default.
+    Srgb,
+    /// Colours are handled in linear space. Sampled texels stay linear, an
+    /// sRGB framebuffer is requested, and OpenGL encodes the shader's output
+    /// to sRGB when writing to it, so blending happens in linear space. Vertex
+    /// colours are treated as linear. Offscreen targets (the virtual
+    /// resolution target and render_to_image()) aren't sRGB, so they store
+    /// linear colours.
+    Linear,
+}
+
+impl Default for ColorSpace {
+    fn default() -> ColorSpace {
+        ColorSpace::Srgb
+    }
+}
+
+/// How a texture page is sampled when it's drawn.
+///
+/// Linear filtering and anisotropy smooth scaled or rotated sprites at the
+/// cost of sharpness, while nearest filtering keeps them sharp but makes them
+/// shimmer when they move. Cache textures have no mipmaps, so only the
+/// `Nearest` and `Linear` minify filters should be used.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SamplerSettings {
+    /// The filter used when the texture is drawn smaller than its size.
+    pub minify_filter: glium::uniforms::MinifySamplerFilter,
+    /// The filter used when the texture is drawn bigger than its size.
+    pub magnify_filter: glium::uniforms::MagnifySamplerFilter,
+    /// The maximum level of anisotropic filtering, or None to disable it. This
+    /// is clamped to what the GPU supports.
+    pub anisotropy: Option<u16>,
+}
+
+impl SamplerSettings {
+    /// Linear filtering without anisotropy. This is the default.
+    pub fn linear() -> SamplerSettings {
+        SamplerSettings {
+            minify_filter: glium::uniforms::MinifySamplerFilter::Linear,
+            magnify_filter: glium::uniforms::MagnifySamplerFilter::Linear,
+            anisotropy: None,
+        }
+    }
+
+    /// Nearest neighbour filtering without anisotropy, for crisp pixel art.
+    pub fn nearest() -> SamplerSettings {
+        SamplerSettings {
+            minify_filter: glium::uniforms::MinifySamplerFilter::Nearest,
+            magnify_filter: glium::uniforms::MagnifySamplerFilter::Nearest,
+            anisotropy: None,
+        }
+    }
+
+    /// Returns these settings with the given level of anisotropic filtering.
+    pub fn with_anisotropy(mut self, level: u16) -> SamplerSettings {
+        self.anisotropy = Some(level);
+        self
+    }
+}
+
+impl Default for SamplerSettings {
+    fn default() -> SamplerSettings {
+        SamplerSettings::linear()
+    }
+}
+
+/// The layers vertex data is drawn in, in the order they're drawn.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Layer {
+    /// Everything drawn with the renderer controller.
+    Main,
+    /// Debug drawing and the performance overlay, drawn on top of everything
+    /// else.
+    Overlay,
+}
+
+/// A texture a batch is sampled from. Grayscale and HDR cache pages aren't
+/// sRGB textures, so this covers both glium texture types.
+#[derive(Copy, Clone)]
+enum BatchTex<'t> {
+    Srgb(&'t glium::texture::SrgbTexture2d),
+    /// A grayscale page, which the shader decodes, or an RGBA HDR page, which
+    /// is already linear.
+    Linear(&'t glium::texture::Texture2d, PixelFormat),
+}
+
+impl<'t> BatchTex<'t> {
+    /// The number of channels the texture is stored with, for the shader.
+    fn channels(&self) -> i32 {
+        match *self {
+            BatchTex::Srgb(_) => 4,
+            BatchTex::Linear(_, format) => format.channels() as i32,
+        }
+    }
+}
+
+/// A BatchTex with the sampler settings to draw it with, for use as a
+/// uniform.
+struct BatchSampler<'t>(BatchTex<'t>, SamplerBehavior);
+
+impl<'t> AsUniformValue for BatchSampler<'t> {
+    fn as_uniform_value(&self) -> UniformValue {
+        match self.0 {
+            BatchTex::Srgb(tex) => UniformValue::SrgbTexture2d(tex, Some(self.1)),
+            BatchTex::Linear(tex, _) => UniformValue::Texture2d(tex, Some(self.1)),
+        }
+    }
+}
+
+/// The texture a batch is drawn with. See BatchRecord.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BatchTexture {
+    /// A texture cache page, by index. Textures cached together on an atlas
+    /// page share a batch.
+    Page(usize),
+    /// The glyph cache texture.
+    #[cfg(feature = "font")]
+    Font,
+}
+
+/// A batch received by recv_data(), recorded when batch recording is enabled
+/// (see Renderer::set_batch_recording()). Each batch is drawn with one draw
+/// call, so these can be used to test batching without comparing pixels.
+/// Every batch is alpha blended, so the blend mode isn't recorded.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct BatchRecord {
+    /// The layer the batch is drawn in.
+    pub layer: Layer,
+    pub texture: BatchTexture,
+    /// The number of vertices in the batch, not counting padding.
+    pub vertices: usize,
+    /// The draw overrides set with RendererController::set_draw_overrides(),
+    /// if any.
+    pub draw_overrides: Option<DrawOverridesHandle>,
+}
+
+impl BatchRecord {
+    fn new(layer: Layer, key: &BatchKey, vertices: usize) -> BatchRecord {
+        BatchRecord {
+            layer: layer,
+            texture: match key.tex_type {
+                TexType::Texture => BatchTexture::Page(key.tex_ix),
+                #[cfg(feature = "font")]
+                TexType::Font => BatchTexture::Font,
+            },
+            vertices: vertices,
+            draw_overrides: match key.draw_overrides {
+                0 => None,
+                ix => Some(DrawOverridesHandle(ix)),
+            },
+        }
+    }
+
+    /// The record for a batch of instanced glyphs, counted as the 6 vertices
+    /// each glyph would be drawn as without instancing.
+    #[cfg(feature = "font")]
+    fn glyphs(layer: Layer, draw_overrides: usize, glyphs: usize) -> BatchRecord {
+        BatchRecord::new(layer, &glyph_batch_key(draw_overrides), glyphs * 6)
+    }
+}
+
+/// A vertex as it's uploaded to the VBO. Every field is sent to the shader,
+/// so every field must be listed in implement_vertex!, and anything the
+/// renderer only needs for batching belongs in BatchKey instead.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Vertex {
     /// The position of the vertex. Sent to the shader.
     pub pos: [f32; 2],
     /// The UV coordinates of the vertex. Sent to the shader.
     pub tex_coords: [f32; 2],
-    /// The colour of this vertex. Sent to the shader.
-    pub col: [f32; 4],
+    /// The colour of this vertex, as RGBA8 (see pack_color()). Sent to the
+    /// shader.
+    pub col: [u8; 4],
+    /// The ID drawn into the pick buffer, or 0 for none. See
+    /// RendererController::set_pick_id(). This is a float as GLSL 1.20
+    /// doesn't have integer attributes.
+    pub pick_id: f32,
+}
+implement_vertex!(Vertex, pos, tex_coords, col, pick_id);
+
+/// Converts a colour with components from 0.0 to 1.0 to the RGBA8 colour
+/// stored in a Vertex. Components outside that range are clamped.
+pub fn pack_color(col: &[f32; 4]) -> [u8; 4] {
+    let pack = |c: f32| (c.max(0.0).min(1.0) * 255.0 + 0.5) as u8;
+    [pack(col[0]), pack(col[1]), pack(col[2]), pack(col[3])]
+}
+
+/// A glyph drawn with instancing - one instance of a quad, rather than six
+/// vertices. See RendererController::set_instanced_text().
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GlyphInstance {
+    /// The corners of the glyph's quad - X0, Y0, X1, Y1.
+    pub rect: [f32; 4],
+    /// The glyph's rect in the font cache - X0, Y0, X1, Y1.
+    pub uv: [f32; 4],
+    /// The colour of the glyph, as RGBA8 (see pack_color()).
+    pub col: [u8; 4],
+    /// The y coordinate of the baseline the glyph sits on, and the x offset
+    /// per pixel above the baseline, for faux italics.
+    pub shear: [f32; 2],
+}
+implement_vertex!(GlyphInstance, rect, uv, col, shear);
+
+impl GlyphInstance {
+    /// The 2 triangles the glyph is drawn as without instancing.
+    pub fn vertices(&self) -> [Vertex; 6] {
+        let (baseline, shear) = (self.shear[0], self.shear[1]);
+        let corner = |x: f32, y: f32, u: f32, v: f32| {
+            Vertex {
+                pos: [x + (baseline - y) * shear, y],
+                col: self.col,
+                pick_id: 0.0,
+                tex_coords: [u, v],
+            }
+        };
+        let (r, uv) = (&self.rect, &self.uv);
+        let tl = corner(r[0], r[1], uv[0], uv[1]);
+        let tr = corner(r[2], r[1], uv[2], uv[1]);
+        let br = corner(r[2], r[3], uv[2], uv[3]);
+        let bl = corner(r[0], r[3], uv[0], uv[3]);
+        [tl, tr, br, tl, bl, br]
+    }
+}
+
+/// A corner of the quad every GlyphInstance is drawn as, from 0,0 at the top
+/// left to 1,1 at the bottom right.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GlyphCorner {
+    pub corner: [f32; 2],
+}
+implement_vertex!(GlyphCorner, corner);
+
+/// The corners of the glyph quad, as a triangle strip.
+const GLYPH_CORNERS: [GlyphCorner; 4] = [
+    GlyphCorner { corner: [0.0, 0.0] },
+    GlyphCorner { corner: [1.0, 0.0] },
+    GlyphCorner { corner: [0.0, 1.0] },
+    GlyphCorner { corner: [1.0, 1.0] },
+];
+
+/// What a batch of vertices is drawn with. Vertices with the same key are
+/// drawn together in one draw call.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct BatchKey {
     pub tex_type: TexType,
     /// The index of the texture in the cache to use. Texture caches can have
     /// multiple textures stored in video ram, this number indicates which to
-    /// use. NOT sent to the shader.
-    /// Negative means look to font caches, positive means tex caches.
+    /// use. Ignored for the font cache.
     pub tex_ix: usize,
+    /// The index of the DrawOverrides the batch is drawn with, plus one, or 0
+    /// for none. See RendererController::set_draw_overrides().
+    pub draw_overrides: usize,
+}
+
+/// Vertex data sent from a controller to the renderer. Rather than every
+/// vertex carrying its batch key, the vertices are split into runs which
+/// share a key, and each run's key is only stored once.
+#[derive(Clone, Debug, Default)]
+pub struct VertexPacket {
+    pub vertices: Vec<Vertex>,
+    /// The key of each run of vertices, and the index of the run's first
+    /// vertex. Each run ends where the next one starts.
+    pub runs: Vec<(BatchKey, usize)>,
+    /// Glyphs drawn with instancing, see RendererController::set_instanced_text().
+    pub glyphs: Vec<GlyphInstance>,
+    /// Like runs, but for glyphs. Glyphs are always drawn from the font
+    /// cache, so each run only stores its BatchKey::draw_overrides.
+    pub glyph_runs: Vec<(usize, usize)>,
+    /// The stream the packet was recorded on, and its sequence number in the
+    /// stream, or None if it wasn't recorded on a stream. See
+    /// RendererController::set_stream().
+    pub submission: Option<(u32, u64)>,
+}
+
+impl VertexPacket {
+    pub fn new() -> VertexPacket {
+        VertexPacket::default()
+    }
+
+    /// Makes the vertices pushed from now on part of a run with the given
+    /// key, starting a new run if the current one has a different key.
+    pub fn set_key(&mut self, key: BatchKey) {
+        let start = self.vertices.len();
+        if let Some(last) = self.runs.last_mut() {
+            if last.0 == key {
+                return;
+            }
+            // Replace the current run if nothing has been pushed to it.
+            if last.1 == start {
+                last.0 = key;
+                return;
+            }
+        }
+        self.runs.push((key, start));
+    }
+
+    /// Like set_key(), but for the glyphs pushed from now on.
+    pub fn set_glyph_overrides(&mut self, draw_overrides: usize) {
+        let start = self.glyphs.len();
+        if let Some(last) = self.glyph_runs.last_mut() {
+            if last.0 == draw_overrides {
+                return;
+            }
+            if last.1 == start {
+                last.0 = draw_overrides;
+                return;
+            }
+        }
+        self.glyph_runs.push((draw_overrides, start));
+    }
+
+    /// Removes all the vertices, glyphs and runs, keeping the allocated
+    /// memory.
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.runs.clear();
+        self.glyphs.clear();
+        self.glyph_runs.clear();
+        self.submission = None;
+    }
 }
-implement_vertex!(Vertex, pos, tex_coords, col);
 
 pub struct Renderer<'a> {
     /// The VBO to use. This will have data buffered to it when render() is called.
@@ -50,6 +430,12 @@ pub struct Renderer<'a> {
     /// The program to use for rendering
     program: glium::Program,
 
+    /// The instance buffer instanced glyphs are written to, the quad each
+    /// instance is drawn as, and the program they're drawn with.
+    glyph_vbo: VertexBuffer<GlyphInstance>,
+    glyph_corners: VertexBuffer<GlyphCorner>,
+    glyph_program: glium::Program,
+
     /// The vertex data to be draw when render() is called. Data is moved into
     /// this buffer when `recv_data()` is called, then moved to the VBO for
     /// rendering in `render()`.
@@ -58,156 +444,578 @@ pub struct Renderer<'a> {
     /// vertices that need to be drawn with a given texture are grouped together.
     /// The texture ID is negative if it corresponds to a font texture cache, or
     /// positive for a standard texture cache.
-    v_data_list: Vec<(usize, TexType, Vec<Vertex>)>,
+    v_data_list: Vec<(BatchKey, Vec<Vertex>)>,
 
     /// A tuple containing a sender and receiver - used for sending data to
     /// the renderer from different threads to be stored in v_data for the
     /// render() function.
-    v_channel_pair: (mpsc::Sender<Vec<Vertex>>, mpsc::Receiver<Vec<Vertex>>),
+    v_channel_pair: (mpsc::Sender<VertexPacket>, mpsc::Receiver<VertexPacket>),
+
+    /// Like v_data_list, but drawn after it, on top of everything else.
+    overlay_data_list: Vec<(BatchKey, Vec<Vertex>)>,
+
+    /// Like v_channel_pair, but for vertex data to go in overlay_data_list.
+    overlay_channel_pair: (mpsc::Sender<VertexPacket>, mpsc::Receiver<VertexPacket>),
+
+    /// Instanced glyphs for each layer, grouped by their draw overrides.
+    /// They're drawn after the layer's other batches.
+    glyph_data_list: Vec<(usize, Vec<GlyphInstance>)>,
+    overlay_glyph_list: Vec<(usize, Vec<GlyphInstance>)>,
 
     /// The projection matrix used to render the game.
     proj_mat: [[f32; 4]; 4],
+    /// The offset the main layer is drawn at, in pixels. See set_offset().
+    offset: [f32; 2],
+    /// The camera's position, which the main layer scrolls by. See
+    /// set_camera().
+    camera: [f32; 2],
+    /// The number of draw calls made by the last call to render().
+    draw_calls: usize,
+    /// The number of batches the last call to render() skipped, as their
+    /// texture wasn't in the cache.
+    skipped_batches: usize,
+    /// How textures are sampled, unless their page is in `page_samplers`.
+    sampler: SamplerSettings,
+    /// Sampler settings for individual texture cache pages, keyed by page
+    /// index. See set_page_sampler().
+    page_samplers: HashMap<usize, SamplerSettings>,
+    /// If true, vertex positions are rounded to the nearest pixel.
+    pixel_snap: bool,
+    /// If true, alpha is blended for a transparent window. See
+    /// set_transparent_blending().
+    transparent: bool,
+    /// The colour space colours are handled in. The program is compiled for
+    /// this, so it can't be changed.
+    color_space: ColorSpace,
+    /// The size of the area drawn to, in pixels. See set_projection_size().
+    proj_size: (u32, u32),
+    /// If true, render_pick_buffer() renders pick IDs into `pick_buffer`.
+    picking: bool,
+    /// The texture pick IDs are rendered into, and the program which renders
+    /// them. Created on the first call to render_pick_buffer() with picking
+    /// enabled.
+    pick_buffer: Option<(glium::texture::Texture2d, glium::Program)>,
+    /// The gamma correction exponent for text coverage. See set_text_gamma().
+    text_gamma: f32,
+    /// If true, every batch is rasterised as lines. See set_wireframe().
+    wireframe: bool,
+    /// If true, every batch is tinted by its texture page. See
+    /// set_page_tint().
+    page_tint: bool,
+    /// The program every batch is drawn with instead of the normal one, if
+    /// the overdraw heatmap is shown. See set_overdraw_heatmap().
+    overdraw_program: Option<glium::Program>,
+    /// The textures of the frame graph target pool, with their sizes. See
+    /// render_graph().
+    target_pool: Vec<((u32, u32), TexHandle)>,
+    /// What the frame's targets are cleared to. See set_clear().
+    clear: Clear,
+    /// What the next frame's targets are cleared to instead of `clear`, if
+    /// anything. See set_frame_clear().
+    frame_clear: Option<Clear>,
+    /// The draw parameter overrides for each layer.
+    main_overrides: DrawOverrides,
+    overlay_overrides: DrawOverrides,
+    /// The draw parameter overrides registered for batches, indexed by
+    /// DrawOverridesHandle.
+    batch_overrides: Vec<DrawOverrides>,
+    /// Tessellated geometry shared by every controller.
+    tessellation: Arc<TessellationCache>,
+    /// Buffers received from controllers, emptied and waiting to be reused.
+    buffer_pool: BufferPool,
+    /// Batch buffers from previous frames, waiting to be reused.
+    arena: VertexArena,
+    /// The packet allocation stats for the data received by the last call
+    /// to recv_data().
+    packet_stats: ArenaStats,
+    /// Like buffer_pool and arena, but for render_to_image(), so offscreen
+    /// renders don't count towards the frame's allocation stats.
+    offscreen_pool: BufferPool,
+    offscreen_arena: VertexArena,
+    /// The batches received by the last call to recv_data(), in the order
+    /// they're drawn, if batch recording is enabled.
+    batch_records: Option<Vec<BatchRecord>>,
 
+    #[cfg(feature = "font")]
     font_cache: GliumFontCache<'a>,
     tex_cache: GliumTexCache,
+
+    /// Without the font subsystem, nothing else in the renderer is tied to 'a.
+    #[cfg(not(feature = "font"))]
+    phantom: PhantomData<&'a ()>,
 }
 
 impl<'a> Renderer<'a> {
-    /// Create a new renderer.
+    /// Create a new renderer, with the default colour space.
     /// # Params
     /// * `display` - The glutin display (OpenGL Context)
-    /// * `system` - The SysRenderer being used by the ECS. When rendering,
-    ///              vertex data will be buffered from here.
-    pub fn new<F: glium::backend::Facade>(display: &F) -> Box<Renderer<'a>> {
+    /// # Errors
+    /// Returns an error if any of the GPU resources used by the renderer
+    /// couldn't be created.
+    pub fn new<F: glium::backend::Facade>(display: &F) -> Result<Box<Renderer<'a>>, QgfxError> {
+        Renderer::with_color_space(display, ColorSpace::default())
+    }
+
+    /// Create a new renderer which handles colours in the given colour space.
+    /// The display should have an sRGB framebuffer for ColorSpace::Linear.
+    /// See new() for errors.
+    pub fn with_color_space<F: glium::backend::Facade>(
+        display: &F,
+        color_space: ColorSpace,
+    ) -> Result<Box<Renderer<'a>>, QgfxError> {
         let (w, h) = display.get_context().get_framebuffer_dimensions();
-        let font_cache = GliumFontCache::new(display);
-        Box::new(Renderer {
-            vbo: VertexBuffer::empty_dynamic(display, VBO_SIZE).unwrap(),
-            program: shader::get_program(display),
+        Ok(Box::new(Renderer {
+            vbo: try!(VertexBuffer::empty_dynamic(display, VBO_SIZE)),
+            program: try!(shader::get_program(
+                display,
+                color_space == ColorSpace::Srgb,
+            )),
+            glyph_vbo: try!(VertexBuffer::empty_dynamic(display, GLYPH_VBO_SIZE)),
+            glyph_corners: try!(VertexBuffer::new(display, &GLYPH_CORNERS)),
+            glyph_program: try!(shader::get_glyph_program(
+                display,
+                color_space == ColorSpace::Srgb,
+            )),
+            color_space: color_space,
             v_data_list: Vec::new(),
             v_channel_pair: mpsc::channel(),
-            font_cache: font_cache,
+            overlay_data_list: Vec::new(),
+            overlay_channel_pair: mpsc::channel(),
+            glyph_data_list: Vec::new(),
+            overlay_glyph_list: Vec::new(),
+            draw_calls: 0,
+            skipped_batches: 0,
+            sampler: SamplerSettings::default(),
+            page_samplers: HashMap::new(),
+            pixel_snap: false,
+            transparent: false,
+            proj_size: (w, h),
+            picking: false,
+            pick_buffer: None,
+            #[cfg(feature = "font")]
+            font_cache: try!(GliumFontCache::new(display)),
             tex_cache: GliumTexCache::new(),
-            proj_mat: [
-                [2.0 / w as f32, 0.0, 0.0, -0.0],
-                [0.0, -2.0 / h as f32, 0.0, 0.0],
-                [0.0, 0.0, -1.0, 0.0],
-                [-1.0, 1.0, 0.0, 1.0],
-            ],
-        })
+            #[cfg(not(feature = "font"))]
+            phantom: PhantomData,
+            proj_mat: ortho_proj(w, h),
+            offset: [0.0, 0.0],
+            camera: [0.0, 0.0],
+            text_gamma: 1.0,
+            wireframe: false,
+            page_tint: false,
+            overdraw_program: None,
+            target_pool: Vec::new(),
+            clear: Clear::default(),
+            frame_clear: None,
+            main_overrides: DrawOverrides::new(),
+            overlay_overrides: DrawOverrides::new(),
+            batch_overrides: Vec::new(),
+            tessellation: Arc::new(TessellationCache::new()),
+            buffer_pool: Arc::new(Mutex::new(PacketArena::new())),
+            arena: VertexArena::new(),
+            packet_stats: ArenaStats::default(),
+            offscreen_pool: Arc::new(Mutex::new(PacketArena::new())),
+            offscreen_arena: VertexArena::new(),
+            batch_records: None,
+        }))
     }
 
     /// Buffer the vertex data received from the ECS render system
     /// (`SysRenderer`) to the VBO to be rendered. This should be called before
     /// `render()`.
     pub fn recv_data(&mut self) {
-        let mut v_data_list: Vec<(usize, TexType, Vec<Vertex>)> = Vec::new();
-        // VBO_SIZE, no more data must be buffered.
-        loop {
-            let res = self.v_channel_pair.1.try_recv();
-            if res.is_err() {
-                // If the result of try_recv is an error, either all the sender's are
-                // disconnected (not expected, as we own a sender) OR the channel is
-                // empty, which means we've buffered all the data we can.
-                match res.err().unwrap() {
-                    mpsc::TryRecvError::Empty => break,
-                    mpsc::TryRecvError::Disconnected => panic!("Vertex data senders disconnected!"),
-                }
-            }
-            // Copy data from the packet into v_data
-            let data_packet = res.unwrap();
-
-            'Outer: for v in data_packet {
-                // Find the right list to insert this vertex into
-                for &mut (id, tex_type, ref mut list) in &mut v_data_list {
-                    if id == v.tex_ix && tex_type == v.tex_type {
-                        list.push(v);
-                        continue 'Outer;
-                    }
-                }
-                // If we're here, we couldn't find a list to insert into. We need to
-                // create a new tuple and push it onto v_data_list.
-                let mut list = Vec::new();
-                list.push(v);
-                v_data_list.push((v.tex_ix, v.tex_type, list));
-            }
-        }
-
-        // Check data packet won't be too long
-        #[cfg(feature = "vbo_overflow_panic")]
+        trace!("recv_data: start");
+        let start = Instant::now();
+        #[cfg(feature = "font")]
         {
-            for &(_, _, ref list) in &v_data_list {
-                if list.len() >= VBO_SIZE {
-                    panic!("VBO Overflow");
-                }
-            }
+            self.font_cache.recv_rasterised_glyphs(false);
+            self.font_cache.cache_requested_glyphs();
         }
-
-        for &mut (_, _, ref mut list) in &mut v_data_list {
-            while list.len() < VBO_SIZE {
-                list.push(Vertex {
-                    pos: [0.0; 2],
-                    col: [0.0; 4],
-                    tex_coords: [0.0, 0.0],
-                    tex_ix: 0,
-                    tex_type: TexType::Texture,
-                });
-            }
+        // Last frame's batches have been drawn, so their buffers can be reused.
+        self.arena.recycle(self.v_data_list.drain(..).map(|(_, list)| list));
+        self.arena.recycle(self.overlay_data_list.drain(..).map(|(_, list)| list));
+        self.arena.reset_stats();
+        self.packet_stats = self.buffer_pool.lock().unwrap().take_stats();
+        let (num_packets, mut v_data_list, glyph_data_list) =
+            recv_batches(&self.v_channel_pair.1, &self.buffer_pool, &mut self.arena);
+        let (num_overlay_packets, mut overlay_data_list, overlay_glyph_list) =
+            recv_batches(&self.overlay_channel_pair.1, &self.buffer_pool, &mut self.arena);
+        if let Some(ref mut records) = self.batch_records {
+            records.clear();
+            records.extend(v_data_list.iter().map(|&(key, ref list)| {
+                BatchRecord::new(Layer::Main, &key, list.len())
+            }));
+            #[cfg(feature = "font")]
+            records.extend(glyph_data_list.iter().map(|&(overrides, ref list)| {
+                BatchRecord::glyphs(Layer::Main, overrides, list.len())
+            }));
+            records.extend(overlay_data_list.iter().map(|&(key, ref list)| {
+                BatchRecord::new(Layer::Overlay, &key, list.len())
+            }));
+            #[cfg(feature = "font")]
+            records.extend(overlay_glyph_list.iter().map(|&(overrides, ref list)| {
+                BatchRecord::glyphs(Layer::Overlay, overrides, list.len())
+            }));
         }
-
+        pad_batches(&mut v_data_list);
+        pad_batches(&mut overlay_data_list);
+        trace!(
+            "recv_data: end - {} packets into {} batches, {} overlay packets into {} batches in {:?}",
+            num_packets,
+            v_data_list.len(),
+            num_overlay_packets,
+            overlay_data_list.len(),
+            start.elapsed()
+        );
         self.v_data_list = v_data_list;
+        self.overlay_data_list = overlay_data_list;
+        self.glyph_data_list = glyph_data_list;
+        self.overlay_glyph_list = overlay_glyph_list;
     }
 
-    pub fn render<T: glium::Surface>(&mut self, target: &mut T) {
-        for &(tex_id, tex_type, ref list) in &self.v_data_list {
+    /// Discards everything controllers have sent, whether it's been received
+    /// or not, and frees the memory kept for reusing vertex buffers. Used
+    /// when shutting down, so nothing is left queued.
+    pub fn discard_data(&mut self) {
+        while self.v_channel_pair.1.try_recv().is_ok() {}
+        while self.overlay_channel_pair.1.try_recv().is_ok() {}
+        self.v_data_list = Vec::new();
+        self.overlay_data_list = Vec::new();
+        self.glyph_data_list = Vec::new();
+        self.overlay_glyph_list = Vec::new();
+        self.buffer_pool.lock().unwrap().clear();
+        self.arena = VertexArena::new();
+        self.offscreen_pool.lock().unwrap().clear();
+        self.offscreen_arena = VertexArena::new();
+    }
+
+    /// Render the vertex data received in the last call to `recv_data()`.
+    /// Batches which reference a texture that isn't in the cache (e.g. if
+    /// they were drawn before it finished caching) are skipped and logged.
+    /// # Errors
+    /// Returns an error if a draw call fails.
+    pub fn render<T: glium::Surface>(&mut self, target: &mut T) -> Result<(), QgfxError> {
+        trace!("render: start");
+        let start = Instant::now();
+        if self.overdraw_program.is_some() {
+            use glium::Surface;
+            target.clear_color(0.0, 0.0, 0.0, 1.0);
+        }
+        let main_proj = translate_proj(&self.proj_mat, self.offset);
+        let mut skipped = try!(self.draw_batches(
+            target,
+            &self.v_data_list,
+            main_proj,
+            self.camera,
+            &self.main_overrides,
+        ));
+        skipped += try!(self.draw_glyph_batches(
+            target,
+            &self.glyph_data_list,
+            main_proj,
+            self.camera,
+            &self.main_overrides,
+        ));
+        skipped += try!(self.draw_batches(
+            target,
+            &self.overlay_data_list,
+            self.proj_mat,
+            [0.0, 0.0],
+            &self.overlay_overrides,
+        ));
+        skipped += try!(self.draw_glyph_batches(
+            target,
+            &self.overlay_glyph_list,
+            self.proj_mat,
+            [0.0, 0.0],
+            &self.overlay_overrides,
+        ));
+        self.skipped_batches = skipped;
+        self.draw_calls = self.v_data_list.len() + self.overlay_data_list.len() +
+            self.glyph_data_list.len() + self.overlay_glyph_list.len() - skipped;
+        trace!(
+            "render: end - {} draw calls in {:?}",
+            self.draw_calls,
+            start.elapsed()
+        );
+        Ok(())
+    }
+
+    /// Draws batches of vertex data, as created by recv_batches(), to a
+    /// surface. `layer` is applied to every batch's draw parameters, then the
+    /// batch's own overrides are applied on top. Batches are scrolled by
+    /// `camera`, scaled by their parallax factor.
+    /// # Returns
+    /// The number of batches skipped because their texture isn't cached.
+    fn draw_batches<T: glium::Surface>(
+        &self,
+        target: &mut T,
+        batches: &[(BatchKey, Vec<Vertex>)],
+        proj_mat: [[f32; 4]; 4],
+        camera: [f32; 2],
+        layer: &DrawOverrides,
+    ) -> Result<usize, QgfxError> {
+        let mut skipped = 0;
+        for &(key, ref list) in batches {
+            let (tex_id, tex_type) = (key.tex_ix, key.tex_type);
             // Empty indices - basically only rendering sprites, so no need to have it indexed.
             let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
 
+            let (tex, is_font) = match self.batch_tex(tex_id, tex_type) {
+                Ok(tex) => tex,
+                Err(_) => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+
             // Write the vertex data to the VBO
             self.vbo.write(list);
 
-            // Get the texture
-            let font_cache = &self.font_cache;
-            let tex_cache = &self.tex_cache;
-            let tex;
-            match tex_type {
-                TexType::Texture => {
-                    use res::tex::TexCache;
-                    tex = tex_cache.get_tex_with_ix(tex_id as usize);
+            // Dedicated cache textures only contain one texture, so they can be
+            // repeated. Atlas pages must be clamped so neighbouring textures
+            // don't bleed in.
+            let wrap = {
+                use res::tex::TexHandleLookup;
+                if tex_type == TexType::Texture && self.tex_cache.is_page_dedicated(tex_id) {
+                    glium::uniforms::SamplerWrapFunction::Repeat
+                } else {
+                    glium::uniforms::SamplerWrapFunction::Clamp
                 }
-                TexType::Font => tex = Some(font_cache.get_tex()),
-            }
+            };
 
-            // No texture found? Panic.
-            if tex.is_none() {
-                panic!(
-                    r#"Vertex data with tex ID buffered, but
-                                texture with this ix does not exist."#
-                );
-            }
+            let settings = match tex_type {
+                TexType::Texture => self.page_samplers.get(&tex_id).unwrap_or(&self.sampler),
+                #[cfg(feature = "font")]
+                TexType::Font => &self.sampler,
+            };
+            let sampler = BatchSampler(
+                tex,
+                SamplerBehavior {
+                    wrap_function: (wrap, wrap, wrap),
+                    minify_filter: settings.minify_filter,
+                    magnify_filter: settings.magnify_filter,
+                    max_anisotropy: settings.anisotropy.unwrap_or(1),
+                    ..Default::default()
+                },
+            );
+
+            let overrides = self.batch_overrides(&key, layer);
+            let page_tint = if self.page_tint {
+                page_tint_colour(&key)
+            } else {
+                [0.0; 4]
+            };
 
             // Load the uniforms
             let uniforms =
                 uniform! {
-        proj_mat: self.proj_mat,
-        is_font: if tex_type == TexType::Font { 1 } else { 0 },
-        tex: tex.unwrap(),
+        proj_mat: scroll_proj(&proj_mat, camera, overrides.parallax_factor()),
+        pixel_snap: self.pixel_snap as i32,
+        is_font: is_font,
+        tex_channels: tex.channels(),
+        text_gamma: self.text_gamma,
+        encode_srgb: (self.color_space == ColorSpace::Srgb) as i32,
+        page_tint: page_tint,
+        tex: sampler,
       };
 
+            let mut params = glium::DrawParameters {
+                blend: if self.transparent {
+                    transparent_blending()
+                } else {
+                    glium::Blend::alpha_blending()
+                },
+                ..Default::default()
+            };
+            overrides.apply(&mut params);
+            if self.wireframe {
+                params.polygon_mode = glium::draw_parameters::PolygonMode::Line;
+            }
+
+            if let Some(ref program) = self.overdraw_program {
+                let uniforms =
+                    uniform! {
+            proj_mat: scroll_proj(&proj_mat, camera, overrides.parallax_factor()),
+            pixel_snap: self.pixel_snap as i32,
+            step: OVERDRAW_STEP,
+          };
+                params.blend = additive_blending();
+                try!(target.draw(&self.vbo, &indices, program, &uniforms, &params));
+                continue;
+            }
+
             // Draw everything!
-            target
-                .draw(
-                    &self.vbo,
+            try!(target.draw(
+                &self.vbo,
+                &indices,
+                &self.program,
+                &uniforms,
+                &params,
+            ));
+        }
+        Ok(skipped)
+    }
+
+    /// Draws batches of instanced glyphs, as created by recv_batches(), like
+    /// draw_batches(). Each glyph is one instance of a quad, so only the
+    /// instances are uploaded. If instancing isn't supported, or the
+    /// overdraw heatmap is shown, the glyphs are drawn as vertices instead.
+    /// # Returns
+    /// The number of batches skipped because the font cache is missing.
+    #[cfg(feature = "font")]
+    fn draw_glyph_batches<T: glium::Surface>(
+        &self,
+        target: &mut T,
+        batches: &[(usize, Vec<GlyphInstance>)],
+        proj_mat: [[f32; 4]; 4],
+        camera: [f32; 2],
+        layer: &DrawOverrides,
+    ) -> Result<usize, QgfxError> {
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+        let mut skipped = 0;
+        for &(draw_overrides, ref list) in batches {
+            let key = glyph_batch_key(draw_overrides);
+            let (tex, is_font) = match self.batch_tex(key.tex_ix, key.tex_type) {
+                Ok(tex) => tex,
+                Err(_) => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+            let overrides = self.batch_overrides(&key, layer);
+            let uniforms =
+                uniform! {
+        proj_mat: scroll_proj(&proj_mat, camera, overrides.parallax_factor()),
+        pixel_snap: self.pixel_snap as i32,
+        is_font: is_font,
+        tex_channels: tex.channels(),
+        text_gamma: self.text_gamma,
+        encode_srgb: (self.color_space == ColorSpace::Srgb) as i32,
+        page_tint: if self.page_tint { page_tint_colour(&key) } else { [0.0; 4] },
+        tex: BatchSampler(tex, SamplerBehavior {
+            wrap_function: (glium::uniforms::SamplerWrapFunction::Clamp,
+                            glium::uniforms::SamplerWrapFunction::Clamp,
+                            glium::uniforms::SamplerWrapFunction::Clamp),
+            minify_filter: self.sampler.minify_filter,
+            magnify_filter: self.sampler.magnify_filter,
+            max_anisotropy: self.sampler.anisotropy.unwrap_or(1),
+            ..Default::default()
+        }),
+      };
+            let mut params = glium::DrawParameters {
+                blend: if self.transparent {
+                    transparent_blending()
+                } else {
+                    glium::Blend::alpha_blending()
+                },
+                ..Default::default()
+            };
+            overrides.apply(&mut params);
+            if self.wireframe {
+                params.polygon_mode = glium::draw_parameters::PolygonMode::Line;
+            }
+
+            for chunk in list.chunks(GLYPH_VBO_SIZE) {
+                let slice = self.glyph_vbo.slice(0..chunk.len()).unwrap();
+                let instances = match self.overdraw_program {
+                    None => slice.per_instance().ok(),
+                    Some(_) => None,
+                };
+                let instances = match instances {
+                    Some(instances) => instances,
+                    None => {
+                        try!(self.draw_glyphs_as_vertices(target, key, chunk, proj_mat, camera,
+                                                          layer));
+                        continue;
+                    }
+                };
+                slice.write(chunk);
+                try!(target.draw(
+                    (&self.glyph_corners, instances),
                     &indices,
-                    &self.program,
+                    &self.glyph_program,
                     &uniforms,
-                    &glium::DrawParameters {
-                        blend: glium::Blend::alpha_blending(),
-                        ..Default::default()
-                    },
-                )
-                .unwrap();
+                    &params,
+                ));
+            }
         }
+        Ok(skipped)
+    }
+
+    /// Without the font subsystem, nothing is drawn as instanced glyphs.
+    #[cfg(not(feature = "font"))]
+    fn draw_glyph_batches<T: glium::Surface>(
+        &self,
+        _: &mut T,
+        _: &[(usize, Vec<GlyphInstance>)],
+        _: [[f32; 4]; 4],
+        _: [f32; 2],
+        _: &DrawOverrides,
+    ) -> Result<usize, QgfxError> {
+        Ok(0)
+    }
+
+    /// Draws instanced glyphs as 6 vertices each, with draw_batches().
+    #[cfg(feature = "font")]
+    fn draw_glyphs_as_vertices<T: glium::Surface>(
+        &self,
+        target: &mut T,
+        key: BatchKey,
+        glyphs: &[GlyphInstance],
+        proj_mat: [[f32; 4]; 4],
+        camera: [f32; 2],
+        layer: &DrawOverrides,
+    ) -> Result<usize, QgfxError> {
+        let mut skipped = 0;
+        for chunk in glyphs.chunks(VBO_SIZE / 6) {
+            let mut list = Vec::with_capacity(VBO_SIZE);
+            for glyph in chunk {
+                list.extend_from_slice(&glyph.vertices());
+            }
+            let mut batch = [(key, list)];
+            pad_batches(&mut batch);
+            skipped += try!(self.draw_batches(target, &batch, proj_mat, camera, layer));
+        }
+        Ok(skipped)
+    }
+
+    /// The overrides a batch is drawn with - the layer's, with the batch's
+    /// own on top.
+    fn batch_overrides(&self, key: &BatchKey, layer: &DrawOverrides) -> DrawOverrides {
+        match key.draw_overrides {
+            0 => *layer,
+            ix => layer.merge(&self.batch_overrides[ix - 1]),
+        }
+    }
+
+    /// Gets the texture a batch of vertex data is drawn with, and whether
+    /// it's the font cache (1) or not (0).
+    /// # Errors
+    /// Returns an error if the texture isn't in the cache.
+    fn batch_tex(&self, tex_id: usize, tex_type: TexType) -> Result<(BatchTex, i32), QgfxError> {
+        let tex = match tex_type {
+            TexType::Texture => {
+                use res::tex::TexCache;
+                self.tex_cache.get_tex_with_ix(tex_id as usize).map(|tex| match *tex {
+                    CacheTexture::Rgba(ref tex) => (BatchTex::Srgb(tex), 0),
+                    CacheTexture::Gray(ref tex, format) => (BatchTex::Linear(tex, format), 0),
+                    CacheTexture::Hdr(ref tex) => (BatchTex::Linear(tex, PixelFormat::Rgba), 0),
+                })
+            }
+            #[cfg(feature = "font")]
+            TexType::Font => Some((BatchTex::Srgb(self.font_cache.get_tex()), 1)),
+        };
+        tex.ok_or_else(|| {
+            warn!(
+                "Vertex data buffered with {:?} texture ix {} (named textures: {:?}), which \
+                 does not exist",
+                tex_type,
+                tex_id,
+                self.tex_cache.page_tex_names(tex_id)
+            );
+            QgfxError::MissingTexture(tex_id)
+        })
     }
 
     /// # Params
@@ -216,30 +1024,139 @@ impl<'a> Renderer<'a> {
     /// # Returns
     /// A Sender<Vertex> for sending vertex data to the renderer. When
     /// render() is called, this data will be rendered then cleared.
-    pub fn get_renderer_controller(&'a self, white: TexHandle) -> Box<RendererController<'a>> {
-        RendererController::new(
-            self.v_channel_pair.0.clone(),
+    pub fn get_renderer_controller(&self, white: TexHandle) -> Box<RendererController<'a>> {
+        self.controller_with_sender(self.v_channel_pair.0.clone(), self.buffer_pool.clone(), white)
+    }
+
+    /// Like get_renderer_controller(), but data sent by the controller is
+    /// drawn on top of all other data.
+    pub fn get_overlay_controller(&self, white: TexHandle) -> Box<RendererController<'a>> {
+        self.controller_with_sender(self.overlay_channel_pair.0.clone(), self.buffer_pool.clone(),
+                                    white)
+    }
+
+    /// Creates a controller which sends to `sender`, taking its vertex
+    /// packets from `pool`.
+    fn controller_with_sender(
+        &self,
+        sender: mpsc::Sender<VertexPacket>,
+        pool: BufferPool,
+        white: TexHandle,
+    ) -> Box<RendererController<'a>> {
+        #[cfg(feature = "font")]
+        return RendererController::new(
+            sender,
             self.font_cache.get_glyph_lookup(),
             self.tex_cache.get_tex_lookup(),
+            self.tessellation.clone(),
+            pool,
             white,
-        )
+        );
+        #[cfg(not(feature = "font"))]
+        return RendererController::new(
+            sender,
+            self.tex_cache.get_tex_lookup(),
+            self.tessellation.clone(),
+            pool,
+            white,
+        );
     }
 
     /// A function to add the given chars to the cache. See res::font::FontCache
     /// for more details. This wraps the font_cache stored inside the renderer.
     /// This locks the mutex on the font cache, so any font rendering or caching
     /// on other threads will also be blocked for the duration.
+    #[cfg(feature = "font")]
     pub fn cache_glyphs<F: AsRef<Path>>(
         &mut self,
         file: F,
+        face_index: usize,
+        x_scale: f32,
+        y_scale: f32,
+        charset: &[char],
+    ) -> Result<FontHandle, CacheGlyphError> {
+        use res::font::FontCache;
+        self.font_cache.cache_glyphs_face_xy(file, face_index, x_scale, y_scale, charset)
+    }
+
+    /// Caches as many of the given chars as possible. See
+    /// res::font::FontCache::cache_available_glyphs.
+    #[cfg(feature = "font")]
+    pub fn cache_available_glyphs<F: AsRef<Path>>(
+        &mut self,
+        file: F,
+        face_index: usize,
+        x_scale: f32,
+        y_scale: f32,
+        charset: &[char],
+    ) -> Result<CacheReport, CacheGlyphError> {
+        use res::font::FontCache;
+        self.font_cache.cache_available_glyphs_face_xy(file, face_index, x_scale, y_scale, charset)
+    }
+
+    /// Caches glyphs from the bundled default font.
+    #[cfg(feature = "default-font")]
+    pub fn cache_default_font_glyphs(
+        &mut self,
         scale: f32,
         charset: &[char],
     ) -> Result<FontHandle, CacheGlyphError> {
+        use res::font::DEFAULT_FONT_DATA;
+        self.font_cache.cache_glyphs_from_bytes(
+            DEFAULT_FONT_DATA,
+            Path::new("<default font>"),
+            0,
+            scale,
+            scale,
+            charset,
+        )
+    }
+
+    /// Sets whether glyphs are cached on demand. See
+    /// res::font::glium_cache::GliumFontCache::set_cache_on_demand.
+    #[cfg(feature = "font")]
+    pub fn set_cache_glyphs_on_demand(&mut self, on_demand: bool) {
+        self.font_cache.set_cache_on_demand(on_demand)
+    }
+
+    /// Sets how large the font cache texture may grow. See
+    /// res::font::glium_cache::GliumFontCache::set_growth_budget.
+    #[cfg(feature = "font")]
+    pub fn set_font_cache_growth_budget(&mut self, max_bytes: Option<usize>) {
+        self.font_cache.set_growth_budget(max_bytes)
+    }
+
+    /// Sets whether glyphs are rasterised on a worker thread. See
+    /// res::font::glium_cache::GliumFontCache::set_background_rasterisation.
+    #[cfg(feature = "font")]
+    pub fn set_background_glyph_rasterisation(&mut self, background: bool) {
+        self.font_cache.set_background_rasterisation(background)
+    }
+
+    /// True whilst glyphs are being rasterised on a worker thread.
+    #[cfg(feature = "font")]
+    pub fn is_rasterising_glyphs(&self) -> bool {
+        self.font_cache.is_rasterising()
+    }
+
+    /// Gets the vector outline of a glyph. See
+    /// res::font::GlyphLookup::glyph_outline.
+    #[cfg(feature = "font")]
+    pub fn glyph_outline(&self, fh: FontHandle, c: char) -> Option<Vec<PathCommand>> {
+        use res::font::GlyphLookup;
+        self.font_cache.glyph_outline(fh, c)
+    }
+
+    /// Sets a texture to draw in place of a char when rendering text. See
+    /// res::font::FontCache::set_glyph_sprite.
+    #[cfg(feature = "font")]
+    pub fn set_glyph_sprite(&mut self, c: char, tex: Option<TexHandle>) {
         use res::font::FontCache;
-        self.font_cache.cache_glyphs(file, scale, charset)
+        self.font_cache.set_glyph_sprite(c, tex)
     }
 
     /// Cache textures from filepaths, returning a list of texture handles.
+    #[cfg(feature = "image")]
     pub fn cache_tex<Facade: glium::backend::Facade, F: AsRef<Path>>(
         &mut self,
         display: &Facade,
@@ -249,7 +1166,528 @@ impl<'a> Renderer<'a> {
         self.tex_cache.cache_tex(display, filepaths)
     }
 
+    /// Cache textures from filepaths, making `key` transparent in them. See
+    /// res::tex::TexCache::cache_tex_color_keyed.
+    #[cfg(feature = "image")]
+    pub fn cache_tex_color_keyed<Facade: glium::backend::Facade, F: AsRef<Path>>(
+        &mut self,
+        display: &Facade,
+        filepaths: &[F],
+        key: [u8; 3],
+    ) -> Vec<Result<TexHandle, CacheTexError>> {
+        use res::tex::TexCache;
+        self.tex_cache.cache_tex_color_keyed(display, filepaths, key)
+    }
+
+    /// Cache textures from filepaths, decoding them in parallel. See
+    /// res::tex::TexCache::cache_tex_parallel.
+    #[cfg(feature = "image")]
+    pub fn cache_tex_parallel<Facade, F, P>(
+        &mut self,
+        display: &Facade,
+        filepaths: &[F],
+        progress: P,
+    ) -> Vec<Result<TexHandle, CacheTexError>>
+    where
+        Facade: glium::backend::Facade,
+        F: AsRef<Path>,
+        P: FnMut(usize, usize),
+    {
+        use res::tex::TexCache;
+        self.tex_cache.cache_tex_parallel(display, filepaths, progress)
+    }
+
+    /// Sets the size above which textures get their own dedicated cache
+    /// texture. See res::tex::TexCache::set_dedicated_tex_threshold.
+    pub fn set_dedicated_tex_threshold(&mut self, w: u32, h: u32) {
+        use res::tex::TexCache;
+        self.tex_cache.set_dedicated_tex_threshold(w, h)
+    }
+
+    /// Sets the size of the area drawn to, in pixels. Coordinates given to
+    /// renderer controllers range from (0, 0) at the top left to (w, h) at the
+    /// bottom right. By default, this is the size of the display the renderer
+    /// was created with.
+    pub fn set_projection_size(&mut self, w: u32, h: u32) {
+        self.proj_mat = ortho_proj(w, h);
+        self.proj_size = (w, h);
+    }
+
+    /// Sets an offset, in pixels, everything in the main layer is drawn at.
+    /// The overlay layer isn't offset.
+    pub fn set_offset(&mut self, offset: [f32; 2]) {
+        self.offset = offset;
+    }
+
+    /// The offset the main layer is drawn at, as set by set_offset().
+    pub fn offset(&self) -> [f32; 2] {
+        self.offset
+    }
+
+    /// Sets the camera's position, in pixels. Everything in the main layer is
+    /// drawn moved by minus the position, scaled by its parallax factor (see
+    /// DrawOverrides::parallax()). The overlay layer doesn't scroll.
+    pub fn set_camera(&mut self, pos: [f32; 2]) {
+        self.camera = pos;
+    }
+
+    /// The camera's position, as set by set_camera().
+    pub fn camera(&self) -> [f32; 2] {
+        self.camera
+    }
+
+    /// Sets whether pick IDs are rendered by render_pick_buffer(). Disabling
+    /// picking frees the pick buffer.
+    pub fn set_picking(&mut self, picking: bool) {
+        self.picking = picking;
+        if !picking {
+            self.pick_buffer = None;
+        }
+    }
+
+    /// Renders the pick IDs of the data received in the last call to
+    /// recv_data() into the pick buffer, if picking is enabled. Overlay data
+    /// isn't pickable. Untagged data is drawn with the ID 0, so it hides
+    /// anything pickable under it.
+    pub fn render_pick_buffer<F: glium::backend::Facade>(
+        &mut self,
+        display: &F,
+    ) -> Result<(), QgfxError> {
+        use glium::Surface;
+        if !self.picking {
+            return Ok(());
+        }
+        let outdated = match self.pick_buffer {
+            Some((ref tex, _)) => tex.dimensions() != self.proj_size,
+            None => true,
+        };
+        if outdated {
+            let tex = try!(glium::texture::Texture2d::empty_with_format(
+                display,
+                glium::texture::UncompressedFloatFormat::U8U8U8U8,
+                glium::texture::MipmapsOption::NoMipmap,
+                self.proj_size.0,
+                self.proj_size.1,
+            ));
+            let program = try!(shader::get_picking_program(display));
+            self.pick_buffer = Some((tex, program));
+        }
+
+        let (ref pick_tex, ref program) = *self.pick_buffer.as_ref().unwrap();
+        let mut fb = try!(glium::framebuffer::SimpleFrameBuffer::new(display, pick_tex));
+        fb.clear_color(0.0, 0.0, 0.0, 0.0);
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+        for &(key, ref list) in &self.v_data_list {
+            // Batches with a missing texture were skipped by render() too.
+            let (tex, is_font) = match self.batch_tex(key.tex_ix, key.tex_type) {
+                Ok(tex) => tex,
+                Err(_) => continue,
+            };
+            self.vbo.write(list);
+            let parallax = self.batch_overrides(&key, &self.main_overrides).parallax_factor();
+            let proj_mat =
+                scroll_proj(&translate_proj(&self.proj_mat, self.offset), self.camera, parallax);
+            let uniforms =
+                uniform! {
+        proj_mat: proj_mat,
+        pixel_snap: self.pixel_snap as i32,
+        is_font: is_font,
+        tex_channels: tex.channels(),
+        tex: BatchSampler(tex, Default::default()),
+      };
+            try!(fb.draw(&self.vbo, &indices, program, &uniforms, &Default::default()));
+        }
+        Ok(())
+    }
+
+    /// Renders what a closure draws into an offscreen texture, and reads it
+    /// back. This doesn't affect the data drawn by render(). The image has
+    /// straight alpha, so it can be cached and drawn like any other texture.
+    /// # Params
+    /// * `white` - The texture handle to use for white, as in
+    ///             get_renderer_controller().
+    /// * `size` - The size of the image, in pixels.
+    /// * `draw` - Draws the image's contents. Coordinates range from (0, 0)
+    ///            at the top left of the image to `size` at the bottom right.
+    /// # Errors
+    /// Returns an error if the offscreen texture couldn't be created, or if
+    /// drawing failed.
+    pub fn render_to_image<F, C>(
+        &mut self,
+        display: &F,
+        white: TexHandle,
+        size: (u32, u32),
+        draw: C,
+    ) -> Result<RgbaBuffer, QgfxError>
+    where
+        F: glium::backend::Facade,
+        C: FnOnce(&mut RendererController<'a>),
+    {
+        use glium::Surface;
+        let (sender, receiver) = mpsc::channel();
+        {
+            let pool = self.offscreen_pool.clone();
+            let mut controller = self.controller_with_sender(sender, pool, white);
+            draw(&mut controller);
+            controller.flush();
+        }
+        self.offscreen_arena.reset_stats();
+        let (_, mut batches, glyphs) =
+            recv_batches(&receiver, &self.offscreen_pool, &mut self.offscreen_arena);
+        pad_batches(&mut batches);
+
+        let (w, h) = size;
+        let tex = try!(glium::texture::Texture2d::empty_with_format(
+            display,
+            glium::texture::UncompressedFloatFormat::U8U8U8U8,
+            glium::texture::MipmapsOption::NoMipmap,
+            w,
+            h,
+        ));
+        // Blend alpha as coverage, as the image starts out transparent. This
+        // leaves premultiplied colours, which are divided out below.
+        let transparent = self.transparent;
+        self.transparent = true;
+        let res = glium::framebuffer::SimpleFrameBuffer::new(display, &tex)
+            .map_err(QgfxError::from)
+            .and_then(|mut fb| {
+                fb.clear_color(0.0, 0.0, 0.0, 0.0);
+                try!(self.draw_batches(
+                    &mut fb,
+                    &batches,
+                    ortho_proj(w, h),
+                    [0.0, 0.0],
+                    &self.main_overrides,
+                ));
+                self.draw_glyph_batches(
+                    &mut fb,
+                    &glyphs,
+                    ortho_proj(w, h),
+                    [0.0, 0.0],
+                    &self.main_overrides,
+                )
+            });
+        self.transparent = transparent;
+        try!(res);
+        self.offscreen_arena.recycle(batches.into_iter().map(|(_, list)| list));
+
+        // Rows are read bottom first, but RgbaBuffer is top first.
+        let pixels: Vec<Vec<(u8, u8, u8, u8)>> = tex.read();
+        let mut data = Vec::with_capacity(w as usize * h as usize * 4);
+        for row in pixels.iter().rev() {
+            for &(r, g, b, a) in row {
+                data.extend_from_slice(&unpremultiply([r, g, b, a]));
+            }
+        }
+        Ok(RgbaBuffer::new(w, h, data).expect("Offscreen texture read back at the wrong size"))
+    }
+
+    /// Reads the pick ID at a pixel from the pick buffer, with (0, 0) at the
+    /// top left.
+    /// # Returns
+    /// None if picking is disabled, the pixel is outside the pick buffer, or
+    /// there's no pickable data at the pixel.
+    pub fn pick(&self, x: u32, y: u32) -> Option<u32> {
+        let tex = match self.pick_buffer {
+            Some((ref tex, _)) => tex,
+            None => return None,
+        };
+        let (w, h) = tex.dimensions();
+        if x >= w || y >= h {
+            return None;
+        }
+        // Rows are read bottom first.
+        let pixels: Vec<Vec<(u8, u8, u8, u8)>> = tex.read();
+        let (r, g, b, _) = pixels[(h - 1 - y) as usize][x as usize];
+        let id = r as u32 | (g as u32) << 8 | (b as u32) << 16;
+        if id == 0 {
+            None
+        } else {
+            Some(id)
+        }
+    }
+
+    /// Sets whether textures (and glyphs) are sampled with nearest neighbour
+    /// filtering rather than linear filtering. Nearest neighbour filtering
+    /// keeps pixel art crisp when it's scaled up.
+    pub fn set_nearest_filtering(&mut self, nearest: bool) {
+        self.sampler = if nearest {
+            SamplerSettings::nearest()
+        } else {
+            SamplerSettings::linear()
+        };
+    }
+
+    /// Sets how textures (and glyphs) are sampled, unless their page has its
+    /// own settings.
+    pub fn set_sampler(&mut self, settings: SamplerSettings) {
+        self.sampler = settings;
+    }
+
+    /// Sets how the texture cache page containing `tex` is sampled, or
+    /// returns it to the global settings if `settings` is None. This affects
+    /// every texture on the page, so it's most useful for textures with a
+    /// dedicated page (see set_dedicated_tex_threshold()). The settings stay
+    /// with the page if `tex` is freed.
+    ///
+    /// Returns false, and does nothing, if `tex` isn't cached.
+    pub fn set_page_sampler(&mut self, tex: TexHandle, settings: Option<SamplerSettings>) -> bool {
+        use res::tex::TexHandleLookup;
+        let page = match self.tex_cache.rect_for(tex) {
+            Some((page, _)) => page,
+            None => return false,
+        };
+        match settings {
+            Some(settings) => self.page_samplers.insert(page, settings),
+            None => self.page_samplers.remove(&page),
+        };
+        true
+    }
+
+    /// Sets whether every batch is rasterised as lines rather than filled
+    /// triangles, overriding any draw overrides.
+    pub fn set_wireframe(&mut self, wireframe: bool) {
+        self.wireframe = wireframe;
+    }
+
+    /// Sets whether every batch is tinted with a colour identifying the
+    /// texture page it's drawn from. See page_tint_colour().
+    pub fn set_page_tint(&mut self, page_tint: bool) {
+        self.page_tint = page_tint;
+    }
+
+    /// Sets whether the overdraw heatmap is drawn instead of the normal
+    /// output. Every fragment drawn adds OVERDRAW_STEP to its pixel, on a
+    /// black background, so pixels go from black through red (4 layers),
+    /// yellow (10 layers) to white (25 layers) the more times they're drawn
+    /// over.
+    /// # Errors
+    /// Returns an error if the heatmap's shader program couldn't be
+    /// compiled.
+    pub fn set_overdraw_heatmap<F: glium::backend::Facade>(
+        &mut self,
+        display: &F,
+        overdraw: bool,
+    ) -> Result<(), QgfxError> {
+        if !overdraw {
+            self.overdraw_program = None;
+        } else if self.overdraw_program.is_none() {
+            self.overdraw_program = Some(try!(shader::get_overdraw_program(display)));
+        }
+        Ok(())
+    }
+
+    /// Sets what the window, and the virtual resolution and post-processing
+    /// targets, are cleared to at the start of each frame. Clears the colour
+    /// to transparent black by default.
+    pub fn set_clear(&mut self, clear: Clear) {
+        self.clear = clear;
+    }
+
+    /// Overrides what the targets are cleared to for the next frame only, or
+    /// removes the override with None. QGFX removes the override once the
+    /// frame has been rendered.
+    pub fn set_frame_clear(&mut self, clear: Option<Clear>) {
+        self.frame_clear = clear;
+    }
+
+    /// What the frame's targets are cleared to, including this frame's
+    /// override. See set_clear() and set_frame_clear().
+    pub fn get_clear(&self) -> Clear {
+        self.frame_clear.unwrap_or(self.clear)
+    }
+
+    /// Sets the draw parameter overrides for every batch in a layer. Use
+    /// DrawOverrides::new() to remove them.
+    pub fn set_layer_overrides(&mut self, layer: Layer, overrides: DrawOverrides) {
+        match layer {
+            Layer::Main => self.main_overrides = overrides,
+            Layer::Overlay => self.overlay_overrides = overrides,
+        }
+    }
+
+    /// Registers draw parameter overrides for use with
+    /// RendererController::set_draw_overrides(). Batch overrides are applied
+    /// on top of their layer's overrides.
+    pub fn register_draw_overrides(&mut self, overrides: DrawOverrides) -> DrawOverridesHandle {
+        self.batch_overrides.push(overrides);
+        DrawOverridesHandle(self.batch_overrides.len())
+    }
+
+    /// Sets whether alpha is blended for a transparent window. Normal alpha
+    /// blending squares the alpha of everything drawn over a transparent
+    /// background, so it shows through too much. This accumulates alpha
+    /// properly instead, leaving premultiplied colours the compositor can
+    /// blend with the desktop. Clear to a transparent colour to use it.
+    pub fn set_transparent_blending(&mut self, transparent: bool) {
+        self.transparent = transparent;
+    }
+
+    /// Sets whether vertex positions are rounded to the nearest pixel before
+    /// rendering, so shapes and textures line up with the pixel grid.
+    pub fn set_pixel_snap(&mut self, snap: bool) {
+        self.pixel_snap = snap;
+    }
+
+    /// The number of draw calls made by the last call to render().
+    pub fn draw_call_count(&self) -> usize {
+        self.draw_calls
+    }
+
+    /// How many vertex batch buffers recv_data() had to allocate this frame,
+    /// and how many it reused.
+    pub fn arena_stats(&self) -> ArenaStats {
+        self.arena.stats()
+    }
+
+    /// How many vertex packets controllers had to allocate for the data
+    /// received by the last call to recv_data(), and how many were reused.
+    pub fn packet_stats(&self) -> ArenaStats {
+        self.packet_stats
+    }
+
+    /// Sets whether the batches received by recv_data() are recorded. See
+    /// batch_records().
+    pub fn set_batch_recording(&mut self, record: bool) {
+        self.batch_records = if record { Some(Vec::new()) } else { None };
+    }
+
+    /// The batches received by the last call to recv_data(), in the order
+    /// they're drawn. Empty unless batch recording is enabled.
+    pub fn batch_records(&self) -> &[BatchRecord] {
+        self.batch_records.as_ref().map(|r| &r[..]).unwrap_or(&[])
+    }
+
+    /// The number of batches the last call to render() skipped, because
+    /// their texture wasn't in the cache.
+    pub fn skipped_batch_count(&self) -> usize {
+        self.skipped_batches
+    }
+
+    /// The occupancy of each texture cache page, from 0.0 (empty) to 1.0
+    /// (full).
+    pub fn tex_page_occupancy(&self) -> Vec<f32> {
+        self.tex_cache.page_occupancy()
+    }
+
+    /// Sets the gamma correction exponent applied to text coverage when
+    /// rendering. Coverage is raised to the power of 1 / gamma, so 1.0 (the
+    /// default) disables gamma correction, values above 1.0 make text heavier
+    /// and values below 1.0 make it thinner.
+    pub fn set_text_gamma(&mut self, gamma: f32) {
+        self.text_gamma = gamma;
+    }
+
+    /// Sets whether textures can be rotated to fit into the cache. See
+    /// res::tex::TexCache::set_allow_tex_rotation.
+    pub fn set_allow_tex_rotation(&mut self, allow: bool) {
+        use res::tex::TexCache;
+        self.tex_cache.set_allow_tex_rotation(allow)
+    }
+
+    /// Caches a floating point texture. See GliumTexCache::cache_tex_hdr().
+    pub fn cache_tex_hdr<F: glium::backend::Facade>(
+        &mut self,
+        display: &F,
+        w: u32,
+        h: u32,
+        data: &[f32],
+        format: HdrFormat,
+    ) -> Result<TexHandle, CacheTexError> {
+        self.tex_cache.cache_tex_hdr(display, w, h, data, format)
+    }
+
+    /// The colour space the renderer was created with.
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    /// Finds a colour grading LUT in the cache. LUTs must be RGBA textures
+    /// which aren't rotated, and are N * N pixels wide and N high.
+    /// # Returns
+    /// The cache texture the LUT is on, its rect on the cache texture (X, Y,
+    /// W, H in texture coordinates) and N, or None (with a warning logged) if
+    /// the texture isn't cached or isn't a valid LUT.
+    pub fn color_grading_lut(
+        &self,
+        tex: TexHandle,
+    ) -> Option<(&glium::texture::SrgbTexture2d, [f32; 4], u32)> {
+        use res::tex::{TexCache, TexHandleLookup};
+        let (tex_ix, rect) = match self.tex_cache.rect_for(tex) {
+            Some(r) => r,
+            None => {
+                warn!("Colour grading LUT {:?} isn't cached", tex);
+                return None;
+            }
+        };
+        let page = match self.tex_cache.get_tex_with_ix(tex_ix) {
+            Some(&CacheTexture::Rgba(ref page)) if !self.tex_cache.is_tex_rotated(tex) => page,
+            _ => {
+                warn!("Colour grading LUT {:?} must be an unrotated RGBA texture", tex);
+                return None;
+            }
+        };
+        let (page_w, page_h) = page.dimensions();
+        let w = (rect[2] * page_w as f32).round() as u32;
+        let h = (rect[3] * page_h as f32).round() as u32;
+        if h < 2 || w != h * h {
+            warn!(
+                "Colour grading LUT {:?} is {}x{}, rather than N * N by N pixels",
+                tex,
+                w,
+                h
+            );
+            return None;
+        }
+        Some((page, rect, h))
+    }
+
+    /// Sets whether grayscale textures are stored with fewer channels. See
+    /// res::tex::TexCache::set_grayscale_pages.
+    pub fn set_grayscale_pages(&mut self, gray_pages: bool) {
+        use res::tex::TexCache;
+        self.tex_cache.set_grayscale_pages(gray_pages)
+    }
+
+    /// Sets the colour made transparent in every texture as it's cached. See
+    /// res::tex::TexCache::set_color_key.
+    pub fn set_color_key(&mut self, key: Option<[u8; 3]>) {
+        use res::tex::TexCache;
+        self.tex_cache.set_color_key(key)
+    }
+
+    /// Sets whether transparent borders are trimmed from textures as they're
+    /// cached. See res::tex::TexCache::set_trim_transparent.
+    pub fn set_trim_transparent(&mut self, trim: bool) {
+        use res::tex::TexCache;
+        self.tex_cache.set_trim_transparent(trim)
+    }
+
+    /// Sets the packing group textures are cached into. See
+    /// res::tex::TexCache::set_packing_group.
+    pub fn set_packing_group(&mut self, group: Option<&str>) {
+        use res::tex::TexCache;
+        self.tex_cache.set_packing_group(group)
+    }
+
+    /// Gives a texture a human readable name, used in log messages about it.
+    pub fn set_tex_name(&mut self, tex: TexHandle, name: &str) {
+        self.tex_cache.set_tex_name(tex, name)
+    }
+
+    /// The name given to a texture with set_tex_name(), if any.
+    pub fn tex_name(&self, tex: TexHandle) -> Option<&str> {
+        self.tex_cache.tex_name(tex)
+    }
+
+    /// Reads a cached texture back from the GPU. See TexCache::read_tex().
+    pub fn read_tex(&self, tex: TexHandle) -> Option<RgbaBuffer> {
+        use res::tex::TexCache;
+        self.tex_cache.read_tex(tex)
+    }
+
     /// Cache textures from bytes, returning a list of texture handles.
+    #[cfg(feature = "image")]
     pub fn cache_tex_from_bytes<F: glium::backend::Facade>(
         &mut self,
         display: &F,
@@ -258,35 +1696,499 @@ impl<'a> Renderer<'a> {
         use res::tex::TexCache;
         self.tex_cache.cache_tex_from_bytes(display, bytes)
     }
+
+    /// Cache textures from bytes, making `key` transparent in them. See
+    /// res::tex::TexCache::cache_tex_from_bytes_color_keyed.
+    #[cfg(feature = "image")]
+    pub fn cache_tex_from_bytes_color_keyed<F: glium::backend::Facade>(
+        &mut self,
+        display: &F,
+        bytes: &[&[u8]],
+        key: [u8; 3],
+    ) -> Vec<Result<TexHandle, CacheTexError>> {
+        use res::tex::TexCache;
+        self.tex_cache.cache_tex_from_bytes_color_keyed(display, bytes, key)
+    }
+
+    /// Cache textures from raw RGBA pixel data, returning a list of texture
+    /// handles. See res::tex::TexCache::cache_tex_from_rgba.
+    pub fn cache_tex_from_rgba<F: glium::backend::Facade>(
+        &mut self,
+        display: &F,
+        images: &[(u32, u32, &[u8])],
+    ) -> Vec<Result<TexHandle, CacheTexError>> {
+        use res::tex::TexCache;
+        self.tex_cache.cache_tex_from_rgba(display, images)
+    }
+
+    /// Frees cached textures, so their space can be reused. See
+    /// res::tex::TexCache::free_tex.
+    pub fn free_tex(&mut self, tex: &[TexHandle]) {
+        use res::tex::TexCache;
+        self.tex_cache.free_tex(tex)
+    }
+
+    /// Creates a streaming texture. See
+    /// res::tex::glium_cache::GliumTexCache::create_streaming_tex.
+    pub fn create_streaming_tex<F: glium::backend::Facade>(
+        &mut self,
+        display: &F,
+        w: u32,
+        h: u32,
+    ) -> Result<StreamingTexture, CacheTexError> {
+        self.tex_cache.create_streaming_tex(display, w, h)
+    }
+
+    /// Replaces part of the contents of a streaming texture. See
+    /// res::tex::glium_cache::GliumTexCache::update_streaming_tex.
+    pub fn update_streaming_tex(
+        &self,
+        tex: &mut StreamingTexture,
+        region: [u32; 4],
+        data: &[u8],
+    ) -> Result<(), CacheTexError> {
+        self.tex_cache.update_streaming_tex(tex, region, data)
+    }
+}
+
+/// An orthographic projection matrix mapping (0, 0) to the top left of the
+/// screen and (w, h) to the bottom right.
+fn ortho_proj(w: u32, h: u32) -> [[f32; 4]; 4] {
+    [
+        [2.0 / w as f32, 0.0, 0.0, -0.0],
+        [0.0, -2.0 / h as f32, 0.0, 0.0],
+        [0.0, 0.0, -1.0, 0.0],
+        [-1.0, 1.0, 0.0, 1.0],
+    ]
+}
+
+/// Alpha blending for transparent windows. Colours are blended as usual, but
+/// alpha is blended as coverage, so the result is premultiplied by its alpha.
+fn transparent_blending() -> glium::Blend {
+    use glium::{BlendingFunction, LinearBlendingFactor};
+    glium::Blend {
+        color: BlendingFunction::Addition {
+            source: LinearBlendingFactor::SourceAlpha,
+            destination: LinearBlendingFactor::OneMinusSourceAlpha,
+        },
+        alpha: BlendingFunction::Addition {
+            source: LinearBlendingFactor::One,
+            destination: LinearBlendingFactor::OneMinusSourceAlpha,
+        },
+        constant_value: (0.0, 0.0, 0.0, 0.0),
+    }
+}
+
+/// Blending which adds what's drawn to the target, for the overdraw heatmap.
+fn additive_blending() -> glium::Blend {
+    use glium::{BlendingFunction, LinearBlendingFactor};
+    let add = BlendingFunction::Addition {
+        source: LinearBlendingFactor::One,
+        destination: LinearBlendingFactor::One,
+    };
+    glium::Blend {
+        color: add,
+        alpha: add,
+        constant_value: (0.0, 0.0, 0.0, 0.0),
+    }
+}
+
+/// Converts a pixel with premultiplied alpha to straight alpha.
+fn unpremultiply(pixel: [u8; 4]) -> [u8; 4] {
+    let a = pixel[3] as u32;
+    if a == 0 {
+        return [0, 0, 0, 0];
+    }
+    let channel = |c: u8| ((c as u32 * 255 + a / 2) / a).min(255) as u8;
+    [channel(pixel[0]), channel(pixel[1]), channel(pixel[2]), pixel[3]]
+}
+
+/// Returns a projection matrix which draws everything `offset` pixels from
+/// where `proj` would.
+fn translate_proj(proj: &[[f32; 4]; 4], offset: [f32; 2]) -> [[f32; 4]; 4] {
+    let mut result = *proj;
+    for ii in 0..4 {
+        result[3][ii] += proj[0][ii] * offset[0] + proj[1][ii] * offset[1];
+    }
+    result
+}
+
+/// Returns a projection matrix which scrolls `proj` by the camera position,
+/// scaled by a parallax factor.
+fn scroll_proj(proj: &[[f32; 4]; 4], camera: [f32; 2], parallax: [f32; 2]) -> [[f32; 4]; 4] {
+    translate_proj(proj, [-camera[0] * parallax[0], -camera[1] * parallax[1]])
+}
+
+/// Receives all the vertex packets waiting in a channel, grouping the vertices
+/// into batches by texture. Pad the batches with pad_batches() before drawing
+/// them. The emptied packets are returned to `pool`, and batch buffers are
+/// taken from `arena`.
+/// # Returns
+/// The number of packets received, the batches, and the instanced glyphs
+/// grouped by their draw overrides.
+fn recv_batches(
+    receiver: &mpsc::Receiver<VertexPacket>,
+    pool: &Mutex<PacketArena>,
+    arena: &mut VertexArena,
+) -> (usize, Vec<(BatchKey, Vec<Vertex>)>, Vec<(usize, Vec<GlyphInstance>)>) {
+    let mut packets = Vec::new();
+    let mut v_data_list: Vec<(BatchKey, Vec<Vertex>)> = Vec::new();
+    let mut glyph_list: Vec<(usize, Vec<GlyphInstance>)> = Vec::new();
+    // VBO_SIZE, no more data must be buffered.
+    loop {
+        let res = receiver.try_recv();
+        if res.is_err() {
+            // If the result of try_recv is an error, either all the sender's are
+            // disconnected (not expected, as we own a sender) OR the channel is
+            // empty, which means we've buffered all the data we can.
+            match res.err().unwrap() {
+                mpsc::TryRecvError::Empty => break,
+                mpsc::TryRecvError::Disconnected => panic!("Vertex data senders disconnected!"),
+            }
+        }
+        packets.push(res.unwrap());
+    }
+    let num_packets = packets.len();
+    sort_packets(&mut packets);
+
+    for mut data_packet in packets {
+        // Copy data from the packet into v_data
+
+        for (ii, &(key, start)) in data_packet.runs.iter().enumerate() {
+            let end = match data_packet.runs.get(ii + 1) {
+                Some(&(_, next_start)) => next_start,
+                None => data_packet.vertices.len(),
+            };
+            let run = &data_packet.vertices[start..end];
+            if run.is_empty() {
+                continue;
+            }
+            // Find the right list to insert this run into. If there isn't one,
+            // create a new tuple and push it onto v_data_list.
+            match v_data_list.iter().position(|&(k, _)| k == key) {
+                Some(ix) => v_data_list[ix].1.extend_from_slice(run),
+                None => {
+                    let mut list = arena.alloc();
+                    list.extend_from_slice(run);
+                    v_data_list.push((key, list));
+                }
+            }
+        }
+
+        // Instanced glyphs are grouped by their draw overrides in the same
+        // way.
+        for (ii, &(draw_overrides, start)) in data_packet.glyph_runs.iter().enumerate() {
+            let end = match data_packet.glyph_runs.get(ii + 1) {
+                Some(&(_, next_start)) => next_start,
+                None => data_packet.glyphs.len(),
+            };
+            let run = &data_packet.glyphs[start..end];
+            if run.is_empty() {
+                continue;
+            }
+            match glyph_list.iter().position(|&(o, _)| o == draw_overrides) {
+                Some(ix) => glyph_list[ix].1.extend_from_slice(run),
+                None => glyph_list.push((draw_overrides, run.to_vec())),
+            }
+        }
+
+        pool.lock().unwrap().recycle(data_packet);
+    }
+
+    // Check data packet won't be too long
+    #[cfg(feature = "vbo_overflow_panic")]
+    {
+        for &(_, ref list) in &v_data_list {
+            if list.len() >= VBO_SIZE {
+                panic!("VBO Overflow");
+            }
+        }
+    }
+
+    (num_packets, v_data_list, glyph_list)
+}
+
+/// Sorts packets into the order they're drawn in. Packets recorded on
+/// streams are ordered by stream then sequence number, so the order doesn't
+/// depend on which thread flushed first. They're drawn after packets which
+/// weren't recorded on a stream, which are left in the order they arrived.
+fn sort_packets(packets: &mut [VertexPacket]) {
+    packets.sort_by_key(|p| p.submission.map_or((0, 0, 0), |(stream, seq)| (1, stream, seq)));
+}
+
+/// Pads each batch to VBO_SIZE, so it fills the VBO.
+fn pad_batches(batches: &mut [(BatchKey, Vec<Vertex>)]) {
+    for &mut (_, ref mut list) in batches {
+        while list.len() < VBO_SIZE {
+            list.push(Vertex {
+                pos: [0.0; 2],
+                col: [0; 4],
+                tex_coords: [0.0, 0.0],
+                pick_id: 0.0,
+            });
+        }
+    }
+}
+
+/// The key instanced glyphs with the given draw overrides would be batched
+/// with if they were drawn as vertices.
+#[cfg(feature = "font")]
+fn glyph_batch_key(draw_overrides: usize) -> BatchKey {
+    BatchKey {
+        tex_type: TexType::Font,
+        tex_ix: 0,
+        draw_overrides: draw_overrides,
+    }
+}
+
+/// The colour a batch is tinted with when page tinting is enabled - a
+/// different hue for each texture cache page, and grey for the font cache.
+/// The alpha is how strongly the tint is mixed in.
+fn page_tint_colour(key: &BatchKey) -> [f32; 4] {
+    match key.tex_type {
+        TexType::Texture => {
+            // Step around the colour wheel by the golden ratio, so pages next
+            // to each other get very different hues.
+            let hue = (key.tex_ix as f32 * 0.618034).fract() * 6.0;
+            let x = 1.0 - (hue % 2.0 - 1.0).abs();
+            let (r, g, b) = match hue as u32 {
+                0 => (1.0, x, 0.0),
+                1 => (x, 1.0, 0.0),
+                2 => (0.0, 1.0, x),
+                3 => (0.0, x, 1.0),
+                4 => (x, 0.0, 1.0),
+                _ => (1.0, 0.0, x),
+            };
+            [r, g, b, 0.6]
+        }
+        #[cfg(feature = "font")]
+        TexType::Font => [0.5, 0.5, 0.5, 0.6],
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "bench")]
     use test::Bencher;
     use test_helper::create_headless_display;
     use super::*;
 
+    #[cfg(feature = "bench")]
     fn get_white<F: glium::backend::Facade>(r: &mut Renderer, display: F) -> TexHandle {
         // We need to buffer a small white rectangle, for when drawing coloured
-        // shapes. The following is an array for a bitmap with a 1x1 white pixel.
-        let bytes = [0x42, 0x4d, 0x42, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x3e, 0x00, 0x00, 0x00, 0x28, 0x00, 0x00, 0x00, 0x01, 0x00,
-                    0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff,
-                    0xff, 0x00, 0x80, 0x00, 0x00, 0x00];
-        let t_vec_ref = &r.cache_tex_from_bytes(&display, &[&bytes[..]])[0];
+        // shapes. This is a single white RGBA pixel.
+        let pixel = [0xff, 0xff, 0xff, 0xff];
+        let t_vec_ref = &r.cache_tex_from_rgba(&display, &[(1, 1, &pixel[..])])[0];
         if t_vec_ref.is_err() {
             println!("{:?}", t_vec_ref.as_ref().err().unwrap());
         }
         return t_vec_ref.as_ref().unwrap().clone();
     }
 
+    #[test]
+    fn page_tints_differ() {
+        let tint = |tex_ix| {
+            page_tint_colour(&BatchKey {
+                tex_type: TexType::Texture,
+                tex_ix: tex_ix,
+                draw_overrides: 0,
+            })
+        };
+        for a in 0..6 {
+            for b in (a + 1)..6 {
+                assert_ne!(tint(a), tint(b));
+            }
+        }
+    }
+
+    #[test]
+    fn packets_sort_by_stream() {
+        let vertex = Vertex {
+            pos: [0.0; 2],
+            col: [0; 4],
+            tex_coords: [0.0, 0.0],
+            pick_id: 0.0,
+        };
+        let packet = |submission, len| VertexPacket {
+            vertices: vec![vertex; len],
+            submission: submission,
+            ..VertexPacket::new()
+        };
+        let mut packets = vec![
+            packet(Some((1, 0)), 1),
+            packet(None, 2),
+            packet(Some((0, 1)), 3),
+            packet(None, 4),
+            packet(Some((0, 0)), 5),
+        ];
+        sort_packets(&mut packets);
+        let lens: Vec<usize> = packets.iter().map(|p| p.vertices.len()).collect();
+        assert_eq!(lens, vec![2, 4, 5, 3, 1]);
+    }
+
+    #[test]
+    fn vertex_packet_runs_share_keys() {
+        let key = |tex_ix| BatchKey {
+            tex_type: TexType::Texture,
+            tex_ix: tex_ix,
+            draw_overrides: 0,
+        };
+        let v = Vertex {
+            pos: [0.0; 2],
+            col: [0; 4],
+            tex_coords: [0.0, 0.0],
+            pick_id: 0.0,
+        };
+        let mut packet = VertexPacket::new();
+        packet.set_key(key(0));
+        packet.vertices.push(v);
+        packet.set_key(key(0));
+        packet.vertices.push(v);
+        // An empty run is replaced rather than kept.
+        packet.set_key(key(1));
+        packet.set_key(key(2));
+        packet.vertices.push(v);
+        assert_eq!(packet.runs, vec![(key(0), 0), (key(2), 2)]);
+    }
+
+    #[test]
+    fn glyph_instance_vertices_are_sheared_about_the_baseline() {
+        let glyph = GlyphInstance {
+            rect: [10.0, 0.0, 20.0, 10.0],
+            uv: [0.0, 0.0, 1.0, 1.0],
+            col: [255; 4],
+            shear: [10.0, 0.5],
+        };
+        let v = glyph.vertices();
+        // Top left, top right, bottom right, top left, bottom left, bottom
+        // right. The top is 10 pixels above the baseline.
+        let pos: Vec<[f32; 2]> = v.iter().map(|v| v.pos).collect();
+        assert_eq!(pos, vec![[15.0, 0.0], [25.0, 0.0], [20.0, 10.0],
+                             [15.0, 0.0], [10.0, 10.0], [20.0, 10.0]]);
+        assert_eq!(v[2].tex_coords, [1.0, 1.0]);
+    }
+
+    #[test]
+    fn vertex_packet_glyph_runs_share_overrides() {
+        let glyph = GlyphInstance {
+            rect: [0.0; 4],
+            uv: [0.0; 4],
+            col: [0; 4],
+            shear: [0.0; 2],
+        };
+        let mut packet = VertexPacket::new();
+        packet.set_glyph_overrides(0);
+        packet.glyphs.push(glyph);
+        packet.set_glyph_overrides(0);
+        packet.glyphs.push(glyph);
+        packet.set_glyph_overrides(1);
+        packet.set_glyph_overrides(2);
+        packet.glyphs.push(glyph);
+        assert_eq!(packet.glyph_runs, vec![(0, 0), (2, 2)]);
+        packet.clear();
+        assert!(packet.glyphs.is_empty() && packet.glyph_runs.is_empty());
+    }
+
+    #[test]
+    fn vertex_only_contains_shader_attributes() {
+        use glium::vertex::Vertex as GliumVertex;
+        // pos, tex_coords, col and pick_id, with no padding.
+        assert_eq!(std::mem::size_of::<Vertex>(), 8 + 8 + 4 + 4);
+        assert_eq!(Vertex::build_bindings().len(), 4);
+    }
+
+    #[test]
+    fn unpremultiply_restores_colour() {
+        assert_eq!(unpremultiply([64, 32, 0, 128]), [128, 64, 0, 128]);
+        assert_eq!(unpremultiply([255, 255, 255, 255]), [255, 255, 255, 255]);
+        assert_eq!(unpremultiply([3, 0, 0, 0]), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn translate_proj_offsets_in_pixels() {
+        let proj = translate_proj(&ortho_proj(100, 50), [10.0, 5.0]);
+        // The top left corner of the screen moves 10 pixels right (0.2 in
+        // clip space) and 5 pixels down (0.2).
+        assert!((proj[3][0] - -0.8).abs() < 1e-6);
+        assert!((proj[3][1] - 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn pack_color_rounds_and_clamps() {
+        assert_eq!(pack_color(&[0.0, 0.5, 1.0, 2.0]), [0, 128, 255, 255]);
+        assert_eq!(pack_color(&[-1.0, 0.2, 0.0, 1.0]), [0, 51, 0, 255]);
+    }
+
+    #[test]
+    fn batch_records_group_by_texture() {
+        let display = create_headless_display();
+        let mut r = Renderer::new(&display).unwrap();
+        let pixel = [0xff, 0xff, 0xff, 0xff];
+        let texs: Vec<TexHandle> = r
+            .cache_tex_from_rgba(&display, &[(1, 1, &pixel[..]), (1, 1, &pixel[..])])
+            .into_iter()
+            .map(|t| t.unwrap())
+            .collect();
+        r.set_batch_recording(true);
+        {
+            let mut g = r.get_renderer_controller(texs[0]);
+            g.rect(&[0.0, 0.0, 1.0, 1.0], &[1.0, 1.0, 1.0, 1.0]);
+            g.tex(texs[1], &[0.0, 0.0, 1.0, 1.0], &[1.0, 1.0, 1.0, 1.0]).unwrap();
+            g.rect(&[0.0, 0.0, 1.0, 1.0], &[1.0, 1.0, 1.0, 1.0]);
+            g.flush();
+        }
+        r.recv_data();
+        // Both textures are on the same atlas page, so everything is batched
+        // together.
+        let records = r.batch_records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].layer, Layer::Main);
+        assert_eq!(records[0].vertices, 18);
+        assert_eq!(records[0].draw_overrides, None);
+    }
+
+    #[test]
+    fn read_tex_returns_cached_pixels() {
+        let display = create_headless_display();
+        let mut r = Renderer::new(&display).unwrap();
+        // 2x2: red, green on the top row, blue, white on the bottom row.
+        let data = [
+            255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 255,
+        ];
+        let texs: Vec<TexHandle> = r
+            .cache_tex_from_rgba(&display, &[(1, 1, &[0; 4][..]), (2, 2, &data[..])])
+            .into_iter()
+            .map(|t| t.unwrap())
+            .collect();
+        let img = r.read_tex(texs[1]).unwrap();
+        assert_eq!((img.w, img.h), (2, 2));
+        assert_eq!(img.data, data.to_vec());
+        assert!(r.read_tex(TexHandle(100)).is_none());
+    }
+
+    #[test]
+    fn packing_groups_get_their_own_pages() {
+        use res::tex::TexHandleLookup;
+        let display = create_headless_display();
+        let mut r = Renderer::new(&display).unwrap();
+        let pixel = [255; 4];
+        let cache = |r: &mut Renderer, group| {
+            r.set_packing_group(group);
+            let tex = r.cache_tex_from_rgba(&display, &[(1, 1, &pixel[..])]).remove(0);
+            r.tex_cache.rect_for(tex.unwrap()).unwrap().0
+        };
+        let ungrouped = cache(&mut r, None);
+        let ui = cache(&mut r, Some("ui"));
+        assert!(ui != ungrouped);
+        assert_eq!(cache(&mut r, Some("ui")), ui);
+        assert_eq!(cache(&mut r, None), ungrouped);
+    }
+
+    #[cfg(feature = "bench")]
     #[bench]
     fn rect_bench(b: &mut Bencher) {
         let display = create_headless_display();
-        let mut r = Renderer::new(&display);
+        let mut r = Renderer::new(&display).unwrap();
 
         let white = get_white(&mut r, display);
 
@@ -297,10 +2199,11 @@ mod tests {
         });
     }
 
+    #[cfg(feature = "bench")]
     #[bench]
     fn recv_data_bench(b: &mut Bencher) {
         let display = create_headless_display();
-        let mut r = Renderer::new(&display);
+        let mut r = Renderer::new(&display).unwrap();
         let white = get_white(&mut r, display);
 
         b.iter(|| {
@@ -309,8 +2212,91 @@ mod tests {
                 for _ in 0..100 {
                     g.rect(&[0.0, 0.0, 0.0, 0.0], &[0.0, 0.0, 0.0, 0.0])
                 }
+                g.flush();
             }
             r.recv_data();
         });
     }
+
+    #[cfg(feature = "bench")]
+    #[bench]
+    fn circle_bench(b: &mut Bencher) {
+        let display = create_headless_display();
+        let mut r = Renderer::new(&display).unwrap();
+        let white = get_white(&mut r, display);
+        let mut g = r.get_renderer_controller(white);
+
+        // Tessellation is cached after the first call, so this measures
+        // scaling and translating the unit circle.
+        b.iter(|| {
+            g.circle(&[100.0, 100.0], 50.0, 0, &[1.0, 1.0, 1.0, 1.0]);
+        });
+    }
+
+    #[cfg(feature = "bench")]
+    #[bench]
+    fn flush_bench(b: &mut Bencher) {
+        let display = create_headless_display();
+        let mut r = Renderer::new(&display).unwrap();
+        let white = get_white(&mut r, display);
+        let mut g = r.get_renderer_controller(white);
+
+        b.iter(|| {
+            for _ in 0..1000 {
+                g.rect(&[0.0, 0.0, 1.0, 1.0], &[1.0, 1.0, 1.0, 1.0]);
+            }
+            g.flush();
+            r.recv_data();
+        });
+    }
+
+    #[cfg(feature = "bench")]
+    #[bench]
+    fn recv_data_batching_bench(b: &mut Bencher) {
+        let display = create_headless_display();
+        let mut r = Renderer::new(&display).unwrap();
+        let pixel = [0xff, 0xff, 0xff, 0xff];
+        let texs: Vec<TexHandle> = r
+            .cache_tex_from_rgba(&display, &[(1, 1, &pixel[..]), (2, 1, &[0xff; 8][..])])
+            .into_iter()
+            .map(|t| t.unwrap())
+            .collect();
+
+        // Interleave textures, so every quad starts a new run.
+        b.iter(|| {
+            {
+                let mut g = r.get_renderer_controller(texs[0]);
+                for ii in 0..1000 {
+                    g.tex(texs[ii % 2], &[0.0, 0.0, 1.0, 1.0], &[1.0, 1.0, 1.0, 1.0])
+                        .unwrap();
+                }
+                g.flush();
+            }
+            r.recv_data();
+        });
+    }
+
+    #[cfg(feature = "bench")]
+    #[bench]
+    fn render_bench(b: &mut Bencher) {
+        let display = create_headless_display();
+        let mut r = Renderer::new(&display).unwrap();
+        let pixel = [0xff, 0xff, 0xff, 0xff];
+        let white = r.cache_tex_from_rgba(&display, &[(1, 1, &pixel[..])]).remove(0).unwrap();
+        {
+            let mut g = r.get_renderer_controller(white);
+            for _ in 0..10000 {
+                g.rect(&[0.0, 0.0, 8.0, 8.0], &[1.0, 1.0, 1.0, 1.0]);
+            }
+            g.flush();
+        }
+        r.recv_data();
+
+        // Measures uploading the received batches to VBOs and drawing them.
+        b.iter(|| {
+            let mut target = display.draw();
+            r.render(&mut target).unwrap();
+            target.finish().unwrap();
+        });
+    }
 }
@@ -0,0 +1,350 @@
+use geometry;
+use renderer::{Gradient, RendererController, StrokeStyle};
+#[cfg(feature = "font")]
+use res::font::PathCommand;
+
+/// Which parts of a path with overlapping or nested contours are filled.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum FillRule {
+    /// Points are inside if the contours around them don't cancel out, so
+    /// a contour inside another is only a hole if it winds the other way.
+    NonZero,
+    /// Points are inside if they're inside an odd number of contours, so
+    /// every nested contour is a hole.
+    EvenOdd,
+}
+
+/// What a path is filled with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FillStyle {
+    Solid([f32; 4]),
+    Gradient(Gradient),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Segment {
+    Line([f32; 2]),
+    Quad([f32; 2], [f32; 2]),
+    Cubic([f32; 2], [f32; 2], [f32; 2]),
+}
+
+/// A contour of a path.
+#[derive(Clone, Debug, PartialEq)]
+struct SubPath {
+    start: [f32; 2],
+    segments: Vec<Segment>,
+    closed: bool,
+}
+
+/// A vector shape made of straight lines and bezier curves, which can be
+/// filled and stroked.
+///
+/// ```ignore
+/// let leaf = Path::new()
+///     .move_to([0.0, 40.0])
+///     .quad_to([0.0, 0.0], [40.0, 0.0])
+///     .quad_to([40.0, 40.0], [0.0, 40.0])
+///     .close();
+/// leaf.fill(&mut controller, &FillStyle::Solid([0.2, 0.7, 0.2, 1.0]));
+/// leaf.stroke(&mut controller, &StrokeStyle::new(2.0), &[0.0, 0.3, 0.0, 1.0]);
+/// ```
+///
+/// Curves are split into straight segments when the path is drawn, based on
+/// their size and the controller's tessellation scale. Fills always join
+/// each contour back to its start, but strokes only do for closed contours.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    subpaths: Vec<SubPath>,
+    rule: FillRule,
+}
+
+impl Default for Path {
+    fn default() -> Path {
+        Path::new()
+    }
+}
+
+impl Path {
+    /// An empty path, filled with FillRule::NonZero.
+    pub fn new() -> Path {
+        Path {
+            subpaths: Vec::new(),
+            rule: FillRule::NonZero,
+        }
+    }
+
+    /// Converts a glyph outline from QGFX::glyph_outline().
+    #[cfg(feature = "font")]
+    pub fn from_commands(commands: &[PathCommand]) -> Path {
+        commands.iter().fold(Path::new(), |path, command| match *command {
+            PathCommand::MoveTo(p) => path.move_to(p),
+            PathCommand::LineTo(p) => path.line_to(p),
+            PathCommand::QuadTo(ctrl, p) => path.quad_to(ctrl, p),
+            PathCommand::Close => path.close(),
+        })
+    }
+
+    /// Starts a new contour at a point.
+    pub fn move_to(mut self, p: [f32; 2]) -> Path {
+        self.subpaths.push(SubPath {
+            start: p,
+            segments: Vec::new(),
+            closed: false,
+        });
+        self
+    }
+
+    /// Adds a straight line from the current point.
+    pub fn line_to(self, p: [f32; 2]) -> Path {
+        self.segment(p, Segment::Line(p))
+    }
+
+    /// Adds a quadratic bezier curve from the current point, with one
+    /// control point.
+    pub fn quad_to(self, ctrl: [f32; 2], p: [f32; 2]) -> Path {
+        self.segment(p, Segment::Quad(ctrl, p))
+    }
+
+    /// Adds a cubic bezier curve from the current point, with two control
+    /// points.
+    pub fn cubic_to(self, ctrl1: [f32; 2], ctrl2: [f32; 2], p: [f32; 2]) -> Path {
+        self.segment(p, Segment::Cubic(ctrl1, ctrl2, p))
+    }
+
+    /// Closes the current contour, joining it back to its start. Lines and
+    /// curves added after this start a new contour from the same start.
+    pub fn close(mut self) -> Path {
+        if let Some(subpath) = self.subpaths.last_mut() {
+            subpath.closed = true;
+        }
+        self
+    }
+
+    /// Sets how overlapping and nested contours are filled.
+    pub fn fill_rule(mut self, rule: FillRule) -> Path {
+        self.rule = rule;
+        self
+    }
+
+    /// Fills the path. The same as RendererController::fill_path().
+    pub fn fill(&self, controller: &mut RendererController, style: &FillStyle) {
+        controller.fill_path(self, style);
+    }
+
+    /// Strokes the path. The same as RendererController::stroke_path().
+    pub fn stroke(
+        &self,
+        controller: &mut RendererController,
+        style: &StrokeStyle,
+        col: &[f32; 4],
+    ) {
+        controller.stroke_path(self, style, col);
+    }
+
+    /// Adds a segment to the current contour, starting a new one if there
+    /// isn't one or it's closed. A path without a move_to() starts at the
+    /// end of its first segment.
+    fn segment(mut self, end: [f32; 2], segment: Segment) -> Path {
+        let start = match self.subpaths.last() {
+            Some(subpath) if !subpath.closed => None,
+            Some(subpath) => Some(subpath.start),
+            None => Some(end),
+        };
+        if let Some(start) = start {
+            self = self.move_to(start);
+        }
+        self.subpaths.last_mut().unwrap().segments.push(segment);
+        self
+    }
+
+    /// Splits the path's curves into straight segments.
+    /// # Params
+    /// * `scale` - The tessellation scale. See
+    ///             RendererController::set_tessellation_scale().
+    /// # Returns
+    /// Each contour's points, and whether it's closed.
+    pub(crate) fn flatten(&self, scale: f32) -> Vec<(Vec<[f32; 2]>, bool)> {
+        self.subpaths
+            .iter()
+            .map(|subpath| {
+                let mut points = vec![subpath.start];
+                for segment in &subpath.segments {
+                    let p0 = *points.last().unwrap();
+                    let (p1, p2, p3) = match *segment {
+                        Segment::Line(p) => {
+                            points.push(p);
+                            continue;
+                        }
+                        // Raise quadratics to cubics with the same curve
+                        Segment::Quad(c, p) => {
+                            let lerp = |a: [f32; 2], t: f32| {
+                                [a[0] + (c[0] - a[0]) * t, a[1] + (c[1] - a[1]) * t]
+                            };
+                            (lerp(p0, 2.0 / 3.0), lerp(p, 2.0 / 3.0), p)
+                        }
+                        Segment::Cubic(c1, c2, p) => (c1, c2, p),
+                    };
+                    let segments = curve_segments(&[p0, p1, p2, p3], scale);
+                    points.extend_from_slice(&geometry::cubic_bezier(&p0, &p1, &p2, &p3,
+                                                                     segments)[1..]);
+                }
+                (points, subpath.closed)
+            })
+            .collect()
+    }
+
+    /// Splits the filled area of the path into trapezoids, with corners in
+    /// order around each. Overlapping and self-intersecting contours are
+    /// handled, at a cost which grows with the square of the number of
+    /// edges.
+    pub(crate) fn fill_trapezoids(&self, scale: f32) -> Vec<[[f32; 2]; 4]> {
+        // Every contour is closed for filling
+        let mut edges = Vec::new();
+        for (points, _) in self.flatten(scale) {
+            for ii in 0..points.len() {
+                let (a, b) = (points[ii], points[(ii + 1) % points.len()]);
+                if a[1] != b[1] {
+                    edges.push((a, b));
+                }
+            }
+        }
+
+        // Split the area into horizontal slabs which no edge starts, ends or
+        // crosses another edge inside of.
+        let mut ys: Vec<f32> = edges.iter().flat_map(|&(a, b)| vec![a[1], b[1]]).collect();
+        for (ii, &e0) in edges.iter().enumerate() {
+            for &e1 in &edges[ii + 1..] {
+                if let Some(y) = crossing_y(e0, e1) {
+                    ys.push(y);
+                }
+            }
+        }
+        ys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        ys.dedup();
+
+        let mut traps = Vec::new();
+        for slab in ys.windows(2) {
+            let (y0, y1) = (slab[0], slab[1]);
+            let mid = (y0 + y1) / 2.0;
+            // The edges crossing the slab, as (x at mid, x at y0, x at y1,
+            // winding), from left to right.
+            let mut crossings: Vec<(f32, f32, f32, i32)> = edges
+                .iter()
+                .filter(|&&(a, b)| a[1].min(b[1]) < mid && a[1].max(b[1]) > mid)
+                .map(|&(a, b)| {
+                    let x_at = |y: f32| a[0] + (b[0] - a[0]) * (y - a[1]) / (b[1] - a[1]);
+                    (x_at(mid), x_at(y0), x_at(y1), if a[1] < b[1] { 1 } else { -1 })
+                })
+                .collect();
+            crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            let mut winding = 0;
+            let mut entered = None;
+            for &crossing in &crossings {
+                let was_inside = self.is_inside(winding);
+                winding += crossing.3;
+                match (was_inside, self.is_inside(winding), entered) {
+                    (false, true, _) => entered = Some(crossing),
+                    (true, false, Some(left)) => {
+                        traps.push([[left.1, y0], [crossing.1, y0], [crossing.2, y1],
+                                    [left.2, y1]]);
+                        entered = None;
+                    }
+                    _ => (),
+                }
+            }
+        }
+        traps
+    }
+
+    fn is_inside(&self, winding: i32) -> bool {
+        match self.rule {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding % 2 != 0,
+        }
+    }
+}
+
+/// The number of straight segments to draw a cubic bezier curve with, from
+/// its control points, so it looks smooth at the given tessellation scale.
+pub(crate) fn curve_segments(ctrl: &[[f32; 2]; 4], scale: f32) -> usize {
+    // The control polygon is at least as long as the curve
+    let hull: f32 = ctrl.windows(2)
+        .map(|w| ((w[1][0] - w[0][0]).powi(2) + (w[1][1] - w[0][1]).powi(2)).sqrt())
+        .sum();
+    ((hull * scale / 8.0).ceil() as usize).max(4).min(256)
+}
+
+/// The height at which two edges cross, if they cross anywhere but their
+/// ends.
+fn crossing_y(e0: ([f32; 2], [f32; 2]), e1: ([f32; 2], [f32; 2])) -> Option<f32> {
+    let ((a, b), (c, d)) = (e0, e1);
+    let r = [b[0] - a[0], b[1] - a[1]];
+    let s = [d[0] - c[0], d[1] - c[1]];
+    let denom = r[0] * s[1] - r[1] * s[0];
+    if denom == 0.0 {
+        return None;
+    }
+    let ac = [c[0] - a[0], c[1] - a[1]];
+    let t = (ac[0] * s[1] - ac[1] * s[0]) / denom;
+    let u = (ac[0] * r[1] - ac[1] * r[0]) / denom;
+    if t > 0.0 && t < 1.0 && u > 0.0 && u < 1.0 {
+        Some(a[1] + r[1] * t)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(traps: &[[[f32; 2]; 4]]) -> f32 {
+        traps
+            .iter()
+            .map(|t| ((t[1][0] - t[0][0]) + (t[2][0] - t[3][0])) / 2.0 * (t[2][1] - t[0][1]))
+            .sum()
+    }
+
+    fn square(path: Path, x: f32, y: f32, size: f32) -> Path {
+        path.move_to([x, y])
+            .line_to([x + size, y])
+            .line_to([x + size, y + size])
+            .line_to([x, y + size])
+            .close()
+    }
+
+    #[test]
+    fn fill_rules_handle_holes() {
+        let nested = square(square(Path::new(), 0.0, 0.0, 10.0), 2.0, 2.0, 4.0);
+        assert!((area(&nested.fill_trapezoids(1.0)) - 100.0).abs() < 1e-4);
+        let even_odd = nested.fill_rule(FillRule::EvenOdd);
+        assert!((area(&even_odd.fill_trapezoids(1.0)) - 84.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn self_intersecting_paths_are_split() {
+        // A bow tie, crossing itself at (5, 5)
+        let bow_tie = Path::new()
+            .move_to([0.0, 0.0])
+            .line_to([10.0, 10.0])
+            .line_to([10.0, 0.0])
+            .line_to([0.0, 10.0])
+            .close();
+        assert!((area(&bow_tie.fill_trapezoids(1.0)) - 50.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn curves_are_flattened() {
+        let path = Path::new()
+            .move_to([0.0, 0.0])
+            .quad_to([10.0, 0.0], [10.0, 10.0])
+            .line_to([0.0, 10.0]);
+        let flat = path.flatten(1.0);
+        assert_eq!(flat.len(), 1);
+        let (ref points, closed) = flat[0];
+        assert!(!closed);
+        assert_eq!(points[0], [0.0, 0.0]);
+        assert_eq!(*points.last().unwrap(), [0.0, 10.0]);
+        assert!(points.len() > 4);
+    }
+}
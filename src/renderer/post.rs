@@ -0,0 +1,388 @@
+//! Post-processing. The frame is rendered into an offscreen texture, which is
+//! then drawn to the window with a full screen pass. With a floating point
+//! texture, colours brighter than white aren't clipped until the pass tone
+//! maps them. The pass then applies pixelation, the colour grading LUT and
+//! the CRT effect, if there are any.
+//!
+//! Before the final pass, the frame can be blurred, and the bright parts of
+//! it blurred into a bloom texture which the final pass adds back. Blurs are
+//! separable, so each is a horizontal pass into a second offscreen texture,
+//! then a vertical pass out of it.
+
+use glium::{self, Surface};
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{MipmapsOption, Texture2d, UncompressedFloatFormat};
+use error::QgfxError;
+use res::tex::glium_cache::HdrFormat;
+use res::tex::TexHandle;
+use renderer::shader;
+use renderer::{ColorSpace, Renderer};
+
+/// How colours outside the 0 to 1 range are mapped into it, by the final
+/// post-processing pass. Colours are multiplied by the exposure first.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Tonemap {
+    /// Colours are clamped, so bright colours clip to white.
+    Clamp,
+    /// The Reinhard operator, c / (1 + c). Bright colours approach white
+    /// without reaching it.
+    Reinhard,
+    /// A fit of the ACES filmic curve, which gives more contrast than
+    /// Reinhard.
+    Aces,
+}
+
+impl Default for Tonemap {
+    fn default() -> Tonemap {
+        Tonemap::Clamp
+    }
+}
+
+/// Settings for the bloom pass, which makes bright colours glow.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bloom {
+    /// Colours with a luminance below this don't glow, and brighter colours
+    /// are reduced by it. Without HDR, colours can't be brighter than 1.0, so
+    /// this should be lower.
+    pub threshold: f32,
+    /// How much of the glow is added to the frame.
+    pub intensity: f32,
+    /// The radius of the glow in pixels, up to 32.
+    pub radius: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Bloom {
+        Bloom {
+            threshold: 0.8,
+            intensity: 1.0,
+            radius: 8.0,
+        }
+    }
+}
+
+/// Settings for the CRT effect, which makes the frame look like an old
+/// television - a good match for pixel art.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Crt {
+    /// How much the screen bulges out. 0 is flat, and the edges curve more as
+    /// this increases.
+    pub curvature: f32,
+    /// How dark the gaps between scanlines are, from 0 (no scanlines) to 1
+    /// (black).
+    pub scanlines: f32,
+    /// The number of scanlines from the top of the screen to the bottom. For
+    /// pixel art, use the virtual resolution's height.
+    pub scanline_count: f32,
+    /// How far red and blue are split apart, in pixels.
+    pub aberration: f32,
+    /// How much the corners are darkened. 0 is no vignette.
+    pub vignette: f32,
+}
+
+impl Default for Crt {
+    fn default() -> Crt {
+        Crt {
+            curvature: 0.1,
+            scanlines: 0.5,
+            scanline_count: 240.0,
+            aberration: 1.0,
+            vignette: 0.3,
+        }
+    }
+}
+
+/// The largest blur radius, in pixels. The blur shader has a fixed number of
+/// taps.
+const MAX_BLUR_RADIUS: f32 = 32.0;
+
+#[derive(Copy, Clone)]
+struct PostVertex {
+    pos: [f32; 2],
+}
+
+implement_vertex!(PostVertex, pos);
+
+/// The offscreen target the frame is rendered into, and the pass which draws
+/// it to the window.
+pub struct PostProcess {
+    /// The format of the offscreen target, or None for RGBA8.
+    format: Option<HdrFormat>,
+    /// The offscreen target. It's recreated whenever the window is resized.
+    target: Option<Texture2d>,
+    /// Textures the same size as the target, for blurring. They're only
+    /// created when blur or bloom is enabled.
+    ping: Option<Texture2d>,
+    pong: Option<Texture2d>,
+    /// The radius of the blur over the whole frame, if any.
+    blur: Option<f32>,
+    bloom: Option<Bloom>,
+    crt: Option<Crt>,
+    /// The size of a pixelation block in pixels. 1 or less is no pixelation.
+    pixelate: f32,
+    tonemap: Tonemap,
+    exposure: f32,
+    /// The colour grading LUT. See Renderer::color_grading_lut().
+    lut: Option<TexHandle>,
+    color_space: ColorSpace,
+    /// A quad covering the screen.
+    quad: glium::VertexBuffer<PostVertex>,
+    program: glium::Program,
+    blur_program: glium::Program,
+}
+
+impl PostProcess {
+    /// # Params
+    /// * `format` - The format to render the frame in, or None for RGBA8.
+    /// * `color_space` - The renderer's colour space, as colours are stored
+    ///                   sRGB encoded with ColorSpace::Srgb.
+    pub fn new<F: glium::backend::Facade>(
+        display: &F,
+        format: Option<HdrFormat>,
+        color_space: ColorSpace,
+    ) -> Result<PostProcess, QgfxError> {
+        let corners = [
+            PostVertex { pos: [-1.0, -1.0] },
+            PostVertex { pos: [1.0, -1.0] },
+            PostVertex { pos: [-1.0, 1.0] },
+            PostVertex { pos: [1.0, 1.0] },
+        ];
+        Ok(PostProcess {
+            format: format,
+            target: None,
+            ping: None,
+            pong: None,
+            blur: None,
+            bloom: None,
+            crt: None,
+            pixelate: 0.0,
+            tonemap: Tonemap::default(),
+            exposure: 1.0,
+            lut: None,
+            color_space: color_space,
+            quad: try!(glium::VertexBuffer::new(display, &corners)),
+            program: try!(shader::get_post_program(
+                display,
+                color_space == ColorSpace::Srgb,
+            )),
+            blur_program: try!(shader::get_blur_program(display)),
+        })
+    }
+
+    /// Sets how bright colours are mapped into the displayable range.
+    pub fn set_tonemap(&mut self, tonemap: Tonemap) {
+        self.tonemap = tonemap;
+    }
+
+    /// Sets the exposure colours are multiplied by before tone mapping.
+    /// Defaults to 1.0.
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure;
+    }
+
+    /// Sets the colour grading LUT, a texture cached from a strip image. See
+    /// QGFX::set_color_grading().
+    pub fn set_color_grading(&mut self, lut: Option<TexHandle>) {
+        self.lut = lut;
+    }
+
+    /// Sets the radius, in pixels, of a Gaussian blur over the whole frame,
+    /// or None for no blur. The radius is clamped to 32.
+    pub fn set_blur(&mut self, radius: Option<f32>) {
+        self.blur = radius.map(|r| r.max(0.0).min(MAX_BLUR_RADIUS));
+    }
+
+    /// Sets the bloom settings, or None for no bloom.
+    pub fn set_bloom(&mut self, bloom: Option<Bloom>) {
+        self.bloom = bloom.map(|b| Bloom {
+            radius: b.radius.max(0.0).min(MAX_BLUR_RADIUS),
+            ..b
+        });
+    }
+
+    /// Sets the CRT effect settings, or None for no CRT effect.
+    pub fn set_crt(&mut self, crt: Option<Crt>) {
+        self.crt = crt;
+    }
+
+    /// Sets the size of a pixelation block, in pixels. 1 or less is no
+    /// pixelation. This is cheap, so can be changed every frame.
+    pub fn set_pixelate(&mut self, block_size: f32) {
+        self.pixelate = block_size;
+    }
+
+    /// The format the frame is rendered in, or None for RGBA8.
+    pub fn format(&self) -> Option<HdrFormat> {
+        self.format
+    }
+
+    /// Draws the frame into the offscreen target with `draw`, then draws the
+    /// offscreen target to `target` with the post-processing pass.
+    pub fn render<F, S, D>(
+        &mut self,
+        display: &F,
+        target: &mut S,
+        renderer: &mut Renderer,
+        draw: D,
+    ) -> Result<(), QgfxError>
+    where
+        F: glium::backend::Facade,
+        S: Surface,
+        D: FnOnce(&mut SimpleFrameBuffer, &mut Renderer) -> Result<(), QgfxError>,
+    {
+        let size = target.get_dimensions();
+        let format = match self.format {
+            Some(format) => format.texture_format(),
+            None => UncompressedFloatFormat::U8U8U8U8,
+        };
+        if outdated(&self.target, size) {
+            self.target = Some(try!(empty_target(display, format, size)));
+        }
+        let blurring = self.blur.is_some() || self.bloom.is_some();
+        if blurring && outdated(&self.ping, size) {
+            self.ping = Some(try!(empty_target(display, format, size)));
+            self.pong = Some(try!(empty_target(display, format, size)));
+        }
+        let scene = self.target.as_ref().unwrap();
+        {
+            let mut fb = try!(SimpleFrameBuffer::new(display, scene));
+            renderer.get_clear().apply(&mut fb);
+            try!(draw(&mut fb, renderer));
+        }
+
+        let texel = [1.0 / size.0 as f32, 1.0 / size.1 as f32];
+        if let Some(radius) = self.blur {
+            let ping = self.ping.as_ref().unwrap();
+            try!(self.blur_pass(display, scene, ping, [texel[0], 0.0], radius, -1.0));
+            try!(self.blur_pass(display, ping, scene, [0.0, texel[1]], radius, -1.0));
+        }
+        let (bloom, bloom_intensity) = match self.bloom {
+            Some(bloom) => {
+                let (ping, pong) = (self.ping.as_ref().unwrap(), self.pong.as_ref().unwrap());
+                try!(self.blur_pass(
+                    display,
+                    scene,
+                    ping,
+                    [texel[0], 0.0],
+                    bloom.radius,
+                    bloom.threshold.max(0.0),
+                ));
+                try!(self.blur_pass(display, ping, pong, [0.0, texel[1]], bloom.radius, -1.0));
+                (pong, bloom.intensity)
+            }
+            // The bloom sampler must be bound to something.
+            None => (scene, 0.0),
+        };
+
+        let lut = self.lut.and_then(|tex| renderer.color_grading_lut(tex));
+        let (lut_rect, lut_size) = match lut {
+            Some((_, rect, size)) => (rect, size as f32),
+            None => ([0.0; 4], 0.0),
+        };
+        let crt = self.crt.unwrap_or_default();
+        let pixelate = if self.pixelate > 1.0 {
+            [self.pixelate * texel[0], self.pixelate * texel[1]]
+        } else {
+            [0.0, 0.0]
+        };
+        let uniforms =
+            uniform! {
+        scene: scene.sampled()
+          .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest)
+          .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest),
+        encoded: (self.color_space == ColorSpace::Srgb) as i32,
+        tonemap: self.tonemap as i32,
+        exposure: self.exposure,
+        lut_rect: lut_rect,
+        lut_size: lut_size,
+        bloom: bloom.sampled()
+          .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest)
+          .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest),
+        bloom_intensity: bloom_intensity,
+        crt: self.crt.is_some() as i32,
+        crt_curvature: crt.curvature,
+        crt_scanlines: crt.scanlines,
+        crt_scanline_count: crt.scanline_count,
+        crt_aberration: crt.aberration * texel[0],
+        crt_vignette: crt.vignette,
+        pixelate: pixelate,
+      };
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+        let params = Default::default();
+        match lut {
+            Some((page, _, _)) => {
+                let sampler = page.sampled()
+                    .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp)
+                    .minify_filter(glium::uniforms::MinifySamplerFilter::Linear)
+                    .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear);
+                let uniforms = uniforms.add("lut", sampler);
+                try!(target.draw(&self.quad, &indices, &self.program, &uniforms, &params));
+            }
+            None => {
+                try!(target.draw(&self.quad, &indices, &self.program, &uniforms, &params));
+            }
+        }
+        Ok(())
+    }
+
+    /// Blurs `src` in one direction into `dst`.
+    /// # Params
+    /// * `direction` - The distance between two texels in the direction to
+    ///                 blur, in texture coordinates.
+    /// * `radius` - The blur radius, in texels.
+    /// * `threshold` - The luminance below which colours are dropped, or
+    ///                 negative to keep every colour.
+    fn blur_pass<F: glium::backend::Facade>(
+        &self,
+        display: &F,
+        src: &Texture2d,
+        dst: &Texture2d,
+        direction: [f32; 2],
+        radius: f32,
+        threshold: f32,
+    ) -> Result<(), QgfxError> {
+        let mut fb = try!(SimpleFrameBuffer::new(display, dst));
+        let uniforms =
+            uniform! {
+        src: src.sampled()
+          .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp)
+          .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest)
+          .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest),
+        encoded: (self.color_space == ColorSpace::Srgb) as i32,
+        direction: direction,
+        radius: radius,
+        threshold: threshold,
+      };
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+        try!(fb.draw(
+            &self.quad,
+            &indices,
+            &self.blur_program,
+            &uniforms,
+            &Default::default(),
+        ));
+        Ok(())
+    }
+}
+
+/// True if `tex` needs to be (re)created for a frame of the given size.
+fn outdated(tex: &Option<Texture2d>, size: (u32, u32)) -> bool {
+    match *tex {
+        Some(ref tex) => tex.dimensions() != size,
+        None => true,
+    }
+}
+
+fn empty_target<F: glium::backend::Facade>(
+    display: &F,
+    format: UncompressedFloatFormat,
+    size: (u32, u32),
+) -> Result<Texture2d, QgfxError> {
+    Ok(try!(Texture2d::empty_with_format(
+        display,
+        format,
+        MipmapsOption::NoMipmap,
+        size.0,
+        size.1,
+    )))
+}
@@ -1,12 +1,90 @@
 use glium;
 
+/// The fragment shader for batches, shared by get_program() and
+/// get_glyph_program().
+const BATCH_F_SHADER: &'static str = r#"
+    #version 120
+
+    uniform sampler2D tex;
+
+    // If we're rendering a font, we only care about the r value of the tex.
+    // Otherwise, we care about the colour. Will be 1 if we're rendering a font.
+    uniform int is_font;
+
+    // Font coverage is raised to the power of 1 / text_gamma. 1.0 leaves
+    // coverage untouched, higher values make text look heavier, lower values
+    // make it look thinner.
+    uniform float text_gamma;
+
+    // Will be 1 if texels should be encoded back to sRGB after sampling. The
+    // cache textures are sRGB, so sampling them decodes to linear colour.
+    uniform int encode_srgb;
+
+    // The number of channels the texture is stored with - 1 for grayscale, 2
+    // for grayscale with alpha, and 4 for RGBA. Grayscale pages aren't sRGB
+    // textures, so they're expanded here, and decoded to linear colour if
+    // encode_srgb is 0.
+    uniform int tex_channels;
+
+    // A colour mixed into everything drawn, by its alpha, to show which
+    // texture page a batch was drawn from. Alpha is 0 unless page tinting is
+    // enabled.
+    uniform vec4 page_tint;
+
+    varying vec4 v_col;
+    varying vec2 v_tex_coords;
+
+    vec4 sample_tex() {
+      vec4 pixel = texture2D(tex, v_tex_coords);
+      if (tex_channels < 4) {
+        pixel = vec4(pixel.rrr, tex_channels == 2 ? pixel.g : 1.0);
+        if (encode_srgb == 0) {
+          vec3 c = pixel.rgb;
+          pixel.rgb = mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)),
+                          step(0.04045, c));
+        }
+      }
+      else if (encode_srgb > 0) {
+        vec3 c = pixel.rgb;
+        pixel.rgb = mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055,
+                        step(0.0031308, c));
+      }
+      return pixel;
+    }
+
+    void main() {
+      if (is_font > 0) {
+        float coverage = pow(sample_tex().r, 1.0 / text_gamma);
+        gl_FragColor = vec4(v_col.rgb, coverage);
+      }
+      else {
+        vec4 pixel = sample_tex();
+        gl_FragColor = vec4(pixel.r * v_col.r, 
+                     pixel.g * v_col.g, 
+                     pixel.b * v_col.b, 
+                     pixel.a * v_col.a);
+      }
+      gl_FragColor.rgb = mix(gl_FragColor.rgb, page_tint.rgb, page_tint.a);
+    }
+  "#;
+
 /// Convenience method to compile the shader program used by the renderer.
-pub fn get_program<F: glium::backend::Facade>(display: &F) -> glium::Program {
+/// # Params
+/// * `outputs_srgb` - True if the program's output is already sRGB encoded,
+///                    so OpenGL shouldn't convert it when writing to an sRGB
+///                    framebuffer. See ColorSpace.
+pub fn get_program<F: glium::backend::Facade>(
+    display: &F,
+    outputs_srgb: bool,
+) -> Result<glium::Program, glium::ProgramCreationError> {
     let v_shader = r#"
     #version 120
 
     uniform mat4 proj_mat;
 
+    // Will be 1 if vertex positions should be rounded to the nearest pixel.
+    uniform int pixel_snap;
+
     attribute vec2 pos;
     attribute vec2 tex_coords;
     attribute vec4 col; 
@@ -15,9 +93,115 @@ pub fn get_program<F: glium::backend::Facade>(display: &F) -> glium::Program {
     varying vec4 v_col;
 
     void main() {
-      v_col = col;
+      // col is RGBA8, which isn't normalised when it's passed in.
+      v_col = col / 255.0;
       v_tex_coords = tex_coords;
-      gl_Position = proj_mat*vec4(pos, 0.0, 1.0);
+      vec2 p = pos;
+      if (pixel_snap > 0) {
+        p = floor(pos + 0.5);
+      }
+      gl_Position = proj_mat*vec4(p, 0.0, 1.0);
+    }
+  "#;
+
+    glium::Program::new(
+        display,
+        glium::program::ProgramCreationInput::SourceCode {
+            vertex_shader: v_shader,
+            tessellation_control_shader: None,
+            tessellation_evaluation_shader: None,
+            geometry_shader: None,
+            fragment_shader: BATCH_F_SHADER,
+            transform_feedback_varyings: None,
+            outputs_srgb: outputs_srgb,
+            uses_point_size: false,
+        },
+    )
+}
+
+/// Compiles the shader program for instanced glyphs. Each glyph is a
+/// GlyphInstance, drawn as one instance of a quad whose corners are
+/// GlyphCorners, and coloured by the same fragment shader as get_program().
+/// # Params
+/// * `outputs_srgb` - As get_program().
+pub fn get_glyph_program<F: glium::backend::Facade>(
+    display: &F,
+    outputs_srgb: bool,
+) -> Result<glium::Program, glium::ProgramCreationError> {
+    let v_shader = r#"
+    #version 120
+
+    uniform mat4 proj_mat;
+    uniform int pixel_snap;
+
+    // The corner of the quad, from 0,0 at the top left to 1,1 at the bottom
+    // right.
+    attribute vec2 corner;
+
+    // Per instance - the glyph's corners and UVs (X0, Y0, X1, Y1), its colour
+    // as RGBA8, and its baseline and faux italic shear.
+    attribute vec4 rect;
+    attribute vec4 uv;
+    attribute vec4 col;
+    attribute vec2 shear;
+
+    varying vec2 v_tex_coords;
+    varying vec4 v_col;
+
+    void main() {
+      v_col = col / 255.0;
+      v_tex_coords = mix(uv.xy, uv.zw, corner);
+      vec2 p = mix(rect.xy, rect.zw, corner);
+      p.x += (shear.x - p.y) * shear.y;
+      if (pixel_snap > 0) {
+        p = floor(p + 0.5);
+      }
+      gl_Position = proj_mat*vec4(p, 0.0, 1.0);
+    }
+  "#;
+    glium::Program::new(
+        display,
+        glium::program::ProgramCreationInput::SourceCode {
+            vertex_shader: v_shader,
+            tessellation_control_shader: None,
+            tessellation_evaluation_shader: None,
+            geometry_shader: None,
+            fragment_shader: BATCH_F_SHADER,
+            transform_feedback_varyings: None,
+            outputs_srgb: outputs_srgb,
+            uses_point_size: false,
+        },
+    )
+}
+
+/// Compiles the shader program used to render pick IDs into the pick buffer.
+/// Each pixel's colour is the ID of what's drawn there, with red holding the
+/// lowest 8 bits and blue the highest. Transparent pixels of textures and
+/// glyphs aren't drawn, so they aren't pickable.
+pub fn get_picking_program<F: glium::backend::Facade>(
+    display: &F,
+) -> Result<glium::Program, glium::ProgramCreationError> {
+    let v_shader = r#"
+    #version 120
+
+    uniform mat4 proj_mat;
+    uniform int pixel_snap;
+
+    attribute vec2 pos;
+    attribute vec2 tex_coords;
+    attribute float pick_id;
+
+    varying vec2 v_tex_coords;
+    varying float v_pick_id;
+
+    void main() {
+      v_tex_coords = tex_coords;
+      v_pick_id = pick_id;
+      vec2 p = pos;
+      if (pixel_snap > 0) {
+        p = floor(pos + 0.5);
+      }
+      gl_Position = proj_mat*vec4(p, 0.0, 1.0);
     }
   "#;
 
@@ -25,26 +209,335 @@ pub fn get_program<F: glium::backend::Facade>(display: &F) -> glium::Program {
     #version 120
 
     uniform sampler2D tex;
-
-    // If we're rendering a font, we only care about the r value of the tex.
-    // Otherwise, we care about the colour. Will be 1 if we're rendering a font.
     uniform int is_font;
+    uniform int tex_channels;
 
-    varying vec4 v_col;
     varying vec2 v_tex_coords;
+    varying float v_pick_id;
 
     void main() {
+      vec4 pixel = texture2D(tex, v_tex_coords);
+      float alpha = pixel.a;
       if (is_font > 0) {
-        gl_FragColor = vec4(v_col.rgb, texture2D(tex, v_tex_coords).r);
+        alpha = pixel.r;
       }
-      else {
-        vec4 pixel = texture2D(tex, v_tex_coords);
-        gl_FragColor = vec4(pixel.r * v_col.r, 
-                     pixel.g * v_col.g, 
-                     pixel.b * v_col.b, 
-                     pixel.a * v_col.a);
+      else if (tex_channels == 2) {
+        alpha = pixel.g;
+      }
+      else if (tex_channels == 1) {
+        alpha = 1.0;
+      }
+      if (alpha < 0.5) {
+        discard;
+      }
+      float id = floor(v_pick_id + 0.5);
+      gl_FragColor = vec4(mod(id, 256.0),
+                          mod(floor(id / 256.0), 256.0),
+                          floor(id / 65536.0),
+                          255.0) / 255.0;
+    }
+  "#;
+    glium::Program::from_source(display, v_shader, f_shader, None)
+}
+
+/// Compiles the shader program for the overdraw heatmap, which draws every
+/// fragment in a constant colour, `step`. Drawn with additive blending, each
+/// pixel's colour is then the number of fragments drawn to it times `step`.
+/// See Renderer::set_overdraw_heatmap().
+pub fn get_overdraw_program<F: glium::backend::Facade>(
+    display: &F,
+) -> Result<glium::Program, glium::ProgramCreationError> {
+    let v_shader = r#"
+    #version 120
+
+    uniform mat4 proj_mat;
+    uniform int pixel_snap;
+
+    attribute vec2 pos;
+
+    void main() {
+      vec2 p = pos;
+      if (pixel_snap > 0) {
+        p = floor(pos + 0.5);
+      }
+      gl_Position = proj_mat*vec4(p, 0.0, 1.0);
+    }
+  "#;
+
+    let f_shader = r#"
+    #version 120
+
+    uniform vec4 step;
+
+    void main() {
+      gl_FragColor = step;
+    }
+  "#;
+    glium::Program::new(
+        display,
+        glium::program::ProgramCreationInput::SourceCode {
+            vertex_shader: v_shader,
+            tessellation_control_shader: None,
+            tessellation_evaluation_shader: None,
+            geometry_shader: None,
+            fragment_shader: f_shader,
+            transform_feedback_varyings: None,
+            // The counts shouldn't be converted to sRGB.
+            outputs_srgb: true,
+            uses_point_size: false,
+        },
+    )
+}
+
+/// Compiles the shader program for the post-processing pass, which draws the
+/// offscreen frame to the window, adding bloom, tone mapping, colour grading
+/// and applying the CRT effect to it. See post::PostProcess.
+/// # Params
+/// * `outputs_srgb` - As get_program().
+pub fn get_post_program<F: glium::backend::Facade>(
+    display: &F,
+    outputs_srgb: bool,
+) -> Result<glium::Program, glium::ProgramCreationError> {
+    let v_shader = r#"
+    #version 120
+
+    attribute vec2 pos;
+
+    varying vec2 v_tex_coords;
+
+    void main() {
+      v_tex_coords = pos * 0.5 + 0.5;
+      gl_Position = vec4(pos, 0.0, 1.0);
+    }
+  "#;
+
+    let f_shader = r#"
+    #version 120
+
+    uniform sampler2D scene;
+
+    // Will be 1 if the scene is sRGB encoded (ColorSpace::Srgb), in which case
+    // it's decoded before tone mapping and encoded again afterwards.
+    uniform int encoded;
+
+    // 0 to clamp, 1 for Reinhard, 2 for ACES. See Tonemap.
+    uniform int tonemap;
+    uniform float exposure;
+
+    // The colour grading LUT, a strip of lut_size tiles of lut_size x
+    // lut_size texels. Red increases left to right within a tile, green top
+    // to bottom, and blue from tile to tile. The strip is part of an atlas
+    // page, at lut_rect (x, y, w, h in texture coordinates). lut_size is 0 if
+    // there's no LUT.
+    uniform sampler2D lut;
+    uniform vec4 lut_rect;
+    uniform float lut_size;
+
+    // The blurred bright parts of the scene, encoded like the scene, which
+    // are added to it. bloom_intensity is 0 if there's no bloom.
+    uniform sampler2D bloom;
+    uniform float bloom_intensity;
+
+    // The CRT effect, see Crt. crt is 0 if it's disabled. crt_aberration is
+    // in texture coordinates.
+    uniform int crt;
+    uniform float crt_curvature;
+    uniform float crt_scanlines;
+    uniform float crt_scanline_count;
+    uniform float crt_aberration;
+    uniform float crt_vignette;
+
+    // The size of a pixelation block in texture coordinates, or 0 for no
+    // pixelation.
+    uniform vec2 pixelate;
+
+    varying vec2 v_tex_coords;
+
+    vec3 decode_srgb(vec3 c) {
+      c = max(c, 0.0);
+      return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+    }
+
+    vec3 encode_srgb(vec3 c) {
+      return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+    }
+
+    // Samples the LUT tile for blue level `b` at the sRGB encoded red and
+    // green of `c`.
+    vec3 sample_lut(vec3 c, float b) {
+      float n = lut_size;
+      vec2 uv = vec2((b * n + c.r * (n - 1.0) + 0.5) / (n * n),
+                     (c.g * (n - 1.0) + 0.5) / n);
+      // Textures are stored upside down, so the strip's top is at the top
+      // of the rect.
+      return texture2D(lut, vec2(lut_rect.x + uv.x * lut_rect.z,
+                                 lut_rect.y + (1.0 - uv.y) * lut_rect.w)).rgb;
+    }
+
+    // Grades a linear colour, returning a linear colour. The LUT maps sRGB
+    // encoded colours, but the atlas page decodes when it's sampled.
+    vec3 grade(vec3 c) {
+      c = encode_srgb(c);
+      float b = c.b * (lut_size - 1.0);
+      float b0 = floor(b);
+      float b1 = min(b0 + 1.0, lut_size - 1.0);
+      return mix(sample_lut(c, b0), sample_lut(c, b1), b - b0);
+    }
+
+    // Samples the scene, splitting red and blue apart horizontally for the
+    // CRT effect's chromatic aberration.
+    vec4 sample_scene(vec2 uv) {
+      vec4 pixel = texture2D(scene, uv);
+      if (crt > 0 && crt_aberration > 0.0) {
+        pixel.r = texture2D(scene, uv + vec2(crt_aberration, 0.0)).r;
+        pixel.b = texture2D(scene, uv - vec2(crt_aberration, 0.0)).b;
+      }
+      return pixel;
+    }
+
+    void main() {
+      vec2 uv = v_tex_coords;
+      if (crt > 0) {
+        // Bulge the screen out, leaving black beyond its curved edges.
+        vec2 cc = uv * 2.0 - 1.0;
+        cc *= 1.0 + crt_curvature * cc.yx * cc.yx;
+        uv = cc * 0.5 + 0.5;
+        if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+          gl_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+          return;
+        }
+      }
+      // Every pixel in a block samples the block's centre.
+      vec2 sample_uv = uv;
+      if (pixelate.x > 0.0) {
+        sample_uv = (floor(uv / pixelate) + 0.5) * pixelate;
+      }
+      vec4 pixel = sample_scene(sample_uv);
+      vec3 c = pixel.rgb;
+      vec3 glow = texture2D(bloom, sample_uv).rgb;
+      if (encoded > 0) {
+        c = decode_srgb(c);
+        glow = decode_srgb(glow);
+      }
+      c += glow * bloom_intensity;
+      c *= exposure;
+      if (tonemap == 1) {
+        c = c / (1.0 + c);
+      }
+      else if (tonemap == 2) {
+        c = (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14);
+      }
+      c = clamp(c, 0.0, 1.0);
+      if (lut_size > 0.0) {
+        c = grade(c);
+      }
+      if (crt > 0) {
+        float line = 0.5 + 0.5 * cos(uv.y * crt_scanline_count * 6.2831853);
+        c *= 1.0 - crt_scanlines * (1.0 - line);
+        float edges = 16.0 * uv.x * uv.y * (1.0 - uv.x) * (1.0 - uv.y);
+        c *= pow(max(edges, 0.0001), crt_vignette);
+      }
+      if (encoded > 0) {
+        c = encode_srgb(c);
+      }
+      gl_FragColor = vec4(c, clamp(pixel.a, 0.0, 1.0));
+    }
+  "#;
+    glium::Program::new(
+        display,
+        glium::program::ProgramCreationInput::SourceCode {
+            vertex_shader: v_shader,
+            tessellation_control_shader: None,
+            tessellation_evaluation_shader: None,
+            geometry_shader: None,
+            fragment_shader: f_shader,
+            transform_feedback_varyings: None,
+            outputs_srgb: outputs_srgb,
+            uses_point_size: false,
+        },
+    )
+}
+
+/// Compiles the shader program for one direction of a separable Gaussian
+/// blur, used by the blur and bloom post-processing passes. See
+/// post::PostProcess.
+pub fn get_blur_program<F: glium::backend::Facade>(
+    display: &F,
+) -> Result<glium::Program, glium::ProgramCreationError> {
+    let v_shader = r#"
+    #version 120
+
+    attribute vec2 pos;
+
+    varying vec2 v_tex_coords;
+
+    void main() {
+      v_tex_coords = pos * 0.5 + 0.5;
+      gl_Position = vec4(pos, 0.0, 1.0);
+    }
+  "#;
+
+    let f_shader = r#"
+    #version 120
+
+    uniform sampler2D src;
+
+    // Will be 1 if src is sRGB encoded (ColorSpace::Srgb), in which case
+    // samples are decoded before they're blurred, and the result is encoded.
+    uniform int encoded;
+
+    // The distance between two texels in the direction to blur.
+    uniform vec2 direction;
+
+    // The blur radius in texels, up to 32.
+    uniform float radius;
+
+    // Colours with a luminance below this are dropped, and the rest reduced
+    // by it, before blurring. Negative to keep every colour.
+    uniform float threshold;
+
+    varying vec2 v_tex_coords;
+
+    vec3 decode_srgb(vec3 c) {
+      c = max(c, 0.0);
+      return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+    }
+
+    vec3 encode_srgb(vec3 c) {
+      return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+    }
+
+    vec4 bright(vec4 c) {
+      if (encoded > 0) {
+        c.rgb = decode_srgb(c.rgb);
+      }
+      if (threshold >= 0.0) {
+        float luma = dot(c.rgb, vec3(0.2126, 0.7152, 0.0722));
+        c.rgb *= max(luma - threshold, 0.0) / max(luma, 0.0001);
+      }
+      return c;
+    }
+
+    void main() {
+      // The radius covers 3 standard deviations.
+      float sigma = max(radius / 3.0, 0.0001);
+      vec4 sum = vec4(0.0);
+      float total = 0.0;
+      for (int ii = -32; ii <= 32; ii++) {
+        float x = float(ii);
+        if (abs(x) > radius) {
+          continue;
+        }
+        float weight = exp(-(x * x) / (2.0 * sigma * sigma));
+        sum += bright(texture2D(src, v_tex_coords + direction * x)) * weight;
+        total += weight;
+      }
+      vec4 c = sum / total;
+      if (encoded > 0) {
+        c.rgb = encode_srgb(c.rgb);
       }
+      gl_FragColor = c;
     }
   "#;
-    glium::Program::from_source(display, v_shader, f_shader, None).unwrap()
+    glium::Program::from_source(display, v_shader, f_shader, None)
 }
@@ -0,0 +1,307 @@
+use std::f32::consts::PI;
+use std::mem;
+use vec::Vec2;
+
+/// How far a miter join can stick out from the stroke's corner, as a
+/// multiple of half the stroke width, before it's bevelled instead.
+const MITER_LIMIT: f32 = 4.0;
+
+/// The largest angle a triangle of a round join or cap covers.
+const ROUND_STEP: f32 = PI / 12.0;
+
+/// How the open ends of a stroke are drawn.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum LineCap {
+    /// The stroke stops at the end point.
+    Butt,
+    /// The stroke extends past the end point by half its width.
+    Square,
+    /// The stroke ends in a semicircle around the end point.
+    Round,
+}
+
+/// How the corners between segments of a stroke are drawn.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum LineJoin {
+    /// The outer edges are extended until they meet, unless that's further
+    /// than 4 times half the stroke width from the corner, in which case the
+    /// corner is bevelled.
+    Miter,
+    /// The outer corner is cut off.
+    Bevel,
+    /// The outer corner is rounded.
+    Round,
+}
+
+/// How a line, or the outline of a shape, is stroked. Styles can be reused
+/// for any stroked shape - see RendererController::polyline().
+///
+/// ```ignore
+/// let dotted = StrokeStyle {
+///     cap: LineCap::Round,
+///     dash_pattern: vec![1.0, 6.0],
+///     ..StrokeStyle::new(2.0)
+/// };
+/// controller.rect_outline(&selection, &dotted, &[1.0; 4]);
+/// controller.circle_outline(&pos, 20.0, 0, &dotted, &[1.0; 4]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct StrokeStyle {
+    /// The width of the stroke.
+    pub width: f32,
+    /// How the ends of open strokes, and of dashes, are drawn.
+    pub cap: LineCap,
+    /// How corners are drawn.
+    pub join: LineJoin,
+    /// Alternating lengths of dashes and gaps, starting with a dash. An odd
+    /// number of lengths is repeated twice, so dashes and gaps alternate.
+    /// Empty for a solid stroke, as are patterns with negative lengths or no
+    /// positive ones. Caps are added to every dash, so use short dashes with
+    /// round or square caps for dots.
+    pub dash_pattern: Vec<f32>,
+    /// How far into the dash pattern the stroke starts.
+    pub dash_offset: f32,
+}
+
+impl StrokeStyle {
+    /// A solid stroke of the given width, with butt caps and miter joins.
+    pub fn new(width: f32) -> StrokeStyle {
+        StrokeStyle {
+            width: width,
+            cap: LineCap::Butt,
+            join: LineJoin::Miter,
+            dash_pattern: Vec::new(),
+            dash_offset: 0.0,
+        }
+    }
+
+    /// Triangulates a stroke along the given points.
+    /// # Params
+    /// * `closed` - If true, the stroke is joined from the last point back
+    ///              to the first.
+    /// # Returns
+    /// The triangles' vertices, three per triangle.
+    pub(crate) fn stroke(&self, points: &[[f32; 2]], closed: bool) -> Vec<[f32; 2]> {
+        let mut points: Vec<[f32; 2]> = points.to_vec();
+        points.dedup();
+        if closed && points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        let mut tris = Vec::new();
+        if points.len() < 2 || self.width <= 0.0 {
+            return tris;
+        }
+        let pattern = &self.dash_pattern;
+        let dashed = pattern.iter().all(|&l| l >= 0.0) && pattern.iter().any(|&l| l > 0.0);
+        if !dashed {
+            self.stroke_path(&points, closed, &mut tris);
+            return tris;
+        }
+        if closed {
+            let first = points[0];
+            points.push(first);
+        }
+        for dash in dash(&points, &self.dash_pattern, self.dash_offset) {
+            self.stroke_path(&dash, false, &mut tris);
+        }
+        tris
+    }
+
+    /// Strokes one path with no repeated points.
+    fn stroke_path(&self, points: &[[f32; 2]], closed: bool, tris: &mut Vec<[f32; 2]>) {
+        let hw = self.width / 2.0;
+        let n = points.len();
+        let segments = if closed { n } else { n - 1 };
+        let dir = |ii: usize| {
+            let (a, b) = (points[ii % n], points[(ii + 1) % n]);
+            Vec2(b).sub(Vec2(a)).nor()
+        };
+        for ii in 0..segments {
+            let (a, b) = (Vec2(points[ii]), Vec2(points[(ii + 1) % n]));
+            let side = normal(dir(ii)).mul(hw);
+            let quad = [a.add(side).0, a.sub(side).0, b.sub(side).0, b.add(side).0];
+            for &ix in &[0, 1, 2, 0, 2, 3] {
+                tris.push(quad[ix]);
+            }
+        }
+        let joins = if closed { 0..n } else { 1..n - 1 };
+        for ii in joins {
+            let prev = dir((ii + segments - 1) % segments);
+            self.join(points[ii], prev, dir(ii), tris);
+        }
+        if !closed {
+            self.cap(points[0], dir(0).mul(-1.0), tris);
+            self.cap(points[n - 1], dir(n - 2), tris);
+        }
+    }
+
+    /// Fills the outside of the corner at `p`, between a segment going in
+    /// direction `d0` and one going in direction `d1`.
+    fn join(&self, p: [f32; 2], d0: Vec2, d1: Vec2, tris: &mut Vec<[f32; 2]>) {
+        let hw = self.width / 2.0;
+        let cross = d0[0] * d1[1] - d0[1] * d1[0];
+        if cross.abs() < 1e-6 && d0.dot(d1) > 0.0 {
+            return;
+        }
+        // The outside of the corner is on the opposite side to the turn
+        let side = if cross > 0.0 { -hw } else { hw };
+        let p = Vec2(p);
+        let (n0, n1) = (normal(d0).mul(side), normal(d1).mul(side));
+        match self.join {
+            LineJoin::Round => fan(p, n0, n1, tris),
+            LineJoin::Bevel | LineJoin::Miter => {
+                tris.extend_from_slice(&[p.0, p.add(n0).0, p.add(n1).0]);
+                let mid = n0.add(n1);
+                let cos_half = mid.nor().dot(n0.nor());
+                if self.join == LineJoin::Miter && cos_half > 1.0 / MITER_LIMIT {
+                    let tip = p.add(mid.nor().mul(hw / cos_half));
+                    tris.extend_from_slice(&[p.add(n0).0, tip.0, p.add(n1).0]);
+                }
+            }
+        }
+    }
+
+    /// Caps the end of a stroke at `p`, which points in direction `d`.
+    fn cap(&self, p: [f32; 2], d: Vec2, tris: &mut Vec<[f32; 2]>) {
+        let hw = self.width / 2.0;
+        let p = Vec2(p);
+        let side = normal(d).mul(hw);
+        match self.cap {
+            LineCap::Butt => (),
+            LineCap::Square => {
+                let ext = d.mul(hw);
+                let quad = [p.add(side).0, p.sub(side).0, p.sub(side).add(ext).0,
+                            p.add(side).add(ext).0];
+                for &ix in &[0, 1, 2, 0, 2, 3] {
+                    tris.push(quad[ix]);
+                }
+            }
+            LineCap::Round => {
+                fan(p, side, d.mul(hw), tris);
+                fan(p, d.mul(hw), side.mul(-1.0), tris);
+            }
+        }
+    }
+}
+
+/// A direction's normal, turned a quarter turn.
+fn normal(d: Vec2) -> Vec2 {
+    Vec2([-d[1], d[0]])
+}
+
+/// Fills the arc around `p` from offset `from` to offset `to`, the shorter
+/// way round. Both offsets should be the same length.
+fn fan(p: Vec2, from: Vec2, to: Vec2, tris: &mut Vec<[f32; 2]>) {
+    let rad = from.len();
+    let start = from[1].atan2(from[0]);
+    let sweep = (from[0] * to[1] - from[1] * to[0]).atan2(from.dot(to));
+    let steps = (sweep.abs() / ROUND_STEP).ceil().max(1.0) as usize;
+    let point = |ii: usize| {
+        let a = start + sweep * ii as f32 / steps as f32;
+        [p[0] + rad * a.cos(), p[1] + rad * a.sin()]
+    };
+    for ii in 0..steps {
+        tris.extend_from_slice(&[p.0, point(ii), point(ii + 1)]);
+    }
+}
+
+/// Splits a path into the dashes of a dash pattern.
+fn dash(points: &[[f32; 2]], pattern: &[f32], offset: f32) -> Vec<Vec<[f32; 2]>> {
+    let mut pattern = pattern.to_vec();
+    if pattern.len() % 2 == 1 {
+        let repeat = pattern.clone();
+        pattern.extend_from_slice(&repeat);
+    }
+    let total: f32 = pattern.iter().sum();
+    // Find where in the pattern the path starts
+    let mut ix = 0;
+    let mut skip = offset % total;
+    if skip < 0.0 {
+        skip += total;
+    }
+    while skip >= pattern[ix] {
+        skip -= pattern[ix];
+        ix = (ix + 1) % pattern.len();
+    }
+    let mut left = pattern[ix] - skip;
+
+    let mut dashes = Vec::new();
+    let mut current = if ix % 2 == 0 { vec![points[0]] } else { Vec::new() };
+    for seg in points.windows(2) {
+        let (a, b) = (Vec2(seg[0]), Vec2(seg[1]));
+        let len = b.sub(a).len();
+        let dir = b.sub(a).div(len);
+        let mut pos = 0.0;
+        while len - pos > left {
+            pos += left;
+            let p = a.add(dir.mul(pos)).0;
+            if ix % 2 == 0 {
+                current.push(p);
+                dashes.push(mem::replace(&mut current, Vec::new()));
+            } else {
+                current = vec![p];
+            }
+            ix = (ix + 1) % pattern.len();
+            left = pattern[ix];
+        }
+        left -= len - pos;
+        if ix % 2 == 0 {
+            current.push(b.0);
+        }
+    }
+    if current.len() > 1 {
+        dashes.push(current);
+    }
+    // Zero length dashes have nothing to stroke
+    dashes.retain(|dash| dash.windows(2).any(|seg| seg[0] != seg[1]));
+    for dash in &mut dashes {
+        dash.dedup();
+    }
+    dashes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(tris: &[[f32; 2]]) -> f32 {
+        tris.chunks(3)
+            .map(|t| {
+                let (a, b, c) = (t[0], t[1], t[2]);
+                ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])).abs() / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn caps_extend_lines() {
+        let line = [[0.0, 0.0], [10.0, 0.0]];
+        let mut style = StrokeStyle::new(2.0);
+        assert!((area(&style.stroke(&line, false)) - 20.0).abs() < 1e-4);
+        style.cap = LineCap::Square;
+        assert!((area(&style.stroke(&line, false)) - 24.0).abs() < 1e-4);
+        style.cap = LineCap::Round;
+        let round = area(&style.stroke(&line, false));
+        assert!(round > 22.9 && round < 20.0 + PI, "{}", round);
+    }
+
+    #[test]
+    fn closed_rect_has_mitered_corners() {
+        let rect = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]];
+        let style = StrokeStyle::new(2.0);
+        // Four 10x2 sides, plus a 1x1 bevel triangle and miter triangle at
+        // each corner.
+        assert!((area(&style.stroke(&rect, true)) - (80.0 + 4.0)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn dashes_follow_the_pattern() {
+        let path = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]];
+        let dashes = dash(&path, &[4.0, 2.0], 1.0);
+        assert_eq!(dashes[0], vec![[0.0, 0.0], [3.0, 0.0]]);
+        assert_eq!(dashes[1], vec![[5.0, 0.0], [9.0, 0.0]]);
+        assert_eq!(dashes[2], vec![[10.0, 1.0], [10.0, 5.0]]);
+        assert_eq!(dashes.len(), 4);
+        assert_eq!(dash(&path, &[3.0], 0.0).len(), 4);
+    }
+}
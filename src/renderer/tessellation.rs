@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// A cache of tessellated unit geometry, shared between the renderer's
+/// controllers. Shapes look their geometry up here and only scale and
+/// translate it when they're drawn, rather than recomputing it every frame.
+pub struct TessellationCache {
+    /// Points around a unit circle, keyed by number of segments.
+    circles: RwLock<HashMap<usize, Arc<Vec<[f32; 2]>>>>,
+}
+
+impl TessellationCache {
+    pub fn new() -> TessellationCache {
+        TessellationCache { circles: RwLock::new(HashMap::new()) }
+    }
+
+    /// The points around a circle with a radius of 1 centred on the origin,
+    /// split into `segments` segments. The first point is at angle 0, and is
+    /// repeated at the end, so there are `segments + 1` points.
+    pub fn unit_circle(&self, segments: usize) -> Arc<Vec<[f32; 2]>> {
+        if let Some(points) = self.circles.read().unwrap().get(&segments) {
+            return points.clone();
+        }
+        let points = Arc::new(tessellate_unit_circle(segments));
+        self.circles
+            .write()
+            .unwrap()
+            .entry(segments)
+            .or_insert(points)
+            .clone()
+    }
+
+    /// Removes all the cached geometry.
+    pub fn clear(&self) {
+        self.circles.write().unwrap().clear();
+    }
+}
+
+fn tessellate_unit_circle(segments: usize) -> Vec<[f32; 2]> {
+    use std::f64::consts::PI;
+    let angle_increment = 2.0 * PI / segments as f64;
+    (0..segments + 1)
+        .map(|ii| {
+            // Wrap the last point, so it's exactly the same as the first.
+            let angle = (ii % segments) as f64 * angle_increment;
+            [angle.cos() as f32, angle.sin() as f32]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_circle_is_reused() {
+        let cache = TessellationCache::new();
+        let a = cache.unit_circle(16);
+        let b = cache.unit_circle(16);
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(a.len(), 17);
+        assert_eq!(a[0], a[16]);
+        assert_eq!(cache.unit_circle(8).len(), 9);
+    }
+}
@@ -0,0 +1,266 @@
+//! Virtual resolution rendering. Everything is rendered into an offscreen
+//! texture of a fixed size, which is then scaled up to fill the window,
+//! keeping its aspect ratio.
+//!
+//! The offscreen texture can be rendered at a multiple of the virtual
+//! resolution - the render scale - for supersampling (scales above 1) or
+//! drawing fewer pixels (scales below 1). Without a virtual resolution, a
+//! ScaledTarget at the window's size is used for this.
+
+use glium::{self, Surface};
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::Texture2d;
+use glium::uniforms::MagnifySamplerFilter;
+use error::QgfxError;
+use renderer::{Renderer, HdrFormat};
+
+/// An offscreen render target at a virtual resolution.
+pub struct VirtualTarget {
+    tex: Texture2d,
+    /// The virtual resolution, in pixels.
+    size: (u32, u32),
+    /// The size of `tex` relative to the virtual resolution.
+    scale: f32,
+    /// The floating point format of `tex`, if it's rendered in HDR.
+    hdr: Option<HdrFormat>,
+    /// If true, the target is only scaled by whole numbers.
+    integer_scaling: bool,
+    /// The filter used when scaling the target up to the window.
+    filter: MagnifySamplerFilter,
+}
+
+impl VirtualTarget {
+    /// Creates a virtual target of the given size, in pixels.
+    /// # Params
+    /// * `integer_scaling` - If true, the target is only scaled up by whole
+    ///                       numbers, leaving a border around it if the
+    ///                       window isn't a multiple of its size.
+    /// * `nearest` - If true, the target is scaled up with nearest neighbour
+    ///               filtering rather than linear filtering.
+    /// * `hdr` - The floating point format of the target, if it's rendered in
+    ///           HDR. See QGFXBuilder::with_hdr().
+    pub fn new<F: glium::backend::Facade>(
+        display: &F,
+        w: u32,
+        h: u32,
+        integer_scaling: bool,
+        nearest: bool,
+        hdr: Option<HdrFormat>,
+    ) -> Result<VirtualTarget, QgfxError> {
+        Ok(VirtualTarget {
+            tex: try!(empty_tex(display, (w, h), hdr)),
+            size: (w, h),
+            scale: 1.0,
+            hdr: hdr,
+            integer_scaling: integer_scaling,
+            filter: if nearest {
+                MagnifySamplerFilter::Nearest
+            } else {
+                MagnifySamplerFilter::Linear
+            },
+        })
+    }
+
+    /// The size of the target, in pixels.
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Sets the render scale - the size of the texture the target is
+    /// rendered into, relative to its size.
+    /// # Errors
+    /// Returns an error if the texture couldn't be created at the new size,
+    /// in which case the scale is unchanged.
+    pub fn set_scale<F: glium::backend::Facade>(
+        &mut self,
+        display: &F,
+        scale: f32,
+    ) -> Result<(), QgfxError> {
+        let size = scaled_size(self.size, scale);
+        if size != self.tex.dimensions() {
+            self.tex = try!(empty_tex(display, size, self.hdr));
+        }
+        self.scale = scale;
+        Ok(())
+    }
+
+    /// The render scale, as set by set_scale().
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Renders the renderer's data into this target, then scales it up onto
+    /// `target`.
+    pub fn render<F: glium::backend::Facade, S: Surface>(
+        &self,
+        display: &F,
+        renderer: &mut Renderer,
+        target: &mut S,
+    ) -> Result<(), QgfxError> {
+        let dest = self.dest_rect(target.get_dimensions());
+        render_via(display, &self.tex, renderer, target, &dest, self.filter)
+    }
+
+    /// Maps a position in the window (e.g. the mouse position) to a position
+    /// in this target, in pixels.
+    pub fn window_to_virtual(&self, window_size: (u32, u32), pos: [f32; 2]) -> [f32; 2] {
+        let dest = self.dest_rect(window_size);
+        let (w, h) = self.size();
+        // dest's origin is at the bottom left, window positions' at the top
+        // left.
+        let top = window_size.1 as f32 - (dest.bottom as f32 + dest.height as f32);
+        [
+            (pos[0] - dest.left as f32) * w as f32 / dest.width as f32,
+            (pos[1] - top) * h as f32 / dest.height as f32,
+        ]
+    }
+
+    /// The rect in the window this target is scaled up into.
+    fn dest_rect(&self, window_size: (u32, u32)) -> glium::BlitTarget {
+        fit_rect(self.size(), window_size, self.integer_scaling)
+    }
+}
+
+/// An offscreen render target at a multiple of the window's size, which is
+/// stretched over the window. Used for the render scale without a virtual
+/// resolution. The texture follows the window's size.
+pub struct ScaledTarget {
+    /// The texture, once it's been created by render().
+    tex: Option<Texture2d>,
+    scale: f32,
+    /// The floating point format of `tex`, if it's rendered in HDR.
+    hdr: Option<HdrFormat>,
+}
+
+impl ScaledTarget {
+    /// # Params
+    /// * `scale` - The size of the texture, relative to the window's.
+    /// * `hdr` - The floating point format of the target, if it's rendered in
+    ///           HDR. See QGFXBuilder::with_hdr().
+    pub fn new(scale: f32, hdr: Option<HdrFormat>) -> ScaledTarget {
+        ScaledTarget {
+            tex: None,
+            scale: scale,
+            hdr: hdr,
+        }
+    }
+
+    /// The size of the texture, relative to the window's.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Renders the renderer's data into this target, then stretches it over
+    /// `target`.
+    pub fn render<F: glium::backend::Facade, S: Surface>(
+        &mut self,
+        display: &F,
+        renderer: &mut Renderer,
+        target: &mut S,
+    ) -> Result<(), QgfxError> {
+        let (w, h) = target.get_dimensions();
+        let size = scaled_size((w, h), self.scale);
+        if self.tex.as_ref().map_or(true, |tex| tex.dimensions() != size) {
+            self.tex = Some(try!(empty_tex(display, size, self.hdr)));
+        }
+        let dest = glium::BlitTarget {
+            left: 0,
+            bottom: 0,
+            width: w as i32,
+            height: h as i32,
+        };
+        let tex = self.tex.as_ref().unwrap();
+        render_via(display, tex, renderer, target, &dest, MagnifySamplerFilter::Linear)
+    }
+}
+
+/// Renders the renderer's data into `tex`, then scales it onto `dest` in
+/// `target`.
+fn render_via<F: glium::backend::Facade, S: Surface>(
+    display: &F,
+    tex: &Texture2d,
+    renderer: &mut Renderer,
+    target: &mut S,
+    dest: &glium::BlitTarget,
+    filter: MagnifySamplerFilter,
+) -> Result<(), QgfxError> {
+    {
+        let mut fb = try!(SimpleFrameBuffer::new(display, tex));
+        renderer.get_clear().apply(&mut fb);
+        try!(renderer.render(&mut fb));
+    }
+    let (w, h) = tex.dimensions();
+    let source = glium::Rect {
+        left: 0,
+        bottom: 0,
+        width: w,
+        height: h,
+    };
+    let fb = try!(SimpleFrameBuffer::new(display, tex));
+    fb.blit_color(&source, target, dest, filter);
+    Ok(())
+}
+
+fn empty_tex<F: glium::backend::Facade>(
+    display: &F,
+    size: (u32, u32),
+    hdr: Option<HdrFormat>,
+) -> Result<Texture2d, QgfxError> {
+    Ok(match hdr {
+        Some(format) => try!(Texture2d::empty_with_format(
+            display,
+            format.texture_format(),
+            glium::texture::MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )),
+        None => try!(Texture2d::empty(display, size.0, size.1)),
+    })
+}
+
+/// A size multiplied by a render scale, rounded to whole pixels (but at
+/// least 1).
+fn scaled_size(size: (u32, u32), scale: f32) -> (u32, u32) {
+    let scale_dim = |dim: u32| ((dim as f32 * scale).round() as u32).max(1);
+    (scale_dim(size.0), scale_dim(size.1))
+}
+
+/// Fits a rect of size `inner` into the centre of a rect of size `outer` as
+/// large as possible, keeping its aspect ratio. If `integer_scaling` is set,
+/// it's only scaled by whole numbers (but at least 1).
+fn fit_rect(inner: (u32, u32), outer: (u32, u32), integer_scaling: bool) -> glium::BlitTarget {
+    let scale_x = outer.0 as f32 / inner.0 as f32;
+    let scale_y = outer.1 as f32 / inner.1 as f32;
+    let mut scale = scale_x.min(scale_y);
+    if integer_scaling {
+        scale = scale.floor().max(1.0);
+    }
+    let w = (inner.0 as f32 * scale) as u32;
+    let h = (inner.1 as f32 * scale) as u32;
+    glium::BlitTarget {
+        left: (outer.0 as i32 - w as i32) / 2,
+        bottom: (outer.1 as i32 - h as i32) / 2,
+        width: w as i32,
+        height: h as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_rect_letterboxes() {
+        let r = fit_rect((320, 180), (1024, 768), false);
+        assert_eq!((r.left, r.bottom, r.width, r.height), (0, 96, 1024, 576));
+        let r = fit_rect((320, 180), (1024, 768), true);
+        assert_eq!((r.left, r.bottom, r.width, r.height), (32, 114, 960, 540));
+    }
+
+    #[test]
+    fn scaled_sizes_round_to_whole_pixels() {
+        assert_eq!(scaled_size((320, 180), 2.0), (640, 360));
+        assert_eq!(scaled_size((1023, 767), 0.5), (512, 384));
+        assert_eq!(scaled_size((4, 4), 0.01), (1, 1));
+    }
+}
@@ -4,16 +4,33 @@ use std;
 use std::collections::BTreeMap;
 use std::borrow::Cow;
 use std::path::Path;
-use std::sync::Arc;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
 
-use res::font::{FontCache, GlyphLookup, CacheGlyphError, CacheReadError, FontSpec, FontHandle};
+use res::font::{FontCache, GlyphLookup, CacheGlyphError, CacheReadError, CacheReport, FontSpec,
+                FontHandle};
+use res::tex::TexHandle;
+
+const CACHE_W : u32 = 4096;
+const CACHE_H : u32 = 4096;
 
 pub struct GliumGlyphLookup<'a> {
   /// A map of font handles to actual font objects, with an associated x and y
   /// scale.
-  fonts: BTreeMap<FontHandle, (Font<'a>, (f32, f32))>,
+  fonts: BTreeMap<FontHandle, (Font<'a>, rusttype::Scale)>,
+  /// Textures drawn in place of chars, for all fonts. See
+  /// FontCache::set_glyph_sprite.
+  sprites: BTreeMap<char, TexHandle>,
   /// The cache (not including actual texture storage).
   cache: rusttype::gpu_cache::Cache,
+  /// If true, glyphs which aren't cached when rendering text are added to
+  /// `requested`, to be cached before the next frame.
+  cache_on_demand: bool,
+  /// Glyphs requested with GlyphLookup::request_glyph, waiting to be cached
+  /// by GliumFontCache::cache_requested_glyphs.
+  requested: Mutex<Vec<(FontHandle, char)>>,
 }
 
 /// An implementation of a font cache using glium to cache the glyph textures
@@ -29,6 +46,42 @@ pub struct GliumFontCache<'a> {
   glyph_lookup: Arc<GliumGlyphLookup<'a>>,
   /// The texture storage for the font cache.
   cache_tex: glium::texture::srgb_texture2d::SrgbTexture2d,
+  /// The size of the cache texture, in pixels.
+  size: (u32, u32),
+  /// The context the cache texture was created with, for creating a larger
+  /// one.
+  context: Rc<glium::backend::Context>,
+  /// The most bytes the cache texture may grow to, or None if it never
+  /// grows. See set_growth_budget.
+  growth_budget: Option<usize>,
+  /// The glyphs cached with cache_glyphs, to cache again in a larger cache
+  /// when the cache grows.
+  cached_glyphs: BTreeMap<(FontHandle, char), PositionedGlyph<'static>>,
+  /// If true, glyphs are rasterised on a worker thread. See
+  /// set_background_rasterisation.
+  background: bool,
+  /// Receives the rusttype cache back from the worker thread once it's
+  /// finished rasterising glyphs. While this is Some, the glyph lookup holds
+  /// an empty cache in its place.
+  pending: Option<Receiver<Rasterised>>,
+}
+
+/// Glyphs rasterised into a rusttype cache, waiting to be written to the
+/// cache texture on the GL thread.
+struct Rasterised {
+  cache: rusttype::gpu_cache::Cache,
+  /// The size of `cache`. If this differs from the cache texture's size, the
+  /// cache grew, and the texture needs replacing.
+  size: (u32, u32),
+  /// The rasterised glyphs, to be written to the cache texture at these
+  /// rects.
+  staged: Vec<(rusttype::Rect<u32>, Vec<u8>)>,
+  fh: FontHandle,
+  /// The glyphs queued, and their chars.
+  glyphs: Vec<(char, PositionedGlyph<'static>)>,
+  /// The number of glyphs which fit in the cache, from the start of
+  /// `glyphs`.
+  fitted: usize,
 }
 impl<'a> std::fmt::Debug for GliumFontCache<'a> {
   fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
@@ -40,66 +93,223 @@ impl<'a> std::fmt::Debug for GliumFontCache<'a> {
 }
 
 impl<'a> GliumFontCache<'a> {
-  pub fn new<F: glium::backend::Facade>(display: &F) -> GliumFontCache<'a> {
-    const CACHE_W : u32 = 4096;
-    const CACHE_H : u32 = 4096;
-    GliumFontCache {
+  pub fn new<F: glium::backend::Facade>(
+    display: &F) -> Result<GliumFontCache<'a>, glium::texture::TextureCreationError> {
+    let cache_tex = try!(new_cache_tex(display, (CACHE_W, CACHE_H)));
+    Ok(GliumFontCache {
       font_handles: BTreeMap::new(),
       curr_font_handle: FontHandle(0),
-      // 2048 * 2048 cache with 0.1 scale tolerance and 1.0 position fault
-      // tolerance (we aren't using positioning).
       glyph_lookup: Arc::new(GliumGlyphLookup {
         fonts: BTreeMap::new(),
-        cache: rusttype::gpu_cache::Cache::new(CACHE_W, CACHE_H, 0.1, 1.0),
+        sprites: BTreeMap::new(),
+        cache: new_cache((CACHE_W, CACHE_H)),
+        cache_on_demand: false,
+        requested: Mutex::new(Vec::new()),
       }),
-      // Create a new glium 2d texture with the cache width and height as the texture size.
-      cache_tex: glium::texture::srgb_texture2d::SrgbTexture2d::with_format(
-        display,
-        glium::texture::RawImage2d {
-          data: Cow::Owned(vec![0u8; CACHE_W as usize * CACHE_H as usize]),
-          width: CACHE_W,
-          height: CACHE_H,
-          format: glium::texture::ClientFormat::U8
-        },
-        glium::texture::SrgbFormat::U8U8U8U8,
-        glium::texture::MipmapsOption::NoMipmap).unwrap(),
-    }
+      cache_tex: cache_tex,
+      size: (CACHE_W, CACHE_H),
+      context: display.get_context().clone(),
+      growth_budget: None,
+      cached_glyphs: BTreeMap::new(),
+      background: false,
+      pending: None,
+    })
   }
 
-  pub fn get_glyph_lookup(&'a self) -> Arc<GliumGlyphLookup<'a>> {
+  pub fn get_glyph_lookup(&self) -> Arc<GliumGlyphLookup<'a>> {
       self.glyph_lookup.clone()
   }
 
-  /// Gets the next unique, unused font handle
-  fn get_next_font_handle(&mut self) -> FontHandle {
-    let fh = self.curr_font_handle;
-    self.curr_font_handle.0 += 1;
-    return fh;
+  /// Sets whether glyphs missing from the cache are cached on demand. When
+  /// enabled, rendering a glyph which isn't cached draws '?' in its place and
+  /// requests it, and it's cached by the next call to
+  /// cache_requested_glyphs().
+  pub fn set_cache_on_demand(&mut self, on_demand: bool) {
+    let glyph_lookup = Arc::get_mut(&mut self.glyph_lookup)
+    .expect("Failed to acquire mutable reference when setting on-demand caching. Is the font 
+            cache in use?");
+    glyph_lookup.cache_on_demand = on_demand;
   }
 
-  pub fn get_tex(&self) -> &glium::texture::srgb_texture2d::SrgbTexture2d { &self.cache_tex }
-}
+  /// Sets how large the cache texture may grow, in bytes (4 per pixel), or
+  /// None to never grow it. When glyphs don't fit in the cache, and doubling
+  /// its width and height stays within the budget, the cache texture is
+  /// replaced with one twice the size and every glyph cached so far is
+  /// cached again alongside the new ones, rather than failing with
+  /// CacheTooSmall. Glyphs cached on demand aren't cached again, they're
+  /// requested again when they're next rendered. Keep the budget within the
+  /// GPU's maximum texture size - if creating the larger texture fails, all
+  /// cached glyphs are lost. Defaults to None.
+  pub fn set_growth_budget(&mut self, max_bytes: Option<usize>) {
+    self.growth_budget = max_bytes;
+  }
 
-impl<'a> FontCache for GliumFontCache<'a> {
-  fn cache_glyphs<F: AsRef<Path>>(&mut self, filepath: F, scale: f32, 
-                                  charset: &[char]) -> Result<FontHandle, CacheGlyphError> {
-    use std::fs::File;
-    use std::io::Read;
-
-    // Open the font file and read it all.
-    let mut f = try!(File::open(filepath.as_ref()));
-    let mut data = Vec::new();
-    try!(f.read_to_end(&mut data));
-
-    // Create a font from the font file bytes.
-    let font = try!(FontCollection::from_bytes(data).into_font()
-                    .ok_or(std::io::Error::new(
-                        std::io::ErrorKind::InvalidData, 
-                        "Font file did not contain a valid font.")));
+  /// Sets whether glyphs are rasterised on a worker thread. When enabled,
+  /// caching glyphs returns straight away, and rasterising them - which can
+  /// take seconds for thousands of glyphs - happens in the background. Only
+  /// writing the rasterised glyphs to the cache texture happens on the GL
+  /// thread, in recv_rasterised_glyphs(). Defaults to false.
+  ///
+  /// Whilst glyphs are being rasterised, no glyphs can be looked up, so text
+  /// is drawn according to its MissingGlyphPolicy. Use is_rasterising() to
+  /// show a loading screen in the meantime. Errors like the cache being too
+  /// small are logged rather than returned.
+  pub fn set_background_rasterisation(&mut self, background: bool) {
+    self.background = background;
+  }
+
+  /// True whilst glyphs are being rasterised on the worker thread. See
+  /// set_background_rasterisation().
+  pub fn is_rasterising(&self) -> bool {
+    self.pending.is_some()
+  }
+
+  /// Writes glyphs rasterised on the worker thread to the cache texture, if
+  /// the worker has finished, and makes them available to the glyph lookup.
+  /// This does nothing if the glyph lookup is in use by a renderer
+  /// controller.
+  /// # Params
+  /// * `wait` - If true, blocks until the worker has finished.
+  /// # Returns
+  /// True if no glyphs are still being rasterised.
+  pub fn recv_rasterised_glyphs(&mut self, wait: bool) -> bool {
+    if self.pending.is_none() { return true; }
+    if Arc::get_mut(&mut self.glyph_lookup).is_none() { return false; }
+    let rasterised = match self.pending.as_ref().map(|rx| {
+      if wait { rx.recv().map_err(|_| TryRecvError::Disconnected) } else { rx.try_recv() }
+    }) {
+      Some(Ok(rasterised)) => rasterised,
+      Some(Err(TryRecvError::Empty)) => return false,
+      _ => {
+        error!("Glyph rasterisation thread panicked, all cached glyphs have been lost");
+        self.pending = None;
+        return true;
+      }
+    };
+    self.pending = None;
+
+    let (fh, count) = (rasterised.fh, rasterised.glyphs.len());
+    if self.store_rasterised(rasterised).is_empty() {
+      debug!("Cached {} glyphs with font {:?} in the background", count, fh);
+    }
+    true
+  }
+
+  /// Writes rasterised glyphs to the cache texture, replacing the texture
+  /// first if the cache grew, and gives the cache back to the glyph lookup.
+  /// # Returns
+  /// The chars of the glyphs which didn't fit in the cache.
+  fn store_rasterised(&mut self, rasterised: Rasterised) -> Vec<char> {
+    let Rasterised { mut cache, size, mut staged, fh, mut glyphs, mut fitted } = rasterised;
+    if size != self.size {
+      match new_cache_tex(&self.context, size) {
+        Ok(cache_tex) => {
+          debug!("Grew font cache to {}x{}", size.0, size.1);
+          self.cache_tex = cache_tex;
+          self.size = size;
+        }
+        Err(e) => {
+          // The glyphs were all placed for the larger texture, so none of
+          // them can be kept.
+          error!("Failed to grow font cache to {}x{}, all cached glyphs have been lost: {:?}",
+                 size.0, size.1, e);
+          cache = new_cache(self.size);
+          staged.clear();
+          self.cached_glyphs.clear();
+          fitted = 0;
+        }
+      }
+    }
+    for (rect, data) in staged {
+      upload_glyph(&mut self.cache_tex, rect, &data);
+    }
+    Arc::get_mut(&mut self.glyph_lookup).unwrap().cache = cache;
+
+    let overflowed = glyphs.split_off(fitted);
+    if !overflowed.is_empty() {
+      warn!("Font cache too small for {} of {} glyphs of font {:?}", overflowed.len(),
+            fitted + overflowed.len(), fh);
+    }
+    for (c, g) in glyphs {
+      self.cached_glyphs.insert((fh, c), g);
+    }
+    overflowed.into_iter().map(|(c, _)| c).collect()
+  }
+
+  /// Caches the glyphs requested whilst rendering text since this was last
+  /// called. This does nothing if the glyph lookup is still in use by a
+  /// renderer controller - the glyphs will be cached on a later call instead.
+  ///
+  /// Caching requested glyphs may evict glyphs which haven't been used
+  /// recently. These are requested again if they're rendered, so a working
+  /// set of glyphs streams through the cache.
+  pub fn cache_requested_glyphs(&mut self) {
+    if self.pending.is_some() { return; }
+    let glyph_lookup = match Arc::get_mut(&mut self.glyph_lookup) {
+      Some(glyph_lookup) => glyph_lookup,
+      None => return,
+    };
+    let requested = std::mem::replace(&mut *glyph_lookup.requested.lock().unwrap(), Vec::new());
+    if requested.is_empty() { return; }
+
+    glyph_lookup.cache.clear_queue();
+    for &(fh, c) in &requested {
+      let &(ref font, scale) = match glyph_lookup.fonts.get(&fh) {
+        Some(f) => f,
+        None => continue,
+      };
+      let plain_glyph = font.glyph(c).unwrap();
+      if plain_glyph.id().0 == 0 { continue; }
+      let g = plain_glyph.standalone()
+        .scaled(scale)
+        .positioned(rusttype::Point{x: 0.0, y: 0.0});
+      glyph_lookup.cache.queue_glyph(fh.0, g);
+    }
+
+    let cache_tex = &mut self.cache_tex;
+    match glyph_lookup.cache.cache_queued(|rect, data| upload_glyph(cache_tex, rect, data)) {
+      Ok(_) => debug!("Cached {} glyphs on demand", requested.len()),
+      Err(_) => warn!("Font cache too small for {} glyphs requested on demand", requested.len()),
+    }
+  }
+
+  /// Caches glyphs from font data already in memory, like
+  /// FontCache::cache_glyphs_face_xy(). `name` identifies the font data - data
+  /// cached again under the same name, face and scale gets the same font
+  /// handle.
+  pub fn cache_glyphs_from_bytes<B: Into<rusttype::SharedBytes<'a>>>(
+    &mut self, data: B, name: &Path, face_index: usize, x_scale: f32, y_scale: f32,
+    charset: &[char]) -> Result<FontHandle, CacheGlyphError> {
+    self.cache_glyphs_impl(data, name, face_index, x_scale, y_scale, charset, false)
+      .map(|report| report.font)
+  }
+
+  /// Caches as many glyphs as possible from font data already in memory,
+  /// like FontCache::cache_available_glyphs_face_xy().
+  pub fn cache_available_glyphs_from_bytes<B: Into<rusttype::SharedBytes<'a>>>(
+    &mut self, data: B, name: &Path, face_index: usize, x_scale: f32, y_scale: f32,
+    charset: &[char]) -> Result<CacheReport, CacheGlyphError> {
+    self.cache_glyphs_impl(data, name, face_index, x_scale, y_scale, charset, true)
+  }
+
+  /// Caches glyphs from font data. If `partial` is false, this fails if any
+  /// of the glyphs can't be cached, otherwise it caches all it can.
+  fn cache_glyphs_impl<B: Into<rusttype::SharedBytes<'a>>>(
+    &mut self, data: B, name: &Path, face_index: usize, x_scale: f32, y_scale: f32,
+    charset: &[char], partial: bool) -> Result<CacheReport, CacheGlyphError> {
+    // Glyphs can't be queued whilst the worker thread has the cache.
+    self.recv_rasterised_glyphs(true);
+    let scale = rusttype::Scale { x: x_scale, y: y_scale };
+
+    // Create a font from the font file bytes. Plain font files contain a
+    // single face at index 0, collections (.ttc) contain several.
+    let font = try!(FontCollection::from_bytes(data).font_at(face_index)
+                    .ok_or(CacheGlyphError::NoSuchFace(face_index)));
 
     // See if there's a font handle already used by this font spec - If not,
     // create a new one and store it in the map.
-    let fs = FontSpec::new(filepath, (scale*100.0) as u32, (scale*100.0) as u32);
+    let fs = FontSpec::new(name, face_index, 
+                           (x_scale*100.0) as u32, (y_scale*100.0) as u32);
     let fh : FontHandle;
     if self.font_handles.contains_key(&fs) {
       fh = *self.font_handles.get(&fs).unwrap();
@@ -126,66 +336,120 @@ impl<'a> FontCache for GliumFontCache<'a> {
       }
     }
 
-    let glyph_lookup = Arc::get_mut(&mut self.glyph_lookup)
-    .expect("Failed to acquire mutable reference when caching glyphs. Is the font cache in
-            use?");
-
-    // Clear the queue to make sure we don't cache glyphs we didn't explicitly
-    // ask for in this function.
-    glyph_lookup.cache.clear_queue();
-
     // Now run through the no_dup vec and try to call rect_for on the cache. If
-    // an error is returned (for no rect found) then we can queue this glyph.
+    // an error is returned (for no rect found) then the glyph needs caching.
     let mut glyphs_not_found = Vec::new(); // The list of glyphs not found in this font
-    for c in &no_dup {
-      // Create the positioned glyph
-      let plain_glyph = font.glyph(*c).unwrap();
-      if plain_glyph.id().0 == 0 {
-        glyphs_not_found.push(*c);
-        continue;
-      }
-      let g = plain_glyph.standalone()
-        .scaled(rusttype::Scale::uniform(scale))
-        .positioned(rusttype::Point{x: 0.0, y: 0.0});
+    let mut uncached = Vec::new();
+    {
+      let glyph_lookup = Arc::get_mut(&mut self.glyph_lookup)
+      .expect("Failed to acquire mutable reference when caching glyphs. Is the font cache in
+              use?");
+      for c in &no_dup {
+        // Create the positioned glyph
+        let plain_glyph = font.glyph(*c).unwrap();
+        if plain_glyph.id().0 == 0 {
+          glyphs_not_found.push(*c);
+          continue;
+        }
+        let g = plain_glyph.standalone()
+          .scaled(scale)
+          .positioned(rusttype::Point{x: 0.0, y: 0.0});
 
-      // Look up the rect in the cache
-      let res = glyph_lookup.cache.rect_for(fh.0, &g);
-      let mut cached = true;
-      match res {
-        Err(rusttype::gpu_cache::CacheReadErr::GlyphNotCached) => cached = false,
-        _ => ()
-      }
-      // If the glyph isn't cached, then queue the glyph
-      if !cached {
-        glyph_lookup.cache.queue_glyph(fh.0, g.clone());
+        // Look up the rect in the cache
+        match glyph_lookup.cache.rect_for(fh.0, &g) {
+          Err(rusttype::gpu_cache::CacheReadErr::GlyphNotCached) => uncached.push((*c, g)),
+          _ => (),
+        }
       }
     }
     if glyphs_not_found.len() != 0 {
-      glyph_lookup.cache.clear_queue();
-      return Err(CacheGlyphError::GlyphNotSupported(glyphs_not_found));
+      warn!("Font {:?} does not support the glyphs {:?}", name, glyphs_not_found);
+      if !partial {
+        return Err(CacheGlyphError::GlyphNotSupported(glyphs_not_found));
+      }
     }
+    let mut report = CacheReport {
+      font: fh,
+      cached: no_dup.len() - glyphs_not_found.len(),
+      unsupported: glyphs_not_found,
+      overflowed: Vec::new(),
+    };
 
-    let cache_tex = &mut self.cache_tex;
-    // Cache the whole queue of glyphs
-    try!(glyph_lookup.cache.cache_queued(move |rect, data| {
-      cache_tex.main_level().write(glium::Rect {
-        left: rect.min.x,
-        bottom: rect.min.y,
-        width: rect.width(),
-        height: rect.height()
-      }, glium::texture::RawImage2d {
-        data: Cow::Borrowed(data),
-        width: rect.width(),
-        height: rect.height(),
-        format: glium::texture::ClientFormat::U8
+    // Take the cache out of the glyph lookup to rasterise the glyphs into,
+    // along with the glyphs to cache again if it grows.
+    let kept: Vec<(FontHandle, PositionedGlyph<'static>)> = match self.growth_budget {
+      Some(_) => self.cached_glyphs.iter().map(|(&(font, _), g)| (font, g.clone())).collect(),
+      None => Vec::new(),
+    };
+    let cache = std::mem::replace(&mut Arc::get_mut(&mut self.glyph_lookup).unwrap().cache,
+                                  new_cache(self.size));
+    let (size, budget, count) = (self.size, self.growth_budget, uncached.len());
+    if self.background {
+      // Rasterise on a worker thread, and get the cache back in
+      // recv_rasterised_glyphs().
+      let (tx, rx) = mpsc::channel();
+      thread::spawn(move || {
+        let _ = tx.send(rasterise(cache, size, fh, uncached, &kept, partial, budget));
       });
-    }).map_err(|_| CacheGlyphError::CacheTooSmall));
+      self.pending = Some(rx);
+      debug!("Rasterising {} glyphs with font {:?} at scale {}x{} in the background",
+             count, fh, x_scale, y_scale);
+    } else {
+      let overflowed =
+        self.store_rasterised(rasterise(cache, size, fh, uncached, &kept, partial, budget));
+      if !overflowed.is_empty() {
+        if !partial {
+          return Err(CacheGlyphError::CacheTooSmall);
+        }
+        report.cached -= overflowed.len();
+        report.overflowed = overflowed;
+      }
+      debug!("Cached {} glyphs with font {:?} at scale {}x{}", report.cached, fh, x_scale,
+             y_scale);
+    }
 
+    let glyph_lookup = Arc::get_mut(&mut self.glyph_lookup).unwrap();
     if !glyph_lookup.fonts.contains_key(&fh) {
-      glyph_lookup.fonts.insert(fh, (font, (scale, scale)));
+      glyph_lookup.fonts.insert(fh, (font, scale));
     }
 
-    return Ok(fh);
+    return Ok(report);
+  }
+
+  /// Gets the next unique, unused font handle
+  fn get_next_font_handle(&mut self) -> FontHandle {
+    let fh = self.curr_font_handle;
+    self.curr_font_handle.0 += 1;
+    return fh;
+  }
+
+  pub fn get_tex(&self) -> &glium::texture::srgb_texture2d::SrgbTexture2d { &self.cache_tex }
+}
+
+impl<'a> FontCache for GliumFontCache<'a> {
+  fn cache_glyphs_face_xy<F: AsRef<Path>>(&mut self, filepath: F, face_index: usize,
+                                          x_scale: f32, y_scale: f32,
+                                          charset: &[char]) -> Result<FontHandle, CacheGlyphError> {
+    let data = try!(read_font_file(filepath.as_ref()));
+    self.cache_glyphs_from_bytes(data, filepath.as_ref(), face_index, x_scale, y_scale, charset)
+  }
+
+  fn cache_available_glyphs_face_xy<F: AsRef<Path>>(&mut self, filepath: F, face_index: usize,
+                                                    x_scale: f32, y_scale: f32, charset: &[char])
+    -> Result<CacheReport, CacheGlyphError> {
+    let data = try!(read_font_file(filepath.as_ref()));
+    self.cache_available_glyphs_from_bytes(data, filepath.as_ref(), face_index, x_scale,
+                                           y_scale, charset)
+  }
+
+  fn set_glyph_sprite(&mut self, c: char, tex: Option<TexHandle>) {
+    let glyph_lookup = Arc::get_mut(&mut self.glyph_lookup)
+    .expect("Failed to acquire mutable reference when setting a glyph sprite. Is the font cache 
+            in use?");
+    match tex {
+      Some(tex) => { glyph_lookup.sprites.insert(c, tex); },
+      None => { glyph_lookup.sprites.remove(&c); },
+    }
   }
 }
 
@@ -195,13 +459,21 @@ impl<'a> GlyphLookup for GliumFontCache<'a> {
     self.glyph_lookup.rect_for(font_handle, code_point)
   }
 
-  fn get_font_ref(&self, fh: FontHandle) -> Option<&(Font, (f32, f32))> { 
+  fn get_font_ref(&self, fh: FontHandle) -> Option<&(Font, rusttype::Scale)> { 
       self.glyph_lookup.fonts.get(&fh) 
   }
 
   fn get_glyph(&self, fh: FontHandle, c: char) -> Option<PositionedGlyph> {
       self.glyph_lookup.get_glyph(fh, c)
   }
+
+  fn request_glyph(&self, fh: FontHandle, c: char) {
+      self.glyph_lookup.request_glyph(fh, c)
+  }
+
+  fn sprite_for(&self, c: char) -> Option<TexHandle> {
+      self.glyph_lookup.sprite_for(c)
+  }
 }
 
 impl<'a> GlyphLookup for Arc<GliumGlyphLookup<'a>> {
@@ -219,19 +491,149 @@ impl<'a> GlyphLookup for Arc<GliumGlyphLookup<'a>> {
     Ok(Some([uv_rect.min.x, uv_rect.min.y, uv_rect.max.x, uv_rect.max.y]))
   }
 
-  fn get_font_ref(&self, fh: FontHandle) -> Option<&(Font, (f32, f32))> { 
+  fn get_font_ref(&self, fh: FontHandle) -> Option<&(Font, rusttype::Scale)> { 
       self.fonts.get(&fh) 
   }
 
+  fn sprite_for(&self, c: char) -> Option<TexHandle> {
+      self.sprites.get(&c).cloned()
+  }
+
+  fn request_glyph(&self, fh: FontHandle, c: char) {
+    if !self.cache_on_demand { return; }
+    let mut requested = self.requested.lock().unwrap();
+    if !requested.contains(&(fh, c)) {
+      requested.push((fh, c));
+    }
+  }
+
   fn get_glyph(&self, fh: FontHandle, c: char) -> Option<PositionedGlyph> {
     let f_x_y = self.fonts.get(&fh);
     if f_x_y.is_none() { return None; }
-    let &(ref font, (x_scale, y_scale)) = f_x_y.unwrap();
+    let &(ref font, scale) = f_x_y.unwrap();
     let plain_glyph = font.glyph(c).unwrap();
     if plain_glyph.id().0 == 0 { return None; }
     let g = plain_glyph.standalone()
-      .scaled(rusttype::Scale{ x: x_scale, y: y_scale })
+      .scaled(scale)
       .positioned(rusttype::Point{x: 0.0, y: 0.0});
     return Some(g);
   }
 }
+
+/// Reads a whole font file.
+fn read_font_file(path: &Path) -> Result<Vec<u8>, std::io::Error> {
+  use std::fs::File;
+  use std::io::Read;
+
+  let mut f = try!(File::open(path));
+  let mut data = Vec::new();
+  try!(f.read_to_end(&mut data));
+  Ok(data)
+}
+
+/// Rasterises glyphs into `cache`, which is `size` pixels, keeping the
+/// rasterised glyphs in staging buffers. If they don't fit, and doubling the
+/// cache's width and height stays within `budget` bytes, the cache is
+/// replaced with a larger one and the `kept` glyphs (those already cached)
+/// are rasterised into it too.
+/// # Params
+/// * `partial` - If false, either all of the glyphs are cached or none are.
+fn rasterise(mut cache: rusttype::gpu_cache::Cache, mut size: (u32, u32), fh: FontHandle,
+             glyphs: Vec<(char, PositionedGlyph<'static>)>,
+             kept: &[(FontHandle, PositionedGlyph<'static>)], partial: bool,
+             budget: Option<usize>) -> Rasterised {
+  let queue: Vec<_> = glyphs.iter().map(|&(_, ref g)| g.clone()).collect();
+  let mut staged = Vec::new();
+  // The growing cache has lost the kept glyphs, so they're cached again.
+  let mut requeue: &[(FontHandle, PositionedGlyph<'static>)] = &[];
+  loop {
+    let can_grow = budget.map_or(false, |budget| {
+      size.0 as usize * 2 * size.1 as usize * 2 * 4 <= budget
+    });
+    staged.clear();
+    let fitted = cache_fitting(&mut cache, requeue, fh, &queue, partial && !can_grow,
+                               |rect, data| staged.push((rect, data.to_vec())));
+    if fitted == queue.len() || !can_grow {
+      return Rasterised {
+        cache: cache, size: size, staged: staged, fh: fh, glyphs: glyphs, fitted: fitted
+      };
+    }
+    size = (size.0 * 2, size.1 * 2);
+    cache = new_cache(size);
+    requeue = kept;
+  }
+}
+
+/// Caches as many of `glyphs` as fit in `cache`, in order, along with all of
+/// the `requeue` glyphs, writing them to the cache texture with `upload`.
+/// # Params
+/// * `partial` - If false, either all of the glyphs are cached or none are.
+/// # Returns
+/// The number of glyphs cached, from the start of `glyphs`.
+fn cache_fitting<F: FnMut(rusttype::Rect<u32>, &[u8])>(
+  cache: &mut rusttype::gpu_cache::Cache, requeue: &[(FontHandle, PositionedGlyph<'static>)],
+  fh: FontHandle, glyphs: &[PositionedGlyph<'static>], partial: bool, mut upload: F) -> usize {
+  let mut cache_first = |n: usize| {
+    cache.clear_queue();
+    for &(font, ref g) in requeue {
+      cache.queue_glyph(font.0, g.clone());
+    }
+    for g in &glyphs[..n] {
+      cache.queue_glyph(fh.0, g.clone());
+    }
+    cache.cache_queued(|rect, data| upload(rect, data)).is_ok()
+  };
+  if cache_first(glyphs.len()) { return glyphs.len(); }
+  if !partial { return 0; }
+
+  // The cache only caches whole queues, so binary search for the most glyphs
+  // which fit. `fits` glyphs are known to fit, and `overflows` glyphs don't.
+  let (mut fits, mut overflows) = (0, glyphs.len());
+  let mut last = overflows;
+  while overflows - fits > 1 {
+    let mid = (fits + overflows) / 2;
+    if cache_first(mid) { fits = mid; } else { overflows = mid; }
+    last = mid;
+  }
+  // A failed attempt may have evicted the glyphs which fit.
+  if last != fits { cache_first(fits); }
+  fits
+}
+
+/// Creates an empty rusttype cache of the given size, with 0.1 scale
+/// tolerance and 1.0 position fault tolerance (we aren't using positioning).
+fn new_cache(size: (u32, u32)) -> rusttype::gpu_cache::Cache {
+  rusttype::gpu_cache::Cache::new(size.0, size.1, 0.1, 1.0)
+}
+
+/// Creates an empty cache texture of the given size.
+fn new_cache_tex<F: glium::backend::Facade>(display: &F, size: (u32, u32))
+  -> Result<glium::texture::srgb_texture2d::SrgbTexture2d, glium::texture::TextureCreationError> {
+  glium::texture::srgb_texture2d::SrgbTexture2d::with_format(
+    display,
+    glium::texture::RawImage2d {
+      data: Cow::Owned(vec![0u8; size.0 as usize * size.1 as usize]),
+      width: size.0,
+      height: size.1,
+      format: glium::texture::ClientFormat::U8
+    },
+    glium::texture::SrgbFormat::U8U8U8U8,
+    glium::texture::MipmapsOption::NoMipmap)
+}
+
+/// Writes a glyph's coverage data into the font cache texture, at the given
+/// rect.
+fn upload_glyph(cache_tex: &mut glium::texture::srgb_texture2d::SrgbTexture2d,
+                rect: rusttype::Rect<u32>, data: &[u8]) {
+  cache_tex.main_level().write(glium::Rect {
+    left: rect.min.x,
+    bottom: rect.min.y,
+    width: rect.width(),
+    height: rect.height()
+  }, glium::texture::RawImage2d {
+    data: Cow::Borrowed(data),
+    width: rect.width(),
+    height: rect.height(),
+    format: glium::texture::ClientFormat::U8
+  });
+}
@@ -1,10 +1,11 @@
 use std;
 use rusttype::gpu_cache::CacheReadErr;
-use std::ops::Deref;
+use std::ops::{Deref, Range};
 use std::path::{PathBuf, Path};
 use std::collections::HashSet;
 use std::fmt::{Display, Formatter, self};
-use rusttype::{PositionedGlyph, Font};
+use rusttype::{PositionedGlyph, Font, FontCollection, Scale, Contour, Segment};
+use res::tex::TexHandle;
 
 pub mod glium_cache;
 
@@ -18,11 +19,18 @@ pub enum CacheGlyphError {
   GlyphNotSupported(Vec<char>),
 
   /// Error returned when the cache is too small to accommodate all the
-  /// characters listed in the charset.
+  /// characters listed in the charset. Large charsets (like
+  /// Charset::CjkUnified) should instead be cached on demand - see
+  /// QGFX::set_cache_glyphs_on_demand - or the cache allowed to grow - see
+  /// QGFX::set_font_cache_growth_budget.
   CacheTooSmall,
 
   /// An IO error occurred when reading the font file.
   IoError(std::io::Error),
+
+  /// The font file doesn't contain a face with the given index. Plain font
+  /// files only contain a face at index 0. See font_face_count().
+  NoSuchFace(usize),
 }
 
 impl Display for CacheGlyphError {
@@ -35,6 +43,8 @@ impl Display for CacheGlyphError {
         write!(f, r#"The cache is to small to contain all the characters
              given."#),
       CacheGlyphError::IoError(ref e) => write!(f, "{}", e),
+      CacheGlyphError::NoSuchFace(ix) => 
+        write!(f, "The font file does not contain a face with index {}", ix),
     }
   }
 }
@@ -45,6 +55,14 @@ impl std::error::Error for CacheGlyphError {
       CacheGlyphError::GlyphNotSupported(_) => "A glyph is not supported.",
       CacheGlyphError::CacheTooSmall => "The cache is too small for these characters with this font.",
       CacheGlyphError::IoError(ref e) => e.description(),
+      CacheGlyphError::NoSuchFace(_) => "The font file does not contain a face with this index.",
+    }
+  }
+
+  fn source(&self) -> Option<&(std::error::Error + 'static)> {
+    match *self {
+      CacheGlyphError::IoError(ref e) => Some(e),
+      _ => None,
     }
   }
 }
@@ -53,6 +71,48 @@ impl std::convert::From<std::io::Error> for CacheGlyphError {
   fn from(e: std::io::Error) -> Self { CacheGlyphError::IoError(e) }
 }
 
+/// What was cached by FontCache::cache_available_glyphs(), which caches as
+/// many of the glyphs asked for as it can.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheReport {
+  /// The handle of the font. Its glyphs can be rendered even if some of them
+  /// couldn't be cached.
+  pub font: FontHandle,
+  /// The number of glyphs which are now cached, including any which were
+  /// already cached.
+  pub cached: usize,
+  /// The chars which the font doesn't support.
+  pub unsupported: Vec<char>,
+  /// The chars which didn't fit in the cache. These can still be cached on
+  /// demand (see QGFX::set_cache_glyphs_on_demand). Always empty with
+  /// background rasterisation, as the glyphs haven't been rasterised yet -
+  /// overflows are logged instead.
+  pub overflowed: Vec<char>,
+}
+
+impl CacheReport {
+  /// True if every glyph asked for was cached.
+  pub fn is_complete(&self) -> bool {
+    self.unsupported.is_empty() && self.overflowed.is_empty()
+  }
+}
+
+/// An error returned when rendering text with MissingGlyphPolicy::Error, if
+/// some of the glyphs in the text weren't cached. Contains the chars which
+/// weren't cached.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingGlyphsError(pub Vec<char>);
+
+impl Display for MissingGlyphsError {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    write!(f, "The following chars were not cached: {:?}", self.0)
+  }
+}
+
+impl std::error::Error for MissingGlyphsError {
+  fn description(&self) -> &str { "Some of the glyphs in the text were not cached." }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct CacheReadError;
 impl Display for CacheReadError {
@@ -81,6 +141,24 @@ pub enum Charset {
   Numbers, 
   /// ISO/IEC 8859-1 Punctuation
   Punctuation,
+  /// CJK symbols and punctuation, [U+3000-U+303F]
+  CjkPunctuation,
+  /// Japanese Hiragana, [U+3041-U+3096] and [U+3099-U+309F]
+  Hiragana,
+  /// Japanese Katakana, [U+30A0-U+30FF]
+  Katakana,
+  /// Fullwidth ASCII variants and halfwidth Katakana, [U+FF01-U+FF9F]
+  FullwidthForms,
+  /// Korean Hangul syllables, [U+AC00-U+D7A3]. This is over 11,000 glyphs,
+  /// which won't fit in the font cache at most sizes - prefer caching these
+  /// on demand.
+  HangulSyllables,
+  /// CJK unified ideographs, [U+4E00-U+9FFF]. This is over 20,000 glyphs, and
+  /// will never fit in the font cache - don't cache it up front, cache these
+  /// on demand instead (see QGFX::set_cache_glyphs_on_demand). Few fonts
+  /// contain every ideograph in this range, and cache_glyphs() fails if any
+  /// aren't supported.
+  CjkUnified,
 }
 
 /// Convenience function to generate commonly-used charsets in the form needed
@@ -108,11 +186,117 @@ pub fn gen_charset(sets: &HashSet<Charset>) -> Vec<char> {
                                 '<', '=', '>', '?', '@', '[', '\\', ']', '^',
                                 '_', '`', '{', '|', '}', '~', '¬']);
       }
+      Charset::CjkPunctuation => push_char_range(&mut chars, 0x3000, 0x303F),
+      Charset::Hiragana => {
+        push_char_range(&mut chars, 0x3041, 0x3096);
+        push_char_range(&mut chars, 0x3099, 0x309F);
+      }
+      Charset::Katakana => push_char_range(&mut chars, 0x30A0, 0x30FF),
+      Charset::FullwidthForms => push_char_range(&mut chars, 0xFF01, 0xFF9F),
+      Charset::HangulSyllables => push_char_range(&mut chars, 0xAC00, 0xD7A3),
+      Charset::CjkUnified => push_char_range(&mut chars, 0x4E00, 0x9FFF),
     }
   }
   return chars;
 }
 
+/// Pushes every assigned char in [first, last] onto `chars`.
+fn push_char_range(chars: &mut Vec<char>, first: u32, last: u32) {
+  chars.extend((first..last + 1).filter_map(std::char::from_u32));
+}
+
+/// Which point of a line of text the position given when rendering text
+/// refers to. These are computed from the font's vertical metrics (ascent and
+/// descent), not the glyphs being rendered, so text is positioned consistently
+/// across fonts and strings.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum TextAnchor {
+  /// The position is the left end of the baseline. Descenders (like in 'g')
+  /// hang below it.
+  Baseline,
+  /// The position is the top-left of the line, i.e. the font's ascent above
+  /// the baseline.
+  TopLeft,
+  /// The position is the centre of the line, both horizontally (across the
+  /// width of the text) and vertically (halfway between the ascent and
+  /// descent).
+  Centre,
+}
+
+/// Styling to apply when rendering text. The default is plain text.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct TextStyle {
+  /// Draw a line under the text.
+  pub underline: bool,
+  /// Draw a line through the middle of the text.
+  pub strikethrough: bool,
+  /// Synthesise a bold face by drawing each glyph twice, offset slightly to
+  /// the right. This widens the text a little.
+  pub bold: bool,
+  /// Synthesise an italic face by shearing each glyph to lean right.
+  pub italic: bool,
+}
+
+/// What to do when rendering text containing a glyph which isn't cached.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum MissingGlyphPolicy {
+  /// Draw the given char instead. If that isn't cached either, draw nothing.
+  /// This is the default, with '?'.
+  ReplaceWith(char),
+  /// Draw nothing, and don't advance the cursor.
+  Skip,
+  /// Don't render the text at all, and return a MissingGlyphsError.
+  Error,
+}
+
+impl Default for MissingGlyphPolicy {
+  fn default() -> MissingGlyphPolicy { MissingGlyphPolicy::ReplaceWith('?') }
+}
+
+/// Where a single character was placed when rendering text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphPlacement {
+  /// The byte index of this character in the rendered string.
+  pub ix: usize,
+  /// The character itself.
+  pub c: char,
+  /// The cell this character occupies, as x, y, w, h. The cell spans the
+  /// character's advance horizontally (including kerning), and the font's
+  /// ascent to descent vertically, so adjacent cells on a line touch. Use
+  /// these to draw selections.
+  pub rect: [f32; 4],
+}
+
+/// A run of text rendered in a single direction. Text is laid out with the
+/// Unicode bidirectional algorithm, so right-to-left scripts are displayed in
+/// the correct order when mixed with left-to-right text.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TextRun {
+  /// The byte range of this run in the rendered string.
+  pub range: Range<usize>,
+  /// True if this run is displayed right-to-left. The chars in a
+  /// right-to-left run are displayed from the end of the range to the start.
+  pub rtl: bool,
+}
+
+/// Layout information returned when rendering text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextLayout {
+  /// The placement of every character in the text except for newlines, in
+  /// the order they're displayed from left to right.
+  pub glyphs: Vec<GlyphPlacement>,
+  /// The byte indices in the rendered string at which each line after the
+  /// first starts (i.e. the index just after each '\n').
+  pub line_breaks: Vec<usize>,
+  /// The runs of text with the same direction, line by line, in the order
+  /// they're displayed from left to right. Use these to move a caret through
+  /// mixed left-to-right and right-to-left text.
+  pub runs: Vec<TextRun>,
+  /// The size of the bounding box of the rendered text, as returned by
+  /// RendererController::text().
+  pub size: (f32, f32),
+}
+
 /// A trait for a GPU font cache. Glyphs are loaded into the font cache,
 /// which are stored on the GPU for fast access when rendering text.
 pub trait FontCache : GlyphLookup { 
@@ -128,7 +312,81 @@ pub trait FontCache : GlyphLookup {
   /// # Errors
   /// Will return a CacheGlyph error if this function failed to add the glyphs to the cache.
   fn cache_glyphs<F: AsRef<Path>>(&mut self, file: F, scale: f32, charset: &[char]) 
-    -> Result<FontHandle, CacheGlyphError>;
+    -> Result<FontHandle, CacheGlyphError> {
+    self.cache_glyphs_face(file, 0, scale, charset)
+  }
+
+  /// Like cache_glyphs(), but uses the face at `face_index` in the font
+  /// file. This is for font collections (.ttc files), which contain multiple
+  /// faces. Plain font files only have a face at index 0.
+  /// # Errors
+  /// As cache_glyphs(), and CacheGlyphError::NoSuchFace if the file doesn't
+  /// have a face at `face_index`.
+  fn cache_glyphs_face<F: AsRef<Path>>(&mut self, file: F, face_index: usize, scale: f32, 
+                                       charset: &[char]) -> Result<FontHandle, CacheGlyphError> {
+    self.cache_glyphs_face_xy(file, face_index, scale, scale, charset)
+  }
+
+  /// Like cache_glyphs(), but with separate horizontal and vertical scales,
+  /// for condensed or stretched text. A font cached with a different x and y
+  /// scale gets its own font handle.
+  fn cache_glyphs_xy<F: AsRef<Path>>(&mut self, file: F, x_scale: f32, y_scale: f32, 
+                                     charset: &[char]) -> Result<FontHandle, CacheGlyphError> {
+    self.cache_glyphs_face_xy(file, 0, x_scale, y_scale, charset)
+  }
+
+  /// Like cache_glyphs_face(), but with separate horizontal and vertical
+  /// scales. See cache_glyphs_xy().
+  fn cache_glyphs_face_xy<F: AsRef<Path>>(&mut self, file: F, face_index: usize,
+                                          x_scale: f32, y_scale: f32,
+                                          charset: &[char]) -> Result<FontHandle, CacheGlyphError>;
+
+  /// Like cache_glyphs(), but caches as many of the chars as possible rather
+  /// than failing if any of them can't be cached. Chars the font doesn't
+  /// support are skipped, and if the cache is too small, the chars at the
+  /// end of `charset` which don't fit are left out.
+  /// # Returns
+  /// A report of what was cached.
+  /// # Errors
+  /// Returns an error if the font file couldn't be read, or doesn't have a
+  /// face at `face_index`.
+  fn cache_available_glyphs<F: AsRef<Path>>(&mut self, file: F, scale: f32, charset: &[char])
+    -> Result<CacheReport, CacheGlyphError> {
+    self.cache_available_glyphs_face_xy(file, 0, scale, scale, charset)
+  }
+
+  /// Like cache_available_glyphs(), with a face index and separate
+  /// horizontal and vertical scales. See cache_glyphs_face_xy().
+  fn cache_available_glyphs_face_xy<F: AsRef<Path>>(&mut self, file: F, face_index: usize,
+                                                    x_scale: f32, y_scale: f32,
+                                                    charset: &[char])
+    -> Result<CacheReport, CacheGlyphError>;
+
+  /// Sets a texture to draw in place of a char in all fonts, or removes it if
+  /// `tex` is None. This is how colour glyphs like emoji are rendered - the
+  /// glyphs are cached as regular textures, then drawn as sprites by the text
+  /// rendering functions. Chars with a sprite don't need to be cached with
+  /// cache_glyphs().
+  fn set_glyph_sprite(&mut self, c: char, tex: Option<TexHandle>);
+}
+
+/// The bundled default font, DejaVu Sans Mono. Its license is in
+/// fonts/LICENSE-DejaVu.
+#[cfg(feature = "default-font")]
+pub const DEFAULT_FONT_DATA: &'static [u8] = include_bytes!("../../../fonts/DejaVuSansMono.ttf");
+
+/// Counts the faces in a font file. This is 1 for plain font files (.ttf),
+/// and the number of fonts in the collection for font collections (.ttc).
+/// Each face can be cached with FontCache::cache_glyphs_face().
+/// # Errors
+/// Returns an error if the file couldn't be read.
+pub fn font_face_count<F: AsRef<Path>>(file: F) -> Result<usize, std::io::Error> {
+  use std::fs::File;
+  use std::io::Read;
+  let mut f = try!(File::open(file));
+  let mut data = Vec::new();
+  try!(f.read_to_end(&mut data));
+  Ok(FontCollection::from_bytes(data).into_fonts().count())
 }
 
 /// A trait which has methods for looking up UVs for a glyph given a font handle and a code point.
@@ -148,9 +406,9 @@ pub trait GlyphLookup {
   fn rect_for(&self, font_handle: FontHandle, code_point: char) 
     -> Result<Option<[f32; 4]>, CacheReadError>;
 
-  /// Get a reference to the font (and scale x, y) attached to the given font
-  /// handle.
-  fn get_font_ref(&self, fh: FontHandle) -> Option<&(Font, (f32, f32))>;
+  /// Get a reference to the font (and its x and y scale) attached to the
+  /// given font handle.
+  fn get_font_ref(&self, fh: FontHandle) -> Option<&(Font, Scale)>;
 
   /// A function to get a glyph in the cache, given a font handle and a character.
   /// # Returns
@@ -161,8 +419,80 @@ pub trait GlyphLookup {
   /// currently store in the cache, and requesting a texture rect for the given
   /// glyph may still not return a value.
   fn get_glyph(&self, fh: FontHandle, c: char) -> Option<PositionedGlyph>;
+
+  /// Get the texture to draw in place of the given char, if one has been set
+  /// with FontCache::set_glyph_sprite.
+  fn sprite_for(&self, c: char) -> Option<TexHandle>;
+
+  /// Gets the vector outline of a glyph, at the scale of the given font.
+  /// Unlike the rest of the lookup, this doesn't need the glyph to be cached
+  /// - only the font.
+  /// # Returns
+  /// The outline as a list of path commands, in pixels relative to the
+  /// glyph's origin on the baseline, with y pointing down. None if the font
+  /// handle is invalid or the font doesn't contain the char. Glyphs with no
+  /// outline (like ' ') return an empty path.
+  fn glyph_outline(&self, fh: FontHandle, c: char) -> Option<Vec<PathCommand>> {
+    let &(ref font, scale) = match self.get_font_ref(fh) {
+      Some(f) => f,
+      None => return None,
+    };
+    let glyph = match font.glyph(c) {
+      Some(g) => g,
+      None => return None,
+    };
+    if glyph.id().0 == 0 { return None; }
+    let contours = glyph.scaled(scale).shape().unwrap_or(Vec::new());
+    Some(contours_to_path(&contours))
+  }
+
+  /// Requests that a glyph which wasn't in the cache when rendering text is
+  /// cached before the next frame. This does nothing unless caching glyphs
+  /// on demand is enabled.
+  fn request_glyph(&self, fh: FontHandle, c: char);
+}
+
+
+/// A command in a glyph outline path. See GlyphLookup::glyph_outline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathCommand {
+  /// Start a new contour at the given point.
+  MoveTo([f32; 2]),
+  /// Draw a straight line from the current point to the given point.
+  LineTo([f32; 2]),
+  /// Draw a quadratic bezier curve from the current point, with the given
+  /// control point and end point.
+  QuadTo([f32; 2], [f32; 2]),
+  /// Close the current contour, joining it back to its start.
+  Close,
 }
 
+/// Converts rusttype's glyph contours into a flat list of path commands.
+fn contours_to_path(contours: &[Contour]) -> Vec<PathCommand> {
+  let mut path = Vec::new();
+  for contour in contours {
+    let mut first = true;
+    for segment in &contour.segments {
+      let start = match *segment {
+        Segment::Line(ref l) => l.p[0],
+        Segment::Curve(ref c) => c.p[0],
+      };
+      if first {
+        path.push(PathCommand::MoveTo([start.x, start.y]));
+        first = false;
+      }
+      match *segment {
+        Segment::Line(ref l) => path.push(PathCommand::LineTo([l.p[1].x, l.p[1].y])),
+        Segment::Curve(ref c) => 
+          path.push(PathCommand::QuadTo([c.p[1].x, c.p[1].y], [c.p[2].x, c.p[2].y])),
+      }
+    }
+    if !first {
+      path.push(PathCommand::Close);
+    }
+  }
+  path
+}
 
 /// A struct containing data to uniquely identify a font. Fonts are identified
 /// by paths and sizes - so if you have 2 identical font files, but stored at
@@ -170,6 +500,9 @@ pub trait GlyphLookup {
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub struct FontSpec {
   path: PathBuf,
+  /// The index of the face in the font file - non-zero for faces in font
+  /// collections.
+  face_index: usize,
   /// The x scale of this font * 100. A font of size 24pt will have 24 * 100 * dpi x_scale
   /// and 24 * 100 * dpi y_scale. This is not stored as a floating point number
   /// because it needs to be the key in a map, and as such must implement Eq
@@ -181,9 +514,10 @@ pub struct FontSpec {
 impl FontSpec {
   /// Create a new font spec. The x scale and y scale are 100 times the actual
   /// scale - for a font of size 24, use 2400 as the values for x and y scale.
-  pub fn new<F: AsRef<Path>>(path: F, x_scale: u32, y_scale: u32) -> FontSpec {
+  pub fn new<F: AsRef<Path>>(path: F, face_index: usize, x_scale: u32, y_scale: u32) -> FontSpec {
     FontSpec {
       path: path.as_ref().to_path_buf(),
+      face_index: face_index,
       x_scale: x_scale,
       y_scale: y_scale,
     }
@@ -191,8 +525,10 @@ impl FontSpec {
 }
 
 /// A font handle, to be owned by the end user and used to query for glyph
-/// textures.
-#[derive(Ord, PartialOrd, Eq, PartialEq, Clone, Copy, Debug)]
+/// textures. Like TexHandle, it can be serialized with the serialize feature,
+/// but only refers to the same font if fonts are cached in the same order.
+#[derive(Ord, PartialOrd, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct FontHandle(usize);
 impl Deref for FontHandle {
   type Target = usize;
@@ -1,5 +1,6 @@
 //! Module containing code to do with resources and resource loading.
 
+#[cfg(feature = "font")]
 pub mod font;
 pub mod tex;
 
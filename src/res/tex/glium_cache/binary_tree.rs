@@ -3,7 +3,7 @@
 
 use std::fmt;
 use std;
-use res::tex::{TexHandle, TexHandleLookup};
+use res::tex::{TexHandle, TexHandleLookup, UNTRIMMED};
 
 #[derive(Clone, Copy, Debug)]
 pub enum PackRectError {
@@ -27,7 +27,10 @@ impl std::error::Error for PackRectError {
   }
 }
 
-/// A binary tree node used by the GliumTexCache.
+/// A binary tree node used by the GliumTexCache. A node is either a leaf of
+/// free space, a node holding a texture (with the space left below and to
+/// the right of it as its children), or, once textures have been freed, a
+/// node which only splits its space between its children.
 pub struct BinaryTreeNode {
   l_child: Option<Box<BinaryTreeNode>>,
   r_child: Option<Box<BinaryTreeNode>>,
@@ -37,6 +40,15 @@ pub struct BinaryTreeNode {
 
   /// The texture handle associated with this space.
   tex_handle: Option<TexHandle>,
+
+  /// True if the texture in this space was rotated 90 degrees clockwise to
+  /// fit. The space is the rotated rect, so has the texture's width and
+  /// height swapped.
+  rotated: bool,
+
+  /// The part of the original texture stored in this space, if its
+  /// transparent border was trimmed. See TexHandleLookup::trim_for().
+  trim: [f32; 4],
 }
 impl BinaryTreeNode {
   /// Create a new binary tree node with the given UV rect as space.
@@ -45,13 +57,15 @@ impl BinaryTreeNode {
       l_child: None, r_child: None,
       space: space,
       tex_handle: None,
+      rotated: false,
+      trim: UNTRIMMED,
     }
   }
 
-  /// Check if this node is a leaf. Useful to check if it stores an actual
-  /// texture or not - if it's a leaf, it'll be empty.
+  /// Check if this node is a leaf. Leaves are free space - they don't store
+  /// a texture, or have children which might.
   pub fn is_leaf(&self) -> bool {
-    self.tex_handle.is_none()
+    self.l_child.is_none() && self.r_child.is_none()
   }
 
   /// Pack a rect into this space. Change this node into a branch, and add both
@@ -67,6 +81,8 @@ impl BinaryTreeNode {
   /// * `w` - The width of the rectangle in UV coordinates.
   /// * `h` - The height of the rectangle in UV coordinates.
   /// * `tex` - The texture handle of the texture we're packing.
+  /// * `rotated` - Whether the texture has been rotated to fit this rect (see
+  ///               CachePage::pack).
   /// # Returns
   /// The rect the texture was placed in.
   /// # Errors
@@ -74,16 +90,18 @@ impl BinaryTreeNode {
   /// # Notes
   /// If this node is not a leaf node, then this function will be recursively
   /// called on the child nodes of this node.
-  pub fn pack_rect(&mut self, w: f32, h: f32, tex: TexHandle) -> Result<[f32; 4], PackRectError> {
+  pub fn pack_rect(&mut self, w: f32, h: f32, tex: TexHandle, 
+                   rotated: bool) -> Result<[f32; 4], PackRectError> {
     if !self.is_leaf() {
       // Recurse.
       debug_assert!(self.l_child.is_some() && self.r_child.is_some(), 
-                    r#"A node in the binary tree is a leaf, but for some reason
-                    either l_child or r_child is not set."#);
-      let res = self.r_child.as_mut().unwrap().pack_rect(w, h, tex);
+                    r#"A node in the binary tree isn't a leaf, but for some
+                    reason either l_child or r_child is not set."#);
+      let res = self.r_child.as_mut().unwrap().pack_rect(w, h, tex, rotated);
       if res.is_err() {
         match res.err().unwrap() {
-          PackRectError::SpaceTooSmall => return self.l_child.as_mut().unwrap().pack_rect(w, h, tex),
+          PackRectError::SpaceTooSmall => 
+            return self.l_child.as_mut().unwrap().pack_rect(w, h, tex, rotated),
         }
       }
       else { return res; }
@@ -114,6 +132,7 @@ impl BinaryTreeNode {
     // Set this node's space to the given rect, and the tex_handle
     self.space = [self.space[0], self.space[1], w, h];
     self.tex_handle = Some(tex);
+    self.rotated = rotated;
 
     return Ok(self.space.clone());
   }
@@ -122,23 +141,203 @@ impl BinaryTreeNode {
   /// # Returns
   /// None if the texture was not found in this tree.
   pub fn rect_for(&self, tex_handle: TexHandle) -> Option<[f32; 4]> {
-    if self.tex_handle.is_none() { return None; }
-    if *self.tex_handle.as_ref().unwrap() == tex_handle {
-      return Some(self.space);
+    self.node_for(tex_handle).map(|n| n.space)
+  }
+
+  /// Frees the space used by a texture, so other textures can be packed
+  /// into it. Nodes whose space is entirely free again are merged back into
+  /// a single leaf.
+  /// # Returns
+  /// True if the texture was found in this tree.
+  pub fn free(&mut self, tex_handle: TexHandle) -> bool {
+    if self.is_leaf() { return false; }
+    if self.tex_handle == Some(tex_handle) {
+      // The texture's space becomes a leaf, in a row with the space to its
+      // right, above the space below it.
+      let region = self.region();
+      let below = self.l_child.take().unwrap();
+      let right = self.r_child.take().unwrap();
+      let mut row = BinaryTreeNode::new([region[0], region[1], region[2], self.space[3]]);
+      row.l_child = Some(Box::new(BinaryTreeNode::new(self.space)));
+      row.r_child = Some(right);
+      row.merge();
+      self.space = region;
+      self.tex_handle = None;
+      self.rotated = false;
+      self.trim = UNTRIMMED;
+      self.l_child = Some(below);
+      self.r_child = Some(Box::new(row));
+      self.merge();
+      return true;
+    }
+    let found = self.r_child.as_mut().unwrap().free(tex_handle) ||
+      self.l_child.as_mut().unwrap().free(tex_handle);
+    if found { self.merge(); }
+    found
+  }
+
+  /// Turns a node without a texture back into a leaf if both its children
+  /// are leaves.
+  fn merge(&mut self) {
+    if self.tex_handle.is_none() && 
+      self.l_child.as_ref().map_or(false, |c| c.is_leaf()) &&
+      self.r_child.as_ref().map_or(false, |c| c.is_leaf()) {
+      self.l_child = None;
+      self.r_child = None;
+    }
+  }
+
+  /// The whole space this node covers, including its children - XYWH.
+  fn region(&self) -> [f32; 4] {
+    if self.tex_handle.is_none() { return self.space; }
+    let below = self.l_child.as_ref().unwrap().region();
+    let right = self.r_child.as_ref().unwrap().region();
+    [self.space[0], self.space[1], self.space[2] + right[2], self.space[3] + below[3]]
+  }
+
+  /// The total UV area of the textures packed into this tree.
+  pub fn used_area(&self) -> f32 {
+    if self.is_leaf() { return 0.0; }
+    let mut area = if self.tex_handle.is_some() { self.space[2] * self.space[3] } else { 0.0 };
+    if let Some(ref l) = self.l_child { area += l.used_area(); }
+    if let Some(ref r) = self.r_child { area += r.used_area(); }
+    area
+  }
+
+  /// Get the node storing the given texture handle.
+  /// # Returns
+  /// None if the texture was not found in this tree.
+  fn node_for(&self, tex_handle: TexHandle) -> Option<&BinaryTreeNode> {
+    if self.is_leaf() { return None; }
+    if self.tex_handle == Some(tex_handle) {
+      return Some(self);
     }
     let mut res = None;
     if self.l_child.is_some() {
-      res = self.l_child.as_ref().unwrap().rect_for(tex_handle);
+      res = self.l_child.as_ref().unwrap().node_for(tex_handle);
     }
     if res.is_some() { return res; }
     if self.r_child.is_some() {
-      return self.r_child.as_ref().unwrap().rect_for(tex_handle);
+      return self.r_child.as_ref().unwrap().node_for(tex_handle);
     }
     return None;
   }
+
+  /// Like node_for(), but mutable.
+  fn node_for_mut(&mut self, tex_handle: TexHandle) -> Option<&mut BinaryTreeNode> {
+    if self.is_leaf() { return None; }
+    if self.tex_handle == Some(tex_handle) {
+      return Some(self);
+    }
+    let in_left = self.l_child.as_ref().map_or(false, |l| l.node_for(tex_handle).is_some());
+    if in_left {
+      return self.l_child.as_mut().unwrap().node_for_mut(tex_handle);
+    }
+    match self.r_child {
+      Some(ref mut r) => r.node_for_mut(tex_handle),
+      None => None,
+    }
+  }
+}
+
+/// A cache page - the root node of a packing tree, along with information
+/// about the cache texture it packs into.
+pub struct CachePage {
+  /// The root node of the packing tree for this page.
+  pub root: BinaryTreeNode,
+
+  /// True if this page is dedicated to a single texture rather than being
+  /// shared as an atlas. Nothing else is ever packed into a dedicated page, so
+  /// the single texture it contains covers its entire UV space.
+  pub dedicated: bool,
+
+  /// True once a texture on this page has had its transparent border
+  /// trimmed. Pages without any trimmed textures are skipped when looking up
+  /// trims, so drawing doesn't search every page when trimming is off.
+  trimmed: bool,
 }
 
-pub type BinaryTree = Vec<BinaryTreeNode>;
+impl CachePage {
+  /// Create a new, empty, atlas page.
+  pub fn new() -> CachePage {
+    CachePage {
+      root: BinaryTreeNode::new([0.0, 0.0, 1.0, 1.0]),
+      dedicated: false,
+      trimmed: false,
+    }
+  }
+
+  /// Create a page dedicated to the given texture, which takes up the entire
+  /// UV space of the page.
+  pub fn new_dedicated(tex: TexHandle) -> CachePage {
+    let mut root = BinaryTreeNode::new([0.0, 0.0, 1.0, 1.0]);
+    root.pack_rect(1.0, 1.0, tex, false).unwrap();
+    CachePage {
+      root: root,
+      dedicated: true,
+      trimmed: false,
+    }
+  }
+
+  /// Pack a rect into this page. If `allow_rotate` is set and the rect
+  /// doesn't fit as it is, this also tries to fit the rect rotated 90 degrees
+  /// (i.e. with the width and height swapped).
+  /// # Params
+  /// * `w` - The width of the rect, in pixels
+  /// * `h` - The height of the rect, in pixels
+  /// * `page_size` - The size of this page's cache texture, in pixels. Sizes
+  ///                 are normalised against this, so on a non-square page a
+  ///                 rotated rect doesn't just have its UV extents swapped.
+  /// # Returns
+  /// The rect the texture was placed in, and whether it was rotated. If it
+  /// was rotated, the returned rect has the width and height swapped.
+  /// # Errors
+  /// Returns an error if the rect doesn't fit in either orientation.
+  pub fn pack(&mut self, w: u32, h: u32, page_size: (u32, u32), tex: TexHandle, 
+              allow_rotate: bool) -> Result<([f32; 4], bool), PackRectError> {
+    let (cw, ch) = (page_size.0 as f32, page_size.1 as f32);
+    let res = self.root.pack_rect(w as f32 / cw, h as f32 / ch, tex, false);
+    if res.is_ok() || !allow_rotate || w == h { 
+      return res.map(|rect| (rect, false)); 
+    }
+    self.root.pack_rect(h as f32 / cw, w as f32 / ch, tex, true).map(|rect| (rect, true))
+  }
+
+  /// Frees the space used by a texture. See BinaryTreeNode::free().
+  /// # Returns
+  /// True if the texture was on this page.
+  pub fn free(&mut self, tex: TexHandle) -> bool {
+    self.root.free(tex)
+  }
+
+  /// Records that a texture on this page had its transparent border
+  /// trimmed, keeping the `trim` part of it. See TexHandleLookup::trim_for().
+  /// # Returns
+  /// True if the texture was on this page.
+  pub fn set_trim(&mut self, tex: TexHandle, trim: [f32; 4]) -> bool {
+    match self.root.node_for_mut(tex) {
+      Some(node) => {
+        node.trim = trim;
+        self.trimmed |= trim != UNTRIMMED;
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// True if no textures are packed into this page.
+  pub fn is_empty(&self) -> bool {
+    self.root.is_leaf()
+  }
+
+  /// The fraction of this page's space which textures have been packed into,
+  /// from 0.0 to 1.0.
+  pub fn occupancy(&self) -> f32 {
+    self.root.used_area()
+  }
+}
+
+pub type BinaryTree = Vec<CachePage>;
 
 impl TexHandleLookup for BinaryTree {
   fn is_tex_cached(&self, tex: TexHandle) -> bool {
@@ -147,11 +346,24 @@ impl TexHandleLookup for BinaryTree {
 
   fn rect_for(&self, tex: TexHandle) -> Option<(usize, [f32; 4])> {
     for (ii, t) in self.iter().enumerate() {
-      let res = t.rect_for(tex);
+      let res = t.root.rect_for(tex);
       if res.is_some() { return Some((ii, res.unwrap())); };
     }
     return None;
   }
+
+  fn is_tex_rotated(&self, tex: TexHandle) -> bool {
+    self.iter().filter_map(|t| t.root.node_for(tex)).next().map(|n| n.rotated).unwrap_or(false)
+  }
+
+  fn is_page_dedicated(&self, tex_ix: usize) -> bool {
+    self.get(tex_ix).map(|p| p.dedicated).unwrap_or(false)
+  }
+
+  fn trim_for(&self, tex: TexHandle) -> [f32; 4] {
+    self.iter().filter(|t| t.trimmed).filter_map(|t| t.root.node_for(tex)).next()
+      .map(|n| n.trim).unwrap_or(UNTRIMMED)
+  }
 }
 
 impl TexHandleLookup for std::sync::Arc<BinaryTree> {
@@ -161,9 +373,118 @@ impl TexHandleLookup for std::sync::Arc<BinaryTree> {
 
   fn rect_for(&self, tex: TexHandle) -> Option<(usize, [f32; 4])> {
     for (ii, t) in self.iter().enumerate() {
-      let res = t.rect_for(tex);
+      let res = t.root.rect_for(tex);
       if res.is_some() { return Some((ii, res.unwrap())); };
     }
     return None;
   }
+
+  fn is_tex_rotated(&self, tex: TexHandle) -> bool {
+    self.iter().filter_map(|t| t.root.node_for(tex)).next().map(|n| n.rotated).unwrap_or(false)
+  }
+
+  fn is_page_dedicated(&self, tex_ix: usize) -> bool {
+    self.get(tex_ix).map(|p| p.dedicated).unwrap_or(false)
+  }
+
+  fn trim_for(&self, tex: TexHandle) -> [f32; 4] {
+    self.iter().filter(|t| t.trimmed).filter_map(|t| t.root.node_for(tex)).next()
+      .map(|n| n.trim).unwrap_or(UNTRIMMED)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use res::tex::{TexHandle, TexHandleLookup};
+
+  const PAGE: (u32, u32) = (4, 4);
+
+  #[test]
+  fn pack_rotates_to_fit() {
+    let mut page = CachePage::new();
+    // Fill the top of the page, leaving a space 1.0 wide and 0.25 high.
+    page.pack(4, 3, PAGE, TexHandle(0), true).unwrap();
+    // A 1x4 rect only fits on its side.
+    assert!(page.pack(1, 4, PAGE, TexHandle(1), false).is_err());
+    let (rect, rotated) = page.pack(1, 4, PAGE, TexHandle(1), true).unwrap();
+    assert!(rotated);
+    assert_eq!(rect, [0.0, 0.75, 1.0, 0.25]);
+    assert!(vec![page].is_tex_rotated(TexHandle(1)));
+  }
+
+  #[test]
+  fn pack_rotates_to_fit_non_square_page() {
+    let mut page = CachePage::new();
+    // Leaves a strip 8px wide and 1px high along the bottom of an 8x4 page.
+    page.pack(8, 3, (8, 4), TexHandle(0), true).unwrap();
+    // On its side, a 1x8 rect is 8x1, so fills the strip exactly - even
+    // though its UV extents aren't just the unrotated ones swapped.
+    let (rect, rotated) = page.pack(1, 8, (8, 4), TexHandle(1), true).unwrap();
+    assert!(rotated);
+    assert_eq!(rect, [0.0, 0.75, 1.0, 0.25]);
+  }
+
+  #[test]
+  fn occupancy_sums_packed_rects() {
+    let mut page = CachePage::new();
+    assert_eq!(page.occupancy(), 0.0);
+    page.pack(2, 2, PAGE, TexHandle(0), false).unwrap();
+    page.pack(2, 1, PAGE, TexHandle(1), false).unwrap();
+    assert_eq!(page.occupancy(), 0.375);
+    assert_eq!(CachePage::new_dedicated(TexHandle(2)).occupancy(), 1.0);
+  }
+
+  #[test]
+  fn freed_space_is_reused() {
+    let mut page = CachePage::new();
+    page.pack(2, 2, PAGE, TexHandle(0), false).unwrap();
+    page.pack(2, 2, PAGE, TexHandle(1), false).unwrap();
+    page.pack(4, 2, PAGE, TexHandle(2), false).unwrap();
+    assert!(page.pack(2, 2, PAGE, TexHandle(3), false).is_err());
+
+    // Freeing the first texture leaves a hole its size, with the others
+    // still where they were.
+    assert!(page.free(TexHandle(0)));
+    assert!(!page.free(TexHandle(0)));
+    let pages = vec![page];
+    assert_eq!(pages.rect_for(TexHandle(0)), None);
+    assert_eq!(pages.rect_for(TexHandle(1)), Some((0, [0.5, 0.0, 0.5, 0.5])));
+    assert_eq!(pages.rect_for(TexHandle(2)), Some((0, [0.0, 0.5, 1.0, 0.5])));
+    let mut page = pages.into_iter().next().unwrap();
+    assert_eq!(page.occupancy(), 0.75);
+    assert!(page.pack(2, 3, PAGE, TexHandle(3), false).is_err());
+    assert_eq!(page.pack(2, 2, PAGE, TexHandle(3), false).unwrap(),
+               ([0.0, 0.0, 0.5, 0.5], false));
+  }
+
+  #[test]
+  fn trim_is_stored_with_the_rect() {
+    let mut page = CachePage::new();
+    page.pack(2, 2, PAGE, TexHandle(0), false).unwrap();
+    page.pack(1, 1, PAGE, TexHandle(1), false).unwrap();
+    assert!(page.set_trim(TexHandle(1), [0.25, 0.0, 1.0, 0.5]));
+    assert!(!page.set_trim(TexHandle(2), [0.25, 0.0, 1.0, 0.5]));
+    let mut untrimmed = CachePage::new();
+    untrimmed.pack(1, 1, PAGE, TexHandle(3), false).unwrap();
+    assert!(untrimmed.set_trim(TexHandle(3), UNTRIMMED));
+    assert!(!untrimmed.trimmed);
+    let pages = vec![untrimmed, page];
+    assert_eq!(pages.trim_for(TexHandle(3)), UNTRIMMED);
+    assert_eq!(pages.trim_for(TexHandle(0)), UNTRIMMED);
+    assert_eq!(pages.trim_for(TexHandle(1)), [0.25, 0.0, 1.0, 0.5]);
+  }
+
+  #[test]
+  fn freeing_everything_merges_back_into_one_leaf() {
+    let mut page = CachePage::new();
+    for ii in 0..4 {
+      page.pack(2, 2, PAGE, TexHandle(ii), false).unwrap();
+    }
+    for &ii in &[2, 0, 3, 1] {
+      assert!(page.free(TexHandle(ii)));
+    }
+    assert!(page.is_empty());
+    assert_eq!(page.pack(4, 4, PAGE, TexHandle(4), false).unwrap(), ([0.0, 0.0, 1.0, 1.0], false));
+  }
 }
@@ -1,18 +1,82 @@
 //! A module containing a glium implementation of a tex cache.
 
 use glium;
-use glium::texture::{RawImage2d};
+use glium::texture::{RawImage2d, Texture2d};
 use glium::texture::srgb_texture2d::SrgbTexture2d;
 use res::tex::*;
+#[cfg(feature = "image")]
 use image;
 use std::sync::Arc;
+use std::borrow::Cow;
+use std::collections::HashMap;
 
 mod binary_tree;
+mod rgba;
+mod streaming;
 
-use self::binary_tree::{BinaryTreeNode, BinaryTree};
+use self::binary_tree::{BinaryTree, CachePage};
+pub use self::rgba::{RgbaBuffer, PixelFormat};
+use self::rgba::CacheImage;
+pub use self::streaming::StreamingTexture;
 
 pub type GliumTexHandleLookup = Arc<BinaryTree>;
 
+/// The number of worker threads used to decode textures in
+/// TexCache::cache_tex_parallel.
+#[cfg(feature = "image")]
+const DECODE_THREADS: usize = 4;
+
+/// A cache texture. Colour textures are stored in sRGB RGBA pages. Grayscale
+/// textures can be stored in their own pages with fewer channels (see
+/// TexCache::set_grayscale_pages), which aren't sRGB textures, so the shader
+/// expands them to RGBA and decodes them itself.
+pub enum CacheTexture {
+  Rgba(SrgbTexture2d),
+  /// A page of Gray or GrayAlpha textures.
+  Gray(Texture2d, PixelFormat),
+  /// A dedicated floating point RGBA texture, holding linear colours which
+  /// may be brighter than white. See GliumTexCache::cache_tex_hdr().
+  Hdr(Texture2d),
+}
+
+impl CacheTexture {
+  /// The format of the textures stored in this cache texture.
+  pub fn format(&self) -> PixelFormat {
+    match *self {
+      CacheTexture::Rgba(_) | CacheTexture::Hdr(_) => PixelFormat::Rgba,
+      CacheTexture::Gray(_, format) => format,
+    }
+  }
+
+  /// The size of the cache texture in pixels.
+  pub fn dimensions(&self) -> (u32, u32) {
+    match *self {
+      CacheTexture::Rgba(ref tex) => tex.dimensions(),
+      CacheTexture::Gray(ref tex, _) | CacheTexture::Hdr(ref tex) => tex.dimensions(),
+    }
+  }
+}
+
+/// The floating point format of HDR textures and render targets.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum HdrFormat {
+  /// 16 bits per channel. Enough for most effects, at half the size of F32.
+  F16,
+  /// 32 bits per channel.
+  F32,
+}
+
+impl HdrFormat {
+  /// The glium texture format for this format.
+  pub fn texture_format(&self) -> glium::texture::UncompressedFloatFormat {
+    use glium::texture::UncompressedFloatFormat;
+    match *self {
+      HdrFormat::F16 => UncompressedFloatFormat::F16F16F16F16,
+      HdrFormat::F32 => UncompressedFloatFormat::F32F32F32F32,
+    }
+  }
+}
+
 /// Texture cache which uses glium as the GPU storage medium.
 pub struct GliumTexCache {
   /// The maximum amount of cache textures to be created.
@@ -21,16 +85,46 @@ pub struct GliumTexCache {
   /// The size of the GPU cache textures.
   cache_texture_size: (u32, u32),
 
+  /// Textures with a width or height above this get their own dedicated cache
+  /// texture. (0, 0) means no textures are dedicated.
+  dedicated_tex_threshold: (u32, u32),
+
+  /// Whether textures can be rotated to fit into the cache.
+  allow_rotation: bool,
+
+  /// Whether grayscale textures are stored in their own pages, with fewer
+  /// channels.
+  gray_pages: bool,
+
+  /// The colour made transparent in textures as they're cached. See
+  /// TexCache::set_color_key.
+  color_key: Option<[u8; 3]>,
+
+  /// Whether transparent borders are trimmed from textures as they're
+  /// cached. See TexCache::set_trim_transparent.
+  trim_transparent: bool,
+
   /// The list of cache textures.
-  cache_textures: Vec<SrgbTexture2d>,
+  cache_textures: Vec<CacheTexture>,
 
-  /// This is a list of root nodes for binary trees. They're used to pack
-  /// textures into the cache. Each index in this vector matches a cache
-  /// texture of the same index.
+  /// This is a list of cache pages, each the root node of a binary tree.
+  /// They're used to pack textures into the cache. Each index in this vector
+  /// matches a cache texture of the same index.
   bin_pack_trees: Arc<BinaryTree>,
 
   /// This field holds the value of the next valid TexHandle to hand out.
   next_tex_handle: TexHandle,
+
+  /// Human readable names for textures, for debugging. See set_tex_name().
+  names: HashMap<TexHandle, String>,
+
+  /// The packing group textures are cached into. See
+  /// TexCache::set_packing_group.
+  packing_group: Option<String>,
+
+  /// The packing group owning each atlas page, by page index. Pages which
+  /// aren't in the map belong to no group.
+  page_groups: HashMap<usize, String>,
 }
 
 impl GliumTexCache {
@@ -38,9 +132,17 @@ impl GliumTexCache {
     GliumTexCache {
       max_cache_textures: 0,
       cache_texture_size: (2048, 2048),
+      dedicated_tex_threshold: (1024, 1024),
+      allow_rotation: false,
+      gray_pages: false,
+      color_key: None,
+      trim_transparent: false,
       cache_textures: Vec::new(),
       bin_pack_trees: Arc::new(Vec::new()),
       next_tex_handle: TexHandle(0),
+      names: HashMap::new(),
+      packing_group: None,
+      page_groups: HashMap::new(),
     }
   }
 
@@ -50,174 +152,622 @@ impl GliumTexCache {
       self.bin_pack_trees.clone()
   }
 
+  /// The occupancy of each cache page, from 0.0 (empty) to 1.0 (full). See
+  /// CachePage::occupancy().
+  pub fn page_occupancy(&self) -> Vec<f32> {
+    self.bin_pack_trees.iter().map(|p| p.occupancy()).collect()
+  }
+
+  /// Gives a texture a human readable name, used when logging about it.
+  pub fn set_tex_name(&mut self, tex: TexHandle, name: &str) {
+    self.names.insert(tex, name.to_owned());
+  }
+
+  /// The name given to a texture with set_tex_name(), if any.
+  pub fn tex_name(&self, tex: TexHandle) -> Option<&str> {
+    self.names.get(&tex).map(|n| &n[..])
+  }
+
+  /// The names of the named textures on a cache page.
+  pub fn page_tex_names(&self, page: usize) -> Vec<&str> {
+    let mut names: Vec<&str> = self.names.iter()
+      .filter(|&(&tex, _)| self.bin_pack_trees.rect_for(tex).map(|(p, _)| p) == Some(page))
+      .map(|(_, name)| &name[..])
+      .collect();
+    names.sort();
+    names
+  }
+
   fn get_next_tex_handle(&mut self) -> TexHandle {
     let th = self.next_tex_handle;
     self.next_tex_handle.0 += 1;
     return th;
   }
 
-  /// The method to actually internally cache textures. Called by both of the
-  /// caching methods implemented when implementing the TexCache trait.
-  fn cache_tex_internal<F: glium::backend::Facade>(
-    &mut self, display: &F, 
-    bytes: Vec<Result<&[u8], CacheTexError>>) -> Vec<Result<TexHandle, CacheTexError>> {
-    let mut result = Vec::with_capacity(bytes.len());
-    for buf in bytes {
-      if buf.is_err() { 
-        result.push(Err(buf.err().unwrap()));
+  /// Reads the given files then caches the resulting images, making
+  /// `color_key` transparent in them. Called by cache_tex and
+  /// cache_tex_color_keyed.
+  #[cfg(feature = "image")]
+  fn cache_files<F: AsRef<Path>, Facade: glium::backend::Facade>(
+    &mut self, display: &Facade, filepaths: &[F], 
+    color_key: Option<[u8; 3]>) -> Vec<Result<TexHandle, CacheTexError>> {
+    use std::fs::File;
+    use std::io::Read;
+    let mut result = Vec::with_capacity(filepaths.len());
+    let mut bufs = Vec::with_capacity(filepaths.len());
+    bufs.resize(filepaths.len(), Vec::new());
+
+    // Load all the textures given.
+    for (ii, f) in filepaths.iter().enumerate() {
+      // Try open the file
+      let file = File::open(f);
+      if file.is_err() {
+        result.push(Err(CacheTexError::IoError(file.err().unwrap())));
         continue;
       }
-      let buf = buf.unwrap();
-      // Load into an actual 'image',       
-      let img = image::load_from_memory(buf);
-      if img.is_err() {
-        result.push(Err(CacheTexError::ImageError(img.err().unwrap())));
+      let mut file = file.unwrap();
+
+      // Read all the file data
+      let mut buf = Vec::new();
+      let read_res = file.read_to_end(&mut buf);
+      if read_res.is_err() {
+        result.push(Err(CacheTexError::IoError(read_res.err().unwrap())));
         continue;
       }
-      let img = img.unwrap().to_rgba();
+      bufs.insert(ii, buf);
+      result.push(Ok(())); 
+    }
+
+    let mut result_slices = Vec::with_capacity(filepaths.len());
+    for (ii, r) in result.into_iter().enumerate() {
+      if r.is_ok() { result_slices.push(Ok(bufs[ii].as_slice())); }
+      else { result_slices.push(Err(r.unwrap_err())); }
+    }
+
+    // Need to map the owned data into slices now.
+    self.cache_tex_internal(display, result_slices, color_key)
+  }
+
+  /// Decodes the given buffers then caches the resulting images. Called by
+  /// both of the sequential caching methods implemented when implementing the
+  /// TexCache trait.
+  #[cfg(feature = "image")]
+  fn cache_tex_internal<F: glium::backend::Facade>(
+    &mut self, display: &F, bytes: Vec<Result<&[u8], CacheTexError>>, 
+    color_key: Option<[u8; 3]>) -> Vec<Result<TexHandle, CacheTexError>> {
+    let images = bytes.into_iter().map(|buf| buf.and_then(decode)).collect();
+    self.cache_images(display, images, color_key)
+  }
+
+  /// The method to actually internally cache textures, once they've been
+  /// decoded. `color_key` is made transparent in every image.
+  fn cache_images<F: glium::backend::Facade>(
+    &mut self, display: &F, images: Vec<Result<CacheImage, CacheTexError>>, 
+    color_key: Option<[u8; 3]>) -> Vec<Result<TexHandle, CacheTexError>> {
+    let mut result = Vec::with_capacity(images.len());
+    for img in images {
+      if img.is_err() { 
+        let e = img.err().unwrap();
+        warn!("Failed to load texture: {}", e);
+        result.push(Err(e));
+        continue;
+      }
+      let img = if self.gray_pages { img.unwrap() } else { img.unwrap().to_rgba() };
+      let img = match color_key {
+        Some(key) => img.color_keyed(key),
+        None => img,
+      };
+      // Trimmed textures remember which part of the original they kept, so
+      // it can be drawn in the same place.
+      let (img, trim) = match if self.trim_transparent { img.opaque_bounds() } else { None } {
+        Some(b) if b != [0, 0, img.w, img.h] => {
+          let (o_w, o_h) = (img.w as f32, img.h as f32);
+          let trim = [b[0] as f32 / o_w, b[1] as f32 / o_h,
+                      (b[0] + b[2]) as f32 / o_w, (b[1] + b[3]) as f32 / o_h];
+          (img.cropped(b), trim)
+        }
+        _ => (img, UNTRIMMED),
+      };
+      let (w, h) = (img.w, img.h);
+      let dedicated = self.is_dedicated_size(w, h);
 
       // Check if the cache tex size is big enough to contain this texture.
-      let (w, h) = img.dimensions();
-      if w > self.cache_texture_size.0 || h > self.cache_texture_size.1 {
+      let (c_w, c_h) = self.cache_texture_size;
+      let fits = w <= c_w && h <= c_h;
+      let fits_rotated = self.allow_rotation && h <= c_w && w <= c_h;
+      if !dedicated && !fits && !fits_rotated {
+        warn!("Texture of size {}x{} is too big for the cache", w, h);
         result.push(Err(CacheTexError::CacheTooSmall));
         continue;
       }
 
       let tex_handle = self.get_next_tex_handle();
       // Now try and fit it into the cache using the bin packing algorithm.
-      // Loop over all the current textures and try to pack_rect.
+      // Loop over all the current textures and try to pack_rect. Dedicated
+      // textures skip this, and get a new cache texture unless a freed one
+      // can be reused.
       let mut tex_ix = None;
       let mut rect = None;
-      let bin_pack_trees = Arc::get_mut(&mut self.bin_pack_trees)
-        .expect("Failed to acquire mutable reference when caching texture. Is the texture cache in
-                use?");
-      for (ii, t) in bin_pack_trees.iter_mut().enumerate() {
-        let res = t.pack_rect(w as f32 / self.cache_texture_size.0 as f32, 
-                              h as f32 / self.cache_texture_size.1 as f32, 
-                              tex_handle);
-        if res.is_ok() { tex_ix = Some(ii); rect = Some(res.unwrap()); break; }
+      let mut rotated = false;
+      let at_max_textures = self.max_cache_textures > 0 && 
+        self.cache_textures.len() >= self.max_cache_textures;
+      if dedicated {
+        // Reuse a freed dedicated cache texture of the same size, if there
+        // is one.
+        let bin_pack_trees = Arc::get_mut(&mut self.bin_pack_trees)
+          .expect("Failed to acquire mutable reference when caching texture. Is the texture cache in
+                  use?");
+        for (ii, t) in bin_pack_trees.iter_mut().enumerate() {
+          let page = &self.cache_textures[ii];
+          let reusable = match *page {
+            CacheTexture::Hdr(_) => false,
+            _ => page.format() == img.format && page.dimensions() == (w, h),
+          };
+          if t.dedicated && t.is_empty() && reusable {
+            t.root.pack_rect(1.0, 1.0, tex_handle, false).unwrap();
+            tex_ix = Some(ii);
+            rect = Some([0.0, 0.0, 1.0, 1.0]);
+            break;
+          }
+        }
+      }
+      else {
+        let bin_pack_trees = Arc::get_mut(&mut self.bin_pack_trees)
+          .expect("Failed to acquire mutable reference when caching texture. Is the texture cache in
+                  use?");
+        // Pages owned by the texture's packing group are tried first. Pages
+        // owned by other groups are only used once no more pages can be
+        // created.
+        for pass in 0..2 {
+          if tex_ix.is_some() || (pass == 1 && !at_max_textures) { break; }
+          for (ii, t) in bin_pack_trees.iter_mut().enumerate() {
+            if t.dedicated || self.cache_textures[ii].format() != img.format { continue; }
+            let own_group = self.page_groups.get(&ii) == self.packing_group.as_ref();
+            if own_group != (pass == 0) { continue; }
+            let res = t.pack(w, h, self.cache_texture_size, tex_handle, self.allow_rotation);
+            if let Ok((r, rot)) = res { 
+              tex_ix = Some(ii); 
+              rect = Some(r); 
+              rotated = rot;
+              break; 
+            }
+          }
+        }
       }
 
       // If we haven't managed to pack the texture into existing cache
       // textures, then we need to create a new texture2d.
       if tex_ix.is_none() {
-        if self.max_cache_textures > 0 && 
-          self.cache_textures.len() >= self.max_cache_textures {
+        if at_max_textures {
+            warn!("No space in the cache for texture of size {}x{}", w, h);
             result.push(Err(CacheTexError::NoSpace));
             continue;
           }
 
-        use std::borrow::Cow;
-        let data_len = self.cache_texture_size.0 as usize 
-          * self.cache_texture_size.1 as usize;
-        let mut data = Vec::with_capacity(data_len*4);
-        data.resize(data_len*4, 0.0);
-        let tex = SrgbTexture2d::new(display, RawImage2d {
-          data: Cow::Owned(data),
-          width: self.cache_texture_size.0,
-          height: self.cache_texture_size.1,
-          format: glium::texture::ClientFormat::F32F32F32F32,
-        });
+        // Dedicated textures get a cache texture of exactly their size.
+        let tex_size = if dedicated { (w, h) } else { self.cache_texture_size };
+        debug!("Creating {} {:?} cache texture {} of size {}x{}", 
+               if dedicated { "dedicated" } else { "atlas" }, img.format,
+               self.cache_textures.len(), tex_size.0, tex_size.1);
+        let tex = create_cache_texture(display, tex_size, img.format);
         if tex.is_err() {
           match tex.err().unwrap() {
             glium::texture::TextureCreationError::DimensionsNotSupported => {
+              warn!("Cache texture of size {}x{} not supported", tex_size.0, tex_size.1);
               result.push(Err(CacheTexError::DimensionsNotSupported));
               continue;
             }
-            e => panic!("Unexpected error when creating cache texture: {}", e),
+            e => {
+              error!("Failed to create cache texture: {}", e);
+              result.push(Err(CacheTexError::TextureCreation(e)));
+              continue;
+            }
           }
         }
         self.cache_textures.push(tex.unwrap());
-        bin_pack_trees.push(BinaryTreeNode::new([0.0, 0.0, 1.0, 1.0]));
-
-        // Pack the rect into this new texture.  No need to error handle this
-        // one, too small error handled earlier in this function
-        rect = Some(bin_pack_trees.last_mut().unwrap().pack_rect( 
-            w as f32 / self.cache_texture_size.0 as f32, 
-            h as f32 / self.cache_texture_size.1 as f32, 
-            tex_handle).unwrap());
+
+        let bin_pack_trees = Arc::get_mut(&mut self.bin_pack_trees)
+          .expect("Failed to acquire mutable reference when caching texture. Is the texture cache in
+                  use?");
+        if dedicated {
+          bin_pack_trees.push(CachePage::new_dedicated(tex_handle));
+          rect = Some([0.0, 0.0, 1.0, 1.0]);
+        }
+        else {
+          if let Some(ref group) = self.packing_group {
+            self.page_groups.insert(bin_pack_trees.len(), group.clone());
+          }
+          bin_pack_trees.push(CachePage::new());
+          // Pack the rect into this new texture.  No need to error handle this
+          // one, too small error handled earlier in this function
+          let (r, rot) = bin_pack_trees.last_mut().unwrap().pack( 
+              w, h, self.cache_texture_size, tex_handle, self.allow_rotation).unwrap();
+          rect = Some(r);
+          rotated = rot;
+        }
         tex_ix = Some(self.cache_textures.len() - 1);
       }
 
+      if trim != UNTRIMMED {
+        Arc::get_mut(&mut self.bin_pack_trees)
+          .expect("Failed to acquire mutable reference when caching texture. Is the texture cache in
+                  use?")[tex_ix.unwrap()].set_trim(tex_handle, trim);
+      }
+
+      // Rotated textures are stored rotated 90 degrees clockwise.
+      let img = if rotated { img.rotate90() } else { img };
+      let (w, h) = (img.w, img.h);
+
       // Actually buffer to the GPU.
       let tex_ix = tex_ix.unwrap();
       let rect = rect.unwrap();
-      self.cache_textures[tex_ix].main_level().write(glium::Rect {
-        left: (self.cache_texture_size.0 as f32 * rect[0]) as u32,
-        bottom: (self.cache_texture_size.1 as f32 * rect[1]) as u32,        
-        width: (self.cache_texture_size.0 as f32 * rect[2]) as u32,        
-        height: (self.cache_texture_size.1 as f32 * rect[3]) as u32,      
-      }, glium::texture::RawImage2d::from_raw_rgba_reversed(&img.into_raw(), (w, h)));
+      let (page_w, page_h) = self.cache_textures[tex_ix].dimensions();
+      let region = glium::Rect {
+        left: (page_w as f32 * rect[0]) as u32,
+        bottom: (page_h as f32 * rect[1]) as u32,        
+        width: w,        
+        height: h,      
+      };
+      // Cache textures are stored upside down, so reverse the rows.
+      let data = RawImage2d {
+        data: Cow::Owned(img.rows_reversed()),
+        width: w,
+        height: h,
+        format: client_format(img.format),
+      };
+      match self.cache_textures[tex_ix] {
+        CacheTexture::Rgba(ref tex) => tex.main_level().write(region, data),
+        CacheTexture::Gray(ref tex, _) | CacheTexture::Hdr(ref tex) => {
+          tex.main_level().write(region, data)
+        }
+      }
 
+      trace!("Cached texture {:?} ({}x{}) in cache texture {}{}", tex_handle, w, h, tex_ix,
+             if rotated { ", rotated" } else { "" });
       result.push(Ok(tex_handle));
     }
 
     return result;
   }
+
+  /// Creates a streaming texture of the given size, with its own dedicated
+  /// cache texture. Its contents start transparent.
+  /// # Errors
+  /// Returns an error if there's no space for another cache texture, or it
+  /// couldn't be created.
+  pub fn create_streaming_tex<F: glium::backend::Facade>(
+    &mut self, display: &F, w: u32, h: u32) -> Result<StreamingTexture, CacheTexError> {
+    if self.max_cache_textures > 0 && self.cache_textures.len() >= self.max_cache_textures {
+      warn!("No space in the cache for streaming texture of size {}x{}", w, h);
+      return Err(CacheTexError::NoSpace);
+    }
+    let tex = try!(create_cache_texture(display, (w, h), PixelFormat::Rgba).map_err(|e| match e {
+      glium::texture::TextureCreationError::DimensionsNotSupported =>
+        CacheTexError::DimensionsNotSupported,
+      e => CacheTexError::TextureCreation(e),
+    }));
+    let tex_handle = self.get_next_tex_handle();
+    self.cache_textures.push(tex);
+    Arc::get_mut(&mut self.bin_pack_trees)
+      .expect("Failed to acquire mutable reference when caching texture. Is the texture cache in
+              use?")
+      .push(CachePage::new_dedicated(tex_handle));
+    debug!("Created streaming texture {:?} of size {}x{} in cache texture {}",
+           tex_handle, w, h, self.cache_textures.len() - 1);
+    Ok(StreamingTexture::new(display, tex_handle, (w, h)))
+  }
+
+  /// Caches a texture from floating point RGBA data, with its own dedicated
+  /// cache texture in the given format. Unlike other textures, its colours
+  /// are linear rather than sRGB encoded, and may be brighter than white.
+  /// Draw to an HDR render target to keep them from being clipped.
+  /// # Params
+  /// * `data` - Rows of pixels from top to bottom, 4 floats per pixel.
+  /// # Errors
+  /// Returns CacheTexError::InvalidRgbaData if the data is the wrong size,
+  /// or an error if there's no space for another cache texture, or it
+  /// couldn't be created.
+  pub fn cache_tex_hdr<F: glium::backend::Facade>(
+    &mut self, display: &F, w: u32, h: u32, data: &[f32], 
+    format: HdrFormat) -> Result<TexHandle, CacheTexError> {
+    if data.len() != w as usize * h as usize * 4 {
+      return Err(CacheTexError::InvalidRgbaData);
+    }
+    if self.max_cache_textures > 0 && self.cache_textures.len() >= self.max_cache_textures {
+      warn!("No space in the cache for HDR texture of size {}x{}", w, h);
+      return Err(CacheTexError::NoSpace);
+    }
+    // Cache textures are stored upside down, so reverse the rows.
+    let rows: Vec<f32> = if w == 0 { Vec::new() } else {
+      data.chunks(w as usize * 4).rev().flat_map(|row| row.iter().cloned()).collect()
+    };
+    let tex = try!(Texture2d::with_format(display, RawImage2d {
+      data: Cow::Owned(rows),
+      width: w,
+      height: h,
+      format: glium::texture::ClientFormat::F32F32F32F32,
+    }, format.texture_format(), glium::texture::MipmapsOption::NoMipmap).map_err(|e| match e {
+      glium::texture::TextureCreationError::DimensionsNotSupported =>
+        CacheTexError::DimensionsNotSupported,
+      e => CacheTexError::TextureCreation(e),
+    }));
+    let tex_handle = self.get_next_tex_handle();
+    self.cache_textures.push(CacheTexture::Hdr(tex));
+    Arc::get_mut(&mut self.bin_pack_trees)
+      .expect("Failed to acquire mutable reference when caching texture. Is the texture cache in
+              use?")
+      .push(CachePage::new_dedicated(tex_handle));
+    debug!("Cached {:?} HDR texture {:?} of size {}x{} in cache texture {}",
+           format, tex_handle, w, h, self.cache_textures.len() - 1);
+    Ok(tex_handle)
+  }
+
+  /// Replaces part of the contents of a streaming texture. See
+  /// StreamingTexture::upload_region.
+  pub fn update_streaming_tex(&self, tex: &mut StreamingTexture, region: [u32; 4],
+                              data: &[u8]) -> Result<(), CacheTexError> {
+    let (tex_ix, _) = self.bin_pack_trees.rect_for(tex.handle())
+      .expect("Streaming texture not found in the texture cache");
+    match self.cache_textures[tex_ix] {
+      CacheTexture::Rgba(ref page) => tex.upload_region(page, region, data),
+      _ => unreachable!("Streaming textures are always RGBA8"),
+    }
+  }
+
+  /// Returns true if a texture of the given size should be given its own
+  /// dedicated cache texture.
+  fn is_dedicated_size(&self, w: u32, h: u32) -> bool {
+    let (t_w, t_h) = self.dedicated_tex_threshold;
+    (t_w > 0 || t_h > 0) && (w > t_w || h > t_h)
+  }
+}
+
+/// Decode an image from the given bytes. Grayscale images are kept
+/// grayscale. Paletted images are expanded to RGBA, with the alpha taken from
+/// their transparency chunk if they have one.
+#[cfg(feature = "image")]
+fn decode(buf: &[u8]) -> Result<CacheImage, CacheTexError> {
+  use image::{DynamicImage, GenericImage};
+  let img = try!(image::load_from_memory(buf).map_err(|e| CacheTexError::ImageError(e)));
+  let (w, h) = img.dimensions();
+  let (format, data) = match img {
+    DynamicImage::ImageLuma8(img) => (PixelFormat::Gray, img.into_raw()),
+    DynamicImage::ImageLumaA8(img) => (PixelFormat::GrayAlpha, img.into_raw()),
+    img => (PixelFormat::Rgba, img.to_rgba().into_raw()),
+  };
+  Ok(CacheImage { w: w, h: h, format: format, data: data })
+}
+
+/// Read and decode the image file at the given path.
+#[cfg(feature = "image")]
+fn decode_file(path: &Path) -> Result<CacheImage, CacheTexError> {
+  use std::fs::File;
+  use std::io::Read;
+  let mut buf = Vec::new();
+  try!(File::open(path)
+       .and_then(|mut f| f.read_to_end(&mut buf))
+       .map_err(|e| CacheTexError::IoError(e)));
+  decode(&buf)
+}
+
+/// Create an empty (transparent, or black for Gray) cache texture with the
+/// given size, for storing textures of the given format.
+fn create_cache_texture<F: glium::backend::Facade>(
+  display: &F, size: (u32, u32), 
+  format: PixelFormat) -> Result<CacheTexture, glium::texture::TextureCreationError> {
+  use glium::texture::{MipmapsOption, UncompressedFloatFormat};
+  let data_len = size.0 as usize * size.1 as usize;
+  if format == PixelFormat::Rgba {
+    let mut data = Vec::with_capacity(data_len*4);
+    data.resize(data_len*4, 0.0);
+    return SrgbTexture2d::new(display, RawImage2d {
+      data: Cow::Owned(data),
+      width: size.0,
+      height: size.1,
+      format: glium::texture::ClientFormat::F32F32F32F32,
+    }).map(CacheTexture::Rgba);
+  }
+  let gl_format = match format {
+    PixelFormat::Gray => UncompressedFloatFormat::U8,
+    _ => UncompressedFloatFormat::U8U8,
+  };
+  Texture2d::with_format(display, RawImage2d {
+    data: Cow::Owned(vec![0u8; data_len * format.channels()]),
+    width: size.0,
+    height: size.1,
+    format: client_format(format),
+  }, gl_format, MipmapsOption::NoMipmap).map(|tex| CacheTexture::Gray(tex, format))
+}
+
+/// The glium client format for uploading pixel data of the given format.
+fn client_format(format: PixelFormat) -> glium::texture::ClientFormat {
+  use glium::texture::ClientFormat;
+  match format {
+    PixelFormat::Gray => ClientFormat::U8,
+    PixelFormat::GrayAlpha => ClientFormat::U8U8,
+    PixelFormat::Rgba => ClientFormat::U8U8U8U8,
+  }
 }
 
 impl TexCache for GliumTexCache {
+  #[cfg(feature = "image")]
   fn cache_tex<F: AsRef<Path>, Facade: glium::backend::Facade>(
     &mut self, display: &Facade, 
     filepaths: &[F]) -> Vec<Result<TexHandle, CacheTexError>> {
-    use std::fs::File;
-    use std::io::Read;
-    let mut result = Vec::with_capacity(filepaths.len());
-    let mut bufs = Vec::with_capacity(filepaths.len());
-    bufs.resize(filepaths.len(), Vec::new());
+    let color_key = self.color_key;
+    self.cache_files(display, filepaths, color_key)
+  }
 
-    // Load all the textures given.
-    for (ii, f) in filepaths.iter().enumerate() {
-      // Try open the file
-      let file = File::open(f);
-      if file.is_err() {
-        result.push(Err(CacheTexError::IoError(file.err().unwrap())));
-        continue;
-      }
-      let mut file = file.unwrap();
+  #[cfg(feature = "image")]
+  fn cache_tex_color_keyed<F: AsRef<Path>, Facade: glium::backend::Facade>(
+    &mut self, display: &Facade, filepaths: &[F], 
+    key: [u8; 3]) -> Vec<Result<TexHandle, CacheTexError>> {
+    self.cache_files(display, filepaths, Some(key))
+  }
 
-      // Read all the file data
-      let mut buf = Vec::new();
-      let read_res = file.read_to_end(&mut buf);
-      if read_res.is_err() {
-        result.push(Err(CacheTexError::IoError(read_res.err().unwrap())));
-        continue;
-      }
-      bufs.insert(ii, buf);
-      result.push(Ok(())); 
+  #[cfg(feature = "image")]
+  fn cache_tex_parallel<F, Facade, P>(
+    &mut self, display: &Facade, filepaths: &[F], 
+    mut progress: P) -> Vec<Result<TexHandle, CacheTexError>> 
+    where F: AsRef<Path>, Facade: glium::backend::Facade, P: FnMut(usize, usize) {
+    use std::cmp::min;
+    use std::io;
+    use std::path::PathBuf;
+    use std::sync::{mpsc, Mutex};
+    use std::thread;
+
+    let total = filepaths.len();
+    let jobs : Vec<(usize, PathBuf)> = filepaths.iter()
+      .map(|f| f.as_ref().to_path_buf()).enumerate().collect();
+    let jobs = Arc::new(Mutex::new(jobs));
+
+    // Spawn the workers. Each one takes files off the job list until it's
+    // empty, sending the decoded image back along with its index.
+    let (tx, rx) = mpsc::channel();
+    for _ in 0..min(DECODE_THREADS, total) {
+      let jobs = jobs.clone();
+      let tx = tx.clone();
+      thread::spawn(move || loop {
+        let job = jobs.lock().unwrap().pop();
+        match job {
+          Some((ii, path)) => if tx.send((ii, decode_file(&path))).is_err() { return; },
+          None => return,
+        }
+      });
     }
-
-    let mut result_slices = Vec::with_capacity(filepaths.len());
-    for (ii, r) in result.into_iter().enumerate() {
-      if r.is_ok() { result_slices.push(Ok(bufs[ii].as_slice())); }
-      else { result_slices.push(Err(r.unwrap_err())); }
+    // Drop our sender, so the receiver hangs up once all the workers are done.
+    drop(tx);
+
+    let mut images : Vec<Option<Result<CacheImage, CacheTexError>>> = 
+      (0..total).map(|_| None).collect();
+    let mut done = 0;
+    for (ii, img) in rx {
+      images[ii] = Some(img);
+      done += 1;
+      progress(done, total);
     }
 
-    // Need to map the owned data into slices now.
-    self.cache_tex_internal(display, result_slices)
+    // Any images which are missing were being decoded by a worker which
+    // panicked.
+    let images = images.into_iter().map(|img| img.unwrap_or_else(|| {
+      Err(CacheTexError::IoError(io::Error::new(
+            io::ErrorKind::Other, "Texture decoding thread panicked.")))
+    })).collect();
+    let color_key = self.color_key;
+    self.cache_images(display, images, color_key)
   }
 
   /// This must be called on the main thread, with the GL context as it may
   /// create textures (this is enforced by the need to pass in the
   /// glium::Display).
+  #[cfg(feature = "image")]
   fn cache_tex_from_bytes<F: glium::backend::Facade>(
     &mut self, display: &F, 
     bytes: &[&[u8]]) -> Vec<Result<TexHandle, CacheTexError>> {
     let vec : Vec<Result<&[u8], CacheTexError>> 
       = bytes.iter().map(|buf| Ok(*buf)).collect();
-    self.cache_tex_internal(display, vec)
+    let color_key = self.color_key;
+    self.cache_tex_internal(display, vec, color_key)
   }
 
-#[allow(unused_variables)]
+  #[cfg(feature = "image")]
+  fn cache_tex_from_bytes_color_keyed<F: glium::backend::Facade>(
+    &mut self, display: &F, bytes: &[&[u8]], 
+    key: [u8; 3]) -> Vec<Result<TexHandle, CacheTexError>> {
+    let vec = bytes.iter().map(|buf| Ok(*buf)).collect();
+    self.cache_tex_internal(display, vec, Some(key))
+  }
+
+  fn cache_tex_from_rgba<F: glium::backend::Facade>(
+    &mut self, display: &F, 
+    images: &[(u32, u32, &[u8])]) -> Vec<Result<TexHandle, CacheTexError>> {
+    let images = images.iter().map(|&(w, h, data)| {
+      RgbaBuffer::new(w, h, data.to_vec())
+        .map(CacheImage::from)
+        .ok_or(CacheTexError::InvalidRgbaData)
+    }).collect();
+    let color_key = self.color_key;
+    self.cache_images(display, images, color_key)
+  }
+
+  /// Atlas space is reused by textures cached afterwards. Dedicated cache
+  /// textures are kept on the GPU, and reused for the next dedicated texture
+  /// of the same size and format.
   fn free_tex(&mut self, tex: &[TexHandle]) {
-    unimplemented!();
+    let bin_pack_trees = Arc::get_mut(&mut self.bin_pack_trees)
+      .expect("Failed to acquire mutable reference when freeing texture. Is the texture cache in
+              use?");
+    for &t in tex {
+      match bin_pack_trees.iter_mut().position(|page| page.free(t)) {
+        Some(ii) => trace!("Freed texture {:?} from cache texture {}", t, ii),
+        None => continue,
+      }
+      self.names.remove(&t);
+    }
   }
 
-  fn get_tex_with_ix(&self, ix: usize) -> Option<&SrgbTexture2d> {
+  fn get_tex_with_ix(&self, ix: usize) -> Option<&CacheTexture> {
     if self.cache_textures.len() <= ix { None }
     else { Some(&self.cache_textures[ix]) }
   }
 
+  fn read_tex(&self, tex: TexHandle) -> Option<RgbaBuffer> {
+    let (tex_ix, rect) = match self.rect_for(tex) {
+      Some(r) => r,
+      None => return None,
+    };
+    let page = &self.cache_textures[tex_ix];
+    let (page_w, page_h) = page.dimensions();
+    let x = (page_w as f32 * rect[0]).round() as usize;
+    let y = (page_h as f32 * rect[1]).round() as usize;
+    let w = (page_w as f32 * rect[2]).round() as usize;
+    let h = (page_h as f32 * rect[3]).round() as usize;
+
+    // Rows are read bottom first, and textures are uploaded upside down, so
+    // the texture's rows are read top first. Grayscale pages are read as
+    // (grey, alpha or 0, 0, 1), so expand them to RGBA.
+    let format = page.format();
+    let pixels: Vec<Vec<(u8, u8, u8, u8)>> = match *page {
+      CacheTexture::Rgba(ref tex) => tex.read(),
+      CacheTexture::Gray(ref tex, _) | CacheTexture::Hdr(ref tex) => tex.read(),
+    };
+    let mut data = Vec::with_capacity(w * h * 4);
+    for row in pixels[y..y + h].iter().rev() {
+      for &(r, g, b, a) in &row[x..x + w] {
+        match format {
+          PixelFormat::Rgba => data.extend_from_slice(&[r, g, b, a]),
+          PixelFormat::GrayAlpha => data.extend_from_slice(&[r, r, r, g]),
+          PixelFormat::Gray => data.extend_from_slice(&[r, r, r, 255]),
+        }
+      }
+    }
+    let img = RgbaBuffer::new(w as u32, h as u32, data)
+      .expect("Cache texture read back at the wrong size");
+    // Rotated textures are stored rotated 90 degrees clockwise, so rotate
+    // them the rest of the way round.
+    if self.is_tex_rotated(tex) {
+      Some(img.rotate90().rotate90().rotate90())
+    } else {
+      Some(img)
+    }
+  }
+
+  fn set_grayscale_pages(&mut self, gray_pages: bool) {
+    self.gray_pages = gray_pages;
+  }
+
+  fn set_color_key(&mut self, key: Option<[u8; 3]>) {
+    self.color_key = key;
+  }
+
+  fn set_trim_transparent(&mut self, trim: bool) {
+    self.trim_transparent = trim;
+  }
+
+  fn set_packing_group(&mut self, group: Option<&str>) {
+    self.packing_group = group.map(|g| g.to_owned());
+  }
+
   fn set_max_cache_textures(&mut self, max_cache_textures: usize) {
     self.max_cache_textures = max_cache_textures;
   }
@@ -225,6 +775,14 @@ impl TexCache for GliumTexCache {
   fn set_cache_texture_size(&mut self, w: u32, h: u32) {
     self.cache_texture_size = (w, h);
   }
+
+  fn set_dedicated_tex_threshold(&mut self, w: u32, h: u32) {
+    self.dedicated_tex_threshold = (w, h);
+  }
+
+  fn set_allow_tex_rotation(&mut self, allow: bool) {
+    self.allow_rotation = allow;
+  }
 }
 
 impl TexHandleLookup for GliumTexCache {
@@ -233,11 +791,19 @@ impl TexHandleLookup for GliumTexCache {
   }
 
   fn rect_for(&self, tex: TexHandle) -> Option<(usize, [f32; 4])> {
-    for (ii, t) in self.bin_pack_trees.iter().enumerate() {
-      let res = t.rect_for(tex);
-      if res.is_some() { return Some((ii, res.unwrap())); };
-    }
-    return None;
+    self.bin_pack_trees.rect_for(tex)
+  }
+
+  fn is_page_dedicated(&self, tex_ix: usize) -> bool {
+    self.bin_pack_trees.is_page_dedicated(tex_ix)
+  }
+
+  fn is_tex_rotated(&self, tex: TexHandle) -> bool {
+    self.bin_pack_trees.is_tex_rotated(tex)
+  }
+
+  fn trim_for(&self, tex: TexHandle) -> [f32; 4] {
+    self.bin_pack_trees.trim_for(tex)
   }
 }
 
@@ -0,0 +1,235 @@
+//! A module containing a minimal RGBA image buffer, used to hold decoded
+//! textures before they're uploaded to the cache, and images read back from
+//! the GPU. This avoids depending on the image crate's buffer types, so
+//! textures can be cached without it. Grayscale textures are held in a
+//! CacheImage instead, so they can be stored with fewer channels.
+
+#[cfg(feature = "image")]
+use image;
+#[cfg(feature = "image")]
+use std::io;
+#[cfg(feature = "image")]
+use std::path::Path;
+
+/// A decoded RGBA8 image.
+pub struct RgbaBuffer {
+  /// The width of the image in pixels.
+  pub w: u32,
+  /// The height of the image in pixels.
+  pub h: u32,
+  /// Rows of pixels from top to bottom, 4 bytes per pixel.
+  pub data: Vec<u8>,
+}
+
+impl RgbaBuffer {
+  /// Create a new buffer from raw data. Returns None if the length of the
+  /// data doesn't match the given dimensions.
+  pub fn new(w: u32, h: u32, data: Vec<u8>) -> Option<RgbaBuffer> {
+    if data.len() != w as usize * h as usize * 4 { return None; }
+    Some(RgbaBuffer { w: w, h: h, data: data })
+  }
+
+  /// Returns this image rotated 90 degrees clockwise.
+  pub fn rotate90(&self) -> RgbaBuffer {
+    RgbaBuffer { w: self.h, h: self.w, data: rotate90(self.w, self.h, 4, &self.data) }
+  }
+
+  /// Encodes this image as a PNG file.
+  /// # Errors
+  /// Returns an error if the file couldn't be written.
+  #[cfg(feature = "image")]
+  pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+    image::save_buffer(path, &self.data, self.w, self.h, image::RGBA(8))
+  }
+}
+
+/// The channels a texture is stored with in the cache.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PixelFormat {
+  /// One channel, a grey level. Drawn opaque.
+  Gray,
+  /// Two channels, a grey level and alpha.
+  GrayAlpha,
+  /// Four channels, red, green, blue and alpha.
+  Rgba,
+}
+
+impl PixelFormat {
+  /// The number of bytes per pixel.
+  pub fn channels(&self) -> usize {
+    match *self {
+      PixelFormat::Gray => 1,
+      PixelFormat::GrayAlpha => 2,
+      PixelFormat::Rgba => 4,
+    }
+  }
+}
+
+/// A decoded image in any PixelFormat, ready to be uploaded to the cache.
+pub struct CacheImage {
+  pub w: u32,
+  pub h: u32,
+  pub format: PixelFormat,
+  /// Rows of pixels from top to bottom, format.channels() bytes per pixel.
+  pub data: Vec<u8>,
+}
+
+impl CacheImage {
+  /// Returns this image rotated 90 degrees clockwise.
+  pub fn rotate90(&self) -> CacheImage {
+    CacheImage {
+      w: self.h,
+      h: self.w,
+      format: self.format,
+      data: rotate90(self.w, self.h, self.format.channels(), &self.data),
+    }
+  }
+
+  /// Expands this image to RGBA, for storing in an RGBA cache page.
+  pub fn to_rgba(self) -> CacheImage {
+    let data = match self.format {
+      PixelFormat::Rgba => return self,
+      PixelFormat::Gray => self.data.iter().flat_map(|&g| vec![g, g, g, 255]).collect(),
+      PixelFormat::GrayAlpha => self.data.chunks(2)
+        .flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect(),
+    };
+    CacheImage { w: self.w, h: self.h, format: PixelFormat::Rgba, data: data }
+  }
+
+  /// Expands this image to RGBA, with every pixel of the colour `key` made
+  /// transparent. Keyed pixels are made transparent black rather than only
+  /// having their alpha zeroed, so filtering doesn't blend the key colour
+  /// into the edges of the image.
+  pub fn color_keyed(self, key: [u8; 3]) -> CacheImage {
+    let mut img = self.to_rgba();
+    for p in img.data.chunks_mut(4) {
+      if p[..3] == key[..] {
+        p.copy_from_slice(&[0, 0, 0, 0]);
+      }
+    }
+    img
+  }
+
+  /// The smallest [x, y, w, h] rect of pixels containing every pixel which
+  /// isn't fully transparent. Images without alpha are entirely opaque.
+  /// Returns None if the whole image is transparent.
+  pub fn opaque_bounds(&self) -> Option<[u32; 4]> {
+    let channels = self.format.channels();
+    let alpha = match self.format {
+      PixelFormat::Gray => return if self.w > 0 && self.h > 0 {
+        Some([0, 0, self.w, self.h])
+      } else {
+        None
+      },
+      PixelFormat::GrayAlpha => 1,
+      PixelFormat::Rgba => 3,
+    };
+    let (mut x0, mut y0, mut x1, mut y1) = (self.w, self.h, 0, 0);
+    for (ii, p) in self.data.chunks(channels).enumerate() {
+      if p[alpha] == 0 { continue; }
+      let (x, y) = (ii as u32 % self.w, ii as u32 / self.w);
+      x0 = x0.min(x);
+      y0 = y0.min(y);
+      x1 = x1.max(x + 1);
+      y1 = y1.max(y + 1);
+    }
+    if x1 <= x0 || y1 <= y0 { return None; }
+    Some([x0, y0, x1 - x0, y1 - y0])
+  }
+
+  /// Returns the [x, y, w, h] rect of this image. The rect must be inside
+  /// the image.
+  pub fn cropped(&self, rect: [u32; 4]) -> CacheImage {
+    let channels = self.format.channels();
+    let (x, y, w, h) = (rect[0] as usize, rect[1] as usize, rect[2] as usize, rect[3] as usize);
+    let row_len = self.w as usize * channels;
+    let mut data = Vec::with_capacity(w * h * channels);
+    for row in self.data.chunks(row_len).skip(y).take(h) {
+      data.extend_from_slice(&row[x * channels..(x + w) * channels]);
+    }
+    CacheImage { w: rect[2], h: rect[3], format: self.format, data: data }
+  }
+
+  /// The image's rows from bottom to top, which is the order cache textures
+  /// store them in.
+  pub fn rows_reversed(&self) -> Vec<u8> {
+    let row_len = self.w as usize * self.format.channels();
+    if row_len == 0 { return Vec::new(); }
+    self.data.chunks(row_len).rev().flat_map(|row| row.iter().cloned()).collect()
+  }
+}
+
+impl From<RgbaBuffer> for CacheImage {
+  fn from(img: RgbaBuffer) -> CacheImage {
+    CacheImage { w: img.w, h: img.h, format: PixelFormat::Rgba, data: img.data }
+  }
+}
+
+/// Rotates image data with `channels` bytes per pixel 90 degrees clockwise.
+fn rotate90(w: u32, h: u32, channels: usize, src: &[u8]) -> Vec<u8> {
+  let (w, h) = (w as usize, h as usize);
+  let mut data = vec![0u8; src.len()];
+  for y in 0..h {
+    for x in 0..w {
+      // Pixel (x, y) moves to (h - 1 - y, x) in the rotated image, which is
+      // h pixels wide.
+      let s = (y * w + x) * channels;
+      let d = (x * h + (h - 1 - y)) * channels;
+      data[d..d + channels].copy_from_slice(&src[s..s + channels]);
+    }
+  }
+  data
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rotate90_clockwise() {
+    // A 2x1 image, red then green.
+    let img = RgbaBuffer::new(2, 1, vec![255, 0, 0, 255, 0, 255, 0, 255]).unwrap();
+    let rotated = img.rotate90();
+    assert_eq!((rotated.w, rotated.h), (1, 2));
+    // Red is now at the top, green at the bottom.
+    assert_eq!(rotated.data, vec![255, 0, 0, 255, 0, 255, 0, 255]);
+    assert!(RgbaBuffer::new(2, 2, vec![0; 4]).is_none());
+  }
+
+  #[test]
+  fn gray_expands_to_rgba() {
+    let img = CacheImage { w: 2, h: 1, format: PixelFormat::GrayAlpha, data: vec![10, 20, 30, 40] };
+    let rotated = img.rotate90();
+    assert_eq!((rotated.w, rotated.h), (1, 2));
+    assert_eq!(rotated.data, vec![10, 20, 30, 40]);
+    let rgba = rotated.to_rgba();
+    assert_eq!(rgba.format, PixelFormat::Rgba);
+    assert_eq!(rgba.data, vec![10, 10, 10, 20, 30, 30, 30, 40]);
+  }
+
+  #[test]
+  fn color_key_is_made_transparent() {
+    let img = CacheImage {
+      w: 2, h: 1, format: PixelFormat::Rgba,
+      data: vec![255, 0, 255, 255, 255, 0, 254, 255],
+    };
+    assert_eq!(img.color_keyed([255, 0, 255]).data, vec![0, 0, 0, 0, 255, 0, 254, 255]);
+    let gray = CacheImage { w: 2, h: 1, format: PixelFormat::Gray, data: vec![0, 10] };
+    let keyed = gray.color_keyed([0, 0, 0]);
+    assert_eq!(keyed.format, PixelFormat::Rgba);
+    assert_eq!(keyed.data, vec![0, 0, 0, 0, 10, 10, 10, 255]);
+  }
+
+  #[test]
+  fn transparent_border_is_cropped() {
+    // A 3x3 image, with only the middle right pixel visible.
+    let mut data = vec![0; 3 * 3 * 2];
+    data[(1 * 3 + 2) * 2..(1 * 3 + 2) * 2 + 2].copy_from_slice(&[7, 255]);
+    let img = CacheImage { w: 3, h: 3, format: PixelFormat::GrayAlpha, data: data };
+    let bounds = img.opaque_bounds().unwrap();
+    assert_eq!(bounds, [2, 1, 1, 1]);
+    assert_eq!(img.cropped(bounds).data, vec![7, 255]);
+    let clear = CacheImage { w: 1, h: 1, format: PixelFormat::Rgba, data: vec![9, 9, 9, 0] };
+    assert!(clear.opaque_bounds().is_none());
+  }
+}
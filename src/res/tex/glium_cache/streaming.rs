@@ -0,0 +1,81 @@
+//! A module containing StreamingTexture, a texture whose contents are
+//! replaced every frame (e.g. video frames).
+
+use glium;
+use glium::texture::pixel_buffer::PixelBuffer;
+use glium::texture::srgb_texture2d::SrgbTexture2d;
+use res::tex::{TexHandle, CacheTexError};
+
+/// A fixed-size texture in the texture cache whose contents can be replaced
+/// quickly, for video playback or other per-frame CPU-generated images. It
+/// has a dedicated cache texture, so updating it never re-runs the atlas
+/// packer. Draw it with its handle() like any other texture.
+///
+/// Frames are uploaded through a pixel buffer object, so the copy into the
+/// texture happens asynchronously on the GPU.
+pub struct StreamingTexture {
+  handle: TexHandle,
+  size: (u32, u32),
+  pbo: PixelBuffer<(u8, u8, u8, u8)>,
+  /// Scratch space for reordering frames before they're written to the pixel
+  /// buffer, kept to avoid allocating every frame.
+  scratch: Vec<(u8, u8, u8, u8)>,
+}
+
+impl StreamingTexture {
+  pub fn new<F: glium::backend::Facade>(display: &F, handle: TexHandle,
+                                        size: (u32, u32)) -> StreamingTexture {
+    let len = size.0 as usize * size.1 as usize;
+    StreamingTexture {
+      handle: handle,
+      size: size,
+      pbo: PixelBuffer::new_empty(display, len),
+      scratch: Vec::with_capacity(len),
+    }
+  }
+
+  /// The handle to draw this texture with.
+  pub fn handle(&self) -> TexHandle { self.handle }
+
+  /// The size of this texture in pixels.
+  pub fn size(&self) -> (u32, u32) { self.size }
+
+  /// Uploads a frame of RGBA data (rows from top to bottom, 4 bytes per
+  /// pixel) into `tex`, which must be this texture's cache texture.
+  /// # Errors
+  /// Returns CacheTexError::InvalidRgbaData if the frame is the wrong size.
+  pub fn upload(&mut self, tex: &SrgbTexture2d, frame: &[u8]) -> Result<(), CacheTexError> {
+    let (w, h) = self.size;
+    self.upload_region(tex, [0, 0, w, h], frame)
+  }
+
+  /// Like upload(), but only replaces part of the texture.
+  /// # Params
+  /// * `region` - The region to replace, in pixels - X, Y, W, H, with 0, 0 at
+  ///              the top left. This must be inside the texture.
+  /// * `data` - The region's new RGBA data.
+  /// # Errors
+  /// Returns CacheTexError::InvalidRgbaData if the data is the wrong size for
+  /// the region.
+  pub fn upload_region(&mut self, tex: &SrgbTexture2d, region: [u32; 4],
+                       data: &[u8]) -> Result<(), CacheTexError> {
+    let (x, y, w, h) = (region[0], region[1], region[2], region[3]);
+    debug_assert!(x + w <= self.size.0 && y + h <= self.size.1,
+                  "Streaming texture region {:?} out of bounds", region);
+    if data.len() != w as usize * h as usize * 4 {
+      return Err(CacheTexError::InvalidRgbaData);
+    }
+    if w == 0 || h == 0 { return Ok(()); }
+    // Cache textures are stored upside down (see
+    // RawImage2d::from_raw_rgba_reversed), so reverse the rows.
+    self.scratch.clear();
+    for row in data.chunks(w as usize * 4).rev() {
+      self.scratch.extend(row.chunks(4).map(|p| (p[0], p[1], p[2], p[3])));
+    }
+    let slice = self.pbo.slice(0..self.scratch.len()).unwrap();
+    slice.write(&self.scratch);
+    let bottom = self.size.1 - (y + h);
+    tex.main_level().raw_upload_from_pixel_buffer(slice, x..x + w, bottom..bottom + h, 0..1);
+    Ok(())
+  }
+}
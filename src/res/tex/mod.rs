@@ -2,14 +2,23 @@ pub mod glium_cache;
 
 use glium;
 use std;
+#[cfg(feature = "image")]
 use image;
 use std::path::Path;
-use glium::texture::srgb_texture2d::SrgbTexture2d;
 
 /// A texture handle. This references a texture loaded into the cache.
-#[derive(PartialOrd, Ord, PartialEq, Eq, Copy, Clone)]
+///
+/// With the serialize feature, handles can be serialized, e.g. in save files.
+/// A handle is only an index though, so a deserialized handle only refers to
+/// the same texture if textures are cached in the same order every run.
+#[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct TexHandle(pub usize);
 
+/// The trim of a texture which wasn't trimmed - see
+/// TexHandleLookup::trim_for().
+pub const UNTRIMMED: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
 /// An error returned when caching a texture.
 #[derive(Debug)]
 pub enum CacheTexError {
@@ -28,10 +37,59 @@ pub enum CacheTexError {
   IoError(std::io::Error),
 
   /// An error occurred creating an image from the bytes read.
+  #[cfg(feature = "image")]
   ImageError(image::ImageError),
 
+  /// The length of the raw RGBA data given doesn't match the dimensions
+  /// given (it should be 4 * width * height bytes).
+  InvalidRgbaData,
+
   /// The cache tried to create a texture which was too large to be supported.
   DimensionsNotSupported,
+
+  /// Creating a cache texture on the GPU failed for a reason other than its
+  /// size.
+  TextureCreation(glium::texture::TextureCreationError),
+}
+
+impl std::fmt::Display for CacheTexError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+    use std::error::Error;
+    match *self {
+      CacheTexError::IoError(ref e) => write!(f, "{}", e),
+      #[cfg(feature = "image")]
+      CacheTexError::ImageError(ref e) => write!(f, "{}", e),
+      CacheTexError::TextureCreation(ref e) => write!(f, "{}", e),
+      _ => write!(f, "{}", self.description()),
+    }
+  }
+}
+
+impl std::error::Error for CacheTexError {
+  fn description(&self) -> &str {
+    match *self {
+      CacheTexError::CacheTooSmall => "The texture is too big to fit in a cache texture.",
+      CacheTexError::NoSpace => "There is no space left in the cache for this texture.",
+      CacheTexError::IoError(_) => "An IO error occurred when reading the texture file.",
+      #[cfg(feature = "image")]
+      CacheTexError::ImageError(_) => "The texture could not be decoded.",
+      CacheTexError::InvalidRgbaData => 
+        "The length of the RGBA data given does not match its dimensions.",
+      CacheTexError::DimensionsNotSupported => 
+        "The cache texture needed is too large to be supported.",
+      CacheTexError::TextureCreation(_) => "A cache texture could not be created.",
+    }
+  }
+
+  fn source(&self) -> Option<&(std::error::Error + 'static)> {
+    match *self {
+      CacheTexError::IoError(ref e) => Some(e),
+      #[cfg(feature = "image")]
+      CacheTexError::ImageError(ref e) => Some(e),
+      CacheTexError::TextureCreation(ref e) => Some(e),
+      _ => None,
+    }
+  }
 }
 
 /// A trait for a GPU texture cache.
@@ -40,25 +98,74 @@ pub trait TexCache : TexHandleLookup {
   /// 
   /// Texture handles are returned in a slice with the indexes corresponding to
   /// the indexes in the slice of texture files given.
+  #[cfg(feature = "image")]
   fn cache_tex<F: AsRef<Path>, Facade: glium::backend::Facade>(
     &mut self, display: &Facade, 
     filepaths: &[F]) -> Vec<Result<TexHandle, CacheTexError>>;
 
+  /// Like cache_tex, but reads and decodes the texture files in parallel on
+  /// worker threads. Only uploading the textures to the GPU happens on the
+  /// calling thread.
+  ///
+  /// `progress` is called on the calling thread every time a texture has been
+  /// decoded, with the number of textures decoded so far and the total
+  /// number of textures - this is useful for loading screens.
+  #[cfg(feature = "image")]
+  fn cache_tex_parallel<F, Facade, P>(
+    &mut self, display: &Facade, filepaths: &[F], 
+    progress: P) -> Vec<Result<TexHandle, CacheTexError>> 
+    where F: AsRef<Path>, Facade: glium::backend::Facade, P: FnMut(usize, usize);
+
   /// A function to cache some textures and return texture handles.
   /// 
   /// Texture handles are returned in a slice with the indexes corresponding to
   /// the indexes in the slice of texture files given.
+  #[cfg(feature = "image")]
   fn cache_tex_from_bytes<F: glium::backend::Facade>(
     &mut self, display: &F, 
     bytes: &[&[u8]]) -> Vec<Result<TexHandle, CacheTexError>>;
 
+  /// Like cache_tex, but makes the colour `key` transparent in these
+  /// textures, whatever set_color_key() is set to. For old sprite sheets
+  /// without an alpha channel, which mark transparency with a colour like
+  /// magenta.
+  #[cfg(feature = "image")]
+  fn cache_tex_color_keyed<F: AsRef<Path>, Facade: glium::backend::Facade>(
+    &mut self, display: &Facade, filepaths: &[F], 
+    key: [u8; 3]) -> Vec<Result<TexHandle, CacheTexError>>;
+
+  /// Like cache_tex_from_bytes, but makes the colour `key` transparent in
+  /// these textures. See cache_tex_color_keyed.
+  #[cfg(feature = "image")]
+  fn cache_tex_from_bytes_color_keyed<F: glium::backend::Facade>(
+    &mut self, display: &F, bytes: &[&[u8]], 
+    key: [u8; 3]) -> Vec<Result<TexHandle, CacheTexError>>;
+
+  /// A function to cache some textures from raw, already decoded, RGBA8 data
+  /// and return texture handles. This doesn't need the "image" feature.
+  ///
+  /// Each texture is given as its width, its height, and then its data -
+  /// rows of pixels from top to bottom, 4 bytes per pixel. Texture handles are
+  /// returned in a slice with the indexes corresponding to the indexes in the
+  /// slice of textures given.
+  fn cache_tex_from_rgba<F: glium::backend::Facade>(
+    &mut self, display: &F, 
+    images: &[(u32, u32, &[u8])]) -> Vec<Result<TexHandle, CacheTexError>>;
+
   /// A function to free a given list of texture from the cache. If a
   /// texture is not cached, it is ignored.
   fn free_tex(&mut self, tex: &[TexHandle]);
 
   /// Gets a reference to the cache texture with the given index. If the
   /// texture is not found, returns None.
-  fn get_tex_with_ix(&self, ix: usize) -> Option<&SrgbTexture2d>;
+  fn get_tex_with_ix(&self, ix: usize) -> Option<&glium_cache::CacheTexture>;
+
+  /// Reads a cached texture back from the GPU, in its original orientation.
+  /// This is slow, as it waits for the GPU - use it for verifying uploads and
+  /// exporting textures, not every frame. If the texture's transparent border
+  /// was trimmed, only the trimmed pixels are returned. If the texture is not
+  /// cached, returns None.
+  fn read_tex(&self, tex: TexHandle) -> Option<glium_cache::RgbaBuffer>;
 
   /// Sets the maximum amount of cache textures to create. 0 means limitless.
   /// If you put a cap on the amount of textures that can be used to cache on
@@ -70,6 +177,51 @@ pub trait TexCache : TexHandleLookup {
   /// some GPUs, but smaller sizes will result in more draw calls for
   /// applications with lots of textures.
   fn set_cache_texture_size(&mut self, w: u32, h: u32);
+
+  /// Sets the size above which a texture is given its own dedicated cache
+  /// texture, of exactly the texture's size, rather than being packed into an
+  /// atlas page. A texture is dedicated if either its width is greater than
+  /// `w` or its height is greater than `h`. Dedicated textures may also be
+  /// larger than the cache texture size. 0, 0 disables dedicated textures.
+  fn set_dedicated_tex_threshold(&mut self, w: u32, h: u32);
+
+  /// Sets whether textures may be rotated 90 degrees when packing them into
+  /// the cache, if they don't fit in their normal orientation. This improves
+  /// cache occupancy for long, thin textures. Rotation is compensated for
+  /// when drawing, see TexHandleLookup::is_tex_rotated. Defaults to false.
+  fn set_allow_tex_rotation(&mut self, allow: bool);
+
+  /// Sets whether grayscale textures (with or without alpha) are stored in
+  /// their own cache pages with one or two channels, rather than being
+  /// expanded to RGBA. This uses a quarter or half the VRAM for mask-style
+  /// textures, but grayscale and colour textures can't share a page, so
+  /// drawing both may take more draw calls. Only affects textures cached
+  /// afterwards. Defaults to false.
+  fn set_grayscale_pages(&mut self, gray_pages: bool);
+
+  /// Sets a colour which is made transparent in every texture cached
+  /// afterwards, or None for no colour key. This is a convenience for when
+  /// all of an application's textures use the same key - to key particular
+  /// textures, use cache_tex_color_keyed or cache_tex_from_bytes_color_keyed
+  /// instead. Keyed textures are stored as RGBA, even with grayscale pages.
+  /// Defaults to None.
+  fn set_color_key(&mut self, key: Option<[u8; 3]>);
+
+  /// Sets whether fully transparent borders are trimmed from textures cached
+  /// afterwards, so only the visible part of each texture takes up cache
+  /// space. This helps a lot with sprites drawn on generous canvases. Trimming
+  /// is compensated for when drawing, see TexHandleLookup::trim_for. Fully
+  /// transparent textures are left as they are. Defaults to false.
+  fn set_trim_transparent(&mut self, trim: bool);
+
+  /// Sets the packing group textures cached afterwards belong to, or None
+  /// for no group. Textures are packed onto pages owned by their own group
+  /// where possible, and new pages are owned by the group of the texture
+  /// which created them, so related textures (like all of a UI's icons) end
+  /// up on the same pages and can be drawn in fewer batches. Textures are
+  /// only packed onto other groups' pages once the maximum number of cache
+  /// textures has been reached. Defaults to None.
+  fn set_packing_group(&mut self, group: Option<&str>);
 }
 
 /// A trait which defines behaviour for the looking up of textures given a texture handle. It's
@@ -93,4 +245,23 @@ pub trait TexHandleLookup {
   /// the returned array.
   fn rect_for(&self, tex: TexHandle) -> Option<(usize, [f32; 4])>;
 
+  /// Returns true if the cache texture with the given index is dedicated to a
+  /// single texture (see TexCache::set_dedicated_tex_threshold). Dedicated
+  /// textures fill the whole cache texture, so can be sampled with REPEAT
+  /// wrapping.
+  fn is_page_dedicated(&self, tex_ix: usize) -> bool;
+
+  /// Returns true if the given texture was rotated 90 degrees clockwise when
+  /// packed into the cache. The rect returned by rect_for() is the rotated
+  /// rect, so UVs need to be rotated to match when drawing. Returns false if
+  /// the texture isn't cached.
+  fn is_tex_rotated(&self, tex: TexHandle) -> bool;
+
+  /// Returns the part of the given texture which is stored in the cache, as
+  /// [x0, y0, x1, y1] fractions of the original texture, if its transparent
+  /// border was trimmed (see TexCache::set_trim_transparent). The rect
+  /// returned by rect_for() only covers this part, so quads need cropping to
+  /// match when drawing. Returns UNTRIMMED if the texture wasn't trimmed or
+  /// isn't cached.
+  fn trim_for(&self, tex: TexHandle) -> [f32; 4];
 }
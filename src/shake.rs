@@ -0,0 +1,76 @@
+//! Screen shake, a random offset applied to the main layer which decays over
+//! time. See QGFX::shake().
+
+/// A screen shake in progress.
+pub struct Shake {
+  /// The largest offset, in pixels, at the start of the shake.
+  amplitude: f32,
+  secs: f32,
+  /// The time since the shake started, in seconds.
+  elapsed: f32,
+  /// The state of the xorshift generator the offsets are picked with.
+  rng: u32,
+}
+
+impl Shake {
+  /// # Params
+  /// * `seed` - Seeds the random offsets. 0 picks a default seed.
+  pub fn new(amplitude: f32, secs: f32, seed: u32) -> Shake {
+    // Xorshift never leaves 0.
+    let rng = if seed == 0 { 0x9e37_79b9 } else { seed };
+    Shake { amplitude: amplitude, secs: secs, elapsed: 0.0, rng: rng }
+  }
+
+  /// Advances the shake by `dt` seconds, and returns the offset for the new
+  /// frame, shrinking linearly from the amplitude to 0 over the shake's
+  /// duration.
+  /// # Returns
+  /// None once the shake is over.
+  pub fn advance(&mut self, dt: f32) -> Option<[f32; 2]> {
+    self.elapsed += dt;
+    let t = self.elapsed;
+    self.offset_at(t)
+  }
+
+  fn offset_at(&mut self, t: f32) -> Option<[f32; 2]> {
+    if t >= self.secs {
+      return None;
+    }
+    let amplitude = self.amplitude * (1.0 - t / self.secs);
+    let x = self.next_unit();
+    let y = self.next_unit();
+    Some([x * amplitude, y * amplitude])
+  }
+
+  /// A pseudo-random number from -1 to 1.
+  fn next_unit(&mut self) -> f32 {
+    self.rng ^= self.rng << 13;
+    self.rng ^= self.rng >> 17;
+    self.rng ^= self.rng << 5;
+    (self.rng as f32 / ::std::u32::MAX as f32) * 2.0 - 1.0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn shake_decays_then_ends() {
+    let mut shake = Shake::new(10.0, 1.0, 0);
+    let start = shake.offset_at(0.0).unwrap();
+    assert!(start[0].abs() <= 10.0 && start[1].abs() <= 10.0);
+    let late = shake.offset_at(0.9).unwrap();
+    assert!(late[0].abs() <= 1.0 + 1e-4 && late[1].abs() <= 1.0 + 1e-4);
+    assert!(shake.offset_at(1.0).is_none());
+  }
+
+  #[test]
+  fn seeded_shakes_repeat() {
+    let mut a = Shake::new(10.0, 1.0, 42);
+    let mut b = Shake::new(10.0, 1.0, 42);
+    for _ in 0..10 {
+      assert_eq!(a.advance(0.05), b.advance(0.05));
+    }
+  }
+}
@@ -0,0 +1,354 @@
+//! Tweening, for animating the positions, sizes and colours of the things the
+//! RendererController draws. Positions are `[f32; 2]` and colours `[f32; 4]`,
+//! matching the drawing functions:
+//!
+//! ```ignore
+//! let mut fade = Tween::new([1.0, 1.0, 1.0, 1.0], [1.0, 1.0, 1.0, 0.0], 0.5, Ease::QuadOut);
+//! // Every frame:
+//! fade.update(dt);
+//! controller.rect(&aabb, &fade.value());
+//! ```
+//!
+//! Tweens, and anything else implementing Animation, can be chained with
+//! Sequence and run together with Parallel.
+
+/// An easing function, mapping linear progress from 0 to 1 onto eased
+/// progress. Eased progress starts at 0 and ends at 1, but may overshoot in
+/// between.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Ease {
+  Linear,
+  QuadIn,
+  QuadOut,
+  QuadInOut,
+  CubicIn,
+  CubicOut,
+  CubicInOut,
+  SineInOut,
+  /// Overshoots the end slightly, then settles back.
+  BackOut,
+  /// Overshoots the end and oscillates around it.
+  ElasticOut,
+  /// Bounces off the end, like a dropped ball.
+  BounceOut,
+}
+
+impl Ease {
+  /// Eases `t`, which is clamped to 0 to 1.
+  pub fn apply(&self, t: f32) -> f32 {
+    use std::f32::consts::PI;
+    let t = t.max(0.0).min(1.0);
+    match *self {
+      Ease::Linear => t,
+      Ease::QuadIn => t * t,
+      Ease::QuadOut => t * (2.0 - t),
+      Ease::QuadInOut => if t < 0.5 {
+        2.0 * t * t
+      } else {
+        1.0 - 2.0 * (1.0 - t) * (1.0 - t)
+      },
+      Ease::CubicIn => t * t * t,
+      Ease::CubicOut => 1.0 - (1.0 - t).powi(3),
+      Ease::CubicInOut => if t < 0.5 {
+        4.0 * t * t * t
+      } else {
+        1.0 - 4.0 * (1.0 - t).powi(3)
+      },
+      Ease::SineInOut => 0.5 - 0.5 * (PI * t).cos(),
+      Ease::BackOut => {
+        let s = 1.70158;
+        let t = t - 1.0;
+        1.0 + t * t * ((s + 1.0) * t + s)
+      }
+      Ease::ElasticOut => if t == 0.0 || t == 1.0 {
+        t
+      } else {
+        2.0f32.powf(-10.0 * t) * ((t - 0.075) * 2.0 * PI / 0.3).sin() + 1.0
+      },
+      Ease::BounceOut => {
+        let n = 7.5625;
+        if t < 1.0 / 2.75 {
+          n * t * t
+        } else if t < 2.0 / 2.75 {
+          let t = t - 1.5 / 2.75;
+          n * t * t + 0.75
+        } else if t < 2.5 / 2.75 {
+          let t = t - 2.25 / 2.75;
+          n * t * t + 0.9375
+        } else {
+          let t = t - 2.625 / 2.75;
+          n * t * t + 0.984375
+        }
+      }
+    }
+  }
+}
+
+/// A value which can be interpolated by a Tween.
+pub trait Lerp: Copy {
+  /// Interpolates between `self` (at t = 0) and `to` (at t = 1). `t` may be
+  /// outside of 0 to 1 for overshooting eases.
+  fn lerp(&self, to: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+  fn lerp(&self, to: &f32, t: f32) -> f32 {
+    self + (to - self) * t
+  }
+}
+
+impl Lerp for [f32; 2] {
+  fn lerp(&self, to: &[f32; 2], t: f32) -> [f32; 2] {
+    [self[0].lerp(&to[0], t), self[1].lerp(&to[1], t)]
+  }
+}
+
+/// Colours, or rects (X, Y, W, H).
+impl Lerp for [f32; 4] {
+  fn lerp(&self, to: &[f32; 4], t: f32) -> [f32; 4] {
+    [
+      self[0].lerp(&to[0], t),
+      self[1].lerp(&to[1], t),
+      self[2].lerp(&to[2], t),
+      self[3].lerp(&to[3], t),
+    ]
+  }
+}
+
+/// Something which progresses over time, and finishes.
+pub trait Animation {
+  /// Advances the animation by `dt` seconds.
+  /// # Returns
+  /// The part of `dt` left over after the animation finished, or 0 if it
+  /// hasn't finished. Sequence uses this to carry time over to the next
+  /// animation.
+  fn update(&mut self, dt: f32) -> f32;
+
+  /// True once the animation has reached its end.
+  fn is_finished(&self) -> bool;
+
+  /// Rewinds the animation to its start.
+  fn reset(&mut self);
+}
+
+impl<A: Animation + ?Sized> Animation for Box<A> {
+  fn update(&mut self, dt: f32) -> f32 {
+    (**self).update(dt)
+  }
+
+  fn is_finished(&self) -> bool {
+    (**self).is_finished()
+  }
+
+  fn reset(&mut self) {
+    (**self).reset()
+  }
+}
+
+/// Animates a value from one point to another over a fixed time.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tween<T> {
+  from: T,
+  to: T,
+  secs: f32,
+  elapsed: f32,
+  ease: Ease,
+}
+
+impl<T: Lerp> Tween<T> {
+  /// # Params
+  /// * `from` - The value at the start.
+  /// * `to` - The value at the end.
+  /// * `secs` - How long the tween takes, in seconds.
+  /// * `ease` - How the value moves from `from` to `to`.
+  pub fn new(from: T, to: T, secs: f32, ease: Ease) -> Tween<T> {
+    Tween { from: from, to: to, secs: secs.max(0.0), elapsed: 0.0, ease: ease }
+  }
+
+  /// The current value.
+  pub fn value(&self) -> T {
+    self.from.lerp(&self.to, self.ease.apply(self.progress()))
+  }
+
+  /// The linear progress through the tween, from 0 to 1.
+  pub fn progress(&self) -> f32 {
+    if self.secs == 0.0 {
+      1.0
+    } else {
+      (self.elapsed / self.secs).min(1.0)
+    }
+  }
+
+  /// The value at the end.
+  pub fn end(&self) -> T {
+    self.to
+  }
+}
+
+impl<T: Lerp> Animation for Tween<T> {
+  fn update(&mut self, dt: f32) -> f32 {
+    self.elapsed += dt;
+    let leftover = (self.elapsed - self.secs).max(0.0);
+    self.elapsed = self.elapsed.min(self.secs);
+    leftover
+  }
+
+  fn is_finished(&self) -> bool {
+    self.elapsed >= self.secs
+  }
+
+  fn reset(&mut self) {
+    self.elapsed = 0.0;
+  }
+}
+
+/// Animations which run one after the other.
+pub struct Sequence<A> {
+  items: Vec<A>,
+  /// The index of the running animation. Equals items.len() once finished.
+  current: usize,
+}
+
+impl<A: Animation> Sequence<A> {
+  pub fn new(items: Vec<A>) -> Sequence<A> {
+    let mut seq = Sequence { items: items, current: 0 };
+    seq.skip_finished();
+    seq
+  }
+
+  /// The animations in the sequence.
+  pub fn items(&self) -> &[A] {
+    &self.items
+  }
+
+  /// The running animation, or the last one once the sequence is finished.
+  pub fn current(&self) -> Option<&A> {
+    self.items.get(self.current).or(self.items.last())
+  }
+
+  /// Moves past animations which are already finished, e.g. zero length
+  /// tweens.
+  fn skip_finished(&mut self) {
+    while self.current < self.items.len() && self.items[self.current].is_finished() {
+      self.current += 1;
+    }
+  }
+}
+
+impl<T: Lerp> Sequence<Tween<T>> {
+  /// The value of the running tween, or the last tween's end value once the
+  /// sequence is finished. None if the sequence is empty.
+  pub fn value(&self) -> Option<T> {
+    self.current().map(|tween| tween.value())
+  }
+}
+
+impl<A: Animation> Animation for Sequence<A> {
+  fn update(&mut self, dt: f32) -> f32 {
+    let mut dt = dt;
+    while self.current < self.items.len() {
+      dt = self.items[self.current].update(dt);
+      self.skip_finished();
+      if dt <= 0.0 {
+        return 0.0;
+      }
+    }
+    dt
+  }
+
+  fn is_finished(&self) -> bool {
+    self.current >= self.items.len()
+  }
+
+  fn reset(&mut self) {
+    for item in &mut self.items {
+      item.reset();
+    }
+    self.current = 0;
+    self.skip_finished();
+  }
+}
+
+/// Animations which run at the same time. The group finishes once all of
+/// them have.
+pub struct Parallel<A> {
+  items: Vec<A>,
+}
+
+impl<A: Animation> Parallel<A> {
+  pub fn new(items: Vec<A>) -> Parallel<A> {
+    Parallel { items: items }
+  }
+
+  /// The animations in the group.
+  pub fn items(&self) -> &[A] {
+    &self.items
+  }
+}
+
+impl<A: Animation> Animation for Parallel<A> {
+  fn update(&mut self, dt: f32) -> f32 {
+    // Time is only left over once the longest animation has finished.
+    let mut leftover = dt;
+    for item in &mut self.items {
+      let finished = item.is_finished();
+      let item_leftover = if finished { dt } else { item.update(dt) };
+      leftover = leftover.min(item_leftover);
+    }
+    leftover
+  }
+
+  fn is_finished(&self) -> bool {
+    self.items.iter().all(|item| item.is_finished())
+  }
+
+  fn reset(&mut self) {
+    for item in &mut self.items {
+      item.reset();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn eases_start_at_0_and_end_at_1() {
+    let eases = [Ease::Linear, Ease::QuadIn, Ease::QuadOut, Ease::QuadInOut, Ease::CubicIn,
+                 Ease::CubicOut, Ease::CubicInOut, Ease::SineInOut, Ease::BackOut,
+                 Ease::ElasticOut, Ease::BounceOut];
+    for ease in &eases {
+      assert!(ease.apply(0.0).abs() < 1e-4, "{:?}", ease);
+      assert!((ease.apply(1.0) - 1.0).abs() < 1e-4, "{:?}", ease);
+    }
+  }
+
+  #[test]
+  fn sequence_carries_time_over() {
+    let mut seq = Sequence::new(vec![
+      Tween::new(0.0, 1.0, 1.0, Ease::Linear),
+      Tween::new(1.0, 3.0, 1.0, Ease::Linear),
+    ]);
+    assert_eq!(seq.update(1.5), 0.0);
+    assert_eq!(seq.value(), Some(2.0));
+    assert_eq!(seq.update(1.0), 0.5);
+    assert!(seq.is_finished());
+    assert_eq!(seq.value(), Some(3.0));
+    seq.reset();
+    assert_eq!(seq.value(), Some(0.0));
+  }
+
+  #[test]
+  fn parallel_finishes_with_longest() {
+    let mut group = Parallel::new(vec![
+      Tween::new([0.0, 0.0], [1.0, 1.0], 1.0, Ease::Linear),
+      Tween::new([0.0, 0.0], [2.0, 2.0], 2.0, Ease::Linear),
+    ]);
+    assert_eq!(group.update(1.5), 0.0);
+    assert!(group.items()[0].is_finished() && !group.is_finished());
+    assert_eq!(group.update(1.0), 0.5);
+    assert!(group.is_finished());
+    assert_eq!(group.items()[1].value(), [2.0, 2.0]);
+  }
+}
@@ -0,0 +1,315 @@
+//! A minimal immediate-mode widget layer, built on the RendererController.
+//! Widgets are drawn and their input handled in the same call, every frame:
+//!
+//! ```ignore
+//! let mut state = UiState::new();
+//! let mut input = UiInput::new();
+//! loop {
+//!   qgfx.poll_events(|ev| input.handle_event(&ev));
+//!   {
+//!     let mut controller = qgfx.get_renderer_controller();
+//!     let mut ui = Ui::begin(&mut controller, &mut state, &input, font);
+//!     if ui.button("Quit", &[10.0, 10.0, 100.0, 30.0]) { ... }
+//!     ui.slider("Volume", &[10.0, 50.0, 200.0, 20.0], &mut volume, 0.0, 1.0);
+//!     ui.end();
+//!     controller.flush();
+//!   }
+//!   input.end_frame();
+//!   ...
+//! }
+//! ```
+//!
+//! Widgets are identified by their label, so labels must be unique within a
+//! frame.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use event::{QgfxEvent, ButtonState, MouseButton};
+use geometry::point_in_rect;
+use renderer::RendererController;
+use res::font::{FontHandle, TextAnchor};
+
+/// The input state the widgets respond to. Feed this window events with
+/// handle_event(), and call end_frame() after drawing each frame.
+#[derive(Clone, Debug, Default)]
+pub struct UiInput {
+  /// The position of the mouse cursor, in pixels.
+  pub mouse_pos: [f32; 2],
+  /// True if the left mouse button is held down.
+  pub mouse_down: bool,
+  /// The chars typed since the last call to end_frame().
+  pub text: String,
+  /// The number of times backspace was pressed since the last call to
+  /// end_frame().
+  pub backspaces: usize,
+}
+
+impl UiInput {
+  pub fn new() -> UiInput { UiInput::default() }
+
+  /// Updates the input state from a window event. Events the widgets don't
+  /// care about are ignored.
+  pub fn handle_event(&mut self, ev: &QgfxEvent) {
+    match *ev {
+      QgfxEvent::MouseMove { x, y } => {
+        self.mouse_pos = [x, y];
+      }
+      QgfxEvent::MouseButton { button: MouseButton::Left, state } => {
+        self.mouse_down = state == ButtonState::Pressed;
+      }
+      QgfxEvent::Text(c) => {
+        if c == '\u{8}' {
+          self.backspaces += 1;
+        } else if !c.is_control() {
+          self.text.push(c);
+        }
+      }
+      _ => (),
+    }
+  }
+
+  /// Clears the per-frame input (typed text and backspaces). Call this once
+  /// per frame, after the widgets have been drawn.
+  pub fn end_frame(&mut self) {
+    self.text.clear();
+    self.backspaces = 0;
+  }
+}
+
+/// State which the widgets need to keep between frames - which widget is
+/// being clicked or dragged, and which has keyboard focus. Keep one of these
+/// alive for as long as the UI is shown.
+#[derive(Clone, Debug, Default)]
+pub struct UiState {
+  /// The widget the mouse was pressed on, if it's still held down.
+  active: Option<u64>,
+  /// The widget receiving typed text.
+  focused: Option<u64>,
+  /// Whether the mouse was down at the end of the last frame.
+  mouse_was_down: bool,
+}
+
+impl UiState {
+  pub fn new() -> UiState { UiState::default() }
+}
+
+/// The colours used to draw widgets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UiStyle {
+  pub text_col: [f32; 4],
+  /// The background of buttons, checkboxes, slider tracks and text boxes.
+  pub bg_col: [f32; 4],
+  /// The background of a widget under the mouse.
+  pub hot_col: [f32; 4],
+  /// The background of a widget being clicked, or with focus.
+  pub active_col: [f32; 4],
+  /// The colour of checkbox ticks and slider handles.
+  pub accent_col: [f32; 4],
+}
+
+impl Default for UiStyle {
+  fn default() -> UiStyle {
+    UiStyle {
+      text_col: [1.0, 1.0, 1.0, 1.0],
+      bg_col: [0.25, 0.25, 0.25, 1.0],
+      hot_col: [0.35, 0.35, 0.35, 1.0],
+      active_col: [0.15, 0.15, 0.15, 1.0],
+      accent_col: [0.3, 0.6, 1.0, 1.0],
+    }
+  }
+}
+
+/// A frame of immediate-mode UI. Create one with begin() each frame, call
+/// widget functions on it, then end() it.
+pub struct Ui<'c, 'a: 'c> {
+  controller: &'c mut RendererController<'a>,
+  state: &'c mut UiState,
+  input: &'c UiInput,
+  font: FontHandle,
+  /// The colours to draw widgets with. This can be changed between widgets.
+  pub style: UiStyle,
+}
+
+impl<'c, 'a: 'c> Ui<'c, 'a> {
+  /// Begins a frame of UI, drawing with the given controller and font. The
+  /// font's glyphs must be cached.
+  pub fn begin(controller: &'c mut RendererController<'a>, state: &'c mut UiState,
+               input: &'c UiInput, font: FontHandle) -> Ui<'c, 'a> {
+    Ui {
+      controller: controller,
+      state: state,
+      input: input,
+      font: font,
+      style: UiStyle::default(),
+    }
+  }
+
+  /// Ends this frame of UI.
+  pub fn end(self) {
+    if !self.input.mouse_down {
+      self.state.active = None;
+    }
+    self.state.mouse_was_down = self.input.mouse_down;
+  }
+
+  /// Draws some text, with its top left at `pos`.
+  pub fn label(&mut self, pos: &[f32; 2], text: &str) {
+    let col = self.style.text_col;
+    let _ = self.controller.text_anchored(text, pos, self.font, TextAnchor::TopLeft, &col);
+  }
+
+  /// Draws a button with the given label, filling `aabb` (X, Y, W, H).
+  /// # Returns
+  /// True if the button was clicked this frame.
+  pub fn button(&mut self, label: &str, aabb: &[f32; 4]) -> bool {
+    let id = widget_id(label);
+    let clicked = self.press_and_release(id, aabb);
+    let bg = self.bg_col(id, aabb);
+    self.controller.rect(aabb, &bg);
+    self.centred_text(label, aabb);
+    clicked
+  }
+
+  /// Draws a checkbox with the given label to its right. The box is a square
+  /// the height of `aabb`.
+  /// # Returns
+  /// True if `checked` was toggled this frame.
+  pub fn checkbox(&mut self, label: &str, aabb: &[f32; 4], checked: &mut bool) -> bool {
+    let id = widget_id(label);
+    let toggled = self.press_and_release(id, aabb);
+    if toggled {
+      *checked = !*checked;
+    }
+    let size = aabb[3];
+    let bg = self.bg_col(id, aabb);
+    self.controller.rect(&[aabb[0], aabb[1], size, size], &bg);
+    if *checked {
+      let inset = size / 4.0;
+      let accent = self.style.accent_col;
+      self.controller.rect(&[aabb[0] + inset, aabb[1] + inset,
+                             size - inset * 2.0, size - inset * 2.0], &accent);
+    }
+    let col = self.style.text_col;
+    let _ = self.controller.text_anchored(label, &[aabb[0] + size * 1.25, aabb[1]],
+                                          self.font, TextAnchor::TopLeft, &col);
+    toggled
+  }
+
+  /// Draws a horizontal slider, filling `aabb`. Dragging the slider sets
+  /// `value` between `min` and `max`. The label is only used to identify the
+  /// slider.
+  /// # Returns
+  /// True if `value` changed this frame.
+  pub fn slider(&mut self, label: &str, aabb: &[f32; 4], value: &mut f32,
+                min: f32, max: f32) -> bool {
+    let id = widget_id(label);
+    if self.pressed() && self.mouse_over(aabb) {
+      self.state.active = Some(id);
+    }
+    let mut changed = false;
+    if self.state.active == Some(id) && self.input.mouse_down {
+      let t = ((self.input.mouse_pos[0] - aabb[0]) / aabb[2]).max(0.0).min(1.0);
+      let new_value = min + (max - min) * t;
+      changed = new_value != *value;
+      *value = new_value;
+    }
+
+    let bg = self.bg_col(id, aabb);
+    self.controller.rect(aabb, &bg);
+    let t = if max == min { 0.0 } else { ((*value - min) / (max - min)).max(0.0).min(1.0) };
+    let handle_w = aabb[3] / 2.0;
+    let accent = self.style.accent_col;
+    self.controller.rect(&[aabb[0] + (aabb[2] - handle_w) * t, aabb[1], handle_w, aabb[3]],
+                         &accent);
+    changed
+  }
+
+  /// Draws a single line text box, filling `aabb`. Clicking the text box
+  /// gives it focus, and typed text is then appended to `text`. Clicking
+  /// anywhere else removes focus. The label is only used to identify the text
+  /// box. Text is not clipped, so text longer than the box overflows it.
+  /// # Returns
+  /// True if `text` changed this frame.
+  pub fn text_box(&mut self, label: &str, aabb: &[f32; 4], text: &mut String) -> bool {
+    let id = widget_id(label);
+    if self.pressed() {
+      if self.mouse_over(aabb) {
+        self.state.focused = Some(id);
+      } else if self.state.focused == Some(id) {
+        self.state.focused = None;
+      }
+    }
+
+    let focused = self.state.focused == Some(id);
+    let mut changed = false;
+    if focused {
+      for _ in 0..self.input.backspaces {
+        changed |= text.pop().is_some();
+      }
+      text.push_str(&self.input.text);
+      changed |= !self.input.text.is_empty();
+    }
+
+    let bg = if focused { self.style.active_col } else { self.bg_col(id, aabb) };
+    self.controller.rect(aabb, &bg);
+    let col = self.style.text_col;
+    let padding = aabb[3] / 4.0;
+    let size = self.controller.text_anchored(
+      text, &[aabb[0] + padding, aabb[1] + padding], self.font, TextAnchor::TopLeft, &col)
+      .unwrap_or((0.0, 0.0));
+    if focused {
+      // Caret
+      self.controller.rect(&[aabb[0] + padding + size.0 + 1.0, aabb[1] + padding,
+                             1.0, aabb[3] - padding * 2.0], &col);
+    }
+    changed
+  }
+
+  /// True if the mouse was pressed this frame.
+  fn pressed(&self) -> bool {
+    self.input.mouse_down && !self.state.mouse_was_down
+  }
+
+  fn mouse_over(&self, aabb: &[f32; 4]) -> bool {
+    point_in_rect(&self.input.mouse_pos, aabb)
+  }
+
+  /// Handles clicking a widget. The widget becomes active when the mouse is
+  /// pressed on it, and is clicked if the mouse is released whilst still
+  /// over it.
+  /// # Returns
+  /// True if the widget was clicked.
+  fn press_and_release(&mut self, id: u64, aabb: &[f32; 4]) -> bool {
+    let over = self.mouse_over(aabb);
+    if self.pressed() && over {
+      self.state.active = Some(id);
+    }
+    let released = !self.input.mouse_down && self.state.mouse_was_down;
+    released && over && self.state.active == Some(id)
+  }
+
+  /// The background colour of a widget, depending on whether it's being
+  /// clicked or is under the mouse.
+  fn bg_col(&self, id: u64, aabb: &[f32; 4]) -> [f32; 4] {
+    if self.state.active == Some(id) {
+      self.style.active_col
+    } else if self.state.active.is_none() && self.mouse_over(aabb) {
+      self.style.hot_col
+    } else {
+      self.style.bg_col
+    }
+  }
+
+  fn centred_text(&mut self, text: &str, aabb: &[f32; 4]) {
+    let col = self.style.text_col;
+    let centre = [aabb[0] + aabb[2] / 2.0, aabb[1] + aabb[3] / 2.0];
+    let _ = self.controller.text_anchored(text, &centre, self.font, TextAnchor::Centre, &col);
+  }
+}
+
+/// Generates a widget's ID from its label.
+fn widget_id(label: &str) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  label.hash(&mut hasher);
+  hasher.finish()
+}
@@ -29,6 +29,11 @@ impl Vec2 {
     self.mul(1.0/factor) 
   }
 
+  /// The dot product of this vector and another.
+  pub fn dot(&self, other: Vec2) -> f32 {
+    self[0] * other[0] + self[1] * other[1]
+  }
+
   /// Normalise this vector and return the result.
   pub fn nor(&self) -> Vec2 {
     self.div(self.len())